@@ -0,0 +1,136 @@
+//! Running in the background without a supervisor.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+/// A detached process which hasn't reported to its parent yet.
+///
+/// The original process waits until the daemon is ready to serve requests, so whoever has started
+/// it gets a meaningful exit code. If the daemon fails (and drops this object) before reporting,
+/// the original process exits with an error.
+pub struct Daemon {
+    ready: File,
+}
+
+impl Daemon {
+    /// Reports to the original process that everything is fine.
+    pub fn ready(mut self) -> io::Result<()> {
+        self.ready.write_all(b"1")
+    }
+}
+
+/// Detaches the process from the terminal, see `daemon(7)`.
+///
+/// Returns in the daemon process only, the original one exits once the daemon is ready. Standard
+/// streams are redirected to `/dev/null`, so logs have to go somewhere else. The working directory
+/// is not changed, since relative paths in the options refer to it.
+///
+/// Must be called before any threads are spawned.
+#[cfg(unix)]
+pub fn daemonize() -> io::Result<Daemon> {
+    use libc;
+    use std::io::Read;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    let (read_end, write_end) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    if check(unsafe { libc::fork() })? > 0 {
+        // The original process.
+        drop(write_end);
+        let mut status = [0u8];
+        match (&read_end).read(&mut status) {
+            Ok(1) => process::exit(0),
+            _ => {
+                eprintln!("The daemon has failed to start, please check the logs");
+                process::exit(1)
+            }
+        }
+    }
+    drop(read_end);
+    // Get rid of the controlling terminal, then make sure it is never acquired again by not being
+    // a session leader.
+    check(unsafe { libc::setsid() })?;
+    if check(unsafe { libc::fork() })? > 0 {
+        process::exit(0);
+    }
+    let dev_null = fs::OpenOptions::new().read(true)
+                                         .write(true)
+                                         .open("/dev/null")?;
+    for fd in &[libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        check(unsafe { libc::dup2(dev_null.as_raw_fd(), *fd) })?;
+    }
+    Ok(Daemon { ready: write_end })
+}
+
+/// Daemons are a Unix thing.
+///
+/// Running as a Windows service (talking to the Service Control Manager) is not supported either,
+/// the server should be registered with a generic service wrapper instead, see the README.
+#[cfg(not(unix))]
+pub fn daemonize() -> io::Result<Daemon> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       "Daemon mode is not supported on this platform, please run the server \
+                        with a service wrapper instead"))
+}
+
+/// A file with the process ID, removed when dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the current process ID into a file.
+    ///
+    /// Fails if the file already exists and the process it refers to is still running, otherwise
+    /// the file is considered stale and is overwritten.
+    pub fn create<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        if let Ok(contents) = fs::read_to_string(&path) {
+            if let Ok(pid) = contents.trim().parse() {
+                // The ID might have been reused by this very process (say, in a container).
+                if pid != process::id() as i32 && is_running(pid) {
+                    let message = format!("Process {} from {} is still running",
+                                          pid,
+                                          path.display());
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, message));
+                }
+            }
+        }
+        writeln!(File::create(&path)?, "{}", process::id())?;
+        Ok(PidFile { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            error!("Can't remove the PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: i32) -> bool {
+    use libc;
+    // The signal is not sent, only the existence of the process is checked. `EPERM` means the
+    // process exists, but belongs to someone else.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// There is no cheap way to check it, so the file is assumed to be stale.
+#[cfg(not(unix))]
+fn is_running(_pid: i32) -> bool {
+    false
+}
@@ -0,0 +1,949 @@
+//! Amazon DynamoDB wrapper that implements `DbInterface`.
+
+use chrono::{DateTime, TimeZone, Utc};
+use pastebin::{count_lines, guess_language, DbInterface, MimeType, PasteEntry, PasteId,
+               PasteSummary, StorageStats, Tombstone, Unsupported, UpdateOutcome};
+use pastebin::redact::redact;
+use rusoto_core::Region;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DescribeTableInput, DynamoDb,
+                      DynamoDbClient, GetItemInput, PutItemError, PutItemInput, ScanInput,
+                      UpdateItemError, UpdateItemInput};
+use std::collections::HashMap;
+use std::error;
+
+/// A DynamoDB item, attribute values by their names.
+type Item = HashMap<String, AttributeValue>;
+
+/// Attributes of a paste summary, i.e. everything but the data, along with the trashing date.
+const SUMMARY_ATTRIBUTES: &str = "#id, #file_name, #mime_type, #size, #lines, #language, \
+                                  #created_at, #best_before, #views, #creator, #revision, \
+                                  #views_left, #public, #deleted_at";
+
+/// Key of the IDs counter in the IDs table.
+const COUNTER_KEY: &str = "paste";
+
+/// Prefix of the keys of content hashes in the IDs table.
+const HASH_KEY_PREFIX: &str = "hash:";
+
+/// Prefix of the keys of slugs in the IDs table.
+const SLUG_KEY_PREFIX: &str = "slug:";
+
+/// Prefix of the keys of tombstones of removed pastes in the IDs table.
+const TOMBSTONE_KEY_PREFIX: &str = "tombstone:";
+
+/// Attributes of a tombstone.
+const TOMBSTONE_ATTRIBUTES: &str = "#paste, #removed_at, #actor";
+
+quick_error! {
+    /// DynamoDB wrapper errors.
+    #[derive(Debug)]
+    pub enum DynamoDbError {
+        /// A request has failed.
+        Request(err: Box<error::Error + Send + Sync>) {
+            description("DynamoDB request failed")
+            display("DynamoDB request failed: {}", err)
+            cause(&**err)
+        }
+        /// An item doesn't look like a paste.
+        Malformed(reason: String) {
+            description("Malformed item")
+            display("Malformed item: {}", reason)
+        }
+        /// An operation the wrapper doesn't support.
+        Unsupported(err: Unsupported) {
+            description("Unsupported operation")
+            display("{}", err)
+            from()
+        }
+    }
+}
+
+fn request_error<E: error::Error + Send + Sync + 'static>(err: E) -> DynamoDbError {
+    DynamoDbError::Request(Box::new(err))
+}
+
+/// A DynamoDB wrapper.
+///
+/// Pastes are stored in a table with a numeric `id` partition key, an item per paste. Another
+/// table with a string `id` partition key holds the counter of IDs, the hashes of pastes content
+/// (see `DbInterface::store_hash`) and the tombstones of removed pastes. The tables have to be
+/// created beforehand.
+///
+/// The `expires_at` attribute of a paste holds its expiration date in seconds since the Unix
+/// epoch, so it could be set up as the TTL attribute of the table to have expired pastes removed
+/// by DynamoDB itself.
+///
+/// DynamoDB can't enumerate items in order or find them by anything but the key, so listing,
+/// purging and such scan the whole table.
+pub struct DynamoDbWrapper {
+    client: DynamoDbClient,
+    table: String,
+    ids_table: String,
+}
+
+impl DynamoDbWrapper {
+    /// Creates a wrapper for the tables in a region. The credentials are looked up the usual AWS
+    /// way: environment variables, profiles, instance metadata.
+    pub fn new(region: Region, table: String, ids_table: String) -> Self {
+        DynamoDbWrapper { client: DynamoDbClient::new(region),
+                          table,
+                          ids_table, }
+    }
+
+    fn get_new_id(&self) -> Result<u64, DynamoDbError> {
+        let mut input = update_input(&self.ids_table,
+                                     string_key(COUNTER_KEY),
+                                     "ADD #counter :one",
+                                     None,
+                                     vec![(":one", number(1))]);
+        input.return_values = Some("UPDATED_NEW".into());
+        let output = self.client
+                         .update_item(input)
+                         .sync()
+                         .map_err(request_error)?;
+        let mut attributes = output.attributes.unwrap_or_default();
+        get_number(&mut attributes, "counter")?.map(|counter| counter as u64)
+                                               .ok_or_else(|| malformed("counter", "missing"))
+    }
+
+    /// Makes sure `get_new_id` will never return the given ID (or anything below it).
+    fn reserve_id(&self, id: u64) -> Result<(), DynamoDbError> {
+        let input = update_input(&self.ids_table,
+                                 string_key(COUNTER_KEY),
+                                 "SET #counter = :id",
+                                 Some("attribute_not_exists(#counter) OR #counter < :id"),
+                                 vec![(":id", number(id))]);
+        self.update(input)?;
+        Ok(())
+    }
+
+    /// Runs an update, returns `false` if its condition doesn't hold.
+    fn update(&self, input: UpdateItemInput) -> Result<bool, DynamoDbError> {
+        match self.client.update_item(input).sync() {
+            Ok(_) => Ok(true),
+            Err(UpdateItemError::ConditionalCheckFailed(_)) => Ok(false),
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    /// Updates an existing paste, returns `false` if there is no such paste.
+    fn update_paste(&self,
+                    id: PasteId,
+                    expression: &str,
+                    values: Vec<(&str, AttributeValue)>)
+                    -> Result<bool, DynamoDbError> {
+        self.update(update_input(&self.table,
+                                 paste_key(id),
+                                 expression,
+                                 Some("attribute_exists(#id)"),
+                                 values))
+    }
+
+    /// Returns the paste the item of a slug refers to, which might be gone already.
+    fn slug_owner(&self, slug: &str) -> Result<Option<PasteId>, DynamoDbError> {
+        let input = GetItemInput { table_name: self.ids_table.clone(),
+                                   key: string_key(&format!("{}{}", SLUG_KEY_PREFIX, slug)),
+                                   consistent_read: Some(true),
+                                   ..Default::default() };
+        let mut item = match self.client
+                                 .get_item(input)
+                                 .sync()
+                                 .map_err(request_error)?
+                                 .item
+        {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        Ok(get_number(&mut item, "paste")?.map(|id| PasteId::new(id as u64)))
+    }
+
+    /// Loads the given attributes of a paste (or all of them).
+    fn get(&self, id: PasteId, projection: Option<&str>) -> Result<Option<Item>, DynamoDbError> {
+        let input = GetItemInput { table_name: self.table.clone(),
+                                   key: paste_key(id),
+                                   consistent_read: Some(true),
+                                   projection_expression: projection.map(Into::into),
+                                   expression_attribute_names:
+                                       projection.map(|projection| attribute_names(&[projection])),
+                                   ..Default::default() };
+        Ok(self.client
+               .get_item(input)
+               .sync()
+               .map_err(request_error)?
+               .item
+               .filter(|item| !item.is_empty()))
+    }
+
+    /// Scans a whole table (of pastes or of IDs), optionally with a filter.
+    fn scan(&self,
+            table: &str,
+            projection: &str,
+            filter: Option<&str>,
+            values: Vec<(&str, AttributeValue)>)
+            -> Result<Vec<Item>, DynamoDbError> {
+        let names = attribute_names(&[projection, filter.unwrap_or_default()]);
+        let values = attribute_values(values);
+        let mut items = Vec::new();
+        let mut start_key = None;
+        loop {
+            let input = ScanInput { table_name: table.into(),
+                                    projection_expression: Some(projection.into()),
+                                    filter_expression: filter.map(Into::into),
+                                    expression_attribute_names: Some(names.clone()),
+                                    expression_attribute_values: values.clone(),
+                                    exclusive_start_key: start_key,
+                                    ..Default::default() };
+            let output = self.client.scan(input).sync().map_err(request_error)?;
+            items.extend(output.items.unwrap_or_default());
+            start_key = match output.last_evaluated_key {
+                Some(key) if !key.is_empty() => Some(key),
+                _ => break,
+            };
+        }
+        Ok(items)
+    }
+
+    /// Finds IDs of the pastes that match the filter.
+    fn find_ids(&self,
+                filter: &str,
+                values: Vec<(&str, AttributeValue)>)
+                -> Result<Vec<PasteId>, DynamoDbError> {
+        self.scan(&self.table, "#id", Some(filter), values)?
+            .into_iter()
+            .map(|mut item| paste_id(&mut item))
+            .collect()
+    }
+}
+
+fn malformed(attribute: &str, reason: &str) -> DynamoDbError {
+    DynamoDbError::Malformed(format!("`{}` {}", attribute, reason))
+}
+
+fn number<T: ToString>(value: T) -> AttributeValue {
+    AttributeValue { n: Some(value.to_string()),
+                     ..Default::default() }
+}
+
+fn string<S: Into<String>>(value: S) -> AttributeValue {
+    AttributeValue { s: Some(value.into()),
+                     ..Default::default() }
+}
+
+fn binary(value: Vec<u8>) -> AttributeValue {
+    AttributeValue { b: Some(value),
+                     ..Default::default() }
+}
+
+fn boolean(value: bool) -> AttributeValue {
+    AttributeValue { bool: Some(value),
+                     ..Default::default() }
+}
+
+fn paste_key(id: PasteId) -> Item {
+    let mut key = Item::new();
+    key.insert("id".into(), number(id.as_u64()));
+    key
+}
+
+fn string_key(id: &str) -> Item {
+    let mut key = Item::new();
+    key.insert("id".into(), string(id));
+    key
+}
+
+/// Maps the `#name` placeholders of expressions to the attribute names, since a lot of names
+/// (like `data` or `size`) are reserved words in DynamoDB.
+fn attribute_names(expressions: &[&str]) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for expression in expressions {
+        for placeholder in expression.split(|c: char| !c.is_alphanumeric() && c != '_' && c != '#')
+                                     .filter(|word| word.starts_with('#'))
+        {
+            names.insert(placeholder.to_string(), placeholder[1..].to_string());
+        }
+    }
+    names
+}
+
+/// DynamoDB doesn't accept empty maps of values.
+fn attribute_values(values: Vec<(&str, AttributeValue)>) -> Option<Item> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.into_iter()
+               .map(|(name, value)| (name.to_string(), value))
+               .collect())
+}
+
+fn update_input(table: &str,
+                key: Item,
+                expression: &str,
+                condition: Option<&str>,
+                values: Vec<(&str, AttributeValue)>)
+                -> UpdateItemInput {
+    UpdateItemInput { table_name: table.into(),
+                      key,
+                      update_expression: Some(expression.into()),
+                      condition_expression: condition.map(Into::into),
+                      expression_attribute_names:
+                          Some(attribute_names(&[expression, condition.unwrap_or_default()])),
+                      expression_attribute_values: attribute_values(values),
+                      ..Default::default() }
+}
+
+fn get_string(item: &mut Item, name: &str) -> Result<Option<String>, DynamoDbError> {
+    match item.remove(name) {
+        None => Ok(None),
+        Some(AttributeValue { s: Some(value), .. }) => Ok(Some(value)),
+        Some(_) => Err(malformed(name, "is not a string")),
+    }
+}
+
+fn get_number(item: &mut Item, name: &str) -> Result<Option<i64>, DynamoDbError> {
+    match item.remove(name) {
+        None => Ok(None),
+        Some(AttributeValue { n: Some(value), .. }) => {
+            value.parse()
+                 .map(Some)
+                 .map_err(|_| malformed(name, "is not an integer"))
+        }
+        Some(_) => Err(malformed(name, "is not a number")),
+    }
+}
+
+fn get_binary(item: &mut Item, name: &str) -> Result<Option<Vec<u8>>, DynamoDbError> {
+    match item.remove(name) {
+        None => Ok(None),
+        Some(AttributeValue { b: Some(value), .. }) => Ok(Some(value)),
+        Some(_) => Err(malformed(name, "is not a binary")),
+    }
+}
+
+fn get_boolean(item: &mut Item, name: &str) -> Result<Option<bool>, DynamoDbError> {
+    match item.remove(name) {
+        None => Ok(None),
+        Some(AttributeValue { bool: Some(value), .. }) => Ok(Some(value)),
+        Some(_) => Err(malformed(name, "is not a boolean")),
+    }
+}
+
+fn paste_id(item: &mut Item) -> Result<PasteId, DynamoDbError> {
+    get_number(item, "id")?.map(|id| PasteId::new(id as u64))
+                           .ok_or_else(|| malformed("id", "is missing"))
+}
+
+fn get_mime_type(item: &mut Item) -> Result<MimeType, DynamoDbError> {
+    let mime_type = get_string(item, "mime_type")?.ok_or_else(|| malformed("mime_type",
+                                                                           "is missing"))?;
+    MimeType::new(mime_type).map_err(|e| DynamoDbError::Malformed(e.to_string()))
+}
+
+/// Parses a date stored as milliseconds since the Unix epoch.
+fn get_date(item: &mut Item, name: &str) -> Result<Option<DateTime<Utc>>, DynamoDbError> {
+    Ok(get_number(item, name)?.map(|millis| Utc.timestamp_millis(millis)))
+}
+
+fn tombstone_key(id: PasteId) -> Item {
+    string_key(&format!("{}{}", TOMBSTONE_KEY_PREFIX, id.as_u64()))
+}
+
+fn item_to_tombstone(mut item: Item) -> Result<Tombstone, DynamoDbError> {
+    let id = get_number(&mut item, "paste")?.ok_or_else(|| malformed("paste", "is missing"))?;
+    Ok(Tombstone { id: PasteId::new(id as u64),
+                   removed_at: get_date(&mut item, "removed_at")?
+                       .ok_or_else(|| malformed("removed_at", "is missing"))?,
+                   actor: get_string(&mut item, "actor")?
+                       .ok_or_else(|| malformed("actor", "is missing"))?, })
+}
+
+fn entry_to_item(id: PasteId, entry: PasteEntry) -> Item {
+    let mut item = paste_key(id);
+    item.insert("size".into(), number(entry.data.len()));
+    item.insert("lines".into(), number(count_lines(&entry.data)));
+    // The language given by the uploader, or a guessed one, so listing doesn't need the data.
+    // A given one is kept when the data is replaced.
+    if entry.language.is_some() {
+        item.insert("language_given".into(), boolean(true));
+    }
+    let language = entry.language.or_else(|| {
+                       guess_language(entry.file_name.as_ref().map(|s| s.as_str()),
+                                      entry.mime_type.as_str()).map(Into::into)
+                   });
+    if let Some(language) = language {
+        item.insert("language".into(), string(language));
+    }
+    item.insert("data".into(), binary(entry.data));
+    item.insert("mime_type".into(), string(entry.mime_type));
+    item.insert("created_at".into(), number(entry.created_at.timestamp_millis()));
+    item.insert("views".into(), number(entry.views));
+    item.insert("revision".into(), number(entry.revision));
+    if let Some(file_name) = entry.file_name {
+        item.insert("file_name".into(), string(file_name));
+    }
+    if let Some(best_before) = entry.best_before {
+        item.insert("best_before".into(), number(best_before.timestamp_millis()));
+        item.insert("expires_at".into(), number(best_before.timestamp()));
+    }
+    if let Some(creator) = entry.creator {
+        item.insert("creator".into(), string(creator));
+    }
+    if let Some(deletion_token) = entry.deletion_token {
+        item.insert("deletion_token".into(), string(deletion_token));
+    }
+    if let Some(views_left) = entry.views_left {
+        item.insert("views_left".into(), number(views_left));
+    }
+    if entry.public {
+        item.insert("public".into(), boolean(true));
+    }
+    item
+}
+
+/// Checks whether a paste is in the trash.
+fn is_trashed(item: &Item) -> bool {
+    item.contains_key("deleted_at")
+}
+
+fn item_to_entry(mut item: Item) -> Result<PasteEntry, DynamoDbError> {
+    Ok(PasteEntry { data: get_binary(&mut item, "data")?.unwrap_or_default(),
+                    file_name: get_string(&mut item, "file_name")?,
+                    mime_type: get_mime_type(&mut item)?,
+                    created_at: get_date(&mut item, "created_at")?
+                        .ok_or_else(|| malformed("created_at", "is missing"))?,
+                    best_before: get_date(&mut item, "best_before")?,
+                    views: get_number(&mut item, "views")?.unwrap_or(0) as u64,
+                    creator: get_string(&mut item, "creator")?,
+                    deletion_token: get_string(&mut item, "deletion_token")?,
+                    language: get_string(&mut item, "language")?,
+                    revision: get_number(&mut item, "revision")?.unwrap_or(0) as u64,
+                    views_left: get_number(&mut item, "views_left")?.map(|views| views as u64),
+                    public: get_boolean(&mut item, "public")?.unwrap_or(false), })
+}
+
+fn item_to_summary(mut item: Item) -> Result<PasteSummary, DynamoDbError> {
+    Ok(PasteSummary { id: paste_id(&mut item)?,
+                      file_name: get_string(&mut item, "file_name")?,
+                      mime_type: get_mime_type(&mut item)?,
+                      size: get_number(&mut item, "size")?.unwrap_or(0) as usize,
+                      lines: get_number(&mut item, "lines")?.unwrap_or(0) as usize,
+                      language: get_string(&mut item, "language")?,
+                      created_at: get_date(&mut item, "created_at")?
+                          .ok_or_else(|| malformed("created_at", "is missing"))?,
+                      best_before: get_date(&mut item, "best_before")?,
+                      views: get_number(&mut item, "views")?.unwrap_or(0) as u64,
+                      creator: get_string(&mut item, "creator")?,
+                      revision: get_number(&mut item, "revision")?.unwrap_or(0) as u64,
+                      views_left: get_number(&mut item, "views_left")?.map(|views| views as u64),
+                      public: get_boolean(&mut item, "public")?.unwrap_or(false), })
+}
+
+impl DbInterface for DynamoDbWrapper {
+    type Error = DynamoDbError;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        let id = PasteId::new(self.get_new_id()?);
+        debug!("Storing {} as an item id = {:?}", redact(&entry.data), id);
+        let entry = PasteEntry { views: 0,
+                                 revision: 0,
+                                 ..entry };
+        let input = PutItemInput { table_name: self.table.clone(),
+                                   item: entry_to_item(id, entry),
+                                   ..Default::default() };
+        self.client
+            .put_item(input)
+            .sync()
+            .map_err(request_error)?;
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        debug!("Inserting {} as an item id = {:?}", redact(&entry.data), id);
+        self.reserve_id(id.as_u64())?;
+        // A put replaces the whole item.
+        let input = PutItemInput { table_name: self.table.clone(),
+                                   item: entry_to_item(id, entry),
+                                   ..Default::default() };
+        self.client
+            .put_item(input)
+            .sync()
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    fn store_new(&self, id: PasteId, mut entry: PasteEntry) -> Result<bool, Self::Error> {
+        debug!("Storing {} as a new item id = {:?}", redact(&entry.data), id);
+        if self.load_tombstone(id)?.is_some() {
+            return Ok(false);
+        }
+        entry.views = 0;
+        entry.revision = 0;
+        // Trashed items stay in the table, so their IDs are taken as well.
+        let condition = "attribute_not_exists(#id)";
+        let input = PutItemInput { table_name: self.table.clone(),
+                                   item: entry_to_item(id, entry),
+                                   condition_expression: Some(condition.into()),
+                                   expression_attribute_names:
+                                       Some(attribute_names(&[condition])),
+                                   ..Default::default() };
+        match self.client.put_item(input).sync() {
+            Ok(_) => Ok(true),
+            Err(PutItemError::ConditionalCheckFailed(_)) => Ok(false),
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        debug!("Looking for an item id = {:?}", id);
+        match self.get(id, None)? {
+            Some(ref item) if is_trashed(item) => Ok(None),
+            Some(item) => Ok(Some(item_to_entry(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        debug!("Looking for metadata of an item id = {:?}", id);
+        match self.get(id, Some(SUMMARY_ATTRIBUTES))? {
+            Some(ref item) if is_trashed(item) => Ok(None),
+            Some(item) => Ok(Some(item_to_summary(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        debug!("Looking for a deletion token of an item id = {:?}", id);
+        match self.get(id, Some("#deletion_token, #deleted_at"))? {
+            Some(ref item) if is_trashed(item) => Ok(None),
+            Some(mut item) => get_string(&mut item, "deletion_token"),
+            None => Ok(None),
+        }
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        debug!("Updating an item id = {:?} with {}", id, redact(&data));
+        // The file name is not changed, but the mime type is, so the language might change as
+        // well, unless the uploader has given it.
+        let mut item = match self.get(id, Some("#file_name, #language_given, #deleted_at"))? {
+            Some(ref item) if is_trashed(item) => return Ok(UpdateOutcome::NotFound),
+            Some(item) => item,
+            None => return Ok(UpdateOutcome::NotFound),
+        };
+        let file_name = get_string(&mut item, "file_name")?;
+        let language_given = get_boolean(&mut item, "language_given")?.unwrap_or(false);
+        let mut values = vec![(":size", number(data.len())),
+                              (":lines", number(count_lines(&data))),
+                              (":data", binary(data)),
+                              (":one", number(1))];
+        let expression = match guess_language(file_name.as_ref().map(|s| s.as_str()),
+                                              mime_type.as_str())
+        {
+            _ if language_given => {
+                "SET #data = :data, #size = :size, #lines = :lines, #mime_type = :mime_type \
+                 ADD #revision :one"
+            }
+            Some(language) => {
+                values.push((":language", string(language)));
+                "SET #data = :data, #size = :size, #lines = :lines, #mime_type = :mime_type, \
+                 #language = :language ADD #revision :one"
+            }
+            None => {
+                "SET #data = :data, #size = :size, #lines = :lines, #mime_type = :mime_type \
+                 REMOVE #language ADD #revision :one"
+            }
+        };
+        values.push((":mime_type", string(mime_type)));
+        // Items stored before revisions were counted have none.
+        let condition = match expected_revision {
+            Some(0) => "attribute_exists(#id) AND (attribute_not_exists(#revision) OR \
+                        #revision = :expected)",
+            Some(_) => "attribute_exists(#id) AND #revision = :expected",
+            None => "attribute_exists(#id)",
+        };
+        if let Some(expected) = expected_revision {
+            values.push((":expected", number(expected)));
+        }
+        let mut input =
+            update_input(&self.table, paste_key(id), expression, Some(condition), values);
+        input.return_values = Some("UPDATED_NEW".into());
+        match self.client.update_item(input).sync() {
+            Ok(output) => {
+                let mut attributes = output.attributes.unwrap_or_default();
+                let revision = get_number(&mut attributes, "revision")?.unwrap_or(0) as u64;
+                Ok(UpdateOutcome::Updated(revision))
+            }
+            // Either the paste has been removed meanwhile, or it is at another revision.
+            Err(UpdateItemError::ConditionalCheckFailed(_)) => {
+                Ok(match self.load_metadata(id)? {
+                       Some(metadata) => UpdateOutcome::Conflict(metadata.revision),
+                       None => UpdateOutcome::NotFound,
+                   })
+            }
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        debug!("Counting a view of an item id = {:?}", id);
+        let condition = "attribute_exists(#id) AND attribute_not_exists(#deleted_at)";
+        self.update(update_input(&self.table,
+                                 paste_key(id),
+                                 "ADD #views :one",
+                                 Some(condition),
+                                 vec![(":one", number(1))]))?;
+        Ok(())
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        debug!("Taking a view of an item id = {:?}", id);
+        // The condition keeps concurrent requests from taking the same last view.
+        let condition = "attribute_exists(#id) AND attribute_not_exists(#deleted_at) AND \
+                         #views_left > :zero";
+        let mut input = update_input(&self.table,
+                                     paste_key(id),
+                                     "ADD #views_left :minus_one",
+                                     Some(condition),
+                                     vec![(":minus_one", number(-1)), (":zero", number(0))]);
+        input.return_values = Some("UPDATED_NEW".into());
+        match self.client.update_item(input).sync() {
+            Ok(output) => {
+                let mut attributes = output.attributes.unwrap_or_default();
+                Ok(get_number(&mut attributes, "views_left")?.map(|views| views as u64))
+            }
+            Err(UpdateItemError::ConditionalCheckFailed(_)) => Ok(None),
+            Err(e) => Err(request_error(e)),
+        }
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        debug!("Removing an item id = {:?}", id);
+        let input = DeleteItemInput { table_name: self.table.clone(),
+                                      key: paste_key(id),
+                                      ..Default::default() };
+        self.client
+            .delete_item(input)
+            .sync()
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        debug!("Trashing an item id = {:?}", id);
+        let condition = "attribute_exists(#id) AND attribute_not_exists(#deleted_at)";
+        self.update(update_input(&self.table,
+                                 paste_key(id),
+                                 "SET #deleted_at = :date",
+                                 Some(condition),
+                                 vec![(":date", number(deleted_at.timestamp_millis()))]))
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        debug!("Restoring an item id = {:?}", id);
+        self.update(update_input(&self.table,
+                                 paste_key(id),
+                                 "REMOVE #deleted_at",
+                                 Some("attribute_exists(#deleted_at)"),
+                                 vec![]))
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging items trashed before {}", deleted_before);
+        let ids = self.find_ids("#deleted_at < :date",
+                                vec![(":date", number(deleted_before.timestamp_millis()))])?;
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        debug!("Storing a tombstone of an item id = {:?}", tombstone.id);
+        // Tombstones outlive the pastes, so they are kept along with the IDs.
+        let mut item = tombstone_key(tombstone.id);
+        item.insert("paste".into(), number(tombstone.id.as_u64()));
+        item.insert("removed_at".into(), number(tombstone.removed_at.timestamp_millis()));
+        item.insert("actor".into(), string(tombstone.actor));
+        let input = PutItemInput { table_name: self.ids_table.clone(),
+                                   item,
+                                   ..Default::default() };
+        self.client
+            .put_item(input)
+            .sync()
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        debug!("Looking for a tombstone of an item id = {:?}", id);
+        let input = GetItemInput { table_name: self.ids_table.clone(),
+                                   key: tombstone_key(id),
+                                   consistent_read: Some(true),
+                                   ..Default::default() };
+        match self.client
+                  .get_item(input)
+                  .sync()
+                  .map_err(request_error)?
+                  .item
+        {
+            Some(ref item) if item.is_empty() => Ok(None),
+            Some(item) => Ok(Some(item_to_tombstone(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        debug!("Listing tombstones of items removed since {}", removed_since);
+        let mut tombstones =
+            self.scan(&self.ids_table,
+                      TOMBSTONE_ATTRIBUTES,
+                      Some("begins_with(#id, :prefix) AND #removed_at >= :date"),
+                      vec![(":prefix", string(TOMBSTONE_KEY_PREFIX)),
+                           (":date", number(removed_since.timestamp_millis()))])?
+                .into_iter()
+                .map(item_to_tombstone)
+                .collect::<Result<Vec<_>, _>>()?;
+        tombstones.sort_by(|a, b| b.removed_at.cmp(&a.removed_at));
+        Ok(tombstones)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging tombstones of items removed before {}", removed_before);
+        let items = self.scan(&self.ids_table,
+                              "#id",
+                              Some("begins_with(#id, :prefix) AND #removed_at < :date"),
+                              vec![(":prefix", string(TOMBSTONE_KEY_PREFIX)),
+                                   (":date", number(removed_before.timestamp_millis()))])?;
+        for item in &items {
+            let input = DeleteItemInput { table_name: self.ids_table.clone(),
+                                          key: item.clone(),
+                                          ..Default::default() };
+            self.client
+                .delete_item(input)
+                .sync()
+                .map_err(request_error)?;
+        }
+        Ok(items.len() as u64)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging items expired by {}", now);
+        // DynamoDB removes expired items by itself if TTL is enabled, but it might take a while.
+        let ids = self.find_ids("#best_before < :now",
+                                vec![(":now", number(now.timestamp_millis()))])?;
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Scrubbing creators of items created before {}", created_before);
+        let ids = self.find_ids("#created_at < :date AND attribute_exists(#creator)",
+                                vec![(":date", number(created_before.timestamp_millis()))])?;
+        for &id in &ids {
+            self.update_paste(id, "REMOVE #creator", vec![])?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Removing items of {} created since {}", creator, since);
+        let ids = self.find_ids("#creator = :creator AND #created_at >= :since",
+                                vec![(":creator", string(creator)),
+                                     (":since", number(since.timestamp_millis()))])?;
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        debug!("Removing items of an owner");
+        let ids = self.find_ids("#deletion_token = :token", vec![(":token", string(token))])?;
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        debug!("Listing items of an owner");
+        self.find_ids("#deletion_token = :token AND attribute_not_exists(#deleted_at)",
+                      vec![(":token", string(token))])
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        debug!("Storing hash {} of an item id = {:?}", hash, id);
+        if !self.update_paste(id, "SET #hash = :hash", vec![(":hash", string(hash))])? {
+            return Ok(());
+        }
+        // Items could only be looked up by their keys, so the hash is a key of another item.
+        let mut item = string_key(&format!("{}{}", HASH_KEY_PREFIX, hash));
+        item.insert("paste".into(), number(id.as_u64()));
+        let input = PutItemInput { table_name: self.ids_table.clone(),
+                                   item,
+                                   ..Default::default() };
+        self.client
+            .put_item(input)
+            .sync()
+            .map_err(request_error)?;
+        Ok(())
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        debug!("Looking for an item with hash {}", hash);
+        let input = GetItemInput { table_name: self.ids_table.clone(),
+                                   key: string_key(&format!("{}{}", HASH_KEY_PREFIX, hash)),
+                                   ..Default::default() };
+        let id = match self.client
+                           .get_item(input)
+                           .sync()
+                           .map_err(request_error)?
+                           .item
+        {
+            Some(mut item) => match get_number(&mut item, "paste")? {
+                Some(id) => PasteId::new(id as u64),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        // The paste might have got another hash since then.
+        let stored = match self.get(id, Some("#hash"))? {
+            Some(mut item) => get_string(&mut item, "hash")?,
+            None => None,
+        };
+        if stored.as_ref().map(String::as_str) == Some(hash) {
+            Ok(Some(id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        debug!("Storing slug {} of an item id = {:?}", slug, id);
+        // Same as hashes, a slug is a key of another item, which refers to the paste that has
+        // claimed it. The item is only taken over from a paste that is gone, and only if nobody
+        // has taken it over in the meantime.
+        let owner = self.slug_owner(slug)?;
+        if let Some(owner) = owner {
+            if owner != id && self.find_by_slug(slug)?.is_some() {
+                return Ok(false);
+            }
+        }
+        let (condition, mut values) = match owner {
+            Some(owner) => ("#paste = :owner", vec![(":owner", number(owner.as_u64()))]),
+            None => ("attribute_not_exists(#paste)", vec![]),
+        };
+        values.push((":paste", number(id.as_u64())));
+        let input = update_input(&self.ids_table,
+                                 string_key(&format!("{}{}", SLUG_KEY_PREFIX, slug)),
+                                 "SET #paste = :paste",
+                                 Some(condition),
+                                 values);
+        if !self.update(input)? {
+            return Ok(false);
+        }
+        self.update_paste(id, "SET #slug = :slug", vec![(":slug", string(slug))])?;
+        Ok(true)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        debug!("Looking for an item with slug {}", slug);
+        let id = match self.slug_owner(slug)? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        // The paste might be gone, and another one might have got its ID since then.
+        let stored = match self.get(id, Some("#slug"))? {
+            Some(mut item) => get_string(&mut item, "slug")?,
+            None => None,
+        };
+        if stored.as_ref().map(String::as_str) == Some(slug) {
+            Ok(Some(id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        debug!("Storing a signature of an item id = {:?}", id);
+        self.update_paste(id,
+                          "SET #signature = :signature",
+                          vec![(":signature", string(signature))])?;
+        Ok(())
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        debug!("Looking for a signature of an item id = {:?}", id);
+        match self.get(id, Some("#signature"))? {
+            Some(mut item) => get_string(&mut item, "signature"),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        debug!("Listing {} pastes starting from {}", limit, offset);
+        let mut summaries = self.scan(&self.table,
+                                      SUMMARY_ATTRIBUTES,
+                                      Some("attribute_not_exists(#deleted_at)"),
+                                      vec![])?
+                                .into_iter()
+                                .map(item_to_summary)
+                                .collect::<Result<Vec<_>, _>>()?;
+        summaries.sort_by_key(|summary| summary.id.as_u64());
+        Ok(summaries.into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .collect())
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        debug!("Listing {} public pastes starting from {}", limit, offset);
+        // There are no secondary indexes on the table, so it is scanned just like for `list`.
+        let filter = "#public = :public AND attribute_not_exists(#deleted_at)";
+        let mut summaries = self.scan(&self.table,
+                                      SUMMARY_ATTRIBUTES,
+                                      Some(filter),
+                                      vec![(":public", boolean(true))])?
+                                .into_iter()
+                                .map(item_to_summary)
+                                .collect::<Result<Vec<_>, _>>()?;
+        summaries.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+        Ok(summaries.into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .collect())
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        for table in &[&self.table, &self.ids_table] {
+            let input = DescribeTableInput { table_name: table.to_string() };
+            self.client
+                .describe_table(input)
+                .sync()
+                .map_err(request_error)?;
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        let mut stats = StorageStats::default();
+        let filter = Some("attribute_not_exists(#deleted_at)");
+        for mut item in self.scan(&self.table, "#size", filter, vec![])? {
+            stats.pastes += 1;
+            stats.total_bytes += get_number(&mut item, "size")?.unwrap_or(0) as u64;
+        }
+        Ok(stats)
+    }
+
+    fn max_data_size(&self) -> usize {
+        // An item is limited to 400 KB, including the metadata.
+        390 * 1024
+    }
+}
@@ -2,24 +2,79 @@
 extern crate bson;
 extern crate chrono;
 extern crate iron;
+#[cfg(unix)]
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate mongo_driver;
+#[cfg(feature = "mysql")]
+extern crate mysql;
 extern crate pastebin;
 #[macro_use]
 extern crate quick_error;
+#[cfg(feature = "dynamodb")]
+extern crate rusoto_core;
+#[cfg(feature = "dynamodb")]
+extern crate rusoto_dynamodb;
 extern crate simplelog;
 extern crate tera;
 
 mod cmdargs;
+mod daemon;
 mod mongo_impl;
+#[cfg(feature = "mysql")]
+mod mysql_impl;
+#[cfg(feature = "dynamodb")]
+mod dynamodb_impl;
+mod signals;
+mod systemd;
 
 use iron::error::HttpError;
 use mongo_driver::MongoError;
 use mongo_driver::client::ClientPool;
+use cmdargs::DbUri;
+use daemon::PidFile;
+#[cfg(feature = "dynamodb")]
+use dynamodb_impl::DynamoDbWrapper;
 use mongo_impl::MongoDbWrapper;
+#[cfg(feature = "mysql")]
+use mysql_impl::MySqlDbWrapper;
+use pastebin::db::{self, BoxedDb, CachedDb, CompressedDb, DedupDb, DynError, EncryptedDb,
+                   FsBlobStore, InstrumentedDb, Keyring, KeyringError, MemoryDb, MirroredDb,
+                   RetryingDb, SignedDb, SigningError, SigningKey, SplitDb};
+use pastebin::analytics::LocalAnalytics;
+use pastebin::auth::BasicAuth;
+use pastebin::copy::{self, CopyError};
+use pastebin::dump::{self, DumpError};
+use pastebin::foreign;
+use pastebin::metrics::Metrics;
+use pastebin::web::{inherit_listener, Drain, PastebinBuilder, ResponseCompression,
+                    SecurityHeaders, TrustedProxies};
+use pastebin::{Banner, ExtraPages, IdEncoding, RandomIds, RetentionPolicy, Severity, SizeLimits};
+use pastebin::secrets::{self, EnvSecrets, FileSecrets, Secret, SecretChain, SecretError,
+                        SecretProvider, VaultSecrets};
+use pastebin::spam::{LinkClassifier, SpamFilter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
 use tera::Tera;
 
+/// Prefix of environment variables with secrets.
+const SECRETS_ENV_PREFIX: &str = "PASTEBIN_";
+
+/// Secret with encryption keys, used unless `--encryption-keys` is given.
+const KEYS_SECRET: &str = "encryption-keys";
+
+/// Secret with a key to sign pastes with.
+const SIGNING_KEY_SECRET: &str = "signing-key";
+
+/// Secret with a token of the admin API, which is disabled unless the secret exists.
+const ADMIN_TOKEN_SECRET: &str = "admin-token";
+
+/// Environment variable with a number of an inherited listening socket's file descriptor.
+const LISTEN_FD_ENV: &str = "PASTEBIN_LISTEN_FD";
+
 quick_error! {
     #[derive(Debug)]
     pub enum Error {
@@ -31,6 +86,10 @@ quick_error! {
             cause(err)
             from()
         }
+        Db(err: DynError) {
+            cause(err)
+            from()
+        }
         HttpError(err: HttpError) {
             cause(err)
             from()
@@ -39,39 +98,502 @@ quick_error! {
             cause(err)
             from()
         }
+        Keyring(err: KeyringError) {
+            cause(err)
+            from()
+        }
+        Signing(err: SigningError) {
+            cause(err)
+            from()
+        }
+        Dump(err: DumpError) {
+            cause(err)
+            from()
+        }
+        Copy(err: CopyError) {
+            cause(err)
+            from()
+        }
+        Pastebin(err: pastebin::Error) {
+            cause(err)
+            from()
+        }
+        Secret(err: SecretError) {
+            cause(err)
+            from()
+        }
+        /// The value is not shown, it is a secret after all.
+        BadSecret(name: String) {
+            display("Secret {} is malformed", name)
+        }
+        Io(err: io::Error) {
+            cause(err)
+            from()
+        }
     }
 }
 
-fn init_logs(verbose: usize) -> Result<(), Error> {
+fn init_logs(options: &cmdargs::Options) -> Result<(), Error> {
     // Set up the logging depending on how many times a '-v' option has been used.
-    let verbosity = match verbose {
+    let verbosity = match options.verbose {
         1 => simplelog::LevelFilter::Warn,
         2 => simplelog::LevelFilter::Info,
         3 => simplelog::LevelFilter::Debug,
         4 => simplelog::LevelFilter::Trace,
         _ => simplelog::LevelFilter::Error,
     };
-    simplelog::SimpleLogger::init(verbosity, Default::default()).unwrap();
+    if let Some(ref path) = options.log_file {
+        let file = OpenOptions::new().create(true)
+                                     .append(true)
+                                     .open(path)?;
+        simplelog::WriteLogger::init(verbosity, Default::default(), file).unwrap();
+    } else if systemd::logs_to_journal() {
+        systemd::JournalLogger::init(verbosity).unwrap();
+    } else {
+        simplelog::SimpleLogger::init(verbosity, Default::default()).unwrap();
+    }
     Ok(())
 }
 
 fn run() -> Result<(), Error> {
     let options = cmdargs::parse()?;
-    init_logs(options.verbose)?;
-    let mongo_client_pool = ClientPool::new(options.db_options.uri.clone(), None);
-    let db_wrapper = MongoDbWrapper::new(options.db_options.db_name,
-                                         options.db_options.collection_name,
-                                         options.db_options.ids_collection_name,
-                                         mongo_client_pool);
+    match options.command.clone() {
+        cmdargs::Command::Serve => serve(options),
+        cmdargs::Command::Export(path) => {
+            init_logs(&options)?;
+            let (db, _) = open_db(&options, &secrets_provider(&options)?)?;
+            let output: Box<Write> = match path {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout()),
+            };
+            let exported = dump::export(&*db, output)?;
+            info!("Exported {} pastes", exported);
+            Ok(())
+        }
+        cmdargs::Command::Import(path) => {
+            init_logs(&options)?;
+            let (db, _) = open_db(&options, &secrets_provider(&options)?)?;
+            let input: Box<BufRead> = match path {
+                Some(path) => Box::new(BufReader::new(File::open(path)?)),
+                None => Box::new(BufReader::new(io::stdin())),
+            };
+            let imported = dump::import(&*db, input)?;
+            info!("Imported {} pastes", imported);
+            Ok(())
+        }
+        cmdargs::Command::ImportForeign(format, dir) => {
+            init_logs(&options)?;
+            let format = format.parse::<foreign::Format>()?;
+            let (db, _) = open_db(&options, &secrets_provider(&options)?)?;
+            let imported = foreign::import(&*db, format, Path::new(&dir))?;
+            info!("Imported {} pastes from a {} dump", imported, format);
+            Ok(())
+        }
+        cmdargs::Command::Backup(dir, incremental) => {
+            init_logs(&options)?;
+            let (db, _) = open_db(&options, &secrets_provider(&options)?)?;
+            let (path, pastes) = dump::backup(&*db, Path::new(&dir), incremental)?;
+            info!("Backed up {} pastes to {}", pastes, path.display());
+            Ok(())
+        }
+        cmdargs::Command::Copy(from, to) => {
+            init_logs(&options)?;
+            let copied = copy::copy_paste(&from, &to)?;
+            // Only the URL goes to the standard output, so it is easy to use in scripts.
+            println!("{}", copied.url);
+            if let Some(token) = copied.delete_token {
+                eprintln!("Deletion token: {}", token);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Connects to the database and wraps it into the decorators enabled by the options.
+///
+/// Returns the public key pastes are signed with as well, if signing is enabled.
+fn open_db(options: &cmdargs::Options,
+           secrets: &Arc<SecretProvider>)
+           -> Result<(BoxedDb, Option<String>), Error> {
+    let uri = db_uri(&options.db_options, secrets, "db-uri")?
+        .ok_or_else(|| SecretError::NotFound("db-uri".into()))?;
+    let mut db = connect(&options.db_options, uri)?;
+    if let Some(mirror_uri) = db_uri(&options.mirror_db_options, secrets, "mirror-db-uri")? {
+        // The innermost layer, so both databases get the same (compressed, encrypted...) data.
+        db = db::boxed(MirroredDb::new(db, connect(&options.mirror_db_options, mirror_uri)?));
+    }
+    if options.db_attempts > 1 {
+        // Only the database round trips are retried, so it goes right above the connection.
+        db = db::boxed(RetryingDb::new(db).attempts(options.db_attempts));
+    }
+    if let Some(ref path) = options.blob_dir {
+        db = db::boxed(SplitDb::new(db, FsBlobStore::new(path)?, options.blob_threshold));
+    }
+    if let Some((keys_provider, keys_name, keys)) = load_keys(options, secrets)? {
+        let encrypted =
+            EncryptedDb::new(db, Keyring::from_secret(&keys)?, options.encrypt_file_names)
+                .accept_legacy(options.accept_legacy_pastes);
+        if let Some(interval) = options.secrets_refresh {
+            secrets::watch(keys_provider,
+                           &keys_name,
+                           keys,
+                           interval,
+                           encrypted.keyring(),
+                           Keyring::from_secret);
+        }
+        db = db::boxed(encrypted);
+    }
+    if options.compress {
+        // Compression goes first, since encrypted data doesn't compress.
+        db = db::boxed(CompressedDb::new(db));
+    }
+    let mut signing_public_key = None;
+    if options.sign {
+        // Signatures cover the original data, but have to be below deduplication, which doesn't
+        // store duplicates at all.
+        let key = SigningKey::from_secret(&secrets.get(SIGNING_KEY_SECRET)?)?;
+        signing_public_key = Some(key.public_key());
+        db = db::boxed(SignedDb::new(db, key));
+    }
+    if options.dedup {
+        // Hashes are computed over the original data.
+        db = db::boxed(DedupDb::new(db));
+    }
+    if let Some(size) = options.cache_size {
+        // The cache is on top, so it holds ready to serve data.
+        db = db::boxed(CachedDb::new(db, size));
+    }
+    Ok((db, signing_public_key))
+}
+
+/// Runs the web server until it is asked to terminate.
+fn serve(options: cmdargs::Options) -> Result<(), Error> {
+    // Forking is only safe while there is a single thread.
+    let daemon = if options.daemon {
+        Some(daemon::daemonize()?)
+    } else {
+        None
+    };
+    init_logs(&options)?;
+    let _pid_file = match options.pid_file {
+        Some(ref path) => Some(PidFile::create(path)?),
+        None => None,
+    };
+    let secrets = secrets_provider(&options)?;
     let templates =
         Tera::new(&format!("{}/**/*{}", options.templates_path, options.templates_ext))?;
-    pastebin::web::run_web(db_wrapper,
-                           options.web_addr,
-                           templates,
-                           &options.url_prefix,
-                           options.default_ttl,
-                           options.static_files_path)?;
-    unreachable!()
+    let (mut db, signing_public_key) = if options.preview {
+        let (memory_db, samples) = MemoryDb::with_samples();
+        for (id, description) in samples {
+            info!("Preview of {}: {}{}", description, options.url_prefix, id);
+        }
+        (db::boxed(memory_db), None)
+    } else {
+        open_db(&options, &secrets)?
+    };
+    let metrics = Metrics::new();
+    if options.metrics || options.slow_query_threshold.is_some() {
+        // On top of everything, so the calls are measured the way the server sees them.
+        let mut instrumented = InstrumentedDb::new(db, metrics.clone());
+        if let Some(threshold) = options.slow_query_threshold {
+            instrumented = instrumented.slow_query_threshold(threshold);
+        }
+        db = db::boxed(instrumented);
+    }
+    let basic_auth = load_basic_auth(&options)?;
+    let mut builder = PastebinBuilder::new(db).templates(templates)
+                                              .url_prefix(&options.url_prefix)
+                                              .default_ttl(options.default_ttl)
+                                              .static_files_path(options.static_files_path)
+                                              .log_body_prefix(options.log_body_prefix)
+                                              .count_views(options.count_views)
+                                              .csrf_protection(options.csrf_protection)
+                                              .record_creators(options.record_creators)
+                                              .anonymize_clients(options.anonymize_clients);
+    if let Some(size_limits) = load_size_limits(&options)? {
+        builder = builder.size_limits(size_limits);
+    }
+    if let Some(ref rules) = options.retention {
+        builder = builder.retention(rules.parse::<RetentionPolicy>()?);
+    }
+    if let Some(public_key) = signing_public_key {
+        builder = builder.signing_key(public_key);
+    }
+    if let Some(ref path) = options.pages_file {
+        builder = builder.extra_pages(fs::read_to_string(path)?.parse::<ExtraPages>()?);
+    }
+    if let Some(allowed_hosts) = options.hotlink_allowed_hosts.clone() {
+        builder = builder.hotlink_protection(allowed_hosts);
+    }
+    if let Some(bytes) = options.random_id_bytes {
+        builder = builder.id_generator(Arc::new(RandomIds::new(bytes)));
+    }
+    let id_encoding = match options.id_alphabet {
+        Some(ref alphabet) => alphabet.parse::<IdEncoding>()?,
+        None => IdEncoding::default(),
+    };
+    builder = builder.id_encoding(id_encoding.min_length(options.id_min_length));
+    if let Some(ref proxies) = options.trusted_proxies {
+        builder = builder.trusted_proxies(proxies.parse::<TrustedProxies>()?);
+    }
+    builder = builder.security_headers(security_headers(&options));
+    if let Some(token) = secrets.find(ADMIN_TOKEN_SECRET)? {
+        builder = builder.admin_token(token);
+    }
+    if let Some(ref message) = options.banner {
+        let severity = options.banner_severity.parse::<Severity>()?;
+        builder = builder.banner(Banner::new(message.as_str(), severity));
+    }
+    if let Some(max_links) = options.spam_max_links {
+        // A score of 1 takes all the links, half of them is enough for a review.
+        let mut filter = SpamFilter::new(Arc::new(LinkClassifier::new(max_links))).reject_at(1.);
+        if let Some(ref path) = options.spam_feedback_file {
+            filter = filter.feedback_file(path.into());
+        }
+        builder = builder.spam_filter(filter);
+    }
+    if let Some(auth) = basic_auth {
+        builder = builder.basic_auth(auth);
+    }
+    if options.compress_responses {
+        let mut compression = ResponseCompression::new().min_size(options.compress_min_size);
+        if let Some(ref patterns) = options.compress_types {
+            compression = compression.mime_types(patterns)?;
+        }
+        builder = builder.compress_responses(compression);
+    }
+    if let Some(interval) = options.stats_interval {
+        builder = builder.stats_log_interval(interval);
+    }
+    if let Some(interval) = options.purge_interval {
+        builder = builder.purge_expired(interval);
+    }
+    if options.analytics {
+        builder = builder.analytics(Arc::new(LocalAnalytics::new()));
+    }
+    if options.dedup && !options.preview {
+        // Permalinks are resolved through the hashes deduplication stores.
+        builder = builder.content_permalinks(true);
+    }
+    if options.metrics {
+        builder = builder.metrics(metrics);
+    }
+    if let Some(retention) = options.creator_retention {
+        builder = builder.creator_retention(retention);
+    }
+    if let Some(grace_period) = options.trash_days {
+        builder = builder.trash(grace_period);
+    }
+    if let Some(retention) = options.tombstone_days {
+        builder = builder.tombstones(retention);
+    } else if options.preview {
+        // The removed sample is only told apart from a missing one by its tombstone.
+        builder = builder.tombstones(chrono::Duration::days(1));
+    }
+    builder = builder.upload_timeout(options.upload_timeout);
+    if let Some(ref dir) = options.backup_dir {
+        builder = builder.backups(dir.into(), options.backup_interval, options.incremental_backups);
+    }
+    let drain = Drain::new();
+    builder = builder.drain(drain.clone());
+    signals::install()?;
+    let listener = match systemd::activated_listener()? {
+        Some(listener) => Some(listener),
+        None => inherit_listener(LISTEN_FD_ENV)?,
+    };
+    let mut listening = match listener {
+        Some(listener) => builder.run_on(listener)?,
+        None => builder.run(options.web_addr)?,
+    };
+    systemd::notify_or_log("READY=1");
+    if let Some(daemon) = daemon {
+        daemon.ready()?;
+    }
+    systemd::spawn_watchdog();
+    signals::wait_for_termination();
+    systemd::notify_or_log("STOPPING=1");
+    drain.start();
+    if !drain.wait(options.drain_timeout) {
+        warn!("Gave up draining, {} requests are still in flight", drain.in_flight());
+    }
+    // Detaches the server threads, so they don't block the exit.
+    listening.close()?;
+    info!("Exiting");
+    Ok(())
+}
+
+/// Sets up the secrets provider, falling back to environment variables.
+fn secrets_provider(options: &cmdargs::Options) -> Result<Arc<SecretProvider>, Error> {
+    let env = EnvSecrets::new(SECRETS_ENV_PREFIX);
+    let provider: Arc<SecretProvider> = match options.secrets {
+        cmdargs::SecretsSource::Env => Arc::new(env),
+        cmdargs::SecretsSource::Dir(ref dir) => {
+            Arc::new(SecretChain::new().with(FileSecrets::new(dir)).with(env))
+        }
+        cmdargs::SecretsSource::Vault(ref path) => {
+            // The standard variables of the Vault CLI.
+            let vault_env = EnvSecrets::new("VAULT_");
+            let vault = VaultSecrets::new(vault_env.get("addr")?.expose(),
+                                          vault_env.get("token")?.expose(),
+                                          path);
+            Arc::new(SecretChain::new().with(vault).with(env))
+        }
+    };
+    Ok(provider)
+}
+
+/// Takes a database URI from the options or from a secret, if any.
+///
+/// Mind that the connection is not re-established if the secret is rotated.
+fn db_uri(options: &cmdargs::DbOptions,
+          secrets: &Arc<SecretProvider>,
+          name: &str)
+          -> Result<Option<DbUri>, Error> {
+    if let Some(ref uri) = options.uri {
+        return Ok(Some(uri.clone()));
+    }
+    match secrets.find(name)? {
+        Some(secret) => {
+            let uri = cmdargs::parse_uri(secret.expose());
+            Ok(Some(uri.map_err(|_| Error::BadSecret(name.into()))?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Connects to a database.
+fn connect(options: &cmdargs::DbOptions, uri: DbUri) -> Result<BoxedDb, Error> {
+    match uri {
+        DbUri::Mongo(uri) => {
+            let mongo_client_pool = ClientPool::new(uri, None);
+            let db_wrapper = MongoDbWrapper::new(options.db_name.clone(),
+                                                 options.collection_name.clone(),
+                                                 options.ids_collection_name.clone(),
+                                                 mongo_client_pool);
+            db_wrapper.create_ttl_index().map_err(Box::new)?;
+            db_wrapper.create_hash_index().map_err(Box::new)?;
+            db_wrapper.create_slug_index().map_err(Box::new)?;
+            db_wrapper.create_public_index().map_err(Box::new)?;
+            db_wrapper.create_owner_index().map_err(Box::new)?;
+            // The server doesn't accept documents above its limit anyway.
+            let server_limit = db_wrapper.query_max_data_size().map_err(Box::new)?;
+            let limit = match options.max_data_size {
+                Some(limit) if limit > server_limit => {
+                    warn!("Pastes are limited to {} bytes by the server", server_limit);
+                    server_limit
+                }
+                Some(limit) => limit,
+                None => server_limit,
+            };
+            let db_wrapper = db_wrapper.data_size_limit(limit);
+            Ok(db::boxed(db_wrapper))
+        }
+        #[cfg(feature = "mysql")]
+        DbUri::MySql(uri) => {
+            // The database is a part of the URI, and the collection name is used for the table.
+            let db_wrapper = MySqlDbWrapper::connect(&uri, &options.collection_name)
+                .map_err(|e| DynError(Box::new(e)))?;
+            db_wrapper.create_table()
+                      .map_err(|e| DynError(Box::new(e)))?;
+            Ok(db::boxed(db_wrapper))
+        }
+        #[cfg(feature = "dynamodb")]
+        DbUri::DynamoDb(region) => {
+            use pastebin::DbInterface;
+
+            // The tables are created beforehand, see the README.
+            let region = region.parse::<rusoto_core::Region>()
+                               .map_err(|e| DynError(Box::new(e)))?;
+            let db_wrapper = DynamoDbWrapper::new(region,
+                                                  options.collection_name.clone(),
+                                                  options.ids_collection_name.clone());
+            db_wrapper.health_check()
+                      .map_err(|e| DynError(Box::new(e)))?;
+            Ok(db::boxed(db_wrapper))
+        }
+    }
+}
+
+/// Loads encryption keys either from a file or from the secrets, if any.
+///
+/// Returns where the keys have been loaded from as well, so they could be reloaded later on.
+fn load_keys(options: &cmdargs::Options,
+             secrets: &Arc<SecretProvider>)
+             -> Result<Option<(Arc<SecretProvider>, String, Secret)>, Error> {
+    match options.encryption_keys {
+        Some(ref path) => {
+            let path = Path::new(path);
+            let name = path.file_name()
+                           .and_then(|name| name.to_str())
+                           .ok_or_else(|| {
+                               io::Error::new(io::ErrorKind::InvalidInput,
+                                              format!("Not a file: {}", path.display()))
+                           })?;
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let provider: Arc<SecretProvider> = Arc::new(FileSecrets::new(dir));
+            let keys = provider.get(name)?;
+            Ok(Some((provider, name.to_string(), keys)))
+        }
+        None => {
+            Ok(secrets.find(KEYS_SECRET)?
+                      .map(|keys| (secrets.clone(), KEYS_SECRET.to_string(), keys)))
+        }
+    }
+}
+
+/// Loads limits on the size of pastes from a file and the command line, if any.
+///
+/// The command line rules go last, so they override the ones from the file.
+fn load_size_limits(options: &cmdargs::Options) -> Result<Option<SizeLimits>, Error> {
+    if options.size_limits.is_none() && options.size_limits_file.is_none() {
+        return Ok(None);
+    }
+    let mut rules = String::new();
+    if let Some(ref path) = options.size_limits_file {
+        rules.push_str(&fs::read_to_string(path)?);
+        rules.push('\n');
+    }
+    if let Some(ref cli_rules) = options.size_limits {
+        rules.push_str(cli_rules);
+    }
+    Ok(Some(rules.parse()?))
+}
+
+/// Builds the hardening headers of responses, an empty header value leaves the header out.
+fn security_headers(options: &cmdargs::Options) -> SecurityHeaders {
+    if !options.security_headers {
+        return SecurityHeaders::none();
+    }
+    let value = |value: &String| if value.is_empty() { None } else { Some(value.as_str()) };
+    let mut headers = SecurityHeaders::new();
+    if let Some(ref policy) = options.content_security_policy {
+        headers = headers.content_security_policy(value(policy));
+    }
+    if let Some(ref policy) = options.referrer_policy {
+        headers = headers.referrer_policy(value(policy));
+    }
+    if let Some(ref frame_options) = options.frame_options {
+        headers = headers.frame_options(value(frame_options));
+    }
+    headers
+}
+
+/// Loads Basic authentication users from a file and the command line, if any.
+fn load_basic_auth(options: &cmdargs::Options) -> Result<Option<BasicAuth>, Error> {
+    if options.basic_auth.is_empty() && options.basic_auth_file.is_none() {
+        return Ok(None);
+    }
+    let mut users = String::new();
+    if let Some(ref path) = options.basic_auth_file {
+        users.push_str(&fs::read_to_string(path)?);
+        users.push('\n');
+    }
+    for user in &options.basic_auth {
+        users.push_str(user);
+        users.push('\n');
+    }
+    let auth = users.parse::<BasicAuth>()?;
+    Ok(Some(auth.protect_everything(options.basic_auth_everything)))
 }
 
 fn main() {
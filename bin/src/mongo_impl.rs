@@ -1,25 +1,57 @@
 //! `MongoDB` wrapper that implements `DbInterface`.
 
 use bson::{self, Bson};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use mongo_driver::{CommandAndFindOptions, MongoError};
 use mongo_driver::client::ClientPool;
 use mongo_driver::collection::{Collection, FindAndModifyOperation, FindAndModifyOptions};
 use mongo_driver::database::Database;
-use pastebin::{DbInterface, PasteEntry};
+use mongo_driver::error::MongoErrorCode;
+use pastebin::{count_lines, guess_language, DbInterface, MimeType, PasteEntry, PasteId,
+               PasteSummary, StorageStats, Tombstone, Unsupported, UpdateOutcome};
+use pastebin::redact::redact;
 use std::convert::From;
 use std::sync::Arc;
 
+/// Room left in a document for the metadata of a paste.
+const METADATA_OVERHEAD: usize = 64 * 1024;
+
+quick_error! {
+    /// `MongoDB` wrapper errors.
+    #[derive(Debug)]
+    pub enum MongoDbError {
+        /// The driver has failed, or a document doesn't look like a paste.
+        Driver(err: MongoError) {
+            description("MongoDB driver error")
+            display("{}", err)
+            cause(err)
+            from()
+            from(err: bson::DecoderError) -> (err.into())
+            from(err: bson::ValueAccessError) -> (err.into())
+        }
+        /// An operation the wrapper doesn't support.
+        Unsupported(err: Unsupported) {
+            description("Unsupported operation")
+            display("{}", err)
+            from()
+        }
+    }
+}
+
 /// A `MongoDB` wrapper.
 pub struct MongoDbWrapper {
     db_name: String,
     collection_name: String,
     ids_collection_name: String,
     client_pool: Arc<ClientPool>,
+    max_data_size: usize,
 }
 
 impl MongoDbWrapper {
     /// Constructs a new mongodb wrapper.
+    ///
+    /// Pastes are limited to 15 megabytes, which fits into the default maximum size of a document,
+    /// see [data_size_limit](#method.data_size_limit).
     pub fn new(db_name: String,
                collection_name: String,
                ids_collection_name: String,
@@ -28,7 +60,124 @@ impl MongoDbWrapper {
         Self { db_name,
                collection_name,
                ids_collection_name,
-               client_pool: Arc::new(client_pool), }
+               client_pool: Arc::new(client_pool),
+               max_data_size: 15 * 1024 * 1024, }
+    }
+
+    /// Sets the maximum size of a paste in bytes.
+    pub fn data_size_limit(mut self, max_data_size: usize) -> Self {
+        self.max_data_size = max_data_size;
+        self
+    }
+
+    /// Asks the server how big a paste could be, that is its maximum size of a document
+    /// (`maxBsonObjectSize`) less some room for the metadata.
+    pub fn query_max_data_size(&self) -> Result<usize, MongoError> {
+        let reply = self.get_db().command_simple(doc!{ "isMaster": 1 }, None)?;
+        let max_document_size = match reply.get("maxBsonObjectSize") {
+            Some(&Bson::I32(size)) => size as usize,
+            Some(&Bson::I64(size)) => size as usize,
+            _ => return Ok(self.max_data_size),
+        };
+        Ok(max_document_size.saturating_sub(METADATA_OVERHEAD))
+    }
+
+    /// Creates a [TTL index](https://docs.mongodb.com/manual/core/index-ttl/) on the
+    /// `best_before` field, so expired pastes are evicted by MongoDB itself.
+    ///
+    /// It is safe to call it when the index already exists. Please note that MongoDB removes
+    /// expired documents in the background once a minute or so.
+    pub fn create_ttl_index(&self) -> Result<(), MongoError> {
+        let command = doc!{
+            "createIndexes": self.collection_name.clone(),
+            "indexes": [
+                {
+                    "key": { "best_before": 1 },
+                    "name": "best_before_ttl",
+                    "expireAfterSeconds": 0
+                }
+            ]
+        };
+        self.get_db().command_simple(command, None)?;
+        Ok(())
+    }
+
+    /// Creates an index on the `hash` field, so pastes could be quickly found by their content.
+    ///
+    /// It is safe to call it when the index already exists.
+    pub fn create_hash_index(&self) -> Result<(), MongoError> {
+        let command = doc!{
+            "createIndexes": self.collection_name.clone(),
+            "indexes": [
+                {
+                    "key": { "hash": 1 },
+                    "name": "hash",
+                    "sparse": true
+                }
+            ]
+        };
+        self.get_db().command_simple(command, None)?;
+        Ok(())
+    }
+
+    /// Creates an index on the `public` and `created_at` fields, so public pastes could be
+    /// listed the newest first without going through the whole collection.
+    ///
+    /// It is safe to call it when the index already exists.
+    pub fn create_public_index(&self) -> Result<(), MongoError> {
+        let command = doc!{
+            "createIndexes": self.collection_name.clone(),
+            "indexes": [
+                {
+                    "key": { "public": 1, "created_at": -1 },
+                    "name": "public"
+                }
+            ]
+        };
+        self.get_db().command_simple(command, None)?;
+        Ok(())
+    }
+
+    /// Creates an index on the `deletion_token` field, so all the pastes of an owner could be
+    /// quickly found. The index is sparse, as imported pastes might have no token.
+    ///
+    /// It is safe to call it when the index already exists.
+    pub fn create_owner_index(&self) -> Result<(), MongoError> {
+        let command = doc!{
+            "createIndexes": self.collection_name.clone(),
+            "indexes": [
+                {
+                    "key": { "deletion_token": 1 },
+                    "name": "deletion_token",
+                    "sparse": true
+                }
+            ]
+        };
+        self.get_db().command_simple(command, None)?;
+        Ok(())
+    }
+
+    /// Creates a unique index on the `slug` field, so pastes could be quickly found by their
+    /// slugs and two pastes could never claim the same one. The index is sparse, as most pastes
+    /// have no slug at all.
+    ///
+    /// It is safe to call it when the index already exists. It is named `slug_unique`, so it
+    /// doesn't clash with the plain `slug` index older versions have created, which could be
+    /// dropped.
+    pub fn create_slug_index(&self) -> Result<(), MongoError> {
+        let command = doc!{
+            "createIndexes": self.collection_name.clone(),
+            "indexes": [
+                {
+                    "key": { "slug": 1 },
+                    "name": "slug_unique",
+                    "unique": true,
+                    "sparse": true
+                }
+            ]
+        };
+        self.get_db().command_simple(command, None)?;
+        Ok(())
     }
 
     fn get_db(&self) -> Database {
@@ -40,6 +189,14 @@ impl MongoDbWrapper {
             .take_collection(self.db_name.clone(), self.collection_name.clone())
     }
 
+    /// Tombstones of removed pastes are kept in a collection of their own, named after the
+    /// pastes collection.
+    fn get_tombstones_collection(&self) -> Collection {
+        self.client_pool
+            .pop()
+            .take_collection(self.db_name.clone(), format!("{}_tombstones", self.collection_name))
+    }
+
     fn get_new_id(&self, db: &Database) -> Result<u64, MongoError> {
         let ids = db.get_collection(self.ids_collection_name.clone());
         let opts = {
@@ -55,6 +212,29 @@ impl MongoDbWrapper {
         let entry = result.get_document("value")?;
         Ok(entry.get_i64("counter")? as u64)
     }
+
+    /// Makes sure `get_new_id` will never return the given ID (or anything below it).
+    fn reserve_id(&self, db: &Database, id: u64) -> Result<(), MongoError> {
+        let ids = db.get_collection(self.ids_collection_name.clone());
+        ids.find_and_modify(&doc!("_id": "paste"),
+                            FindAndModifyOperation::Upsert(&doc!("$max": { "counter": id as i64 })),
+                            None)?;
+        Ok(())
+    }
+
+    /// Fills in the size and the number of lines of a paste if they haven't been precomputed.
+    fn complete_summary(&self,
+                        (mut summary, complete): (PasteSummary, bool))
+                        -> Result<PasteSummary, MongoError> {
+        if !complete {
+            // An old entry without precomputed fields, so we have no choice but to load it.
+            if let Some(entry) = self.load_data(summary.id)? {
+                summary.size = entry.data.len();
+                summary.lines = count_lines(&entry.data);
+            }
+        }
+        Ok(summary)
+    }
 }
 
 /// A helper type to encode/decode a BSON database entry.
@@ -62,27 +242,105 @@ struct DbEntry {
     id: u64,
     data: Vec<u8>,
     file_name: Option<String>,
-    mime_type: String,
+    mime_type: MimeType,
+    created_at: DateTime<Utc>,
     best_before: Option<DateTime<Utc>>,
+    views: u64,
+    creator: Option<String>,
+    deletion_token: Option<String>,
+    language: Option<String>,
+    revision: u64,
+    views_left: Option<u64>,
+    public: bool,
+}
+
+/// Creation date of pastes stored before the dates were recorded.
+///
+/// There is no way to find out the real date, so the Unix epoch makes it clear the date is not
+/// known.
+fn unknown_creation_date() -> DateTime<Utc> {
+    Utc.timestamp(0, 0)
+}
+
+/// Checks whether an operation has failed because of a duplicate value of a unique index.
+fn is_duplicate_key(err: &MongoError) -> bool {
+    match *err {
+        MongoError::Bsonc(ref err) => match err.code() {
+            MongoErrorCode::DuplicateKey => true,
+            _ => false,
+        },
+        _ => false,
+    }
 }
 
 fn bson_binary(data: Vec<u8>) -> Bson {
     Bson::Binary(bson::spec::BinarySubtype::Generic, data)
 }
 
+/// Parses a counter, like a number of views. Documents stored before the counter was introduced
+/// have none, so it is zero for them.
+fn parse_counter(field: &str, value: Bson) -> Result<u64, bson::DecoderError> {
+    match value {
+        Bson::I64(count) => Ok(count as u64),
+        Bson::I32(count) => Ok(count as u64),
+        val => {
+            let msg = format!("Field `{}`, expected type i64, got {:?}",
+                              field,
+                              val.element_type());
+            Err(bson::DecoderError::InvalidType(msg))
+        }
+    }
+}
+
+fn parse_mime_type(mime_type: String) -> Result<MimeType, bson::DecoderError> {
+    MimeType::new(mime_type).map_err(|e| bson::DecoderError::InvalidType(e.to_string()))
+}
+
 impl From<DbEntry> for bson::Document {
     fn from(entry: DbEntry) -> bson::Document {
+        let size = entry.data.len() as i64;
+        let lines = count_lines(&entry.data) as i64;
+        // The language given by the uploader, or a guessed one, so listing doesn't need the data.
+        // A given one is kept when the data is replaced.
+        let language_given = entry.language.is_some();
+        let language = entry.language.or_else(|| {
+                           guess_language(entry.file_name.as_ref().map(|s| s.as_str()),
+                                          entry.mime_type.as_str()).map(Into::into)
+                       });
         let mut doc = doc!{
             "_id": entry.id as i64,
             "data": bson_binary(entry.data),
-            "mime_type": entry.mime_type,
+            "size": size,
+            "lines": lines,
+            "mime_type": String::from(entry.mime_type),
+            "created_at": entry.created_at,
+            "views": entry.views as i64,
+            "revision": entry.revision as i64,
         };
+        if let Some(language) = language {
+            doc.insert("language", language);
+        }
+        if language_given {
+            doc.insert("language_given", true);
+        }
         if let Some(file_name) = entry.file_name {
             doc.insert("file_name", file_name);
         }
         if let Some(best_before) = entry.best_before {
             doc.insert("best_before", best_before);
         }
+        if let Some(creator) = entry.creator {
+            doc.insert("creator", creator);
+        }
+        if let Some(deletion_token) = entry.deletion_token {
+            doc.insert("deletion_token", deletion_token);
+        }
+        if let Some(views_left) = entry.views_left {
+            doc.insert("views_left", views_left as i64);
+        }
+        if entry.public {
+            doc.insert("public", true);
+        }
         doc
     }
 }
@@ -92,7 +350,15 @@ impl From<DbEntry> for PasteEntry {
         PasteEntry { data: entry.data,
                      file_name: entry.file_name,
                      mime_type: entry.mime_type,
-                     best_before: entry.best_before, }
+                     created_at: entry.created_at,
+                     best_before: entry.best_before,
+                     views: entry.views,
+                     creator: entry.creator,
+                     deletion_token: entry.deletion_token,
+                     language: entry.language,
+                     revision: entry.revision,
+                     views_left: entry.views_left,
+                     public: entry.public, }
     }
 }
 
@@ -103,7 +369,15 @@ impl DbEntry {
         let mut data = None;
         let mut file_name = None;
         let mut mime_type = None;
+        let mut created_at = None;
         let mut best_before = None;
+        let mut views = 0;
+        let mut creator = None;
+        let mut deletion_token = None;
+        let mut language = None;
+        let mut revision = 0;
+        let mut views_left = None;
+        let mut public = false;
         let wrong_type = |field, val: bson::Bson, expected| {
             let msg = format!("Field `{}`, expected type {}, got {:?}",
                               field,
@@ -125,7 +399,9 @@ impl DbEntry {
                 ("data", val) => {
                     return wrong_type("data", val, "generic binary");
                 }
-                ("mime_type", bson::Bson::String(mime)) => mime_type = Some(mime),
+                ("mime_type", bson::Bson::String(mime)) => {
+                    mime_type = Some(parse_mime_type(mime)?);
+                }
                 ("mime_type", val) => {
                     return wrong_type("mime_type", val, "string");
                 }
@@ -133,10 +409,40 @@ impl DbEntry {
                 ("file_name", val) => {
                     return wrong_type("file_name", val, "string");
                 }
+                ("created_at", bson::Bson::UtcDatetime(date)) => created_at = Some(date),
+                ("created_at", val) => {
+                    return wrong_type("created_at", val, "UtcDatetime");
+                }
                 ("best_before", bson::Bson::UtcDatetime(date)) => best_before = Some(date),
                 ("best_before", val) => {
                     return wrong_type("best_before", val, "UtcDatetime");
                 }
+                ("views", val) => views = parse_counter("views", val)?,
+                ("revision", val) => revision = parse_counter("revision", val)?,
+                ("views_left", val) => views_left = Some(parse_counter("views_left", val)?),
+                ("public", bson::Bson::Boolean(flag)) => public = flag,
+                ("public", val) => {
+                    return wrong_type("public", val, "bool");
+                }
+                ("creator", bson::Bson::String(who)) => creator = Some(who),
+                ("creator", val) => {
+                    return wrong_type("creator", val, "string");
+                }
+                ("deletion_token", bson::Bson::String(token)) => deletion_token = Some(token),
+                ("deletion_token", val) => {
+                    return wrong_type("deletion_token", val, "string");
+                }
+                ("language", bson::Bson::String(lang)) => language = Some(lang),
+                ("language", val) => {
+                    return wrong_type("language", val, "string");
+                }
+                ("size", _)
+                | ("lines", _)
+                | ("language_given", _)
+                | ("hash", _)
+                | ("slug", _)
+                | ("signature", _)
+                | ("deleted_at", _) => {}
                 _ => return Err(bson::DecoderError::UnknownField(key)),
             }
         }
@@ -144,13 +450,36 @@ impl DbEntry {
                      data: data.ok_or(bson::DecoderError::ExpectedField("data"))?,
                      file_name,
                      mime_type: mime_type.ok_or(bson::DecoderError::ExpectedField("mime_type"))?,
-                     best_before, })
+                     created_at: created_at.unwrap_or_else(unknown_creation_date),
+                     best_before,
+                     views,
+                     creator,
+                     deletion_token,
+                     language,
+                     revision,
+                     views_left,
+                     public, })
     }
 }
 
-/// Try to parse a BSON to extract only the file name (if any).
-fn filename_from_bson(doc: bson::Document) -> Result<Option<String>, bson::DecoderError> {
+/// Try to parse a BSON (with the data field excluded) into a paste summary.
+///
+/// Documents created by older versions of the service lack the precomputed `size` and `lines`
+/// fields, in which case `false` is returned alongside the summary (and the fields are zeroed).
+fn summary_from_bson(doc: bson::Document) -> Result<(PasteSummary, bool), bson::DecoderError> {
+    let mut id = None;
     let mut file_name = None;
+    let mut mime_type = None;
+    let mut created_at = None;
+    let mut best_before = None;
+    let mut size = None;
+    let mut lines = None;
+    let mut language = None;
+    let mut views = 0;
+    let mut revision = 0;
+    let mut views_left = None;
+    let mut public = false;
+    let mut creator = None;
     let wrong_type = |field, val: bson::Bson, expected| {
         let msg = format!("Field `{}`, expected type {}, got {:?}",
                           field,
@@ -160,40 +489,178 @@ fn filename_from_bson(doc: bson::Document) -> Result<Option<String>, bson::Decod
     };
     for (key, bson_value) in doc {
         match (key.as_str(), bson_value) {
+            ("_id", bson::Bson::I64(signed)) => id = Some(signed as u64),
+            ("_id", val) => {
+                return wrong_type("_id", val, "i64");
+            }
+            ("mime_type", bson::Bson::String(mime)) => mime_type = Some(parse_mime_type(mime)?),
+            ("mime_type", val) => {
+                return wrong_type("mime_type", val, "string");
+            }
             ("file_name", bson::Bson::String(fname)) => file_name = Some(fname),
             ("file_name", val) => {
                 return wrong_type("file_name", val, "string");
             }
+            ("created_at", bson::Bson::UtcDatetime(date)) => created_at = Some(date),
+            ("created_at", val) => {
+                return wrong_type("created_at", val, "UtcDatetime");
+            }
+            ("best_before", bson::Bson::UtcDatetime(date)) => best_before = Some(date),
+            ("best_before", val) => {
+                return wrong_type("best_before", val, "UtcDatetime");
+            }
+            ("size", bson::Bson::I64(signed)) => size = Some(signed as usize),
+            ("size", val) => {
+                return wrong_type("size", val, "i64");
+            }
+            ("lines", bson::Bson::I64(signed)) => lines = Some(signed as usize),
+            ("lines", val) => {
+                return wrong_type("lines", val, "i64");
+            }
+            ("language", bson::Bson::String(lang)) => language = Some(lang),
+            ("language", val) => {
+                return wrong_type("language", val, "string");
+            }
+            ("views", val) => views = parse_counter("views", val)?,
+            ("revision", val) => revision = parse_counter("revision", val)?,
+            ("views_left", val) => views_left = Some(parse_counter("views_left", val)?),
+            ("public", bson::Bson::Boolean(flag)) => public = flag,
+            ("public", val) => {
+                return wrong_type("public", val, "bool");
+            }
+            ("creator", bson::Bson::String(who)) => creator = Some(who),
+            ("creator", val) => {
+                return wrong_type("creator", val, "string");
+            }
             _ => {}
         }
     }
-    Ok(file_name)
+    let id = id.ok_or(bson::DecoderError::ExpectedField("_id"))?;
+    let mime_type = mime_type.ok_or(bson::DecoderError::ExpectedField("mime_type"))?;
+    let language =
+        language.or_else(|| {
+                             guess_language(file_name.as_ref().map(|s| s.as_str()),
+                                            mime_type.as_str())
+                                 .map(Into::into)
+                         });
+    let complete = size.is_some() && lines.is_some();
+    let summary = PasteSummary { id: PasteId::new(id),
+                                 file_name,
+                                 mime_type,
+                                 size: size.unwrap_or(0),
+                                 lines: lines.unwrap_or(0),
+                                 language,
+                                 created_at: created_at.unwrap_or_else(unknown_creation_date),
+                                 best_before,
+                                 views,
+                                 creator,
+                                 revision,
+                                 views_left,
+                                 public, };
+    Ok((summary, complete))
+}
+
+/// Parses a tombstone of a removed paste.
+fn tombstone_from_bson(doc: bson::Document) -> Result<Tombstone, bson::DecoderError> {
+    let id = match doc.get("_id") {
+        Some(&Bson::I64(id)) => PasteId::new(id as u64),
+        _ => return Err(bson::DecoderError::ExpectedField("_id")),
+    };
+    let removed_at = match doc.get("removed_at") {
+        Some(&Bson::UtcDatetime(date)) => date,
+        _ => return Err(bson::DecoderError::ExpectedField("removed_at")),
+    };
+    let actor = match doc.get("actor") {
+        Some(&Bson::String(ref actor)) => actor.clone(),
+        _ => return Err(bson::DecoderError::ExpectedField("actor")),
+    };
+    Ok(Tombstone { id,
+                   removed_at,
+                   actor, })
 }
 
 impl DbInterface for MongoDbWrapper {
-    type Error = MongoError;
-
-    fn store_data(&self,
-                  data: Vec<u8>,
-                  file_name: Option<String>,
-                  mime_type: String,
-                  best_before: Option<DateTime<Utc>>)
-                  -> Result<u64, Self::Error> {
+    type Error = MongoDbError;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
         let db = self.get_db();
         let id = self.get_new_id(&db)?;
+        debug!("Storing {} as a doc id = {:?}", redact(&entry.data), id);
         let collection = db.get_collection(self.collection_name.clone());
         collection.insert(&DbEntry { id,
-                                      data,
-                                      file_name,
-                                      mime_type,
-                                      best_before, }.into(),
+                                      data: entry.data,
+                                      file_name: entry.file_name,
+                                      mime_type: entry.mime_type,
+                                      created_at: entry.created_at,
+                                      best_before: entry.best_before,
+                                      views: 0,
+                                      creator: entry.creator,
+                                      deletion_token: entry.deletion_token,
+                                      language: entry.language,
+                                      revision: 0,
+                                      views_left: entry.views_left,
+                                      public: entry.public, }.into(),
                            None)?;
-        Ok(id)
+        Ok(PasteId::new(id))
     }
 
-    fn load_data(&self, id: u64) -> Result<Option<PasteEntry>, Self::Error> {
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        debug!("Inserting {} as a doc id = {:?}", redact(&entry.data), id);
+        let db = self.get_db();
+        self.reserve_id(&db, id.as_u64())?;
+        let doc: bson::Document = DbEntry { id: id.as_u64(),
+                                            data: entry.data,
+                                            file_name: entry.file_name,
+                                            mime_type: entry.mime_type,
+                                            created_at: entry.created_at,
+                                            best_before: entry.best_before,
+                                            views: entry.views,
+                                            creator: entry.creator,
+                                            deletion_token: entry.deletion_token,
+                                            language: entry.language,
+                                            revision: entry.revision,
+                                            views_left: entry.views_left,
+                                            public: entry.public, }.into();
+        let collection = db.get_collection(self.collection_name.clone());
+        // A document without update operators replaces the found one.
+        collection.find_and_modify(&doc!("_id": id.as_u64()),
+                                    FindAndModifyOperation::Upsert(&doc),
+                                    None)?;
+        Ok(())
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        debug!("Storing {} as a new doc id = {:?}", redact(&entry.data), id);
+        if self.load_tombstone(id)?.is_some() {
+            return Ok(false);
+        }
+        let mut doc: bson::Document = DbEntry { id: id.as_u64(),
+                                                data: entry.data,
+                                                file_name: entry.file_name,
+                                                mime_type: entry.mime_type,
+                                                created_at: entry.created_at,
+                                                best_before: entry.best_before,
+                                                views: 0,
+                                                creator: entry.creator,
+                                                deletion_token: entry.deletion_token,
+                                                language: entry.language,
+                                                revision: 0,
+                                                views_left: entry.views_left,
+                                                public: entry.public, }.into();
+        doc.remove("_id");
+        let collection = self.get_collection();
+        // The document is only set if there is none with the ID (a trashed one included), and the
+        // previous document is returned, so there is none for a new paste.
+        let update = doc!("$setOnInsert": doc);
+        let result = collection.find_and_modify(&doc!("_id": id.as_u64()),
+                                                FindAndModifyOperation::Upsert(&update),
+                                                None)?;
+        Ok(result.get_document("value").is_err())
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
         debug!("Looking for a doc id = {:?}", id);
-        let filter = doc!("_id": id as u64);
+        let filter = doc!("_id": id.as_u64(), "deleted_at": { "$exists": false });
         let collection = self.get_collection();
         let entry = match collection.find(&filter, None)?
                                     .nth(0)
@@ -206,11 +673,11 @@ impl DbInterface for MongoDbWrapper {
         Ok(Some(db_entry.into()))
     }
 
-    fn get_file_name(&self, id: u64) -> Result<Option<String>, Self::Error> {
-        debug!("Looking for a file name for id = {:?}", id as u64);
-        let filter = doc!("_id": id);
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        debug!("Looking for metadata of a doc id = {:?}", id);
+        let filter = doc!("_id": id.as_u64(), "deleted_at": { "$exists": false });
         let collection = self.get_collection();
-        let find_options = CommandAndFindOptions::with_fields(doc!("_id": 0, "file_name": 1));
+        let find_options = CommandAndFindOptions::with_fields(doc!("data": 0));
         let entry = match collection.find(&filter, Some(&find_options))?
                                     .nth(0)
                                     .and_then(|doc| doc.ok())
@@ -218,19 +685,493 @@ impl DbInterface for MongoDbWrapper {
             None => return Ok(None),
             Some(entry) => entry,
         };
-        Ok(filename_from_bson(entry)?)
+        Ok(Some(self.complete_summary(summary_from_bson(entry)?)?))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        debug!("Looking for a deletion token of a doc id = {:?}", id);
+        let filter = doc!("_id": id.as_u64(), "deleted_at": { "$exists": false });
+        let collection = self.get_collection();
+        let find_options = CommandAndFindOptions::with_fields(doc!("deletion_token": 1));
+        let doc = match collection.find(&filter, Some(&find_options))?
+                                  .nth(0)
+                                  .and_then(|doc| doc.ok())
+        {
+            None => return Ok(None),
+            Some(doc) => doc,
+        };
+        Ok(doc.get_str("deletion_token").ok().map(String::from))
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        debug!("Updating a doc id = {:?} with {}", id, redact(&data));
+        let collection = self.get_collection();
+        let opts = {
+            let mut opts = FindAndModifyOptions::default();
+            opts.fields = Some(doc!("revision": 1));
+            opts.new = true;
+            opts
+        };
+        // Trashed pastes are not updated, just like they are not loaded.
+        let mut filter = doc!("_id": id.as_u64(), "deleted_at": { "$exists": false });
+        match expected_revision {
+            // Documents stored before revisions were counted have none.
+            Some(0) => {
+                filter.insert("revision", doc!{ "$in": [Bson::Null, 0i64] });
+            }
+            Some(revision) => {
+                filter.insert("revision", revision as i64);
+            }
+            None => {}
+        }
+        let size = data.len() as i64;
+        let lines = count_lines(&data) as i64;
+        let mut set = doc!{
+            "data": bson_binary(data),
+            "size": size,
+            "lines": lines,
+        };
+        let mut update = doc!{};
+        // The file name is not changed, but the mime type is, so the language might change as
+        // well, unless the uploader has given it.
+        let find_options =
+            CommandAndFindOptions::with_fields(doc!("file_name": 1, "language_given": 1));
+        let stored = collection.find(&doc!("_id": id.as_u64()), Some(&find_options))?
+                               .nth(0)
+                               .and_then(|doc| doc.ok());
+        let file_name = stored.as_ref()
+                              .and_then(|doc| doc.get_str("file_name").ok())
+                              .map(String::from);
+        let language_given = stored.as_ref()
+                                   .and_then(|doc| doc.get_bool("language_given").ok())
+                                   .unwrap_or(false);
+        if !language_given {
+            match guess_language(file_name.as_ref().map(|s| s.as_str()), mime_type.as_str()) {
+                Some(language) => {
+                    set.insert("language", language);
+                }
+                None => {
+                    update.insert("$unset", doc!{ "language": "" });
+                }
+            }
+        }
+        set.insert("mime_type", String::from(mime_type));
+        update.insert("$set", set);
+        update.insert("$inc", doc!{ "revision": 1i64 });
+        let result = collection.find_and_modify(&filter,
+                                                FindAndModifyOperation::Update(&update),
+                                                Some(&opts))?;
+        // `value` is `null` if nothing has been found, either because there is no such paste or
+        // because it is at another revision.
+        if let Ok(updated) = result.get_document("value") {
+            let revision = match updated.get("revision") {
+                Some(value) => parse_counter("revision", value.clone())?,
+                None => 0,
+            };
+            return Ok(UpdateOutcome::Updated(revision));
+        }
+        Ok(match self.load_metadata(id)? {
+               Some(metadata) => UpdateOutcome::Conflict(metadata.revision),
+               None => UpdateOutcome::NotFound,
+           })
     }
 
-    fn remove_data(&self, id: u64) -> Result<(), Self::Error> {
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        debug!("Counting a view of a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let filter = doc!("_id": id.as_u64(), "deleted_at": { "$exists": false });
+        let update = doc!("$inc": { "views": 1i64 });
+        collection.find_and_modify(&filter, FindAndModifyOperation::Update(&update), None)?;
+        Ok(())
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        debug!("Taking a view of a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let opts = {
+            let mut opts = FindAndModifyOptions::default();
+            opts.fields = Some(doc!("views_left": 1));
+            opts.new = true;
+            opts
+        };
+        // Only a paste with views left is matched, so concurrent requests can't take the same
+        // last view.
+        let filter = doc!("_id": id.as_u64(),
+                          "views_left": { "$gt": 0i64 },
+                          "deleted_at": { "$exists": false });
+        let update = doc!("$inc": { "views_left": -1i64 });
+        let result = collection.find_and_modify(&filter,
+                                                FindAndModifyOperation::Update(&update),
+                                                Some(&opts))?;
+        // `value` is `null` if nothing has been found.
+        match result.get_document("value").ok().and_then(|doc| doc.get("views_left")) {
+            Some(value) => Ok(Some(parse_counter("views_left", value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
         debug!("Looking for a doc id = {:?}", id);
         let collection = self.get_collection();
-        collection.find_and_modify(&doc!("_id": id as u64),
+        collection.find_and_modify(&doc!("_id": id.as_u64()),
                                     FindAndModifyOperation::Remove,
                                     None)?;
         Ok(())
     }
 
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        debug!("Trashing a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let opts = {
+            let mut opts = FindAndModifyOptions::default();
+            opts.fields = Some(doc!("_id": 1));
+            opts
+        };
+        let update = doc!("$set": { "deleted_at": deleted_at });
+        let result =
+            collection.find_and_modify(&doc!("_id": id.as_u64(),
+                                             "deleted_at": { "$exists": false }),
+                                       FindAndModifyOperation::Update(&update),
+                                       Some(&opts))?;
+        // `value` is `null` if nothing has been found.
+        Ok(result.get_document("value").is_ok())
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        debug!("Restoring a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let opts = {
+            let mut opts = FindAndModifyOptions::default();
+            opts.fields = Some(doc!("_id": 1));
+            opts
+        };
+        let update = doc!("$unset": { "deleted_at": "" });
+        let result =
+            collection.find_and_modify(&doc!("_id": id.as_u64(), "deleted_at": { "$exists": true }),
+                                       FindAndModifyOperation::Update(&update),
+                                       Some(&opts))?;
+        Ok(result.get_document("value").is_ok())
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging docs trashed before {}", deleted_before);
+        let command = doc!{
+            "delete": self.collection_name.clone(),
+            "deletes": [
+                { "q": { "deleted_at": { "$lt": deleted_before } }, "limit": 0 }
+            ]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("n") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        debug!("Storing a tombstone of a doc id = {:?}", tombstone.id);
+        let collection = self.get_tombstones_collection();
+        let doc = doc!{
+            "_id": tombstone.id.as_u64(),
+            "removed_at": tombstone.removed_at,
+            "actor": tombstone.actor
+        };
+        collection.find_and_modify(&doc!("_id": tombstone.id.as_u64()),
+                                    FindAndModifyOperation::Upsert(&doc),
+                                    None)?;
+        Ok(())
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        debug!("Looking for a tombstone of a doc id = {:?}", id);
+        let collection = self.get_tombstones_collection();
+        match collection.find(&doc!("_id": id.as_u64()), None)?.nth(0) {
+            None => Ok(None),
+            Some(doc) => Ok(Some(tombstone_from_bson(doc?)?)),
+        }
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        debug!("Listing tombstones of docs removed since {}", removed_since);
+        let query = doc!{
+            "$query": { "removed_at": { "$gte": removed_since } },
+            "$orderby": { "removed_at": -1 }
+        };
+        let collection = self.get_tombstones_collection();
+        let mut result = Vec::new();
+        for doc in collection.find(&query, None)? {
+            result.push(tombstone_from_bson(doc?)?);
+        }
+        Ok(result)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging tombstones of docs removed before {}", removed_before);
+        let command = doc!{
+            "delete": format!("{}_tombstones", self.collection_name),
+            "deletes": [
+                { "q": { "removed_at": { "$lt": removed_before } }, "limit": 0 }
+            ]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("n") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Purging docs expired by {}", now);
+        // A raw `delete` command is used since it removes all the matching documents at once and
+        // reports how many of them have been removed.
+        let command = doc!{
+            "delete": self.collection_name.clone(),
+            "deletes": [
+                { "q": { "best_before": { "$lt": now } }, "limit": 0 }
+            ]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("n") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Scrubbing creators of docs created before {}", created_before);
+        // Same as with purging, a raw `update` command reports how many documents it has
+        // modified.
+        let command = doc!{
+            "update": self.collection_name.clone(),
+            "updates": [
+                {
+                    "q": {
+                        "created_at": { "$lt": created_before },
+                        "creator": { "$exists": true }
+                    },
+                    "u": { "$unset": { "creator": "" } },
+                    "multi": true
+                }
+            ]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("nModified") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        debug!("Removing docs of {} created since {}", creator, since);
+        let command = doc!{
+            "delete": self.collection_name.clone(),
+            "deletes": [
+                { "q": { "creator": creator, "created_at": { "$gte": since } }, "limit": 0 }
+            ]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("n") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        debug!("Removing docs of an owner");
+        let command = doc!{
+            "delete": self.collection_name.clone(),
+            "deletes": [{ "q": { "deletion_token": token }, "limit": 0 }]
+        };
+        let reply = self.get_db().command_simple(command, None)?;
+        Ok(match reply.get("n") {
+               Some(&Bson::I32(n)) => n as u64,
+               Some(&Bson::I64(n)) => n as u64,
+               _ => 0,
+           })
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        debug!("Listing docs of an owner");
+        // Served by the index of `create_owner_index`.
+        let query = doc!{ "deletion_token": token, "deleted_at": { "$exists": false } };
+        let find_options = CommandAndFindOptions::with_fields(doc!("_id": 1));
+        let collection = self.get_collection();
+        let mut ids = Vec::new();
+        for doc in collection.find(&query, Some(&find_options))? {
+            ids.push(PasteId::new(doc?.get_i64("_id")? as u64));
+        }
+        Ok(ids)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        debug!("Storing hash {} of a doc id = {:?}", hash, id);
+        let collection = self.get_collection();
+        collection.find_and_modify(&doc!("_id": id.as_u64()),
+                                    FindAndModifyOperation::Update(&doc!("$set": { "hash": hash })),
+                                    None)?;
+        Ok(())
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        debug!("Looking for a doc with hash {}", hash);
+        let collection = self.get_collection();
+        let find_options = {
+            let mut opts = CommandAndFindOptions::with_fields(doc!("_id": 1));
+            opts.limit = 1;
+            opts
+        };
+        let doc = match collection.find(&doc!("hash": hash), Some(&find_options))?
+                                  .nth(0)
+                                  .and_then(|doc| doc.ok())
+        {
+            None => return Ok(None),
+            Some(doc) => doc,
+        };
+        Ok(Some(PasteId::new(doc.get_i64("_id")? as u64)))
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        debug!("Storing slug {} of a doc id = {:?}", slug, id);
+        // The slug index is unique (see `create_slug_index`), so a slug another document has
+        // fails the update.
+        let collection = self.get_collection();
+        let update = doc!("$set": { "slug": slug });
+        match collection.find_and_modify(&doc!("_id": id.as_u64()),
+                                         FindAndModifyOperation::Update(&update),
+                                         None)
+        {
+            Ok(_) => Ok(true),
+            Err(ref e) if is_duplicate_key(e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        debug!("Looking for a doc with slug {}", slug);
+        let collection = self.get_collection();
+        let find_options = {
+            let mut opts = CommandAndFindOptions::with_fields(doc!("_id": 1));
+            opts.limit = 1;
+            opts
+        };
+        let doc = match collection.find(&doc!("slug": slug), Some(&find_options))?
+                                  .nth(0)
+                                  .and_then(|doc| doc.ok())
+        {
+            None => return Ok(None),
+            Some(doc) => doc,
+        };
+        Ok(Some(PasteId::new(doc.get_i64("_id")? as u64)))
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        debug!("Storing a signature of a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let update = doc!("$set": { "signature": signature });
+        collection.find_and_modify(&doc!("_id": id.as_u64()),
+                                    FindAndModifyOperation::Update(&update),
+                                    None)?;
+        Ok(())
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        debug!("Looking for a signature of a doc id = {:?}", id);
+        let collection = self.get_collection();
+        let find_options = CommandAndFindOptions::with_fields(doc!("signature": 1));
+        let doc = match collection.find(&doc!("_id": id.as_u64()), Some(&find_options))?
+                                  .nth(0)
+                                  .and_then(|doc| doc.ok())
+        {
+            None => return Ok(None),
+            Some(doc) => doc,
+        };
+        Ok(doc.get_str("signature").ok().map(String::from))
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        debug!("Listing {} pastes starting from {}", limit, offset);
+        let query = doc!{
+            "$query": { "deleted_at": { "$exists": false } },
+            "$orderby": { "_id": 1 }
+        };
+        let find_options = {
+            let mut opts = CommandAndFindOptions::with_fields(doc!("data": 0));
+            opts.skip = offset as u32;
+            opts.limit = limit as u32;
+            opts
+        };
+        let collection = self.get_collection();
+        let mut result = Vec::new();
+        for doc in collection.find(&query, Some(&find_options))? {
+            result.push(self.complete_summary(summary_from_bson(doc?)?)?);
+        }
+        Ok(result)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        debug!("Listing {} public pastes starting from {}", limit, offset);
+        // Served by the index of `create_public_index`.
+        let query = doc!{
+            "$query": { "public": true, "deleted_at": { "$exists": false } },
+            "$orderby": { "created_at": -1, "_id": -1 }
+        };
+        let find_options = {
+            let mut opts = CommandAndFindOptions::with_fields(doc!("data": 0));
+            opts.skip = offset as u32;
+            opts.limit = limit as u32;
+            opts
+        };
+        let collection = self.get_collection();
+        let mut result = Vec::new();
+        for doc in collection.find(&query, Some(&find_options))? {
+            result.push(self.complete_summary(summary_from_bson(doc?)?)?);
+        }
+        Ok(result)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.get_db().command_simple(doc!("ping": 1), None)?;
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        let collection = self.get_collection();
+        // Trashed pastes are not counted, just like they are not listed.
+        let stored = doc!{ "deleted_at": { "$exists": false } };
+        let pastes = collection.count(&stored, None)?;
+        // Entries created by older versions of the service have no precomputed size, and hence
+        // are not taken into account.
+        let pipeline = doc!{
+            "pipeline": [
+                { "$match": stored },
+                { "$group": { "_id": Bson::Null, "total": { "$sum": "$size" } } }
+            ]
+        };
+        let total_bytes = match collection.aggregate(&pipeline, None)?.next() {
+            Some(doc) => match doc?.get("total") {
+                Some(&Bson::I64(total)) => total as u64,
+                Some(&Bson::I32(total)) => total as u64,
+                _ => 0,
+            },
+            None => 0,
+        };
+        Ok(StorageStats { pastes: pastes as u64,
+                          total_bytes, })
+    }
+
     fn max_data_size(&self) -> usize {
-        15 * 1024 * 1024
+        self.max_data_size
     }
 }
@@ -0,0 +1,40 @@
+//! Termination signals handling.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::thread;
+use std::time::Duration;
+
+/// Set once a termination signal is received.
+static TERMINATING: AtomicBool = ATOMIC_BOOL_INIT;
+
+#[cfg(unix)]
+extern "C" fn on_terminate(_signal: ::libc::c_int) {
+    // Only async-signal-safe stuff is allowed here, and an atomic store is fine.
+    TERMINATING.store(true, Ordering::SeqCst);
+}
+
+/// Installs handlers of `SIGTERM` and `SIGINT`, so the server could drain before exiting.
+#[cfg(unix)]
+pub fn install() -> io::Result<()> {
+    for &signal in &[::libc::SIGTERM, ::libc::SIGINT] {
+        let handler = on_terminate as extern "C" fn(::libc::c_int) as ::libc::sighandler_t;
+        if unsafe { ::libc::signal(signal, handler) } == ::libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Signals are not supported, so the process is simply killed.
+#[cfg(not(unix))]
+pub fn install() -> io::Result<()> {
+    Ok(())
+}
+
+/// Blocks until a termination signal is received.
+pub fn wait_for_termination() {
+    while !TERMINATING.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
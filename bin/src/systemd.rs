@@ -0,0 +1,196 @@
+//! Integration with systemd: socket activation, service state notifications and logging to the
+//! journal.
+//!
+//! Everything here is driven by the environment systemd sets up for a service, so outside of
+//! systemd (or on a system without it) the functions simply do nothing.
+
+use log::{self, Level, Log, Metadata, Record};
+use std::env;
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// The first file descriptor passed by socket activation, see `sd_listen_fds(3)`.
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Takes over a listening socket passed by systemd socket activation, if any.
+///
+/// Only the first socket is used if several are passed.
+#[cfg(unix)]
+pub fn activated_listener() -> io::Result<Option<TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    let fds = env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse::<u32>().ok());
+    // The variables are meant for this very process only.
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    match (pid, fds) {
+        (Some(pid), Some(fds)) if pid == process::id() && fds > 0 => {
+            if fds > 1 {
+                warn!("Got {} activated sockets, only the first one is used", fds);
+            }
+            let listener = unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) };
+            info!("Got an activated socket on {}", listener.local_addr()?);
+            Ok(Some(listener))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Socket activation is not supported.
+#[cfg(not(unix))]
+pub fn activated_listener() -> io::Result<Option<TcpListener>> {
+    Ok(None)
+}
+
+/// Notifies systemd about the service state (like `READY=1`), see `sd_notify(3)`.
+///
+/// Does nothing unless the service is run by systemd with `Type=notify`.
+#[cfg(unix)]
+pub fn notify(state: &str) -> io::Result<()> {
+    let socket = match env::var("NOTIFY_SOCKET") {
+        Ok(socket) => socket,
+        Err(_) => return Ok(()),
+    };
+    if socket.starts_with('@') {
+        send_abstract(&socket[1..], state.as_bytes())
+    } else {
+        use std::os::unix::net::UnixDatagram;
+        UnixDatagram::unbound()?.send_to(state.as_bytes(), socket)?;
+        Ok(())
+    }
+}
+
+/// Notifications are not supported.
+#[cfg(not(unix))]
+pub fn notify(_state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Sends a datagram to a socket in the abstract namespace, which is not supported by the standard
+/// library.
+#[cfg(target_os = "linux")]
+fn send_abstract(name: &str, data: &[u8]) -> io::Result<()> {
+    use libc;
+    use std::mem;
+
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    // The leading zero byte of the path marks the abstract namespace.
+    if name.len() + 1 > addr.sun_path.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Socket name is too long"));
+    }
+    for (dst, src) in addr.sun_path[1..].iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    let path_offset = mem::size_of::<libc::sa_family_t>();
+    let addr_len = (path_offset + 1 + name.len()) as libc::socklen_t;
+    unsafe {
+        let fd = libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sent = libc::sendto(fd,
+                                data.as_ptr() as *const libc::c_void,
+                                data.len(),
+                                0,
+                                &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+                                addr_len);
+        let result = if sent < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        };
+        libc::close(fd);
+        result
+    }
+}
+
+/// The abstract namespace only exists on Linux (just like systemd).
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send_abstract(_name: &str, _data: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Abstract sockets are not supported"))
+}
+
+/// Logs a failure to notify systemd, there is not much else to do about it.
+pub fn notify_or_log(state: &str) {
+    if let Err(e) = notify(state) {
+        error!("Can't notify systemd ({}): {}", state, e);
+    }
+}
+
+/// Starts pinging the systemd watchdog, if it is enabled for the service (`WatchdogSec=`).
+///
+/// Pings are sent twice as often as required, as `sd_watchdog_enabled(3)` recommends.
+pub fn spawn_watchdog() {
+    let usec = match env::var("WATCHDOG_USEC").ok().and_then(|usec| usec.parse::<u64>().ok()) {
+        Some(usec) if usec > 0 => usec,
+        _ => return,
+    };
+    let pid = env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if pid.map(|pid| pid != process::id()).unwrap_or(false) {
+        // Meant for another process.
+        return;
+    }
+    let interval = Duration::from_micros(usec / 2);
+    let pinger = move || loop {
+        notify_or_log("WATCHDOG=1");
+        thread::sleep(interval);
+    };
+    if let Err(e) = thread::Builder::new().name("watchdog".into()).spawn(pinger) {
+        error!("Can't spawn a watchdog thread: {}", e);
+    }
+}
+
+/// Checks whether the standard error is connected to the journal.
+pub fn logs_to_journal() -> bool {
+    env::var_os("JOURNAL_STREAM").is_some()
+}
+
+/// A logger that writes to the standard error with priority prefixes the journal understands (see
+/// `sd-daemon(3)`), so log levels are not lost.
+pub struct JournalLogger;
+
+impl JournalLogger {
+    /// Installs the logger.
+    pub fn init(level: log::LevelFilter) -> Result<(), log::SetLoggerError> {
+        static LOGGER: JournalLogger = JournalLogger;
+        log::set_logger(&LOGGER)?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let priority = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        // The journal keeps timestamps by itself. Every line needs a prefix, and the whole message
+        // is written at once, so lines of concurrent messages don't mix.
+        let mut message = String::new();
+        for line in format!("{}: {}", record.target(), record.args()).lines() {
+            message.push_str(&format!("<{}>{}\n", priority, line));
+        }
+        let _ = io::stderr().write_all(message.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
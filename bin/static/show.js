@@ -1,5 +1,5 @@
 function highlight(type_input, language) {
-    var code_container = $('pre');
+    var code_container = $('pre.paste-text');
     var worker = new Worker('../hljs_worker.js');
     type_input.prop('disabled', true);
     var placeholder = 'Highlighting the paste';
@@ -21,6 +21,39 @@ function highlight(type_input, language) {
     worker.postMessage(message);
 }
 
+// Parses a line anchor, like #L42 or #L10-L20, into the first and the last line.
+function parse_line_anchor(hash) {
+    var match = /^#L(\d+)(?:-L(\d+))?$/.exec(hash);
+    if (!match) {
+        return null;
+    }
+    var first = parseInt(match[1], 10);
+    var last = match[2] ? parseInt(match[2], 10) : first;
+    return {first: Math.min(first, last), last: Math.max(first, last)};
+}
+
+// Marks the lines of the anchor in the address (if any), scrolling to them if asked to.
+function select_lines(scroll) {
+    var numbers = $('.line-numbers a');
+    var band = $('#line_highlight');
+    var range = parse_line_anchor(window.location.hash);
+    numbers.removeClass('selected-line');
+    if (!range || range.first > numbers.length) {
+        band.hide();
+        return;
+    }
+    var first = $(numbers[range.first - 1]);
+    var last = $(numbers[Math.min(range.last, numbers.length) - 1]);
+    numbers.slice(range.first - 1, range.last).addClass('selected-line');
+    band.css({
+        top: first.position().top,
+        height: last.position().top + last.outerHeight() - first.position().top
+    }).show();
+    if (scroll) {
+        first[0].scrollIntoView();
+    }
+}
+
 function populate_languages() {
     var supported_languages = $('#supported_languages');
     hljs.listLanguages().forEach(function(lang){
@@ -33,9 +66,27 @@ function populate_languages() {
 $(document).ready(function(){
     populate_languages();
 
-    // Start a highlighting task.
+    // Mark the linked lines; a shift-click on a line number extends the selection to a range.
+    select_lines(true);
+    $(window).on('hashchange', function() {
+        select_lines(true);
+    });
+    $('.line-numbers').on('click', 'a', function(event) {
+        var current = parse_line_anchor(window.location.hash);
+        if (!event.shiftKey || !current) {
+            return;
+        }
+        event.preventDefault();
+        var line = parseInt($(this).text(), 10);
+        var first = Math.min(current.first, line);
+        var last = Math.max(current.first, line);
+        history.replaceState(null, '', '#L' + first + (last > first ? '-L' + last : ''));
+        select_lines(false);
+    });
+
+    // Start a highlighting task, using the language guessed by the server (if any).
     var contents_type = $('#contents_type');
-    highlight(contents_type);
+    highlight(contents_type, contents_type.data('language'));
 
     // Make language selector react to 'enter' key.
     $(contents_type).keyup(function(event) {
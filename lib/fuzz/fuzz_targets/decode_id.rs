@@ -0,0 +1,21 @@
+//! IDs come from URLs of requests.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate pastebin;
+
+use pastebin::{decode_id, encode_id, PasteId};
+use std::str::from_utf8;
+
+fuzz_target!(|data: &[u8]| {
+    let text = match from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if let Ok(id) = decode_id(text) {
+        // Not every string is canonical, but the canonical form has to decode to the same ID.
+        assert_eq!(decode_id(&encode_id(id)).unwrap(), id);
+        assert_eq!(text.parse::<PasteId>().unwrap().as_u64(), id);
+    }
+});
@@ -0,0 +1,17 @@
+//! The `expires` argument of uploads.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chrono;
+extern crate pastebin;
+
+use chrono::{Duration, Utc};
+use pastebin::fuzzing::parse_expires;
+use std::str::from_utf8;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(arg) = from_utf8(data) {
+        let _ = parse_expires(Some(arg), Utc::now(), Duration::days(7));
+    }
+});
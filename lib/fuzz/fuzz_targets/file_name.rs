@@ -0,0 +1,21 @@
+//! File names of uploads, which are used to guess mime types and languages of pastes.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate pastebin;
+
+use pastebin::fuzzing::data_mime_type;
+use pastebin::guess_language;
+use std::str::from_utf8;
+
+fuzz_target!(|data: &[u8]| {
+    // The first line is a file name, the rest is the paste.
+    let split = data.iter().position(|&byte| byte == b'\n').unwrap_or(data.len());
+    let file_name = match from_utf8(&data[..split]) {
+        Ok(file_name) => file_name,
+        Err(_) => return,
+    };
+    let mime_type = data_mime_type(Some(file_name), &data[split..]);
+    let _ = guess_language(Some(file_name), mime_type.as_str());
+});
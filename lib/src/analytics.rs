@@ -0,0 +1,166 @@
+//! Usage analytics without third-party trackers.
+//!
+//! The handler reports views and uploads of pastes to an [Analytics](trait.Analytics.html)
+//! implementation, which is a no-op unless one is given to
+//! [PastebinBuilder::analytics](../web/struct.PastebinBuilder.html#method.analytics).
+//! [LocalAnalytics](struct.LocalAnalytics.html) aggregates the events in memory, and its totals
+//! are reported by `/stats`.
+//!
+//! Analytics never get to see who the visitors are: a visitor is identified by a hash of their IP
+//! address (see [Visitor](struct.Visitor.html)) salted with a random value, which is replaced
+//! every day and is never stored. So visitors could be counted within a day, but can't be
+//! recognized on the next one, let alone matched with the IP addresses. Referrers are reduced to
+//! their hosts, and the service's own pages are not reported as referrers.
+
+use chrono::{Date, Utc};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Maximum number of distinct referrers `LocalAnalytics` keeps track of, the rest of them are
+/// counted as `other`.
+const MAX_REFERRERS: usize = 1000;
+
+/// Number of referrers `LocalAnalytics` reports.
+const TOP_REFERRERS: usize = 10;
+
+/// An anonymous visitor: a salted hash of an IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Visitor(u64);
+
+impl Visitor {
+    /// Hashes an address with the salt of the day.
+    pub fn from_ip(ip: IpAddr) -> Visitor {
+        lazy_static! {
+            static ref SALT: Mutex<(Date<Utc>, RandomState)> =
+                Mutex::new((Utc::today(), RandomState::new()));
+        }
+        let mut salt = SALT.lock().unwrap();
+        let today = Utc::today();
+        if salt.0 != today {
+            *salt = (today, RandomState::new());
+        }
+        let mut hasher = salt.1.build_hasher();
+        ip.hash(&mut hasher);
+        Visitor(hasher.finish())
+    }
+}
+
+/// A receiver of usage events.
+///
+/// All the methods do nothing by default.
+pub trait Analytics: Send + Sync {
+    /// A paste has been viewed (downloaded or shown), possibly following a link from another
+    /// site.
+    fn page_view(&self, _visitor: Visitor, _referrer: Option<&str>) {}
+
+    /// A paste of `size` bytes has been uploaded.
+    fn upload(&self, _visitor: Visitor, _size: usize) {}
+
+    /// Returns a summary of the collected data to be reported by `/stats`, if any.
+    fn summary(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// Analytics that discard all the events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoAnalytics;
+
+impl Analytics for NoAnalytics {}
+
+#[derive(Debug)]
+struct Counters {
+    page_views: u64,
+    uploads: u64,
+    uploaded_bytes: u64,
+    day: Date<Utc>,
+    visitors_today: HashSet<Visitor>,
+    referrers: HashMap<String, u64>,
+    other_referrers: u64,
+}
+
+/// Analytics that aggregate the events in memory, since the start of the server.
+///
+/// The summary looks like `{"page_views": 42, "uploads": 7, "uploaded_bytes": 1536,
+/// "visitors_today": 5, "top_referrers": [{"host": "example.com", "views": 3}]}`.
+#[derive(Debug)]
+pub struct LocalAnalytics {
+    counters: Mutex<Counters>,
+}
+
+impl Default for LocalAnalytics {
+    fn default() -> Self {
+        LocalAnalytics::new()
+    }
+}
+
+impl LocalAnalytics {
+    /// Starts counting from zero.
+    pub fn new() -> Self {
+        LocalAnalytics { counters: Mutex::new(Counters { page_views: 0,
+                                                         uploads: 0,
+                                                         uploaded_bytes: 0,
+                                                         day: Utc::today(),
+                                                         visitors_today: HashSet::new(),
+                                                         referrers: HashMap::new(),
+                                                         other_referrers: 0, }), }
+    }
+
+    /// Counts a visitor, the visitors are forgotten every day.
+    fn visit(counters: &mut Counters, visitor: Visitor) {
+        let today = Utc::today();
+        if counters.day != today {
+            counters.day = today;
+            counters.visitors_today.clear();
+        }
+        counters.visitors_today.insert(visitor);
+    }
+}
+
+impl Analytics for LocalAnalytics {
+    fn page_view(&self, visitor: Visitor, referrer: Option<&str>) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.page_views += 1;
+        LocalAnalytics::visit(&mut counters, visitor);
+        if let Some(referrer) = referrer {
+            let host = referrer.to_lowercase();
+            if counters.referrers.contains_key(&host) || counters.referrers.len() < MAX_REFERRERS {
+                *counters.referrers.entry(host).or_insert(0) += 1;
+            } else {
+                counters.other_referrers += 1;
+            }
+        }
+    }
+
+    fn upload(&self, visitor: Visitor, size: usize) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.uploads += 1;
+        counters.uploaded_bytes += size as u64;
+        LocalAnalytics::visit(&mut counters, visitor);
+    }
+
+    fn summary(&self) -> Option<Value> {
+        let counters = self.counters.lock().unwrap();
+        let mut referrers = counters.referrers.iter().collect::<Vec<_>>();
+        // The most popular first, ties are broken by the host names to keep the order stable.
+        referrers.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let mut top_referrers = referrers.into_iter()
+                                         .take(TOP_REFERRERS)
+                                         .map(|(host, views)| json!({"host": host, "views": views}))
+                                         .collect::<Vec<_>>();
+        if counters.other_referrers > 0 {
+            top_referrers.push(json!({"host": "other", "views": counters.other_referrers}));
+        }
+        Some(json!({
+            "page_views": counters.page_views,
+            "uploads": counters.uploads,
+            "uploaded_bytes": counters.uploaded_bytes,
+            "visitors_today": counters.visitors_today.len(),
+            "top_referrers": top_referrers,
+        }))
+    }
+}
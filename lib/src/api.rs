@@ -0,0 +1,132 @@
+//! JSON API under `/api/v1`.
+//!
+//! Unlike the rest of the routes, the API always responds with JSON, errors included: a failed
+//! request gets `{"error": "<message>"}` along with the corresponding status code. Internal
+//! errors are not described to clients, they are only logged.
+
+use Error;
+use PasteEntry;
+use PasteId;
+use PasteSummary;
+use base64;
+use error::display_chain;
+use iron::headers::ContentType;
+use iron::mime::{Mime, SubLevel, TopLevel};
+use iron::modifiers::Header;
+use iron::prelude::*;
+use iron::status;
+use serde_json::{self, Value};
+
+/// A paste uploaded through the API.
+pub struct Upload {
+    /// Data of the paste.
+    pub data: Vec<u8>,
+    /// File name associated with the paste, if any.
+    pub file_name: Option<String>,
+    /// The `expires` value, as in the `expires` argument of an ordinary upload.
+    pub expires: Option<String>,
+    /// Whether the paste is to be listed at `/browse`.
+    pub public: bool,
+    /// The CSRF token of a form upload (see the `csrf` module), if any.
+    pub csrf_token: Option<String>,
+}
+
+impl Upload {
+    /// Parses a JSON upload like `{"data": "...", "file_name": "main.rs", "expires": "never"}`.
+    ///
+    /// The data is a string, which is taken as is unless `"encoding": "base64"` is given.
+    /// `expires` is either a Unix timestamp (as a number or a string), a lifetime like `"1h"` or
+    /// `never`, and an optional `"public": true` lists the paste publicly.
+    pub fn from_json(body: &[u8]) -> Result<Self, Error> {
+        let upload: Value =
+            serde_json::from_slice(body).map_err(|e| Error::InvalidJson(e.to_string()))?;
+        let data = match upload.get("data").and_then(Value::as_str) {
+            Some(data) => data,
+            None => return Err(Error::InvalidJson("`data` is not a string".into())),
+        };
+        let data = match upload.get("encoding").and_then(Value::as_str) {
+            None | Some("utf-8") => data.as_bytes().to_vec(),
+            Some("base64") => {
+                base64::decode(data).map_err(|e| Error::InvalidJson(format!("`data`: {}", e)))?
+            }
+            Some(encoding) => {
+                return Err(Error::InvalidJson(format!("unknown encoding {:?}", encoding)))
+            }
+        };
+        let file_name = match upload.get("file_name") {
+            None | Some(&Value::Null) => None,
+            Some(&Value::String(ref file_name)) => Some(file_name.clone()),
+            Some(_) => return Err(Error::InvalidJson("`file_name` is not a string".into())),
+        };
+        let expires = match upload.get("expires") {
+            None | Some(&Value::Null) => None,
+            Some(&Value::String(ref expires)) => Some(expires.clone()),
+            Some(&Value::Number(ref expires)) => Some(expires.to_string()),
+            Some(_) => return Err(Error::InvalidJson("`expires` is not a timestamp".into())),
+        };
+        let public = match upload.get("public") {
+            None | Some(&Value::Null) => false,
+            Some(&Value::Bool(public)) => public,
+            Some(_) => return Err(Error::InvalidJson("`public` is not a boolean".into())),
+        };
+        Ok(Upload { data,
+                    file_name,
+                    expires,
+                    public,
+                    csrf_token: None, })
+    }
+}
+
+/// Checks whether a request has a JSON body, according to its `Content-Type`.
+pub fn is_json(req: &Request) -> bool {
+    match req.headers.get::<ContentType>() {
+        Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => true,
+        _ => false,
+    }
+}
+
+/// Builds a response with a JSON body.
+pub fn json_response(status: status::Status, body: &Value) -> Response {
+    let mut response = Response::with((status, body.to_string()));
+    response.headers.set(ContentType::json());
+    response
+}
+
+/// Describes a paste along with its (base64 encoded) data, `public_id` is its ID the way it is
+/// written in URLs. The number of views is only given if views are counted.
+pub fn paste_json(id: PasteId,
+                  public_id: String,
+                  entry: &PasteEntry,
+                  url: String,
+                  views: Option<u64>)
+                  -> Value {
+    let summary = PasteSummary::from_entry(id, entry);
+    json!({
+        "id": public_id,
+        "url": url,
+        "file_name": summary.file_name,
+        "mime_type": summary.mime_type.as_str(),
+        "size": summary.size,
+        "lines": summary.lines,
+        "language": summary.language,
+        "created_at": summary.created_at.to_rfc3339(),
+        "expires_at": summary.best_before.map(|date| date.to_rfc3339()),
+        "views": views,
+        "revision": entry.revision,
+        "views_left": entry.views_left,
+        "public": entry.public,
+        "data": base64::encode(&entry.data),
+    })
+}
+
+/// Turns an error into a response with a JSON body.
+pub fn error(err: Error) -> IronError {
+    let status = err.status();
+    let message = if status.is_server_error() {
+        "Internal server error".to_string()
+    } else {
+        display_chain(&err)
+    };
+    let body = json!({ "error": message }).to_string();
+    IronError::new(err, (status, body, Header(ContentType::json())))
+}
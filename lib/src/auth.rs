@@ -0,0 +1,186 @@
+//! HTTP Basic authentication of users, for small private installs.
+//!
+//! A [BasicAuth](struct.BasicAuth.html) given to
+//! [PastebinBuilder::basic_auth](../web/struct.PastebinBuilder.html#method.basic_auth) asks for a
+//! user name and a password before letting anyone upload, update or remove pastes, or, if it
+//! protects everything, before serving anything at all. Requests without valid credentials are
+//! answered with `401 Unauthorized`, which makes browsers prompt for them.
+//!
+//! Users are described the way `htpasswd -B` does it, one `user:hash` per line, where the hash is
+//! a bcrypt one (`$2y$...`). Everything after a `#` up to the end of a line is a comment:
+//!
+//! ```
+//! # extern crate pastebin;
+//! use pastebin::auth::BasicAuth;
+//!
+//! # fn main() {
+//! let auth: BasicAuth = "# The team\n\
+//!                        alice:$2y$10$ZgM7zJ4lGqzV0vLSn9Ru8O7rD1Hf4p2Gq6y9Y1r0dY8c4ZKxJv9nS\n"
+//!     .parse()
+//!     .unwrap();
+//! assert_eq!(auth.users(), vec!["alice"]);
+//! # }
+//! ```
+//!
+//! The admin API (everything under `/admin/`) is protected by the admin token instead, so admins
+//! don't need an account.
+
+use Error;
+use bcrypt;
+use iron::{status, BeforeMiddleware, IronError, IronResult, Request, Response};
+use iron::headers::{Authorization, Basic};
+use iron::method::Method;
+use pastebin::{tokens_equal, WWW_AUTHENTICATE_HEADER};
+use request::RequestExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Users allowed in, along with what they are required for.
+pub struct BasicAuth {
+    realm: String,
+    users: HashMap<String, String>,
+    everything: bool,
+    /// The last password verified for every user, so bcrypt (which is slow on purpose) runs once
+    /// per user rather than on every request.
+    verified: Mutex<HashMap<String, String>>,
+}
+
+impl Default for BasicAuth {
+    fn default() -> Self {
+        BasicAuth::new()
+    }
+}
+
+impl BasicAuth {
+    /// Creates a policy without users, which protects uploads, updates and removals.
+    pub fn new() -> Self {
+        BasicAuth { realm: "pastebin".into(),
+                    users: HashMap::new(),
+                    everything: false,
+                    verified: Mutex::new(HashMap::new()), }
+    }
+
+    /// Adds a user with a bcrypt hash of the password, replacing the previous hash of the user if
+    /// any.
+    pub fn user(mut self, name: &str, hash: &str) -> Result<Self, Error> {
+        if name.is_empty() || name.contains(':') {
+            return Err(Error::InvalidBasicAuth(format!("invalid user name {:?}", name)));
+        }
+        let is_bcrypt = ["$2y$", "$2b$", "$2a$"].iter()
+                                                .any(|prefix| hash.starts_with(prefix));
+        if !is_bcrypt {
+            let reason = format!("the hash of {} is not a bcrypt one", name);
+            return Err(Error::InvalidBasicAuth(reason));
+        }
+        self.users.insert(name.into(), hash.into());
+        Ok(self)
+    }
+
+    /// Protects reading pastes (and every other page) as well, not only writing them.
+    pub fn protect_everything(mut self, everything: bool) -> Self {
+        self.everything = everything;
+        self
+    }
+
+    /// Sets the realm browsers show when they prompt for credentials (`pastebin` by default).
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    /// Returns the names of the users, sorted.
+    pub fn users(&self) -> Vec<&str> {
+        let mut users: Vec<&str> = self.users.keys().map(|name| name.as_str()).collect();
+        users.sort();
+        users
+    }
+
+    /// Checks a user name and a password.
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        let hash = match self.users.get(name) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        let cached = self.verified
+                         .lock()
+                         .unwrap()
+                         .get(name)
+                         .map_or(false, |verified| tokens_equal(verified, password));
+        if cached {
+            return true;
+        }
+        match bcrypt::verify(password, hash) {
+            Ok(true) => {
+                self.verified
+                    .lock()
+                    .unwrap()
+                    .insert(name.into(), password.into());
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                error!("Can't verify the password of {}: {}", name, e);
+                false
+            }
+        }
+    }
+
+    /// Checks whether a request has to be authenticated.
+    fn is_protected(&self, req: &Request) -> bool {
+        if req.url_segment_n(0) == Some("admin") {
+            return false;
+        }
+        match req.method {
+            Method::Post | Method::Put | Method::Delete | Method::Patch => true,
+            _ => self.everything,
+        }
+    }
+}
+
+impl FromStr for BasicAuth {
+    type Err = Error;
+
+    /// Parses `htpasswd`-style lines, like `alice:$2y$10$...`.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut auth = BasicAuth::new();
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            let hash = match parts.next() {
+                Some(hash) => hash,
+                None => return Err(Error::InvalidBasicAuth(format!("no hash for {}", name))),
+            };
+            auth = auth.user(name, hash)?;
+        }
+        Ok(auth)
+    }
+}
+
+impl BeforeMiddleware for BasicAuth {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        if !self.is_protected(req) {
+            return Ok(());
+        }
+        let authorized = match req.headers.get::<Authorization<Basic>>() {
+            Some(auth) => {
+                self.verify(&auth.username,
+                            auth.password.as_ref().map_or("", |password| password.as_str()))
+            }
+            None => false,
+        };
+        if authorized {
+            return Ok(());
+        }
+        let mut response = Response::with((status::Unauthorized, "Authentication required\n"));
+        let challenge = format!("Basic realm=\"{}\"", self.realm);
+        response.headers
+                .set_raw(WWW_AUTHENTICATE_HEADER, vec![challenge.into_bytes()]);
+        Err(IronError { error: Box::new(Error::Unauthorized),
+                        response, })
+    }
+}
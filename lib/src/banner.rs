@@ -0,0 +1,126 @@
+//! Announcements shown on every page of the service.
+
+use Error;
+use chrono::{DateTime, Utc};
+use request::parse_timestamp;
+use serde_json::{self, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// How serious an announcement is, which decides how it looks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something good to know, like a new feature.
+    Info,
+    /// Something to prepare for, like a maintenance window.
+    Warning,
+    /// Something that is going wrong right now, like an outage.
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(Error::InvalidBanner(format!("unknown severity {:?}, expected info, \
+                                                   warning or critical",
+                                                  s))),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        Severity::Info => "info",
+                        Severity::Warning => "warning",
+                        Severity::Critical => "critical",
+                    })
+    }
+}
+
+/// An announcement banner, like a warning about an upcoming maintenance window.
+///
+/// The banner is shown on top of every HTML page and is given by the instance API
+/// (`/api/v1/instance`). It is set up with
+/// [PastebinBuilder::banner](web/struct.PastebinBuilder.html#method.banner) and replaced at
+/// runtime with `PUT /admin/banner`, which takes it as JSON:
+///
+/// ```
+/// # extern crate pastebin;
+/// use pastebin::{Banner, Severity};
+///
+/// # fn main() {
+/// let json = br#"{"message": "Maintenance at 22:00 UTC", "severity": "warning",
+///                 "expires_at": 1530000000}"#;
+/// let banner = Banner::from_json(json).unwrap();
+/// assert_eq!(banner.severity, Severity::Warning);
+/// assert_eq!(banner.expires_at.unwrap().timestamp(), 1530000000);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Banner {
+    /// The text of the announcement.
+    pub message: String,
+    /// How serious the announcement is.
+    pub severity: Severity,
+    /// When the banner is taken down by itself, if ever.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Banner {
+    /// Creates a banner that is shown until it is replaced or removed.
+    pub fn new<S: Into<String>>(message: S, severity: Severity) -> Self {
+        Banner { message: message.into(),
+                 severity,
+                 expires_at: None, }
+    }
+
+    /// Sets the date the banner is taken down at.
+    pub fn expires_at(mut self, date: DateTime<Utc>) -> Self {
+        self.expires_at = Some(date);
+        self
+    }
+
+    /// Checks whether the banner is still to be shown at the moment.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.map_or(true, |date| date > now)
+    }
+
+    /// Parses a banner from a JSON object with a (non-empty) `message`, an optional `severity`
+    /// (`info` by default) and an optional `expires_at` Unix timestamp.
+    pub fn from_json(body: &[u8]) -> Result<Self, Error> {
+        let value: Value =
+            serde_json::from_slice(body).map_err(|e| Error::InvalidBanner(e.to_string()))?;
+        let message = match value["message"] {
+            Value::String(ref message) if !message.trim().is_empty() => message.clone(),
+            _ => return Err(Error::InvalidBanner("`message` is missing".into())),
+        };
+        let severity = match value["severity"] {
+            Value::Null => Severity::Info,
+            Value::String(ref severity) => severity.parse()?,
+            _ => return Err(Error::InvalidBanner("`severity` is not a string".into())),
+        };
+        let expires_at = match value["expires_at"] {
+            Value::Null => None,
+            Value::Number(ref timestamp) => Some(parse_timestamp(&timestamp.to_string())?),
+            _ => return Err(Error::InvalidBanner("`expires_at` is not a timestamp".into())),
+        };
+        Ok(Banner { message,
+                    severity,
+                    expires_at, })
+    }
+
+    /// Describes the banner, the expiration date is given in RFC 3339 format.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "message": self.message,
+            "severity": self.severity.to_string(),
+            "expires_at": self.expires_at.map(|date| date.to_rfc3339()),
+        })
+    }
+}
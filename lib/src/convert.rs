@@ -0,0 +1,268 @@
+//! Converting pastes into other formats.
+//!
+//! A paste is served converted at `/<id>/as/<format>`, where the format is a name of a
+//! [Converter](trait.Converter.html) in the [Converters](struct.Converters.html) registry.
+//! A paste the converter doesn't accept is answered with `415 Unsupported Media Type`, and the
+//! data it fails to convert with `422 Unprocessable Entity`.
+//!
+//! The built-in converters are:
+//!
+//! * `hexdump`, a canonical hex and ASCII dump (like `hexdump -C` prints) of any paste;
+//! * `base64-decoded`, the data of a base64 encoded text paste;
+//! * `json`, a YAML paste as JSON (with the `yaml` feature);
+//! * `yaml`, a JSON paste as YAML (with the `yaml` feature);
+//! * `html`, a Markdown paste rendered into HTML (with the `markdown` feature), see
+//! [render_markdown](fn.render_markdown.html).
+
+use Error;
+use MimeType;
+use base64;
+use mime;
+#[cfg(feature = "markdown")]
+use pulldown_cmark::{self, Event, Parser, Tag};
+#[cfg(feature = "yaml")]
+use serde_json;
+#[cfg(feature = "yaml")]
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Pastes bigger than that are not converted, so a conversion never takes too long or produces
+/// too much.
+pub const MAX_INPUT_SIZE: usize = 1024 * 1024;
+
+/// A conversion of pastes into a format.
+pub trait Converter: Send + Sync {
+    /// Checks whether pastes of the mime type could be converted at all.
+    fn accepts(&self, mime_type: &MimeType) -> bool;
+
+    /// Converts data of a paste, returns the result along with its mime type.
+    ///
+    /// An error describes why the data can't be converted.
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String>;
+}
+
+/// Converters by the names of their formats.
+///
+/// The default registry holds all the built-in converters (see the
+/// [module](index.html) documentation), while `Converters::new()` starts from an empty one.
+#[derive(Clone)]
+pub struct Converters {
+    converters: BTreeMap<String, Arc<Converter>>,
+}
+
+impl Default for Converters {
+    fn default() -> Self {
+        let converters = Converters::new().converter("hexdump", Hexdump)
+                                          .converter("base64-decoded", Base64Decode);
+        #[cfg(feature = "yaml")]
+        let converters = converters.converter("json", YamlToJson)
+                                   .converter("yaml", JsonToYaml);
+        #[cfg(feature = "markdown")]
+        let converters = converters.converter("html", MarkdownToHtml);
+        converters
+    }
+}
+
+impl Converters {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Converters { converters: BTreeMap::new() }
+    }
+
+    /// Adds a converter, replacing the previous converter into the format if any.
+    pub fn converter<C: Converter + 'static>(mut self, format: &str, converter: C) -> Self {
+        self.converters.insert(format.to_string(), Arc::new(converter));
+        self
+    }
+
+    /// Converts data of a paste of the given mime type into the format.
+    pub fn convert(&self,
+                   format: &str,
+                   data: &[u8],
+                   mime_type: &MimeType)
+                   -> Result<(Vec<u8>, MimeType), Error> {
+        let converter = match self.converters.get(format) {
+            Some(converter) if converter.accepts(mime_type) => converter,
+            _ => return Err(Error::UnsupportedConversion(mime_type.clone(), format.to_string())),
+        };
+        if data.len() > MAX_INPUT_SIZE {
+            let reason = format!("pastes bigger than {} bytes are not converted", MAX_INPUT_SIZE);
+            return Err(Error::ConversionFailed(reason));
+        }
+        converter.convert(data).map_err(Error::ConversionFailed)
+    }
+}
+
+/// Checks whether a mime type is a textual one, including structured text formats.
+fn is_textual(mime_type: &MimeType) -> bool {
+    let essence = mime_type.as_str().split(';').next().unwrap_or_default().trim();
+    mime_type.is_text()
+    || match essence {
+        "application/json" | "application/x-yaml" | "application/xml" => true,
+        _ => false,
+    }
+}
+
+/// Validates a built-in mime type.
+#[cfg(any(feature = "yaml", feature = "markdown"))]
+fn mime_type(mime_type: &str) -> MimeType {
+    MimeType::new(mime_type).expect("Built-in mime types are valid")
+}
+
+/// Decodes data of a textual paste.
+fn text(data: &[u8]) -> Result<&str, String> {
+    ::std::str::from_utf8(data).map_err(|e| format!("not a UTF-8 text: {}", e))
+}
+
+/// A canonical hex and ASCII dump.
+struct Hexdump;
+
+impl Converter for Hexdump {
+    fn accepts(&self, _mime_type: &MimeType) -> bool {
+        true
+    }
+
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String> {
+        let mut dump = String::new();
+        for (line, chunk) in data.chunks(16).enumerate() {
+            dump.push_str(&format!("{:08x} ", line * 16));
+            for i in 0..16 {
+                if i % 8 == 0 {
+                    dump.push(' ');
+                }
+                match chunk.get(i) {
+                    Some(byte) => dump.push_str(&format!("{:02x} ", byte)),
+                    None => dump.push_str("   "),
+                }
+            }
+            dump.push_str(" |");
+            dump.extend(chunk.iter().map(|&byte| printable(byte)));
+            dump.push_str("|\n");
+        }
+        dump.push_str(&format!("{:08x}\n", data.len()));
+        Ok((dump.into_bytes(), MimeType::text_plain()))
+    }
+}
+
+/// Shows a byte as a character in a dump, non-printable ones are replaced with dots.
+fn printable(byte: u8) -> char {
+    if byte == b' ' || byte.is_ascii_graphic() {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Decoding of base64 encoded text.
+struct Base64Decode;
+
+impl Converter for Base64Decode {
+    fn accepts(&self, mime_type: &MimeType) -> bool {
+        is_textual(mime_type)
+    }
+
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String> {
+        // Encoded data is often wrapped into lines.
+        let encoded = text(data)?.split_whitespace().collect::<String>();
+        let decoded = base64::decode(&encoded).map_err(|e| e.to_string())?;
+        let mime_type = mime::data_mime_type(None::<&str>, &decoded);
+        Ok((decoded, mime_type))
+    }
+}
+
+/// YAML into JSON.
+#[cfg(feature = "yaml")]
+struct YamlToJson;
+
+#[cfg(feature = "yaml")]
+impl Converter for YamlToJson {
+    fn accepts(&self, mime_type: &MimeType) -> bool {
+        is_textual(mime_type)
+    }
+
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String> {
+        let value: serde_json::Value =
+            serde_yaml::from_str(text(data)?).map_err(|e| e.to_string())?;
+        let json = serde_json::to_vec_pretty(&value).map_err(|e| e.to_string())?;
+        Ok((json, mime_type("application/json")))
+    }
+}
+
+/// JSON into YAML.
+#[cfg(feature = "yaml")]
+struct JsonToYaml;
+
+#[cfg(feature = "yaml")]
+impl Converter for JsonToYaml {
+    fn accepts(&self, mime_type: &MimeType) -> bool {
+        is_textual(mime_type)
+    }
+
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String> {
+        let value: serde_json::Value = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+        let yaml = serde_yaml::to_string(&value).map_err(|e| e.to_string())?;
+        Ok((yaml.into_bytes(), mime_type("text/plain; charset=utf-8")))
+    }
+}
+
+/// Markdown into HTML.
+#[cfg(feature = "markdown")]
+struct MarkdownToHtml;
+
+#[cfg(feature = "markdown")]
+impl Converter for MarkdownToHtml {
+    fn accepts(&self, mime_type: &MimeType) -> bool {
+        is_textual(mime_type)
+    }
+
+    fn convert(&self, data: &[u8]) -> Result<(Vec<u8>, MimeType), String> {
+        let html = render_markdown(text(data)?);
+        Ok((html.into_bytes(), mime_type("text/html; charset=utf-8")))
+    }
+}
+
+/// URL schemes links and images of a Markdown document might have, besides relative URLs.
+#[cfg(feature = "markdown")]
+const SAFE_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Renders a Markdown document into HTML that is safe to put on the service's own pages.
+///
+/// Raw HTML of the document is escaped rather than passed through, and links and images with
+/// URLs of other schemes than `http`, `https` and `mailto` (like `javascript:`) lead nowhere.
+#[cfg(feature = "markdown")]
+pub fn render_markdown(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new(markdown).map(sanitize));
+    html
+}
+
+/// Turns raw HTML of a Markdown document into text and drops unsafe URLs, so a paste can't put
+/// scripts and such on the service's pages.
+#[cfg(feature = "markdown")]
+fn sanitize(event: Event) -> Event {
+    match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link(ref url, ref title)) if !is_safe_url(url) => {
+            Event::Start(Tag::Link("".into(), title.clone()))
+        }
+        Event::Start(Tag::Image(ref url, ref title)) if !is_safe_url(url) => {
+            Event::Start(Tag::Image("".into(), title.clone()))
+        }
+        event => event,
+    }
+}
+
+/// Checks whether a URL is a relative one or has one of the `SAFE_URL_SCHEMES`.
+#[cfg(feature = "markdown")]
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in schemes, like in `java\tscript:`.
+    let url = url.chars()
+                 .filter(|c| !c.is_whitespace() && !c.is_control())
+                 .collect::<String>()
+                 .to_lowercase();
+    match url.find(|c| c == ':' || c == '/' || c == '?' || c == '#') {
+        Some(end) if url[end..].starts_with(':') => SAFE_URL_SCHEMES.contains(&&url[..end]),
+        _ => true,
+    }
+}
@@ -0,0 +1,167 @@
+//! Copying pastes between pastebin instances.
+//!
+//! A paste is fetched through the JSON API of one instance and uploaded through the API of
+//! another one, which helps migrating users between deployments. The data, the file name and the
+//! expiration date are preserved, while the mime type is detected anew by the target instance.
+//! The creation date, views and the deletion token can't be carried over: the copy gets a
+//! deletion token of its own.
+
+use chrono::{DateTime, Utc};
+use iron::Url;
+use reqwest::header::ContentType;
+use reqwest::{self, StatusCode};
+use serde_json::{self, Value};
+
+quick_error! {
+    /// Copying errors.
+    #[derive(Debug)]
+    pub enum CopyError {
+        /// Malformed URL of a paste or an instance.
+        InvalidUrl(url: String) {
+            description("Invalid URL")
+            display("Invalid URL {:?}", url)
+        }
+        /// The request has failed.
+        Request(err: reqwest::Error) {
+            description("Request failed")
+            display("Request failed: {}", err)
+            from()
+        }
+        /// An instance has responded with an error.
+        Status(url: String, status: StatusCode, message: String) {
+            description("Request rejected")
+            display("{} responded with {}: {}", url, status, message)
+        }
+        /// An instance has responded with something that is not a valid API response.
+        Response(reason: String) {
+            description("Invalid response")
+            display("Invalid response: {}", reason)
+        }
+        /// The paste has expired already, so there is nothing to copy.
+        Expired(url: String) {
+            description("Paste has expired")
+            display("Paste {} has expired", url)
+        }
+    }
+}
+
+/// A copied paste.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopiedPaste {
+    /// URL of the copy at the target instance.
+    pub url: String,
+    /// Token the copy could be removed with.
+    pub delete_token: Option<String>,
+    /// Expiration date of the copy, if it ever expires.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Copies a paste to another instance.
+///
+/// `from` is a URL of a paste, as the instance gives it out: `<url prefix><id>`, optionally
+/// followed by a file name. IDs are taken as they are, whatever alphabet the instance writes them
+/// in. A file name that could be an ID itself (like `Makefile`) can't be told apart from an ID,
+/// so such URLs should be given without it. `to` is the URL prefix of the
+/// target instance, like `https://paste.example.com/`.
+pub fn copy_paste(from: &str, to: &str) -> Result<CopiedPaste, CopyError> {
+    let (source_prefix, id) = split_paste_url(from)?;
+    let paste = fetch(&format!("{}api/v1/pastes/{}", source_prefix, id))?;
+    let data = paste["data"].as_str()
+                            .ok_or_else(|| CopyError::Response("no paste data".into()))?;
+    let expires = match paste["expires_at"].as_str() {
+        Some(expires_at) => {
+            let expires_at = parse_date(expires_at)?;
+            if expires_at <= Utc::now() {
+                return Err(CopyError::Expired(from.to_string()));
+            }
+            Value::from(expires_at.timestamp())
+        }
+        None => Value::from("never"),
+    };
+    let upload = json!({
+        "data": data,
+        "encoding": "base64",
+        "file_name": paste["file_name"],
+        "expires": expires,
+    });
+    let target_prefix = format!("{}/", to.trim_right_matches('/'));
+    let copy = upload_json(&format!("{}api/v1/pastes", target_prefix), &upload)?;
+    let url = copy["url"].as_str()
+                         .ok_or_else(|| CopyError::Response("no URL of the copy".into()))?
+                         .to_string();
+    let expires_at = match copy["expires_at"].as_str() {
+        Some(expires_at) => Some(parse_date(expires_at)?),
+        None => None,
+    };
+    Ok(CopiedPaste { url,
+                     delete_token: copy["delete_token"].as_str().map(str::to_string),
+                     expires_at, })
+}
+
+/// Splits a URL of a paste into the URL prefix of the instance (with a trailing slash) and the
+/// ID of the paste.
+fn split_paste_url(url: &str) -> Result<(String, String), CopyError> {
+    let invalid = || CopyError::InvalidUrl(url.to_string());
+    let mut prefix = Url::parse(url).map_err(|_| invalid())?
+                                    .into_generic_url();
+    let mut segments = prefix.path_segments()
+                             .ok_or_else(invalid)?
+                             .filter(|segment| !segment.is_empty())
+                             .map(str::to_string)
+                             .collect::<Vec<_>>();
+    // The last segment is either the ID or a file name that follows it.
+    let mut id = segments.pop().ok_or_else(invalid)?;
+    if !could_be_id(&id) {
+        id = segments.pop().ok_or_else(invalid)?;
+    }
+    if !could_be_id(&id) {
+        return Err(invalid());
+    }
+    prefix.set_query(None);
+    prefix.set_fragment(None);
+    let path = segments.iter()
+                       .map(|segment| format!("{}/", segment))
+                       .collect::<String>();
+    prefix.set_path(&format!("/{}", path));
+    Ok((prefix.to_string(), id))
+}
+
+/// Checks whether a path segment could be an ID (or a slug) of a paste, in any alphabet.
+fn could_be_id(segment: &str) -> bool {
+    !segment.is_empty() &&
+    segment.chars()
+           .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parses a date given by the API.
+fn parse_date(date: &str) -> Result<DateTime<Utc>, CopyError> {
+    DateTime::parse_from_rfc3339(date).map(|date| date.with_timezone(&Utc))
+                                      .map_err(|e| CopyError::Response(format!("{}: {}", date, e)))
+}
+
+/// Fetches a JSON document.
+fn fetch(url: &str) -> Result<Value, CopyError> {
+    let response = reqwest::Client::new().get(url).send()?;
+    parse_response(url, response)
+}
+
+/// Posts a JSON document, returns the response.
+fn upload_json(url: &str, body: &Value) -> Result<Value, CopyError> {
+    let response = reqwest::Client::new().post(url)
+                                         .header(ContentType::json())
+                                         .body(body.to_string())
+                                         .send()?;
+    parse_response(url, response)
+}
+
+/// Parses an API response, turning errors into `CopyError::Status`.
+fn parse_response(url: &str, response: reqwest::Response) -> Result<Value, CopyError> {
+    let status = response.status();
+    let body: Value = serde_json::from_reader(response)
+        .map_err(|e| CopyError::Response(format!("{}: {}", url, e)))?;
+    if !status.is_success() {
+        let message = body["error"].as_str().unwrap_or("unknown error").to_string();
+        return Err(CopyError::Status(url.to_string(), status, message));
+    }
+    Ok(body)
+}
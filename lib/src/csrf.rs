@@ -0,0 +1,92 @@
+//! Protection of the upload form against cross-site request forgery.
+//!
+//! A page with a form (the upload page and the fork button of a paste) gets a random token, which
+//! is both put into the form as the hidden `csrf_token` field and set as a cookie of the same
+//! name. Another site could make a browser submit a form to the pastebin, along with whatever
+//! credentials or addresses the browser is let in by, but it can neither read the cookie nor the
+//! pages, so it has no way to submit the matching token.
+//!
+//! The token is kept for as long as the browser keeps the cookie (that is, for a browsing
+//! session), so several open pages don't invalidate each other. Scripts might send it in the
+//! `X-CSRF-Token` header instead of the field. Only browsers are checked: other clients don't
+//! have cookies to be abused.
+
+use Error;
+use base64;
+use iron::Request;
+use iron::Response;
+use pastebin::tokens_equal;
+use rand::{thread_rng, Rng};
+use request::RequestExt;
+use std::str;
+
+/// Name of both the cookie and the form field.
+pub const CSRF_TOKEN_NAME: &str = "csrf_token";
+
+/// Request header a script might pass the token in.
+const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// Number of random bytes in a token.
+const TOKEN_BYTES: usize = 16;
+
+/// Generates a random token.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    thread_rng().fill(&mut bytes);
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Checks whether a token looks like one `new_token` makes, so a cookie set by someone else
+/// (like a neighbouring subdomain) isn't taken at face value.
+fn is_well_formed(token: &str) -> bool {
+    base64::decode_config(token, base64::URL_SAFE_NO_PAD).map(|bytes| bytes.len() == TOKEN_BYTES)
+                                                         .unwrap_or(false)
+}
+
+/// Returns the token of the request's cookie, if it has a well-formed one.
+pub fn cookie_token(req: &Request) -> Option<String> {
+    let lines = req.headers.get_raw("Cookie")?;
+    lines.iter()
+         .filter_map(|line| str::from_utf8(line).ok())
+         .flat_map(|line| line.split(';'))
+         .filter_map(|cookie| {
+                         let mut parts = cookie.trim().splitn(2, '=');
+                         match (parts.next(), parts.next()) {
+                             (Some(CSRF_TOKEN_NAME), Some(value)) => Some(value.to_string()),
+                             _ => None,
+                         }
+                     })
+         .find(|token| is_well_formed(token))
+}
+
+/// Returns the token of the request's cookie, or a new one if there is none.
+pub fn token(req: &Request) -> String {
+    cookie_token(req).unwrap_or_else(new_token)
+}
+
+/// Sets the cookie with a token, for the rest of the browsing session.
+///
+/// The cookie is not sent along with requests made from other sites at all, and it is not
+/// readable by scripts.
+pub fn set_cookie(response: &mut Response, token: &str) {
+    let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Strict", CSRF_TOKEN_NAME, token);
+    response.headers.set_raw("Set-Cookie", vec![cookie.into_bytes()]);
+}
+
+/// Checks a form submission, where `submitted` is the token from the form (if any).
+///
+/// Requests from browsers have to carry the token of their cookie, either in the form or in the
+/// `X-CSRF-Token` header.
+pub fn check(req: &Request, submitted: Option<&str>) -> Result<(), Error> {
+    if !req.is_browser() {
+        return Ok(());
+    }
+    let header = req.headers
+                    .get_raw(CSRF_TOKEN_HEADER)
+                    .and_then(|lines| lines.first())
+                    .and_then(|line| str::from_utf8(line).ok());
+    match (cookie_token(req), submitted.or(header)) {
+        (Some(ref expected), Some(submitted)) if tokens_equal(expected, submitted.trim()) => Ok(()),
+        _ => Err(Error::InvalidCsrfToken),
+    }
+}
@@ -0,0 +1,301 @@
+//! In-memory caching of pastes.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// A least recently used cache bounded by the total size of the cached data.
+struct Lru {
+    entries: HashMap<PasteId, (PasteEntry, u64)>,
+    /// Paste IDs by the "time" they were used at.
+    usage: BTreeMap<u64, PasteId>,
+    clock: u64,
+    size: usize,
+    capacity: usize,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Lru { entries: HashMap::new(),
+              usage: BTreeMap::new(),
+              clock: 0,
+              size: 0,
+              capacity, }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, id: PasteId) -> Option<PasteEntry> {
+        let now = self.tick();
+        let &mut (ref entry, ref mut used_at) = self.entries.get_mut(&id)?;
+        self.usage.remove(used_at);
+        self.usage.insert(now, id);
+        *used_at = now;
+        Some(entry.clone())
+    }
+
+    fn insert(&mut self, id: PasteId, entry: PasteEntry) {
+        self.remove(id);
+        if entry.data.len() > self.capacity {
+            return;
+        }
+        while self.size + entry.data.len() > self.capacity {
+            let oldest = match self.usage.keys().next() {
+                Some(&used_at) => self.usage[&used_at],
+                None => break,
+            };
+            self.remove(oldest);
+        }
+        let now = self.tick();
+        self.size += entry.data.len();
+        self.usage.insert(now, id);
+        self.entries.insert(id, (entry, now));
+    }
+
+    fn increment_views(&mut self, id: PasteId) {
+        if let Some(&mut (ref mut entry, _)) = self.entries.get_mut(&id) {
+            entry.views += 1;
+        }
+    }
+
+    fn remove(&mut self, id: PasteId) {
+        if let Some((entry, used_at)) = self.entries.remove(&id) {
+            self.size -= entry.data.len();
+            self.usage.remove(&used_at);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.usage.clear();
+        self.size = 0;
+    }
+}
+
+/// A decorator that keeps recently loaded pastes in memory.
+///
+/// Popular pastes tend to be requested over and over again, so caching them saves a lot of
+/// database round trips. The cache is bounded by the total size of the cached data: the least
+/// recently used pastes are evicted once it is exceeded, and pastes bigger than the whole cache
+/// are not cached at all. Updated, removed and trashed pastes are evicted immediately, expired ones
+/// are not served from the cache.
+///
+/// Mind that the data is cached as it is returned by the wrapped database, so it is better to put
+/// the cache on top of other decorators: that way it holds decompressed (or decrypted) data.
+pub struct CachedDb<D> {
+    inner: D,
+    cache: Mutex<Lru>,
+}
+
+impl<D: DbInterface> CachedDb<D> {
+    /// Wraps a database with a cache of `capacity` bytes.
+    pub fn new(inner: D, capacity: usize) -> Self {
+        CachedDb { inner,
+                   cache: Mutex::new(Lru::new(capacity)), }
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Looks a paste up in the cache.
+    fn cached(&self, id: PasteId) -> Option<PasteEntry> {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.get(id)?;
+        if entry.best_before.map(|date| date < Utc::now()).unwrap_or(false) {
+            cache.remove(id);
+            return None;
+        }
+        Some(entry)
+    }
+
+    fn evict(&self, id: PasteId) {
+        self.cache.lock().unwrap().remove(id);
+    }
+}
+
+impl<D: DbInterface> DbInterface for CachedDb<D> {
+    type Error = D::Error;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        self.inner.store_data(entry)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        self.inner.insert_data(id, entry)?;
+        self.evict(id);
+        Ok(())
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        self.inner.store_new(id, entry)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        if let Some(entry) = self.cached(id) {
+            return Ok(Some(entry));
+        }
+        let entry = self.inner.load_data(id)?;
+        if let Some(ref entry) = entry {
+            self.cache.lock().unwrap().insert(id, entry.clone());
+        }
+        Ok(entry)
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        match self.cached(id) {
+            Some(entry) => Ok(Some(PasteSummary::from_entry(id, &entry))),
+            None => self.inner.load_metadata(id),
+        }
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        match self.cached(id) {
+            Some(entry) => Ok(entry.deletion_token.clone()),
+            None => self.inner.load_deletion_token(id),
+        }
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let updated = self.inner.update_data(id, expected_revision, data, mime_type)?;
+        self.evict(id);
+        Ok(updated)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id)?;
+        // Keep the cached copy in line, evicting it would defeat the cache for popular pastes.
+        self.cache.lock().unwrap().increment_views(id);
+        Ok(())
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        let views_left = self.inner.take_view(id)?;
+        // Pastes with a view limit are not going to be popular, so there is no point in keeping
+        // the cached copy in line.
+        self.evict(id);
+        Ok(views_left)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id)?;
+        self.evict(id);
+        Ok(())
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        let trashed = self.inner.soft_delete(id, deleted_at)?;
+        self.evict(id);
+        Ok(trashed)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        // Trashed pastes are not cached, so there is nothing to evict.
+        self.inner.restore(id)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // Expired pastes are never served from the cache anyway, but there is no point in keeping
+        // them.
+        self.cache.lock().unwrap().clear();
+        self.inner.purge_expired(now)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // Scrubbed creators must not be served from the cache.
+        self.cache.lock().unwrap().clear();
+        self.inner.scrub_creators(created_before)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // The removed pastes are not known here, so the whole cache goes.
+        self.cache.lock().unwrap().clear();
+        self.inner.remove_by_creator(creator, since)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.cache.lock().unwrap().clear();
+        self.inner.remove_by_owner(token)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner.store_signature(id, signature)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list(offset, limit)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list_public(offset, limit)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check()
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats()
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.inner.max_data_size()
+    }
+}
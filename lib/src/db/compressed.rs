@@ -0,0 +1,240 @@
+//! Transparent compression of the stored data.
+
+use super::WrapperError;
+use chrono::{DateTime, Utc};
+use std::io;
+use zstd;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// Magic number that starts every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Default compression level.
+const DEFAULT_LEVEL: i32 = 3;
+
+/// A decorator that compresses pastes data with [zstd](https://facebook.github.io/zstd/).
+///
+/// The data is compressed before it is passed to the wrapped database and decompressed upon
+/// loading, everything else (including the mime type) is stored as is, so the pastes are served
+/// exactly as they would be without the decorator.
+///
+/// Data that doesn't look like a zstd frame is returned unchanged, so compression could be enabled
+/// for a database that already contains uncompressed pastes.
+///
+/// Since sizes and line counts are calculated by a database, `load_metadata` and `list` have to
+/// load and decompress the data in order to report correct values. Storage statistics, on the
+/// contrary, reflect the compressed sizes.
+pub struct CompressedDb<D> {
+    inner: D,
+    level: i32,
+}
+
+impl<D: DbInterface> CompressedDb<D> {
+    /// Wraps a database using the default compression level.
+    pub fn new(inner: D) -> Self {
+        Self::with_level(inner, DEFAULT_LEVEL)
+    }
+
+    /// Wraps a database using the given compression level (from 1 to 21, higher is slower but
+    /// produces smaller output).
+    pub fn with_level(inner: D, level: i32) -> Self {
+        CompressedDb { inner, level }
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, WrapperError<D::Error>> {
+        zstd::encode_all(data, self.level).map_err(WrapperError::wrapper)
+    }
+
+    /// Loads a paste and computes its summary from the decompressed data.
+    fn load_summary(&self, id: PasteId) -> Result<Option<PasteSummary>, WrapperError<D::Error>> {
+        Ok(self.load_data(id)?
+               .map(|entry| PasteSummary::from_entry(id, &entry)))
+    }
+}
+
+/// Decompresses the data unless it is not compressed at all.
+fn decompress(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    if data.starts_with(&ZSTD_MAGIC) {
+        zstd::decode_all(&data[..])
+    } else {
+        Ok(data)
+    }
+}
+
+impl<D: DbInterface> DbInterface for CompressedDb<D> {
+    type Error = WrapperError<D::Error>;
+
+    fn store_data(&self, mut entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        entry.data = self.compress(&entry.data)?;
+        self.inner.store_data(entry).map_err(WrapperError::Db)
+    }
+
+    fn insert_data(&self, id: PasteId, mut entry: PasteEntry) -> Result<(), Self::Error> {
+        entry.data = self.compress(&entry.data)?;
+        self.inner.insert_data(id, entry).map_err(WrapperError::Db)
+    }
+
+    fn store_new(&self, id: PasteId, mut entry: PasteEntry) -> Result<bool, Self::Error> {
+        entry.data = self.compress(&entry.data)?;
+        self.inner.store_new(id, entry).map_err(WrapperError::Db)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(mut entry) => {
+                entry.data = decompress(entry.data).map_err(WrapperError::wrapper)?;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.load_summary(id)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        // Tokens are stored as they are.
+        self.inner.load_deletion_token(id).map_err(WrapperError::Db)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let data = self.compress(&data)?;
+        self.inner.update_data(id, expected_revision, data, mime_type).map_err(WrapperError::Db)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id).map_err(WrapperError::Db)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id).map_err(WrapperError::Db)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id).map_err(WrapperError::Db)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.inner.soft_delete(id, deleted_at).map_err(WrapperError::Db)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id).map_err(WrapperError::Db)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before).map_err(WrapperError::Db)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone).map_err(WrapperError::Db)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id).map_err(WrapperError::Db)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since).map_err(WrapperError::Db)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before).map_err(WrapperError::Db)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_expired(now).map_err(WrapperError::Db)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.scrub_creators(created_before).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.remove_by_creator(creator, since).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.inner.remove_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash).map_err(WrapperError::Db)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash).map_err(WrapperError::Db)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug).map_err(WrapperError::Db)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug).map_err(WrapperError::Db)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner
+            .store_signature(id, signature)
+            .map_err(WrapperError::Db)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id).map_err(WrapperError::Db)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_summary(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list_public(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_summary(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().map_err(WrapperError::Db)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats().map_err(WrapperError::Db)
+    }
+
+    fn max_data_size(&self) -> usize {
+        // Compression ratio is not known in advance, so the limit of the wrapped database is
+        // applied to the uncompressed data.
+        self.inner.max_data_size()
+    }
+}
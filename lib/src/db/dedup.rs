@@ -0,0 +1,256 @@
+//! Deduplication of pastes with identical content.
+
+use blake2_rfc::blake2b::blake2b;
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// Computes a hash of a paste's data, as stored by [DedupDb](struct.DedupDb.html).
+///
+/// It is a hex-encoded 256-bit BLAKE2b digest prefixed with `blake2b:`, so other algorithms could
+/// be introduced later without confusing the old hashes with the new ones.
+pub fn content_hash(data: &[u8]) -> String {
+    let digest = blake2b(32, &[], data);
+    let mut hash = String::from("blake2b:");
+    for byte in digest.as_bytes() {
+        write!(hash, "{:02x}", byte).expect("Writing to a string never fails");
+    }
+    hash
+}
+
+/// A decorator that stores identical pastes only once.
+///
+/// A hash of every new paste is stored alongside the paste (see `DbInterface::store_hash`), and
+/// if a paste with the same hash already exists, its ID is returned instead of storing the data
+/// once again. Only pastes with the same content, file name and mime type (and language, if the
+/// uploader gives one) are considered identical, and a paste is only reused if it lives at least
+/// as long as the new one would.
+///
+/// Since everyone who uploads the same content gets the same paste, only anonymous public pastes
+/// are shared: both the new paste and the existing one have to be listed and have neither a
+/// deletion token nor a recorded creator. Sharing anything else would either reveal the ID of an
+/// unlisted paste (or one with a random ID) to whoever guesses its content, or leave the second
+/// uploader with a deletion token that doesn't work and a paste somebody else can remove. Pastes
+/// with a view limit are neither shared nor hashed, as they are used up by views. All the other
+/// pastes are stored as they are, but their hashes are still recorded, so they can be found by
+/// `find_by_hash` (see `PastebinBuilder::content_permalinks`).
+///
+/// The decorator has to be on top of the ones that transform the data (like
+/// [CompressedDb](struct.CompressedDb.html)), so the hash is computed over the original data.
+pub struct DedupDb<D> {
+    inner: D,
+}
+
+impl<D: DbInterface> DedupDb<D> {
+    /// Wraps a database.
+    pub fn new(inner: D) -> Self {
+        DedupDb { inner }
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Finds an existing paste that could be reused instead of storing a new one.
+    fn find_duplicate(&self, hash: &str, entry: &PasteEntry) -> Result<Option<PasteId>, D::Error> {
+        if !is_shareable(entry) {
+            return Ok(None);
+        }
+        let id = match self.inner.find_by_hash(hash)? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let existing = match self.inner.load_data(id)? {
+            Some(existing) => existing,
+            None => return Ok(None),
+        };
+        let lives_long_enough = match (existing.best_before, entry.best_before) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(existing), Some(requested)) => existing >= requested,
+        };
+        if lives_long_enough && is_shareable(&existing) && existing.data == entry.data
+           && existing.file_name == entry.file_name
+           && existing.mime_type == entry.mime_type
+           && (entry.language.is_none() || existing.language == entry.language)
+        {
+            Ok(Some(id))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Checks whether a paste could be given to anyone who uploads the same content.
+fn is_shareable(entry: &PasteEntry) -> bool {
+    entry.public && entry.views_left.is_none() && entry.deletion_token.is_none()
+    && entry.creator.is_none()
+}
+
+impl<D: DbInterface> DbInterface for DedupDb<D> {
+    type Error = D::Error;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        if entry.views_left.is_some() {
+            return self.inner.store_data(entry);
+        }
+        let hash = content_hash(&entry.data);
+        if let Some(id) = self.find_duplicate(&hash, &entry)? {
+            debug!("Paste {} has the same content, reusing it", id);
+            return Ok(id);
+        }
+        let id = self.inner.store_data(entry)?;
+        self.inner.store_hash(id, &hash)?;
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        let hash = content_hash(&entry.data);
+        self.inner.insert_data(id, entry)?;
+        self.inner.store_hash(id, &hash)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        // The ID is chosen by the caller, so an identical paste can't be reused.
+        let hash = content_hash(&entry.data);
+        if !self.inner.store_new(id, entry)? {
+            return Ok(false);
+        }
+        self.inner.store_hash(id, &hash)?;
+        Ok(true)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        self.inner.load_data(id)
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.inner.load_metadata(id)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_deletion_token(id)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let hash = content_hash(&data);
+        let outcome = self.inner.update_data(id, expected_revision, data, mime_type)?;
+        if let UpdateOutcome::Updated(_) = outcome {
+            self.inner.store_hash(id, &hash)?;
+        }
+        Ok(outcome)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.inner.soft_delete(id, deleted_at)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_expired(now)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.scrub_creators(created_before)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.remove_by_creator(creator, since)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.inner.remove_by_owner(token)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner.store_signature(id, signature)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list(offset, limit)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list_public(offset, limit)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check()
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats()
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.inner.max_data_size()
+    }
+}
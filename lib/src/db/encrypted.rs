@@ -0,0 +1,630 @@
+//! Encryption of the stored data.
+
+use super::WrapperError;
+use base64;
+use chrono::{DateTime, Utc};
+use ring::aead::{self, OpeningKey, SealingKey, AES_256_GCM};
+use ring::rand::{SecureRandom, SystemRandom};
+use secrets::{Rotating, Secret};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// Marks encrypted data that is not bound to a paste, as older versions stored it.
+const MAGIC: &[u8] = b"\0pbenc";
+
+/// Marks encrypted data that is bound to a paste, i.e. authenticated along with the paste ID.
+///
+/// It is as long as `MAGIC`, so both kinds of data share the same header layout.
+const BOUND_MAGIC: &[u8] = b"\0pbenb";
+
+/// Prefix of encrypted file names.
+const FILE_NAME_PREFIX: &str = "enc:";
+
+/// Length of a key ID in the header of encrypted data.
+const KEY_ID_LEN: usize = 4;
+
+/// AES-GCM nonce length.
+const NONCE_LEN: usize = 12;
+
+quick_error! {
+    /// Keys loading and encryption errors.
+    #[derive(Debug)]
+    pub enum KeyringError {
+        /// Can't read a keys file.
+        Io(err: io::Error) {
+            cause(err)
+            from()
+        }
+        /// The keys environment variable is not set.
+        NoEnv(name: String) {
+            description("Keys variable is not set")
+            display("Environment variable {} is not set", name)
+        }
+        /// Keys description is malformed.
+        Format(reason: String) {
+            description("Malformed keys")
+            display("Malformed keys: {}", reason)
+        }
+        /// No keys at all.
+        Empty {
+            description("No encryption keys provided")
+        }
+        /// Data is encrypted with a key that is not in the keyring.
+        UnknownKey(id: u32) {
+            description("Unknown encryption key")
+            display("Encryption key #{} is not known", id)
+        }
+        /// Encryption or decryption failure (including tampered data).
+        Crypto {
+            description("Encryption or decryption failed")
+        }
+        /// A paste is not encrypted, or it is not bound to its ID.
+        Unbound(id: PasteId) {
+            description("Paste is not bound to its ID")
+            display("Paste {} is not encrypted or not bound to its ID", id)
+        }
+    }
+}
+
+/// Encrypted fields of a paste.
+///
+/// A field tag is authenticated along with the paste ID, so encrypted data can't be moved to
+/// another paste, nor passed off as a file name (and vice versa).
+#[derive(Clone, Copy)]
+enum Field {
+    Data,
+    FileName,
+}
+
+impl Field {
+    fn tag(self) -> &'static [u8] {
+        match self {
+            Field::Data => b"data:",
+            Field::FileName => b"file_name:",
+        }
+    }
+}
+
+/// Builds the associated data that binds a field to a paste.
+fn associated_data(id: PasteId, field: Field) -> Vec<u8> {
+    let mut aad = field.tag().to_vec();
+    aad.extend((0..8).rev().map(|byte| (id.as_u64() >> (byte * 8)) as u8));
+    aad
+}
+
+struct Key {
+    sealing: SealingKey,
+    opening: OpeningKey,
+}
+
+impl Key {
+    fn new(bytes: &[u8]) -> Option<Self> {
+        Some(Key { sealing: SealingKey::new(&AES_256_GCM, bytes).ok()?,
+                   opening: OpeningKey::new(&AES_256_GCM, bytes).ok()?, })
+    }
+}
+
+/// A set of versioned encryption keys.
+///
+/// Every key has a numeric ID, which is stored alongside the encrypted data. New data is always
+/// encrypted with the key that has the highest ID, while older keys are only used for decryption.
+/// So to rotate keys just add a new one with a higher ID, and (optionally) once all the pastes
+/// are re-encrypted (see `EncryptedDb::reencrypt`) remove the old one. Mind that file names are
+/// only re-encrypted if they are not bound to their pastes yet, so if they are encrypted old keys
+/// have to be kept.
+///
+/// Keys are described in a text form: whitespace separated `<id>:<key>` entries, where `<key>` is a
+/// base64 encoded 32-byte AES-256 key. Everything after a `#` up to the end of a line is a comment.
+pub struct Keyring {
+    keys: BTreeMap<u32, Key>,
+    current: u32,
+    rng: SystemRandom,
+}
+
+impl Keyring {
+    /// Parses a textual keys description.
+    pub fn parse(text: &str) -> Result<Self, KeyringError> {
+        let mut keys = BTreeMap::new();
+        let entries = text.lines()
+                          .map(|line| line.split('#').next().unwrap_or(""))
+                          .flat_map(|line| line.split_whitespace());
+        for entry in entries {
+            let mut parts = entry.splitn(2, ':');
+            let id = parts.next()
+                          .and_then(|id| id.parse().ok())
+                          .ok_or_else(|| KeyringError::Format(format!("bad key ID in {}", entry)))?;
+            let bytes = parts.next()
+                             .and_then(|key| base64::decode(key).ok())
+                             .ok_or_else(|| KeyringError::Format(format!("bad key #{}", id)))?;
+            let key = Key::new(&bytes).ok_or_else(|| {
+                          KeyringError::Format(format!("key #{} is not 32 bytes long", id))
+                      })?;
+            if keys.insert(id, key).is_some() {
+                return Err(KeyringError::Format(format!("duplicate key #{}", id)));
+            }
+        }
+        let current = *keys.keys().next_back().ok_or(KeyringError::Empty)?;
+        Ok(Keyring { keys,
+                     current,
+                     rng: SystemRandom::new(), })
+    }
+
+    /// Loads keys from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, KeyringError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    /// Parses keys from a secret.
+    pub fn from_secret(secret: &Secret) -> Result<Self, KeyringError> {
+        Self::parse(secret.expose())
+    }
+
+    /// Loads keys from an environment variable.
+    pub fn from_env(name: &str) -> Result<Self, KeyringError> {
+        let text = env::var(name).map_err(|_| KeyringError::NoEnv(name.into()))?;
+        Self::parse(&text)
+    }
+
+    /// Returns the ID of the key that is used for encryption.
+    pub fn current_key_id(&self) -> u32 {
+        self.current
+    }
+
+    /// Encrypts a field of a paste with the current key, binding it to the paste.
+    ///
+    /// The output consists of a magic prefix, the key ID, a random nonce and the ciphertext
+    /// (including the authentication tag).
+    fn encrypt(&self, data: &[u8], id: PasteId, field: Field) -> Result<Vec<u8>, KeyringError> {
+        let key = &self.keys[&self.current].sealing;
+        let header_len = BOUND_MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+        let tag_len = key.algorithm().tag_len();
+        let mut output = Vec::with_capacity(header_len + data.len() + tag_len);
+        output.extend_from_slice(BOUND_MAGIC);
+        let aad = associated_data(id, field);
+        output.extend_from_slice(&u32_to_bytes(self.current));
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| KeyringError::Crypto)?;
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(data);
+        output.resize(header_len + data.len() + tag_len, 0);
+        let sealed = aead::seal_in_place(key, &nonce, &aad, &mut output[header_len..], tag_len)
+            .map_err(|_| KeyringError::Crypto)?;
+        output.truncate(header_len + sealed);
+        Ok(output)
+    }
+
+    /// Decrypts a field of a paste, unless it is not encrypted at all.
+    ///
+    /// Data that is bound to a paste fails to decrypt if it is stored under another one.
+    fn decrypt(&self,
+               mut data: Vec<u8>,
+               paste_id: PasteId,
+               field: Field)
+               -> Result<Vec<u8>, KeyringError> {
+        let id = match Self::key_id(&data) {
+            Some(id) => id,
+            None => return Ok(data),
+        };
+        let aad = if Self::is_bound(&data) {
+            associated_data(paste_id, field)
+        } else {
+            Vec::new()
+        };
+        let header_len = MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+        if data.len() < header_len {
+            return Err(KeyringError::Crypto);
+        }
+        let key = &self.keys.get(&id).ok_or(KeyringError::UnknownKey(id))?.opening;
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&data[MAGIC.len() + KEY_ID_LEN..header_len]);
+        let plain_len = aead::open_in_place(key, &nonce, &aad, header_len, &mut data)
+            .map_err(|_| KeyringError::Crypto)?
+            .len();
+        data.truncate(plain_len);
+        Ok(data)
+    }
+
+    /// Returns the ID of the key the data is encrypted with, if it is encrypted at all.
+    fn key_id(data: &[u8]) -> Option<u32> {
+        if data.len() < MAGIC.len() + KEY_ID_LEN ||
+           !(data.starts_with(MAGIC) || data.starts_with(BOUND_MAGIC))
+        {
+            return None;
+        }
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&data[MAGIC.len()..MAGIC.len() + KEY_ID_LEN]);
+        Some(u32_from_bytes(id))
+    }
+
+    /// Checks whether the encrypted data is bound to a paste.
+    fn is_bound(data: &[u8]) -> bool {
+        data.starts_with(BOUND_MAGIC)
+    }
+
+    /// Checks whether a file name is encrypted and bound to a paste.
+    fn is_file_name_bound(name: &str) -> bool {
+        name.starts_with(FILE_NAME_PREFIX) &&
+        base64::decode_config(&name[FILE_NAME_PREFIX.len()..], base64::URL_SAFE)
+            .map(|encrypted| Self::is_bound(&encrypted))
+            .unwrap_or(false)
+    }
+
+    fn encrypt_file_name(&self, name: &str, id: PasteId) -> Result<String, KeyringError> {
+        let encrypted = self.encrypt(name.as_bytes(), id, Field::FileName)?;
+        Ok(format!("{}{}",
+                   FILE_NAME_PREFIX,
+                   base64::encode_config(&encrypted, base64::URL_SAFE)))
+    }
+
+    fn decrypt_file_name(&self, name: String, id: PasteId) -> Result<String, KeyringError> {
+        if !name.starts_with(FILE_NAME_PREFIX) {
+            return Ok(name);
+        }
+        let encrypted = base64::decode_config(&name[FILE_NAME_PREFIX.len()..], base64::URL_SAFE)
+            .map_err(|e| KeyringError::Format(format!("bad file name: {}", e)))?;
+        String::from_utf8(self.decrypt(encrypted, id, Field::FileName)?)
+            .map_err(|e| KeyringError::Format(format!("bad file name: {}", e)))
+    }
+}
+
+fn u32_to_bytes(x: u32) -> [u8; 4] {
+    [(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]
+}
+
+fn u32_from_bytes(bytes: [u8; 4]) -> u32 {
+    bytes.iter().fold(0, |acc, &byte| (acc << 8) | u32::from(byte))
+}
+
+/// A decorator that encrypts pastes with AES-256-GCM using a server-side
+/// [Keyring](struct.Keyring.html).
+///
+/// The data (and, optionally, file names) is encrypted before it is passed to the wrapped
+/// database and decrypted upon loading. Mime types and expiration dates are stored as is.
+///
+/// Encrypted fields are bound to the paste ID, so they can't be swapped between pastes by someone
+/// who has access to the database. When the database issues the ID (`store_data`) it is not known
+/// beforehand, so an empty paste is stored first to get the ID, and then it is replaced with the
+/// encrypted one (`insert_data`), which the wrapped database has to support.
+///
+/// Pastes that are not encrypted or not bound to their IDs (stored before encryption has been
+/// enabled, or by older versions) fail to load, unless they are [accepted](#method.accept_legacy)
+/// until they are [re-encrypted](#method.reencrypt).
+///
+/// Since sizes and line counts are calculated by a database, `load_metadata` and `list` have to
+/// load and decrypt the data in order to report correct values. Storage statistics, on the
+/// contrary, reflect the encrypted sizes.
+///
+/// The keyring could be replaced on the fly through [keyring](#method.keyring), for example by
+/// [secrets::watch](../secrets/fn.watch.html) when the keys secret is rotated.
+pub struct EncryptedDb<D> {
+    inner: D,
+    keyring: Rotating<Keyring>,
+    encrypt_file_names: bool,
+    accept_legacy: bool,
+}
+
+impl<D: DbInterface> EncryptedDb<D> {
+    /// Wraps a database. File names are only encrypted if `encrypt_file_names` is set.
+    pub fn new(inner: D, keyring: Keyring, encrypt_file_names: bool) -> Self {
+        EncryptedDb { inner,
+                      keyring: Rotating::new(keyring),
+                      encrypt_file_names,
+                      accept_legacy: false, }
+    }
+
+    /// Sets whether pastes that are not encrypted or not bound to their IDs are loaded rather than
+    /// rejected. They are rejected by default.
+    pub fn accept_legacy(mut self, accept: bool) -> Self {
+        self.accept_legacy = accept;
+        self
+    }
+
+    /// Returns a handle to the keyring, which could be used to replace it.
+    ///
+    /// Mind that the new keyring has to keep all the keys that are still in use.
+    pub fn keyring(&self) -> Rotating<Keyring> {
+        self.keyring.clone()
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Re-encrypts a paste with the current key, unless it is already encrypted with it and bound
+    /// to the paste. Legacy pastes are re-encrypted no matter whether they are accepted.
+    ///
+    /// Returns `true` if the paste has been re-encrypted. Usually only the data is re-encrypted: a
+    /// paste that is updated meanwhile is left as it is (it is encrypted with the current key
+    /// anyway), and a re-encrypted one moves to the next revision. A file name that is not bound
+    /// to the paste can only be replaced along with the rest of the paste though, so views counted
+    /// meanwhile might be lost then.
+    pub fn reencrypt(&self, id: PasteId) -> Result<bool, WrapperError<D::Error>> {
+        let entry = match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        let file_name_bound = self.check_file_name(id, &entry).is_ok();
+        let current = Keyring::key_id(&entry.data) == Some(self.keyring.get().current);
+        if current && Keyring::is_bound(&entry.data) && file_name_bound {
+            return Ok(false);
+        }
+        let entry = self.decrypt_fields(id, entry)
+                        .map_err(WrapperError::wrapper)?;
+        if !file_name_bound {
+            self.insert_data(id, entry)?;
+            return Ok(true);
+        }
+        let outcome = self.update_data(id, Some(entry.revision), entry.data, entry.mime_type)?;
+        Ok(match outcome {
+               UpdateOutcome::Updated(_) => true,
+               UpdateOutcome::NotFound | UpdateOutcome::Conflict(_) => false,
+           })
+    }
+
+    /// Encrypts a file name, if enabled.
+    fn encrypt_file_name(&self,
+                         file_name: Option<String>,
+                         id: PasteId)
+                         -> Result<Option<String>, KeyringError> {
+        match file_name {
+            Some(ref name) if self.encrypt_file_names => {
+                Ok(Some(self.keyring.get().encrypt_file_name(name, id)?))
+            }
+            name => Ok(name),
+        }
+    }
+
+    /// Encrypts a paste that is about to be stored under the given ID.
+    fn encrypt_entry(&self,
+                     id: PasteId,
+                     mut entry: PasteEntry)
+                     -> Result<PasteEntry, KeyringError> {
+        entry.data = self.keyring
+                         .get()
+                         .encrypt(&entry.data, id, Field::Data)?;
+        entry.file_name = self.encrypt_file_name(entry.file_name, id)?;
+        Ok(entry)
+    }
+
+    /// Checks that a file name (if any) is encrypted and bound to the paste, unless file names
+    /// are not encrypted at all.
+    fn check_file_name(&self, id: PasteId, entry: &PasteEntry) -> Result<(), KeyringError> {
+        match entry.file_name {
+            Some(ref name) if Keyring::is_file_name_bound(name) => Ok(()),
+            Some(ref name) if self.encrypt_file_names || name.starts_with(FILE_NAME_PREFIX) => {
+                Err(KeyringError::Unbound(id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypts a loaded paste, rejecting a legacy one unless legacy pastes are accepted.
+    fn decrypt_entry(&self, id: PasteId, entry: PasteEntry) -> Result<PasteEntry, KeyringError> {
+        if !self.accept_legacy {
+            if !Keyring::is_bound(&entry.data) {
+                return Err(KeyringError::Unbound(id));
+            }
+            self.check_file_name(id, &entry)?;
+        }
+        self.decrypt_fields(id, entry)
+    }
+
+    /// Decrypts the fields of a paste, leaving the ones that are not encrypted as they are.
+    fn decrypt_fields(&self,
+                      id: PasteId,
+                      mut entry: PasteEntry)
+                      -> Result<PasteEntry, KeyringError> {
+        let keyring = self.keyring.get();
+        entry.data = keyring.decrypt(entry.data, id, Field::Data)?;
+        entry.file_name = match entry.file_name {
+            Some(name) => Some(keyring.decrypt_file_name(name, id)?),
+            None => None,
+        };
+        Ok(entry)
+    }
+
+    /// Loads a paste and computes its summary from the decrypted data.
+    fn load_summary(&self, id: PasteId) -> Result<Option<PasteSummary>, WrapperError<D::Error>> {
+        Ok(self.load_data(id)?
+               .map(|entry| PasteSummary::from_entry(id, &entry)))
+    }
+}
+
+impl<D: DbInterface> DbInterface for EncryptedDb<D> {
+    type Error = WrapperError<D::Error>;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        // The ID is not known yet, so it is obtained with an empty paste, which is replaced with
+        // the bound one right away. The empty paste is not bound, so it never loads.
+        let placeholder = PasteEntry { data: Vec::new(),
+                                       file_name: None,
+                                       ..entry.clone() };
+        let id = self.inner
+                     .store_data(placeholder)
+                     .map_err(WrapperError::Db)?;
+        let entry = PasteEntry { views: 0,
+                                 revision: 0,
+                                 ..entry };
+        if let Err(e) = self.insert_data(id, entry) {
+            if let Err(e) = self.inner.remove_data(id) {
+                error!("Can't remove the placeholder of paste {}: {}", id, e);
+            }
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        let entry = self.encrypt_entry(id, entry)
+                        .map_err(WrapperError::wrapper)?;
+        self.inner.insert_data(id, entry).map_err(WrapperError::Db)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        let entry = self.encrypt_entry(id, entry)
+                        .map_err(WrapperError::wrapper)?;
+        self.inner.store_new(id, entry).map_err(WrapperError::Db)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => Ok(Some(self.decrypt_entry(id, entry).map_err(WrapperError::wrapper)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.load_summary(id)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        // Tokens are stored as they are.
+        self.inner.load_deletion_token(id).map_err(WrapperError::Db)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let data = self.keyring
+                       .get()
+                       .encrypt(&data, id, Field::Data)
+                       .map_err(WrapperError::wrapper)?;
+        self.inner.update_data(id, expected_revision, data, mime_type).map_err(WrapperError::Db)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id).map_err(WrapperError::Db)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id).map_err(WrapperError::Db)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id).map_err(WrapperError::Db)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.inner.soft_delete(id, deleted_at).map_err(WrapperError::Db)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id).map_err(WrapperError::Db)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before).map_err(WrapperError::Db)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone).map_err(WrapperError::Db)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id).map_err(WrapperError::Db)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since).map_err(WrapperError::Db)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before).map_err(WrapperError::Db)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_expired(now).map_err(WrapperError::Db)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.scrub_creators(created_before).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.remove_by_creator(creator, since).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.inner.remove_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash).map_err(WrapperError::Db)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash).map_err(WrapperError::Db)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug).map_err(WrapperError::Db)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug).map_err(WrapperError::Db)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner
+            .store_signature(id, signature)
+            .map_err(WrapperError::Db)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id).map_err(WrapperError::Db)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_summary(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list_public(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_summary(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().map_err(WrapperError::Db)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats().map_err(WrapperError::Db)
+    }
+
+    fn max_data_size(&self) -> usize {
+        // Leave some room for the header and the authentication tag.
+        self.inner
+            .max_data_size()
+            .saturating_sub(MAGIC.len() + KEY_ID_LEN + NONCE_LEN + aead::MAX_TAG_LEN)
+    }
+}
@@ -0,0 +1,221 @@
+//! Measuring database calls.
+
+use chrono::{DateTime, Utc};
+use metrics::Metrics;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// A decorator that records every call to the wrapped database in a
+/// [Metrics](../metrics/struct.Metrics.html) registry: how many times each method has been
+/// called, how many of the calls have failed and how long they took.
+///
+/// Calls that take longer than a threshold are also logged (on the `warn` level) as slow
+/// queries. There is no threshold by default, it could be set for all the methods with
+/// [slow_query_threshold](#method.slow_query_threshold) and overridden for a particular method
+/// with [slow_query_threshold_for](#method.slow_query_threshold_for).
+///
+/// Put it right above the database connection to measure the database itself, or on top of the
+/// other decorators to measure them all together.
+pub struct InstrumentedDb<D> {
+    inner: D,
+    metrics: Metrics,
+    slow_query_threshold: Option<Duration>,
+    slow_query_thresholds: HashMap<&'static str, Duration>,
+}
+
+impl<D: DbInterface> InstrumentedDb<D> {
+    /// Wraps a database, the calls are recorded in `metrics`.
+    pub fn new(inner: D, metrics: Metrics) -> Self {
+        InstrumentedDb { inner,
+                         metrics,
+                         slow_query_threshold: None,
+                         slow_query_thresholds: HashMap::new(), }
+    }
+
+    /// Logs calls that take longer than `threshold`.
+    pub fn slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Logs calls of a method (like `"load_data"`) that take longer than `threshold`, no matter
+    /// what the threshold for the rest of the methods is.
+    pub fn slow_query_threshold_for(mut self, method: &'static str, threshold: Duration) -> Self {
+        self.slow_query_thresholds.insert(method, threshold);
+        self
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Runs a call of a method, measures and records it.
+    fn measure<T, F>(&self, method: &'static str, call: F) -> Result<T, D::Error>
+        where F: FnOnce() -> Result<T, D::Error>
+    {
+        let started = Instant::now();
+        let result = call();
+        let elapsed = started.elapsed();
+        self.metrics.record_db_call(method, elapsed, result.is_err());
+        let threshold = self.slow_query_thresholds
+                            .get(method)
+                            .or_else(|| self.slow_query_threshold.as_ref());
+        if threshold.map_or(false, |&threshold| elapsed > threshold) {
+            warn!("Slow database call: {} took {}.{:03} s{}",
+                  method,
+                  elapsed.as_secs(),
+                  elapsed.subsec_millis(),
+                  if result.is_err() { " and failed" } else { "" });
+        }
+        result
+    }
+}
+
+impl<D: DbInterface> DbInterface for InstrumentedDb<D> {
+    type Error = D::Error;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        self.measure("store_data", || {
+            self.inner.store_data(entry)
+        })
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        self.measure("insert_data", || self.inner.insert_data(id, entry))
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        self.measure("store_new", || self.inner.store_new(id, entry))
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        self.measure("load_data", || self.inner.load_data(id))
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.measure("load_metadata", || self.inner.load_metadata(id))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.measure("load_deletion_token", || self.inner.load_deletion_token(id))
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        self.measure("update_data",
+                     || self.inner.update_data(id, expected_revision, data, mime_type))
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.measure("increment_views", || self.inner.increment_views(id))
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.measure("take_view", || self.inner.take_view(id))
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.measure("remove_data", || self.inner.remove_data(id))
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.measure("soft_delete", || self.inner.soft_delete(id, deleted_at))
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.measure("restore", || self.inner.restore(id))
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.measure("purge_trash", || self.inner.purge_trash(deleted_before))
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.measure("store_tombstone", || self.inner.store_tombstone(tombstone))
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.measure("load_tombstone", || self.inner.load_tombstone(id))
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.measure("list_tombstones", || self.inner.list_tombstones(removed_since))
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.measure("purge_tombstones", || self.inner.purge_tombstones(removed_before))
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.measure("purge_expired", || self.inner.purge_expired(now))
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.measure("scrub_creators", || self.inner.scrub_creators(created_before))
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.measure("remove_by_creator", || self.inner.remove_by_creator(creator, since))
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.measure("remove_by_owner", || self.inner.remove_by_owner(token))
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.measure("list_by_owner", || self.inner.list_by_owner(token))
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.measure("store_hash", || self.inner.store_hash(id, hash))
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.measure("find_by_hash", || self.inner.find_by_hash(hash))
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.measure("store_slug", || self.inner.store_slug(id, slug))
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.measure("find_by_slug", || self.inner.find_by_slug(slug))
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.measure("store_signature", || self.inner.store_signature(id, signature))
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.measure("load_signature", || self.inner.load_signature(id))
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.measure("list", || self.inner.list(offset, limit))
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.measure("list_public", || self.inner.list_public(offset, limit))
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.measure("health_check", || self.inner.health_check())
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.measure("stats", || self.inner.stats())
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.inner.max_data_size()
+    }
+}
@@ -0,0 +1,475 @@
+//! A database that keeps everything in memory.
+
+use base64;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::sync::Mutex;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     Unsupported, UpdateOutcome};
+
+/// A transparent 1x1 PNG image.
+const SAMPLE_IMAGE: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAC\
+                            hwGA60e6kgAAAABJRU5ErkJggg==";
+
+/// A `MemoryDb` supports every operation, so it never fails.
+const SUPPORTED: &str = "a memory database never fails";
+
+/// An error of a `MemoryDb`, which never happens: it supports every operation.
+#[derive(Debug)]
+pub enum MemoryError {
+    /// An unsupported operation.
+    Unsupported(Unsupported),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MemoryError::Unsupported(ref err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl error::Error for MemoryError {
+    fn description(&self) -> &str {
+        match *self {
+            MemoryError::Unsupported(ref err) => err.description(),
+        }
+    }
+}
+
+impl From<Unsupported> for MemoryError {
+    fn from(err: Unsupported) -> Self {
+        MemoryError::Unsupported(err)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    pastes: BTreeMap<PasteId, PasteEntry>,
+    trash: HashMap<PasteId, (PasteEntry, DateTime<Utc>)>,
+    tombstones: HashMap<PasteId, Tombstone>,
+    hashes: HashMap<String, PasteId>,
+    slugs: HashMap<String, PasteId>,
+    signatures: HashMap<PasteId, String>,
+    next_id: u64,
+}
+
+impl State {
+    fn forget(&mut self, id: PasteId) {
+        self.signatures.remove(&id);
+        self.hashes.retain(|_, hashed| *hashed != id);
+        self.slugs.retain(|_, named| *named != id);
+    }
+
+    /// Removes the pastes that don't pass the `keep` check, returns the number of removed ones.
+    fn retain<F>(&mut self, mut keep: F) -> u64
+        where F: FnMut(&PasteEntry) -> bool
+    {
+        let removed: Vec<_> = self.pastes
+                                  .iter()
+                                  .filter(|&(_, entry)| !keep(entry))
+                                  .map(|(&id, _)| id)
+                                  .collect();
+        for &id in &removed {
+            self.pastes.remove(&id);
+            self.forget(id);
+        }
+        removed.len() as u64
+    }
+}
+
+/// A database that keeps pastes in memory, so they are gone once it is dropped.
+///
+/// It needs no setup at all, which makes it handy for trying the service out, for previews of
+/// templates (see the `--preview` flag of the server) and for tests. IDs are issued sequentially.
+#[derive(Default)]
+pub struct MemoryDb {
+    state: Mutex<State>,
+}
+
+impl MemoryDb {
+    /// Creates an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a database with a sample paste of every kind the pages tell apart: plain text,
+    /// code, an image, a binary file, an expiring paste, and a removed one (only its tombstone is
+    /// left). Returns the database along with the IDs of the samples and their descriptions.
+    ///
+    /// The samples are public, so they are listed at `/browse` as well.
+    pub fn with_samples() -> (Self, Vec<(PasteId, &'static str)>) {
+        let db = Self::new();
+        let now = Utc::now();
+        let yesterday = now - Duration::days(1);
+        let store = |data: Vec<u8>,
+                         file_name: Option<&str>,
+                         mime_type: &str,
+                         best_before: Option<DateTime<Utc>>| {
+            let mime_type = MimeType::new(mime_type).expect("a valid sample mime type");
+            let entry = PasteEntry { data,
+                                     file_name: file_name.map(String::from),
+                                     mime_type,
+                                     created_at: yesterday,
+                                     best_before,
+                                     views: 0,
+                                     creator: None,
+                                     deletion_token: None,
+                                     language: None,
+                                     revision: 0,
+                                     views_left: None,
+                                     public: true, };
+            let id = db.store_data(entry).expect(SUPPORTED);
+            db.increment_views(id).expect(SUPPORTED);
+            id
+        };
+        let text = "Shopping list:\n\n* milk\n* bread <organic>\n* \"good\" coffee & tea\n";
+        let code = "use std::env;\n\nfn main() {\n    for arg in env::args().skip(1) {\n        \
+                    println!(\"Hello, {}!\", arg);\n    }\n}\n";
+        let image = base64::decode(SAMPLE_IMAGE).expect("a valid sample image");
+        let bytes = (0..256).map(|byte| byte as u8).collect();
+        let mut samples = vec![(store(text.into(), None, "text/plain", None), "plain text"),
+                               (store(code.into(), Some("main.rs"), "text/x-rust", None), "code"),
+                               (store(image, Some("pixel.png"), "image/png", None), "an image"),
+                               (store(bytes, Some("bytes.bin"), "application/octet-stream", None),
+                                "a binary file"),
+                               (store(text.into(),
+                                      None,
+                                      "text/plain",
+                                      Some(now + Duration::hours(1))),
+                                "an expiring paste")];
+        let removed = store(text.into(), None, "text/plain", None);
+        db.remove_data(removed).expect(SUPPORTED);
+        db.store_tombstone(Tombstone { id: removed,
+                                       removed_at: now,
+                                       actor: "uploader".into(), })
+          .expect(SUPPORTED);
+        samples.push((removed, "a removed paste"));
+        (db, samples)
+    }
+}
+
+impl fmt::Debug for MemoryDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("MemoryDb")
+         .field("pastes", &state.pastes.len())
+         .field("trash", &state.trash.len())
+         .finish()
+    }
+}
+
+impl DbInterface for MemoryDb {
+    type Error = MemoryError;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let id = PasteId::new(state.next_id);
+        state.next_id += 1;
+        state.pastes.insert(id,
+                            PasteEntry { views: 0,
+                                         revision: 0,
+                                         ..entry });
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        // Never issue the ID of an inserted paste later.
+        state.next_id = state.next_id.max(id.as_u64().saturating_add(1));
+        state.pastes.insert(id, entry);
+        Ok(())
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.pastes.contains_key(&id) || state.trash.contains_key(&id) ||
+           state.tombstones.contains_key(&id)
+        {
+            return Ok(false);
+        }
+        state.pastes.insert(id, entry);
+        Ok(true)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        Ok(self.state.lock().unwrap().pastes.get(&id).cloned())
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .pastes
+               .get(&id)
+               .map(|entry| PasteSummary::from_entry(id, entry)))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .pastes
+               .get(&id)
+               .and_then(|entry| entry.deletion_token.clone()))
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        Ok(match self.state.lock().unwrap().pastes.get_mut(&id) {
+               Some(ref entry) if expected_revision.map_or(false, |rev| rev != entry.revision) => {
+                   UpdateOutcome::Conflict(entry.revision)
+               }
+               Some(entry) => {
+                   entry.data = data;
+                   entry.mime_type = mime_type;
+                   entry.revision += 1;
+                   UpdateOutcome::Updated(entry.revision)
+               }
+               None => UpdateOutcome::NotFound,
+           })
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        if let Some(entry) = self.state.lock().unwrap().pastes.get_mut(&id) {
+            entry.views += 1;
+        }
+        Ok(())
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let views_left = state.pastes
+                              .get_mut(&id)
+                              .and_then(|entry| entry.views_left.as_mut());
+        Ok(match views_left {
+               Some(views_left) if *views_left > 0 => {
+                   *views_left -= 1;
+                   Some(*views_left)
+               }
+               _ => None,
+           })
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.pastes.remove(&id);
+        state.trash.remove(&id);
+        state.forget(id);
+        Ok(())
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        Ok(match state.pastes.remove(&id) {
+               Some(entry) => {
+                   state.trash.insert(id, (entry, deleted_at));
+                   true
+               }
+               None => false,
+           })
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        Ok(match state.trash.remove(&id) {
+               Some((entry, _)) => {
+                   state.pastes.insert(id, entry);
+                   true
+               }
+               None => false,
+           })
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let purged: Vec<_> = state.trash
+                                  .iter()
+                                  .filter(|&(_, &(_, deleted_at))| deleted_at < deleted_before)
+                                  .map(|(&id, _)| id)
+                                  .collect();
+        for &id in &purged {
+            state.trash.remove(&id);
+            state.forget(id);
+        }
+        Ok(purged.len() as u64)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .tombstones
+            .insert(tombstone.id, tombstone);
+        Ok(())
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        Ok(self.state.lock().unwrap().tombstones.get(&id).cloned())
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        let mut tombstones = self.state
+                                 .lock()
+                                 .unwrap()
+                                 .tombstones
+                                 .values()
+                                 .filter(|tombstone| tombstone.removed_at >= removed_since)
+                                 .cloned()
+                                 .collect::<Vec<_>>();
+        tombstones.sort_by(|a, b| b.removed_at.cmp(&a.removed_at));
+        Ok(tombstones)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.tombstones.len();
+        state.tombstones
+             .retain(|_, tombstone| tombstone.removed_at >= removed_before);
+        Ok((before - state.tombstones.len()) as u64)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .retain(|entry| entry.best_before.map_or(true, |date| date >= now)))
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let mut scrubbed = 0;
+        for entry in self.state.lock().unwrap().pastes.values_mut() {
+            if entry.created_at < created_before && entry.creator.take().is_some() {
+                scrubbed += 1;
+            }
+        }
+        Ok(scrubbed)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .retain(|entry| {
+                           entry.created_at < since
+                           || entry.creator.as_ref().map(String::as_str) != Some(creator)
+                       }))
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        let owned = |entry: &PasteEntry| {
+            entry.deletion_token.as_ref().map(String::as_str) == Some(token)
+        };
+        let mut state = self.state.lock().unwrap();
+        let trashed: Vec<_> = state.trash
+                                   .iter()
+                                   .filter(|&(_, trashed)| owned(&trashed.0))
+                                   .map(|(&id, _)| id)
+                                   .collect();
+        for &id in &trashed {
+            state.trash.remove(&id);
+            state.forget(id);
+        }
+        Ok(state.retain(|entry| !owned(entry)) + trashed.len() as u64)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .pastes
+               .iter()
+               .filter(|&(_, entry)| {
+                           entry.deletion_token.as_ref().map(String::as_str) == Some(token)
+                       })
+               .map(|(&id, _)| id)
+               .collect())
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .hashes
+            .insert(hash.to_string(), id);
+        Ok(())
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        Ok(self.state.lock().unwrap().hashes.get(hash).cloned())
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let owner = *state.slugs.entry(slug.to_string()).or_insert(id);
+        Ok(owner == id)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        Ok(self.state.lock().unwrap().slugs.get(slug).cloned())
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .signatures
+            .insert(id, signature.to_string());
+        Ok(())
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        Ok(self.state.lock().unwrap().signatures.get(&id).cloned())
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        Ok(self.state
+               .lock()
+               .unwrap()
+               .pastes
+               .iter()
+               .skip(offset)
+               .take(limit)
+               .map(|(&id, entry)| PasteSummary::from_entry(id, entry))
+               .collect())
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let mut public: Vec<_> = state.pastes
+                                      .iter()
+                                      .filter(|&(_, entry)| entry.public)
+                                      .collect();
+        public.sort_by(|a, b| (b.1.created_at, b.0).cmp(&(a.1.created_at, a.0)));
+        Ok(public.into_iter()
+                 .skip(offset)
+                 .take(limit)
+                 .map(|(&id, entry)| PasteSummary::from_entry(id, entry))
+                 .collect())
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(StorageStats { pastes: state.pastes.len() as u64,
+                          total_bytes: state.pastes
+                                            .values()
+                                            .map(|entry| entry.data.len() as u64)
+                                            .sum(), })
+    }
+
+    fn max_data_size(&self) -> usize {
+        usize::max_value()
+    }
+}
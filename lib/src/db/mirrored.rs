@@ -0,0 +1,448 @@
+//! Writing pastes to two databases at once.
+
+use chrono::{DateTime, Utc};
+use std::cmp;
+use std::error;
+use std::fmt;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     Unsupported, UpdateOutcome};
+
+/// An error of a [MirroredDb](struct.MirroredDb.html).
+#[derive(Debug)]
+pub enum MirrorError<P, S> {
+    /// An error reported by the primary database.
+    Primary(P),
+    /// An error reported by the secondary database.
+    Secondary(S),
+}
+
+impl<P: fmt::Display, S: fmt::Display> fmt::Display for MirrorError<P, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MirrorError::Primary(ref err) => write!(f, "Primary database error: {}", err),
+            MirrorError::Secondary(ref err) => write!(f, "Secondary database error: {}", err),
+        }
+    }
+}
+
+impl<P: error::Error, S: error::Error> error::Error for MirrorError<P, S> {
+    fn description(&self) -> &str {
+        match *self {
+            MirrorError::Primary(_) => "primary database error",
+            MirrorError::Secondary(_) => "secondary database error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            MirrorError::Primary(ref err) => Some(err),
+            MirrorError::Secondary(ref err) => Some(err),
+        }
+    }
+}
+
+impl<P: From<Unsupported>, S> From<Unsupported> for MirrorError<P, S> {
+    fn from(err: Unsupported) -> Self {
+        MirrorError::Primary(err.into())
+    }
+}
+
+/// A decorator that writes pastes to two databases, reading them from the primary one and falling
+/// back to the secondary one.
+///
+/// This makes it possible to migrate to another database without a downtime: put the new database
+/// as the primary one and the old one as the secondary, copy the old pastes over in the background
+/// (with `DbInterface::insert_data`), then drop the old database. Or just keep a hot copy of the
+/// pastes around.
+///
+/// New pastes get IDs from the primary database and are stored under the same IDs in the
+/// secondary one. Failures to write to the secondary database are logged but otherwise ignored,
+/// while removals (and moves to the trash) have to succeed on both databases, otherwise removed
+/// pastes would come back from the secondary one. Listing and statistics only take the primary
+/// database into account.
+pub struct MirroredDb<P, S> {
+    primary: P,
+    secondary: S,
+}
+
+impl<P: DbInterface, S: DbInterface> MirroredDb<P, S> {
+    /// Mirrors writes to the `primary` database into the `secondary` one.
+    pub fn new(primary: P, secondary: S) -> Self {
+        MirroredDb { primary, secondary }
+    }
+
+    /// Returns a reference to the primary database.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns a reference to the secondary database.
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+
+    /// Reads something from the primary database, falling back to the secondary one if it is not
+    /// found there or if the primary database fails.
+    fn read<T, FP, FS>(&self,
+                       id: PasteId,
+                       primary: FP,
+                       secondary: FS)
+                       -> Result<Option<T>, MirrorError<P::Error, S::Error>>
+        where FP: FnOnce(&P) -> Result<Option<T>, P::Error>,
+              FS: FnOnce(&S) -> Result<Option<T>, S::Error>
+    {
+        match primary(&self.primary) {
+            Ok(Some(found)) => Ok(Some(found)),
+            Ok(None) => secondary(&self.secondary).map_err(MirrorError::Secondary),
+            Err(e) => {
+                warn!("Primary database failed to load paste {}, trying the secondary one: {}",
+                      id,
+                      e);
+                match secondary(&self.secondary) {
+                    Ok(Some(found)) => Ok(Some(found)),
+                    _ => Err(MirrorError::Primary(e)),
+                }
+            }
+        }
+    }
+}
+
+impl<P: DbInterface, S: DbInterface> DbInterface for MirroredDb<P, S> {
+    type Error = MirrorError<P::Error, S::Error>;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        let entry = PasteEntry { views: 0,
+                                 revision: 0,
+                                 ..entry };
+        let id = self.primary
+                     .store_data(entry.clone())
+                     .map_err(MirrorError::Primary)?;
+        if let Err(e) = self.secondary.insert_data(id, entry) {
+            error!("Can't mirror paste {} to the secondary database: {}", id, e);
+        }
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        self.primary
+            .insert_data(id, entry.clone())
+            .map_err(MirrorError::Primary)?;
+        if let Err(e) = self.secondary.insert_data(id, entry) {
+            error!("Can't mirror paste {} to the secondary database: {}", id, e);
+        }
+        Ok(())
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        // The primary database decides whether the ID is taken, the mirror just follows.
+        if !self.primary
+                .store_new(id, entry.clone())
+                .map_err(MirrorError::Primary)?
+        {
+            return Ok(false);
+        }
+        if let Err(e) = self.secondary.insert_data(id, entry) {
+            error!("Can't mirror paste {} to the secondary database: {}", id, e);
+        }
+        Ok(true)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        self.read(id, |db| db.load_data(id), |db| db.load_data(id))
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.read(id, |db| db.load_metadata(id), |db| db.load_metadata(id))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.read(id, |db| db.load_deletion_token(id), |db| db.load_deletion_token(id))
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let in_primary = self.primary
+                             .update_data(id, expected_revision, data.clone(), mime_type.clone())
+                             .map_err(MirrorError::Primary)?;
+        match in_primary {
+            UpdateOutcome::Updated(_) => {
+                // The revision has been checked by the primary database, which is the one that
+                // counts, so the secondary one just follows.
+                if let Err(e) = self.secondary.update_data(id, None, data, mime_type) {
+                    error!("Can't mirror an update of paste {} to the secondary database: {}",
+                           id,
+                           e);
+                }
+                Ok(in_primary)
+            }
+            UpdateOutcome::Conflict(_) => Ok(in_primary),
+            // A paste that hasn't been copied to the primary database yet is still updated in
+            // the secondary one, so readers don't get stale data.
+            UpdateOutcome::NotFound => {
+                self.secondary
+                    .update_data(id, expected_revision, data, mime_type)
+                    .map_err(MirrorError::Secondary)
+            }
+        }
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.primary
+            .increment_views(id)
+            .map_err(MirrorError::Primary)?;
+        if let Err(e) = self.secondary.increment_views(id) {
+            error!("Can't mirror views of paste {} to the secondary database: {}", id, e);
+        }
+        Ok(())
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        // The primary database decides whether there is a view left, the secondary one follows.
+        let views_left = self.primary
+                             .take_view(id)
+                             .map_err(MirrorError::Primary)?;
+        if views_left.is_some() {
+            if let Err(e) = self.secondary.take_view(id) {
+                error!("Can't mirror a view of paste {} to the secondary database: {}", id, e);
+            }
+        }
+        Ok(views_left)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.primary
+            .remove_data(id)
+            .map_err(MirrorError::Primary)?;
+        self.secondary
+            .remove_data(id)
+            .map_err(MirrorError::Secondary)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        // Same as with removals, otherwise trashed pastes would be read from the secondary
+        // database.
+        let primary = self.primary
+                          .soft_delete(id, deleted_at)
+                          .map_err(MirrorError::Primary)?;
+        let secondary = self.secondary
+                            .soft_delete(id, deleted_at)
+                            .map_err(MirrorError::Secondary)?;
+        Ok(primary || secondary)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        let primary = self.primary
+                          .restore(id)
+                          .map_err(MirrorError::Primary)?;
+        let secondary = self.secondary
+                            .restore(id)
+                            .map_err(MirrorError::Secondary)?;
+        Ok(primary || secondary)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let purged = self.primary
+                         .purge_trash(deleted_before)
+                         .map_err(MirrorError::Primary)?;
+        self.secondary
+            .purge_trash(deleted_before)
+            .map_err(MirrorError::Secondary)?;
+        Ok(purged)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.primary
+            .store_tombstone(tombstone.clone())
+            .map_err(MirrorError::Primary)?;
+        let id = tombstone.id;
+        if let Err(e) = self.secondary.store_tombstone(tombstone) {
+            error!("Can't mirror a tombstone of paste {} to the secondary database: {}", id, e);
+        }
+        Ok(())
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        match self.primary
+                  .load_tombstone(id)
+                  .map_err(MirrorError::Primary)?
+        {
+            Some(tombstone) => Ok(Some(tombstone)),
+            None => self.secondary
+                        .load_tombstone(id)
+                        .map_err(MirrorError::Secondary),
+        }
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.primary
+            .list_tombstones(removed_since)
+            .map_err(MirrorError::Primary)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let purged = self.primary
+                         .purge_tombstones(removed_before)
+                         .map_err(MirrorError::Primary)?;
+        self.secondary
+            .purge_tombstones(removed_before)
+            .map_err(MirrorError::Secondary)?;
+        Ok(purged)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let purged = self.primary
+                         .purge_expired(now)
+                         .map_err(MirrorError::Primary)?;
+        self.secondary
+            .purge_expired(now)
+            .map_err(MirrorError::Secondary)?;
+        Ok(purged)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // Unlike with expired pastes, the secondary database has to be scrubbed as well since
+        // pastes are read from it.
+        let scrubbed = self.primary
+                           .scrub_creators(created_before)
+                           .map_err(MirrorError::Primary)?;
+        self.secondary
+            .scrub_creators(created_before)
+            .map_err(MirrorError::Secondary)?;
+        Ok(scrubbed)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let removed = self.primary
+                          .remove_by_creator(creator, since)
+                          .map_err(MirrorError::Primary)?;
+        self.secondary
+            .remove_by_creator(creator, since)
+            .map_err(MirrorError::Secondary)?;
+        Ok(removed)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        let removed = self.primary
+                          .remove_by_owner(token)
+                          .map_err(MirrorError::Primary)?;
+        self.secondary
+            .remove_by_owner(token)
+            .map_err(MirrorError::Secondary)?;
+        Ok(removed)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.primary
+            .list_by_owner(token)
+            .map_err(MirrorError::Primary)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.primary
+            .store_hash(id, hash)
+            .map_err(MirrorError::Primary)?;
+        if let Err(e) = self.secondary.store_hash(id, hash) {
+            error!("Can't mirror a hash of paste {} to the secondary database: {}", id, e);
+        }
+        Ok(())
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        match self.primary
+                  .find_by_hash(hash)
+                  .map_err(MirrorError::Primary)?
+        {
+            Some(id) => Ok(Some(id)),
+            None => self.secondary
+                        .find_by_hash(hash)
+                        .map_err(MirrorError::Secondary),
+        }
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        // The primary database decides who gets the slug.
+        if !self.primary
+                .store_slug(id, slug)
+                .map_err(MirrorError::Primary)?
+        {
+            return Ok(false);
+        }
+        match self.secondary.store_slug(id, slug) {
+            Ok(true) => {}
+            Ok(false) => warn!("Slug {} of paste {} is taken in the secondary database", slug, id),
+            Err(e) => {
+                error!("Can't mirror a slug of paste {} to the secondary database: {}", id, e)
+            }
+        }
+        Ok(true)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        match self.primary
+                  .find_by_slug(slug)
+                  .map_err(MirrorError::Primary)?
+        {
+            Some(id) => Ok(Some(id)),
+            None => self.secondary
+                        .find_by_slug(slug)
+                        .map_err(MirrorError::Secondary),
+        }
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.primary
+            .store_signature(id, signature)
+            .map_err(MirrorError::Primary)?;
+        if let Err(e) = self.secondary.store_signature(id, signature) {
+            error!("Can't mirror a signature of paste {} to the secondary database: {}", id, e);
+        }
+        Ok(())
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        match self.primary
+                  .load_signature(id)
+                  .map_err(MirrorError::Primary)?
+        {
+            Some(signature) => Ok(Some(signature)),
+            None => self.secondary
+                        .load_signature(id)
+                        .map_err(MirrorError::Secondary),
+        }
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.primary
+            .list(offset, limit)
+            .map_err(MirrorError::Primary)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.primary
+            .list_public(offset, limit)
+            .map_err(MirrorError::Primary)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        if let Err(e) = self.secondary.health_check() {
+            warn!("Secondary database is unhealthy: {}", e);
+        }
+        self.primary.health_check().map_err(MirrorError::Primary)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.primary.stats().map_err(MirrorError::Primary)
+    }
+
+    fn max_data_size(&self) -> usize {
+        // Pastes have to fit into both databases.
+        cmp::min(self.primary.max_data_size(),
+                 self.secondary.max_data_size())
+    }
+}
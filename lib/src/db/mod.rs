@@ -0,0 +1,438 @@
+//! Database decorators, and a database that keeps pastes in memory ([MemoryDb]
+//! (struct.MemoryDb.html)).
+//!
+//! A decorator wraps any `DbInterface` implementation and implements `DbInterface` itself, adding
+//! some feature on top of the wrapped database. Decorators can be stacked, and the result can be
+//! passed to [PastebinBuilder](../web/struct.PastebinBuilder.html) just like a plain database.
+//!
+//! Most of the decorators are gated behind cargo features, see the crate-level documentation.
+//!
+//! Every decorator has its own type, so when decorators are chosen at runtime (say, by command
+//! line flags) it is handy to erase the type with [boxed](fn.boxed.html):
+//!
+//! ```
+//! # extern crate pastebin;
+//! # use pastebin::DbInterface;
+//! use pastebin::db::{self, BoxedDb};
+//!
+//! # fn wrap<Db: DbInterface + 'static>(plain_db: Db) -> BoxedDb {
+//! let db: BoxedDb = db::boxed(plain_db);
+//! // if compression_enabled { db = db::boxed(db::CompressedDb::new(db)) }
+//! # db
+//! # }
+//! # fn main() {}
+//! ```
+
+mod cached;
+#[cfg(feature = "compression")]
+mod compressed;
+#[cfg(feature = "dedup")]
+mod dedup;
+#[cfg(feature = "encryption")]
+mod encrypted;
+#[cfg(feature = "metrics")]
+mod instrumented;
+mod memory;
+mod mirrored;
+mod retrying;
+#[cfg(feature = "signing")]
+mod signed;
+mod split;
+
+pub use self::cached::CachedDb;
+#[cfg(feature = "compression")]
+pub use self::compressed::CompressedDb;
+#[cfg(feature = "dedup")]
+pub use self::dedup::{content_hash, DedupDb};
+#[cfg(feature = "encryption")]
+pub use self::encrypted::{EncryptedDb, Keyring, KeyringError};
+#[cfg(feature = "metrics")]
+pub use self::instrumented::InstrumentedDb;
+pub use self::memory::{MemoryDb, MemoryError};
+pub use self::mirrored::{MirrorError, MirroredDb};
+pub use self::retrying::RetryingDb;
+#[cfg(feature = "signing")]
+pub use self::signed::{signed_message, verify_signature, SignedDb, SigningError, SigningKey};
+pub use self::split::{BlobStore, FsBlobStore, SplitDb};
+
+use chrono::{DateTime, Utc};
+use std::error;
+use std::fmt;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     Unsupported, UpdateOutcome};
+
+/// A database with an erased type, see [boxed](fn.boxed.html).
+pub type BoxedDb = Box<DbInterface<Error = DynError>>;
+
+/// An error of a database with an erased type.
+#[derive(Debug)]
+pub struct DynError(pub Box<error::Error + Send + Sync>);
+
+impl fmt::Display for DynError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl error::Error for DynError {
+    fn description(&self) -> &str {
+        self.0.description()
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        self.0.cause()
+    }
+}
+
+impl From<Unsupported> for DynError {
+    fn from(err: Unsupported) -> Self {
+        erase(err)
+    }
+}
+
+/// Erases the type of a database (including its error type).
+pub fn boxed<D: DbInterface + 'static>(db: D) -> BoxedDb {
+    Box::new(Erased(db))
+}
+
+/// Converts errors of a database into `DynError`.
+struct Erased<D>(D);
+
+fn erase<E: error::Error + Send + Sync + 'static>(err: E) -> DynError {
+    DynError(Box::new(err))
+}
+
+impl<D: DbInterface> DbInterface for Erased<D> {
+    type Error = DynError;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        self.0.store_data(entry).map_err(erase)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        self.0.insert_data(id, entry).map_err(erase)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        self.0.store_new(id, entry).map_err(erase)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        self.0.load_data(id).map_err(erase)
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.0.load_metadata(id).map_err(erase)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.0.load_deletion_token(id).map_err(erase)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        self.0.update_data(id, expected_revision, data, mime_type).map_err(erase)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.0.increment_views(id).map_err(erase)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.0.take_view(id).map_err(erase)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.0.remove_data(id).map_err(erase)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.0.soft_delete(id, deleted_at).map_err(erase)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.0.restore(id).map_err(erase)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.0.purge_trash(deleted_before).map_err(erase)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.0.store_tombstone(tombstone).map_err(erase)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.0.load_tombstone(id).map_err(erase)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.0.list_tombstones(removed_since).map_err(erase)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.0.purge_tombstones(removed_before).map_err(erase)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.0.purge_expired(now).map_err(erase)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.0.scrub_creators(created_before).map_err(erase)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.0.remove_by_creator(creator, since).map_err(erase)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.0.remove_by_owner(token).map_err(erase)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.0.list_by_owner(token).map_err(erase)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.0.store_hash(id, hash).map_err(erase)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.0.find_by_hash(hash).map_err(erase)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.0.store_slug(id, slug).map_err(erase)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.0.find_by_slug(slug).map_err(erase)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.0.store_signature(id, signature).map_err(erase)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.0.load_signature(id).map_err(erase)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.0.list(offset, limit).map_err(erase)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.0.list_public(offset, limit).map_err(erase)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.0.health_check().map_err(erase)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.0.stats().map_err(erase)
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.0.max_data_size()
+    }
+}
+
+impl<D: DbInterface + ?Sized> DbInterface for Box<D> {
+    type Error = D::Error;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        (**self).store_data(entry)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        (**self).insert_data(id, entry)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        (**self).store_new(id, entry)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        (**self).load_data(id)
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        (**self).load_metadata(id)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        (**self).load_deletion_token(id)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        (**self).update_data(id, expected_revision, data, mime_type)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        (**self).increment_views(id)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        (**self).take_view(id)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        (**self).remove_data(id)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        (**self).soft_delete(id, deleted_at)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        (**self).restore(id)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        (**self).purge_trash(deleted_before)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        (**self).store_tombstone(tombstone)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        (**self).load_tombstone(id)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        (**self).list_tombstones(removed_since)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        (**self).purge_tombstones(removed_before)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        (**self).purge_expired(now)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        (**self).scrub_creators(created_before)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        (**self).remove_by_creator(creator, since)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        (**self).remove_by_owner(token)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        (**self).list_by_owner(token)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        (**self).store_hash(id, hash)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        (**self).find_by_hash(hash)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        (**self).store_slug(id, slug)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        (**self).find_by_slug(slug)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        (**self).store_signature(id, signature)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        (**self).load_signature(id)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        (**self).list(offset, limit)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        (**self).list_public(offset, limit)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        (**self).health_check()
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        (**self).stats()
+    }
+
+    fn max_data_size(&self) -> usize {
+        (**self).max_data_size()
+    }
+}
+
+/// An error of a database decorator.
+#[derive(Debug)]
+pub enum WrapperError<E> {
+    /// An error reported by the wrapped database.
+    Db(E),
+    /// A failure of the decorator itself.
+    Wrapper(Box<error::Error + Send + Sync>),
+}
+
+impl<E> WrapperError<E> {
+    /// Creates a decorator's own error.
+    pub fn wrapper<T>(err: T) -> Self
+        where T: Into<Box<error::Error + Send + Sync>>
+    {
+        WrapperError::Wrapper(err.into())
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for WrapperError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WrapperError::Db(ref err) => write!(f, "Database error: {}", err),
+            WrapperError::Wrapper(ref err) => write!(f, "Database decorator error: {}", err),
+        }
+    }
+}
+
+impl<E: error::Error> error::Error for WrapperError<E> {
+    fn description(&self) -> &str {
+        match *self {
+            WrapperError::Db(_) => "database error",
+            WrapperError::Wrapper(_) => "database decorator error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            WrapperError::Db(ref err) => Some(err),
+            WrapperError::Wrapper(ref err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl<E: From<Unsupported>> From<Unsupported> for WrapperError<E> {
+    fn from(err: Unsupported) -> Self {
+        WrapperError::Db(err.into())
+    }
+}
@@ -0,0 +1,243 @@
+//! Retrying of failed database requests.
+
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use std::cmp;
+use std::thread;
+use std::time::Duration;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// A predicate that tells transient errors from the permanent ones.
+type TransientCheck<E> = Box<Fn(&E) -> bool + Send + Sync>;
+
+/// A decorator that retries storing and loading of pastes when the wrapped database fails.
+///
+/// A brief outage (like a MongoDB replica set electing a new primary) would otherwise turn into
+/// errors for the users. A failed request is repeated up to the configured number of attempts,
+/// with exponentially growing delays between them (`base_delay`, twice as much, four times as
+/// much... but no more than `max_delay`). Each delay is picked at random below that bound, so
+/// the clients of a recovering database don't come back all at once.
+///
+/// Only `store_data`, `load_data` and `load_metadata` are retried, the rest of the requests fail
+/// right away. By default every error is considered transient, see
+/// [retry_if](#method.retry_if). Mind that a paste might end up stored twice if the database has
+/// stored it but failed to report that.
+pub struct RetryingDb<D: DbInterface> {
+    inner: D,
+    attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    is_transient: TransientCheck<D::Error>,
+}
+
+impl<D: DbInterface> RetryingDb<D> {
+    /// Wraps a database. A request is tried 3 times, with delays starting at 50 milliseconds and
+    /// up to a second.
+    pub fn new(inner: D) -> Self {
+        RetryingDb { inner,
+                     attempts: 3,
+                     base_delay: Duration::from_millis(50),
+                     max_delay: Duration::from_secs(1),
+                     is_transient: Box::new(|_| true), }
+    }
+
+    /// Sets the number of attempts, including the first one. Zero is treated as one.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = cmp::max(attempts, 1);
+        self
+    }
+
+    /// Sets the bounds of delays between attempts.
+    pub fn delays(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Only retries the errors `is_transient` returns `true` for.
+    pub fn retry_if<F>(mut self, is_transient: F) -> Self
+        where F: Fn(&D::Error) -> bool + Send + Sync + 'static
+    {
+        self.is_transient = Box::new(is_transient);
+        self
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Picks a delay before the given retry (starting from zero).
+    fn delay(&self, retry: u32) -> Duration {
+        let bound = self.base_delay
+                        .checked_mul(1 << cmp::min(retry, 16))
+                        .map_or(self.max_delay, |delay| cmp::min(delay, self.max_delay));
+        let millis = bound.as_secs() * 1000 + u64::from(bound.subsec_millis());
+        Duration::from_millis(thread_rng().gen_range(0, millis + 1))
+    }
+
+    /// Runs a request until it succeeds, fails permanently or runs out of attempts.
+    fn retry<T, F>(&self, what: &str, request: F) -> Result<T, D::Error>
+        where F: Fn() -> Result<T, D::Error>
+    {
+        let mut retry = 0;
+        loop {
+            match request() {
+                Err(ref e) if retry + 1 < self.attempts && (self.is_transient)(e) => {
+                    let delay = self.delay(retry);
+                    warn!("{} failed ({}), retrying in {:?}", what, e, delay);
+                    thread::sleep(delay);
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<D: DbInterface> DbInterface for RetryingDb<D> {
+    type Error = D::Error;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        self.retry("Storing a paste", || {
+            self.inner.store_data(entry.clone())
+        })
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        self.inner.insert_data(id, entry)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        // Not retried: a retry might find the paste stored by the failed attempt itself.
+        self.inner.store_new(id, entry)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        self.retry("Loading a paste", || self.inner.load_data(id))
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.retry("Loading metadata of a paste", || self.inner.load_metadata(id))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.retry("Loading a deletion token", || self.inner.load_deletion_token(id))
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        self.inner.update_data(id, expected_revision, data, mime_type)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.inner.soft_delete(id, deleted_at)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_expired(now)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.scrub_creators(created_before)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.remove_by_creator(creator, since)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.inner.remove_by_owner(token)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner.store_signature(id, signature)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list(offset, limit)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list_public(offset, limit)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check()
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats()
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.inner.max_data_size()
+    }
+}
@@ -0,0 +1,374 @@
+//! Signing pastes by the server.
+
+use super::WrapperError;
+use base64;
+use chrono::{DateTime, Utc};
+use ring::digest::{self, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, ED25519};
+use secrets::Secret;
+use std::fmt::Write;
+use untrusted::Input;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// Prefix of signatures, so other algorithms could be introduced later.
+const SIGNATURE_PREFIX: &str = "ed25519:";
+
+quick_error! {
+    /// Signing errors.
+    #[derive(Debug)]
+    pub enum SigningError {
+        /// The signing key is malformed.
+        Key(reason: String) {
+            description("Malformed signing key")
+            display("Malformed signing key: {}", reason)
+        }
+        /// A paste doesn't match its signature, i.e. it has been tampered with.
+        Mismatch(id: PasteId) {
+            description("Paste signature mismatch")
+            display("Paste {} doesn't match its signature", id)
+        }
+    }
+}
+
+/// An Ed25519 key pair to sign pastes with.
+pub struct SigningKey {
+    key_pair: Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// Parses a base64 encoded PKCS#8 document with an Ed25519 private key, like the one printed
+    /// by `openssl genpkey -algorithm ed25519 -outform DER | base64`. Whitespace is ignored.
+    pub fn parse(text: &str) -> Result<Self, SigningError> {
+        let text: String = text.split_whitespace().collect();
+        let der = base64::decode(&text).map_err(|e| SigningError::Key(e.to_string()))?;
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(Input::from(&der))
+            .map_err(|_| SigningError::Key("not an Ed25519 PKCS#8 document".into()))?;
+        Ok(SigningKey { key_pair })
+    }
+
+    /// Parses a key from a secret, see [parse](#method.parse).
+    pub fn from_secret(secret: &Secret) -> Result<Self, SigningError> {
+        Self::parse(secret.expose())
+    }
+
+    /// Generates a new key in the format [parse](#method.parse) accepts.
+    pub fn generate() -> Result<String, SigningError> {
+        let der = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| SigningError::Key("can't generate a key".into()))?;
+        Ok(base64::encode(&der[..]))
+    }
+
+    /// Returns the base64 encoded public key, which is needed to verify signatures.
+    pub fn public_key(&self) -> String {
+        base64::encode(self.key_pair.public_key_bytes())
+    }
+
+    /// Signs a paste.
+    fn sign(&self, id: PasteId, entry: &PasteEntry) -> String {
+        let signature = self.key_pair.sign(signed_message(id, entry).as_bytes());
+        format!("{}{}", SIGNATURE_PREFIX, base64::encode(signature.as_ref()))
+    }
+}
+
+/// Builds the message that is signed for a paste.
+///
+/// The message is a text with a field per line: a `pastebin-signature-v1` header, the paste ID,
+/// the creation and the expiration dates (as milliseconds since the Unix epoch, expiration could
+/// be `never`), the mime type, the base64 encoded file name (empty if none) and a hex encoded
+/// SHA-256 digest of the data. Every line, including the last one, ends with `\n`.
+pub fn signed_message(id: PasteId, entry: &PasteEntry) -> String {
+    let best_before = match entry.best_before {
+        Some(date) => date.timestamp_millis().to_string(),
+        None => "never".to_string(),
+    };
+    let file_name = entry.file_name
+                         .as_ref()
+                         .map(base64::encode)
+                         .unwrap_or_default();
+    let mut message = format!("pastebin-signature-v1\n{}\n{}\n{}\n{}\n{}\n",
+                              id,
+                              entry.created_at.timestamp_millis(),
+                              best_before,
+                              entry.mime_type,
+                              file_name);
+    for byte in digest::digest(&SHA256, &entry.data).as_ref() {
+        write!(message, "{:02x}", byte).expect("Writing to a string never fails");
+    }
+    message.push('\n');
+    message
+}
+
+/// Checks a signature of a paste with a base64 encoded public key, see
+/// [SigningKey::public_key](struct.SigningKey.html#method.public_key).
+pub fn verify_signature(public_key: &str,
+                        id: PasteId,
+                        entry: &PasteEntry,
+                        signature: &str)
+                        -> bool {
+    if !signature.starts_with(SIGNATURE_PREFIX) {
+        return false;
+    }
+    let (public_key, signature) = match (base64::decode(public_key),
+                                         base64::decode(&signature[SIGNATURE_PREFIX.len()..]))
+    {
+        (Ok(public_key), Ok(signature)) => (public_key, signature),
+        _ => return false,
+    };
+    signature::verify(&ED25519,
+                      Input::from(&public_key),
+                      Input::from(signed_message(id, entry).as_bytes()),
+                      Input::from(&signature)).is_ok()
+}
+
+/// A decorator that signs pastes with a server [SigningKey](struct.SigningKey.html) and checks
+/// the signatures on loading.
+///
+/// A signature covers the data and the metadata of a paste (see
+/// [signed_message](fn.signed_message.html)) and is stored alongside the paste with
+/// `DbInterface::store_signature`. Anyone with the public key could check that a paste hasn't
+/// been tampered with since it was stored (or updated through the service), which is useful for
+/// mirrors and audits.
+///
+/// Loading a paste that doesn't match its signature fails. Pastes stored before signing was
+/// enabled have no signatures and are loaded as is.
+///
+/// The decorator has to be on top of the ones that transform the data (like
+/// [CompressedDb](struct.CompressedDb.html)), but below [DedupDb](struct.DedupDb.html), which
+/// doesn't store duplicates at all.
+pub struct SignedDb<D> {
+    inner: D,
+    key: SigningKey,
+}
+
+impl<D: DbInterface> SignedDb<D> {
+    /// Wraps a database.
+    pub fn new(inner: D, key: SigningKey) -> Self {
+        SignedDb { inner, key }
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Returns the signing key.
+    pub fn key(&self) -> &SigningKey {
+        &self.key
+    }
+}
+
+impl<D: DbInterface> DbInterface for SignedDb<D> {
+    type Error = WrapperError<D::Error>;
+
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        // The ID is a part of the signature, so it has to be signed once the paste is stored.
+        let entry = PasteEntry { views: 0,
+                                 revision: 0,
+                                 ..entry };
+        let id = self.inner
+                     .store_data(entry.clone())
+                     .map_err(WrapperError::Db)?;
+        self.inner
+            .store_signature(id, &self.key.sign(id, &entry))
+            .map_err(WrapperError::Db)?;
+        Ok(id)
+    }
+
+    fn insert_data(&self, id: PasteId, entry: PasteEntry) -> Result<(), Self::Error> {
+        let signature = self.key.sign(id, &entry);
+        self.inner
+            .insert_data(id, entry)
+            .map_err(WrapperError::Db)?;
+        self.inner
+            .store_signature(id, &signature)
+            .map_err(WrapperError::Db)
+    }
+
+    fn store_new(&self, id: PasteId, entry: PasteEntry) -> Result<bool, Self::Error> {
+        let signature = self.key.sign(id, &entry);
+        if !self.inner
+                .store_new(id, entry)
+                .map_err(WrapperError::Db)?
+        {
+            return Ok(false);
+        }
+        self.inner
+            .store_signature(id, &signature)
+            .map_err(WrapperError::Db)?;
+        Ok(true)
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        let entry = match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        match self.inner
+                  .load_signature(id)
+                  .map_err(WrapperError::Db)?
+        {
+            Some(ref signature)
+                if !verify_signature(&self.key.public_key(), id, &entry, signature) =>
+            {
+                Err(WrapperError::wrapper(SigningError::Mismatch(id)))
+            }
+            Some(_) => Ok(Some(entry)),
+            None => {
+                debug!("Paste {} is not signed", id);
+                Ok(Some(entry))
+            }
+        }
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        self.inner.load_metadata(id).map_err(WrapperError::Db)
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_deletion_token(id).map_err(WrapperError::Db)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let metadata = match self.inner.load_metadata(id).map_err(WrapperError::Db)? {
+            Some(metadata) => metadata,
+            None => return Ok(UpdateOutcome::NotFound),
+        };
+        let entry = PasteEntry { data,
+                                 file_name: metadata.file_name,
+                                 mime_type,
+                                 created_at: metadata.created_at,
+                                 best_before: metadata.best_before,
+                                 views: metadata.views,
+                                 creator: metadata.creator,
+                                 deletion_token: None,
+                                 language: None,
+                                 revision: metadata.revision,
+                                 views_left: metadata.views_left,
+                                 public: metadata.public, };
+        let signature = self.key.sign(id, &entry);
+        let outcome = self.inner
+                          .update_data(id, expected_revision, entry.data, entry.mime_type)
+                          .map_err(WrapperError::Db)?;
+        if let UpdateOutcome::Updated(_) = outcome {
+            self.inner
+                .store_signature(id, &signature)
+                .map_err(WrapperError::Db)?;
+        }
+        Ok(outcome)
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id).map_err(WrapperError::Db)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id).map_err(WrapperError::Db)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.remove_data(id).map_err(WrapperError::Db)
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        self.inner.soft_delete(id, deleted_at).map_err(WrapperError::Db)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id).map_err(WrapperError::Db)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_trash(deleted_before).map_err(WrapperError::Db)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone).map_err(WrapperError::Db)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id).map_err(WrapperError::Db)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since).map_err(WrapperError::Db)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before).map_err(WrapperError::Db)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_expired(now).map_err(WrapperError::Db)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.scrub_creators(created_before).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.remove_by_creator(creator, since).map_err(WrapperError::Db)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        self.inner.remove_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash).map_err(WrapperError::Db)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash).map_err(WrapperError::Db)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug).map_err(WrapperError::Db)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug).map_err(WrapperError::Db)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner
+            .store_signature(id, signature)
+            .map_err(WrapperError::Db)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id).map_err(WrapperError::Db)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list(offset, limit).map_err(WrapperError::Db)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        self.inner.list_public(offset, limit).map_err(WrapperError::Db)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().map_err(WrapperError::Db)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        self.inner.stats().map_err(WrapperError::Db)
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.inner.max_data_size()
+    }
+}
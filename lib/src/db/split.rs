@@ -0,0 +1,502 @@
+//! Storing big pastes outside of a database.
+
+use super::WrapperError;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::str::from_utf8;
+use summary::count_lines;
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary, StorageStats, Tombstone,
+     UpdateOutcome};
+
+/// Marks a reference to a blob, which is stored in a database instead of the data.
+const MARKER: &[u8] = b"\0pbblob:";
+
+/// A storage of big chunks of data, like a directory on a disk or an object storage bucket.
+pub trait BlobStore: Send + Sync {
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Stores the data under the given key, overwriting existing data if any.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Loads the data stored under the given key, if any.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Removes the data stored under the given key. Removal of a missing key is not an error.
+    fn remove(&self, key: &str) -> Result<(), Self::Error>;
+
+    /// Lists all the stored keys.
+    fn keys(&self) -> Result<Vec<String>, Self::Error>;
+}
+
+/// A blob storage in a local directory, one file per blob.
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    /// Opens a directory, creating it if necessary.
+    pub fn new<P: Into<PathBuf>>(root: P) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(FsBlobStore { root })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        // Keys are generated by `SplitDb`, but they are read from a database, so better safe than
+        // sorry.
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Invalid blob key {:?}", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    type Error = io::Error;
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        // Write into a temporary file first, so a blob is never seen half-written.
+        let tmp = path.with_extension("tmp");
+        File::create(&tmp)?.write_all(data)?;
+        fs::rename(tmp, path)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let mut file = match File::open(self.path(key)?) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.extension().is_some() {
+                // Leftovers of interrupted writes.
+                continue;
+            }
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                keys.push(name.to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// A reference to a blob stored in a database in place of the data.
+struct BlobRef {
+    key: String,
+    size: usize,
+    lines: usize,
+}
+
+impl BlobRef {
+    fn encode(&self) -> Vec<u8> {
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(format!("{}:{}:{}", self.key, self.size, self.lines).as_bytes());
+        data
+    }
+
+    /// Decodes a reference, if the data is a reference at all.
+    fn decode(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(MARKER) {
+            return None;
+        }
+        let mut parts = from_utf8(&data[MARKER.len()..]).ok()?.split(':');
+        Some(BlobRef { key: parts.next()?.to_string(),
+                       size: parts.next()?.parse().ok()?,
+                       lines: parts.next()?.parse().ok()?, })
+    }
+
+    /// Generates a key for a new blob.
+    ///
+    /// The expiration date is a part of the key, so expired blobs could be found without asking
+    /// the database.
+    fn new_key(best_before: Option<DateTime<Utc>>) -> String {
+        let expires = match best_before {
+            Some(date) => date.timestamp().to_string(),
+            None => "never".into(),
+        };
+        let mut rng = thread_rng();
+        format!("{}-{:016x}{:016x}", expires, rng.gen::<u64>(), rng.gen::<u64>())
+    }
+
+    /// Checks whether a blob with the given key has expired by `now`.
+    fn key_expired(key: &str, now: DateTime<Utc>) -> bool {
+        key.split('-')
+           .next()
+           .and_then(|expires| expires.parse::<i64>().ok())
+           .map(|expires| expires < now.timestamp())
+           .unwrap_or(false)
+    }
+}
+
+/// A decorator that keeps big pastes in a [BlobStore](trait.BlobStore.html), leaving only
+/// metadata (and a reference to a blob) in the wrapped database.
+///
+/// Pastes up to `threshold` bytes are stored in the database as usual. Blobs are removed together
+/// with their pastes, while expired ones are removed by `purge_expired` even if the database has
+/// already dropped their pastes by itself (like MongoDB does with a TTL index).
+///
+/// Blobs of pastes in the trash are kept so the pastes could be restored. Mind that purging the
+/// trash leaves the blobs behind until they expire, and blobs of pastes that never expire stay
+/// for good.
+pub struct SplitDb<D, B> {
+    inner: D,
+    blobs: B,
+    threshold: usize,
+    max_data_size: Option<usize>,
+}
+
+impl<D: DbInterface, B: BlobStore> SplitDb<D, B> {
+    /// Wraps a database, storing pastes bigger than `threshold` bytes in `blobs`.
+    pub fn new(inner: D, blobs: B, threshold: usize) -> Self {
+        SplitDb { inner,
+                  blobs,
+                  threshold,
+                  max_data_size: None, }
+    }
+
+    /// Sets the maximum size of a paste.
+    ///
+    /// By default the limit of the wrapped database is used, but blob storages usually could
+    /// handle much bigger pastes.
+    pub fn with_max_data_size(mut self, max_data_size: usize) -> Self {
+        self.max_data_size = Some(max_data_size);
+        self
+    }
+
+    /// Returns a reference to the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Stores the data in the blob storage if it is big enough.
+    ///
+    /// Returns the data to be stored in the database and a blob key, if any.
+    fn split(&self,
+             data: Vec<u8>,
+             best_before: Option<DateTime<Utc>>)
+             -> Result<(Vec<u8>, Option<String>), WrapperError<D::Error>> {
+        if data.len() <= self.threshold {
+            return Ok((data, None));
+        }
+        let blob = BlobRef { key: BlobRef::new_key(best_before),
+                             size: data.len(),
+                             lines: count_lines(&data), };
+        self.blobs
+            .put(&blob.key, &data)
+            .map_err(WrapperError::wrapper)?;
+        Ok((blob.encode(), Some(blob.key)))
+    }
+
+    /// Removes a blob, if it has been stored.
+    fn remove_blob(&self, key: Option<String>) -> Result<(), WrapperError<D::Error>> {
+        match key {
+            Some(key) => self.blobs.remove(&key).map_err(WrapperError::wrapper),
+            None => Ok(()),
+        }
+    }
+
+    /// Loads a reference to a blob stored in place of a paste data, if any.
+    fn load_blob_ref(&self, id: PasteId) -> Result<Option<BlobRef>, WrapperError<D::Error>> {
+        Ok(self.inner
+               .load_data(id)
+               .map_err(WrapperError::Db)?
+               .and_then(|entry| BlobRef::decode(&entry.data)))
+    }
+}
+
+impl<D: DbInterface, B: BlobStore> DbInterface for SplitDb<D, B> {
+    type Error = WrapperError<D::Error>;
+
+    fn store_data(&self, mut entry: PasteEntry) -> Result<PasteId, Self::Error> {
+        let (data, key) = self.split(entry.data, entry.best_before)?;
+        entry.data = data;
+        match self.inner.store_data(entry) {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                self.remove_blob(key)?;
+                Err(WrapperError::Db(e))
+            }
+        }
+    }
+
+    fn insert_data(&self, id: PasteId, mut entry: PasteEntry) -> Result<(), Self::Error> {
+        let old_blob = self.load_blob_ref(id)?;
+        let (data, key) = self.split(entry.data, entry.best_before)?;
+        entry.data = data;
+        if let Err(e) = self.inner.insert_data(id, entry) {
+            self.remove_blob(key)?;
+            return Err(WrapperError::Db(e));
+        }
+        self.remove_blob(old_blob.map(|blob| blob.key))
+    }
+
+    fn store_new(&self, id: PasteId, mut entry: PasteEntry) -> Result<bool, Self::Error> {
+        let (data, key) = self.split(entry.data, entry.best_before)?;
+        entry.data = data;
+        match self.inner.store_new(id, entry) {
+            Ok(true) => Ok(true),
+            Ok(false) => {
+                self.remove_blob(key)?;
+                Ok(false)
+            }
+            Err(e) => {
+                self.remove_blob(key)?;
+                Err(WrapperError::Db(e))
+            }
+        }
+    }
+
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
+        let mut entry = match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Some(blob) = BlobRef::decode(&entry.data) {
+            let missing = || WrapperError::wrapper(format!("Blob {} is missing", blob.key));
+            entry.data = self.blobs
+                             .get(&blob.key)
+                             .map_err(WrapperError::wrapper)?
+                             .ok_or_else(missing)?;
+        }
+        Ok(Some(entry))
+    }
+
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        // Data of big pastes is not stored in the database, so loading it is cheap.
+        let entry = match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut summary = PasteSummary::from_entry(id, &entry);
+        if let Some(blob) = BlobRef::decode(&entry.data) {
+            summary.size = blob.size;
+            summary.lines = blob.lines;
+        }
+        Ok(Some(summary))
+    }
+
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_deletion_token(id).map_err(WrapperError::Db)
+    }
+
+    fn update_data(&self,
+                   id: PasteId,
+                   expected_revision: Option<u64>,
+                   data: Vec<u8>,
+                   mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        let entry = match self.inner.load_data(id).map_err(WrapperError::Db)? {
+            Some(entry) => entry,
+            None => return Ok(UpdateOutcome::NotFound),
+        };
+        let (data, key) = self.split(data, entry.best_before)?;
+        match self.inner.update_data(id, expected_revision, data, mime_type) {
+            Ok(UpdateOutcome::Updated(revision)) => {
+                self.remove_blob(BlobRef::decode(&entry.data).map(|blob| blob.key))?;
+                Ok(UpdateOutcome::Updated(revision))
+            }
+            Ok(outcome) => {
+                self.remove_blob(key)?;
+                Ok(outcome)
+            }
+            Err(e) => {
+                self.remove_blob(key)?;
+                Err(WrapperError::Db(e))
+            }
+        }
+    }
+
+    fn increment_views(&self, id: PasteId) -> Result<(), Self::Error> {
+        self.inner.increment_views(id).map_err(WrapperError::Db)
+    }
+
+    fn take_view(&self, id: PasteId) -> Result<Option<u64>, Self::Error> {
+        self.inner.take_view(id).map_err(WrapperError::Db)
+    }
+
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error> {
+        let blob = self.load_blob_ref(id)?;
+        self.inner.remove_data(id).map_err(WrapperError::Db)?;
+        self.remove_blob(blob.map(|blob| blob.key))
+    }
+
+    fn soft_delete(&self, id: PasteId, deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        // The blob is kept, so the paste could be restored.
+        self.inner
+            .soft_delete(id, deleted_at)
+            .map_err(WrapperError::Db)
+    }
+
+    fn restore(&self, id: PasteId) -> Result<bool, Self::Error> {
+        self.inner.restore(id).map_err(WrapperError::Db)
+    }
+
+    fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // Trashed pastes can't be loaded, so their blobs are only removed once they expire.
+        self.inner
+            .purge_trash(deleted_before)
+            .map_err(WrapperError::Db)
+    }
+
+    fn store_tombstone(&self, tombstone: Tombstone) -> Result<(), Self::Error> {
+        self.inner.store_tombstone(tombstone).map_err(WrapperError::Db)
+    }
+
+    fn load_tombstone(&self, id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        self.inner.load_tombstone(id).map_err(WrapperError::Db)
+    }
+
+    fn list_tombstones(&self,
+                       removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        self.inner.list_tombstones(removed_since).map_err(WrapperError::Db)
+    }
+
+    fn purge_tombstones(&self, removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner.purge_tombstones(removed_before).map_err(WrapperError::Db)
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        let purged = self.inner.purge_expired(now).map_err(WrapperError::Db)?;
+        for key in self.blobs.keys().map_err(WrapperError::wrapper)? {
+            if BlobRef::key_expired(&key, now) {
+                self.blobs.remove(&key).map_err(WrapperError::wrapper)?;
+            }
+        }
+        Ok(purged)
+    }
+
+    fn scrub_creators(&self, created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        self.inner
+            .scrub_creators(created_before)
+            .map_err(WrapperError::Db)
+    }
+
+    fn remove_by_creator(&self, creator: &str, since: DateTime<Utc>) -> Result<u64, Self::Error> {
+        // Blobs of the pastes have to be removed as well, so the pastes are looked up one by one.
+        let mut ids = Vec::new();
+        let mut offset = 0;
+        loop {
+            let batch = self.inner
+                            .list(offset, 100)
+                            .map_err(WrapperError::Db)?;
+            if batch.is_empty() {
+                break;
+            }
+            offset += batch.len();
+            ids.extend(batch.into_iter()
+                            .filter(|summary| {
+                                        summary.created_at >= since
+                                        && summary.creator.as_ref().map(String::as_str)
+                                           == Some(creator)
+                                    })
+                            .map(|summary| summary.id));
+        }
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        Ok(ids.len() as u64)
+    }
+
+    fn remove_by_owner(&self, token: &str) -> Result<u64, Self::Error> {
+        // Blobs of the pastes have to be removed as well, so the pastes are removed one by one.
+        let ids = self.inner
+                      .list_by_owner(token)
+                      .map_err(WrapperError::Db)?;
+        for &id in &ids {
+            self.remove_data(id)?;
+        }
+        // Trashed pastes are not listed, their blobs are removed once they expire.
+        let trashed = self.inner
+                          .remove_by_owner(token)
+                          .map_err(WrapperError::Db)?;
+        Ok(ids.len() as u64 + trashed)
+    }
+
+    fn list_by_owner(&self, token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        self.inner.list_by_owner(token).map_err(WrapperError::Db)
+    }
+
+    fn store_hash(&self, id: PasteId, hash: &str) -> Result<(), Self::Error> {
+        self.inner.store_hash(id, hash).map_err(WrapperError::Db)
+    }
+
+    fn find_by_hash(&self, hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_hash(hash).map_err(WrapperError::Db)
+    }
+
+    fn store_slug(&self, id: PasteId, slug: &str) -> Result<bool, Self::Error> {
+        self.inner.store_slug(id, slug).map_err(WrapperError::Db)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        self.inner.find_by_slug(slug).map_err(WrapperError::Db)
+    }
+
+    fn store_signature(&self, id: PasteId, signature: &str) -> Result<(), Self::Error> {
+        self.inner
+            .store_signature(id, signature)
+            .map_err(WrapperError::Db)
+    }
+
+    fn load_signature(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        self.inner.load_signature(id).map_err(WrapperError::Db)
+    }
+
+    fn list(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_metadata(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn list_public(&self, offset: usize, limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        let mut summaries = Vec::new();
+        for summary in self.inner.list_public(offset, limit).map_err(WrapperError::Db)? {
+            // A paste might be removed in between, in which case it is just skipped.
+            if let Some(summary) = self.load_metadata(summary.id)? {
+                summaries.push(summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().map_err(WrapperError::Db)
+    }
+
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        // Only the database is taken into account, blobs are represented by their references.
+        self.inner.stats().map_err(WrapperError::Db)
+    }
+
+    fn max_data_size(&self) -> usize {
+        self.max_data_size
+            .unwrap_or_else(|| self.inner.max_data_size())
+    }
+}
@@ -0,0 +1,161 @@
+//! Graceful shutdown support.
+
+use api;
+use iron::headers::Connection;
+use iron::{status, AroundMiddleware, Handler, IronResult, Request, Response};
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A handle to put a server into a drain mode.
+///
+/// Once draining, the server keeps serving requests, but responds with `Connection: close` so
+/// clients don't reuse their connections, and `GET /readyz` (as well as `GET /healthz`) fails so
+/// load balancers stop routing requests to it. Meanwhile another process (which has inherited the
+/// listening socket, see [inherit_listener](fn.inherit_listener.html)) picks up new connections,
+/// and the old one could exit as soon as [wait](#method.wait) reports no requests are in flight.
+///
+/// Handles are cheap to clone, all the clones control the same server.
+#[derive(Clone, Default)]
+pub struct Drain {
+    inner: Arc<DrainState>,
+}
+
+#[derive(Default)]
+struct DrainState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Drain {
+    /// Creates a new handle, not in a drain mode.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts the server into a drain mode. There is no way back.
+    pub fn start(&self) {
+        if !self.inner.draining.swap(true, Ordering::SeqCst) {
+            info!("Draining, {} requests in flight", self.in_flight());
+        }
+    }
+
+    /// Checks whether the server is draining.
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of requests being handled at the moment.
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until there are no requests in flight, but no longer than `timeout`.
+    ///
+    /// Returns `false` on timeout.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.in_flight() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        true
+    }
+}
+
+impl AroundMiddleware for Drain {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        Box::new(DrainingHandler { drain: self,
+                                   handler, })
+    }
+}
+
+/// A handler wrapped by `Drain`.
+struct DrainingHandler {
+    drain: Drain,
+    handler: Box<Handler>,
+}
+
+/// Decrements the in-flight requests counter, even if a handler panics.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Handler for DrainingHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.drain.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlight(&self.drain.inner.in_flight);
+        if !self.drain.is_draining() {
+            return self.handler.handle(req);
+        }
+        let path = req.url.path();
+        let mut response = if path == ["healthz"] || path == ["readyz"] {
+            api::json_response(status::ServiceUnavailable, &json!({ "status": "draining" }))
+        } else {
+            match self.handler.handle(req) {
+                Ok(response) => response,
+                Err(mut err) => {
+                    err.response.headers.set(Connection::close());
+                    return Err(err);
+                }
+            }
+        };
+        response.headers.set(Connection::close());
+        Ok(response)
+    }
+}
+
+/// Takes over a listening socket passed by a parent process (or a supervisor), if any.
+///
+/// The socket's file descriptor number is read from the environment variable `var`, which is
+/// then removed so it isn't passed down to children. Returns `Ok(None)` if the variable is not
+/// set. The descriptor has to be inherited without the close-on-exec flag, obviously.
+///
+/// Only supported on Unix-like systems, elsewhere having the variable set is an error.
+pub fn inherit_listener(var: &str) -> io::Result<Option<TcpListener>> {
+    let fd = match env::var(var) {
+        Ok(fd) => fd,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    };
+    env::remove_var(var);
+    let fd = match fd.trim().parse() {
+        Ok(fd) => fd,
+        Err(_) => {
+            let message = format!("Invalid file descriptor {:?} in {}", fd, var);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    };
+    listener_from_fd(fd).map(Some)
+}
+
+#[cfg(unix)]
+fn listener_from_fd(fd: i32) -> io::Result<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+    if fd < 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Invalid file descriptor {}", fd)));
+    }
+    // There is no way to check the descriptor is really a listening TCP socket without `libc`, but
+    // `local_addr` at least fails on anything but a socket.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    let addr = listener.local_addr()?;
+    info!("Inherited a listening socket on {} (fd {})", addr, fd);
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn listener_from_fd(_fd: i32) -> io::Result<TcpListener> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       "Inheriting sockets is only supported on Unix-like systems"))
+}
@@ -0,0 +1,266 @@
+//! Exporting pastes to a file and importing them back.
+//!
+//! Pastes are dumped as newline delimited JSON, one paste per line, with all the metadata and the
+//! data itself encoded with base64 (wrapped here for readability):
+//!
+//! ```text
+//! {"id":"AQAAAAAAAAA","file_name":"a.txt","mime_type":"text/plain",
+//!  "created_at":"2018-05-01T12:30:00+00:00","best_before":null,"views":3,"creator":null,
+//!  "deletion_token":null,"language":null,"revision":0,"views_left":null,"public":false,
+//!  "data":"bG9s"}
+//! ```
+//!
+//! The format doesn't depend on a database, so a dump of one database could be imported into a
+//! database of another kind. Pastes keep their IDs, so links to them stay valid.
+//!
+//! Pastes go through the given database as is, so decorators (like
+//! [db::EncryptedDb](../db/struct.EncryptedDb.html)) should be in place on both ends: that way a
+//! dump holds the original data.
+//!
+//! [backup](fn.backup.html) writes dumps into a directory, one file per backup.
+
+use base64;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::{self, Value};
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use {DbInterface, MimeType, PasteEntry, PasteId, PasteSummary};
+
+/// Number of pastes listed at once.
+const BATCH_SIZE: usize = 100;
+
+/// Prefix of backup file names.
+const BACKUP_PREFIX: &str = "pastes-";
+
+/// Format of the dates in backup file names.
+const BACKUP_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+quick_error! {
+    /// An error of exporting or importing pastes.
+    #[derive(Debug)]
+    pub enum DumpError {
+        /// Input/output error.
+        Io(err: io::Error) {
+            from()
+            cause(err)
+        }
+        /// The database has failed.
+        Db(err: Box<error::Error + Send + Sync>) {
+            description("Database error")
+            display("Database error: {}", err)
+        }
+        /// A line of a dump is malformed.
+        Malformed(line: usize, reason: String) {
+            description("Malformed dump")
+            display("Malformed dump at line {}: {}", line, reason)
+        }
+        /// The dump format is not known.
+        UnknownFormat(name: String) {
+            description("Unknown dump format")
+            display("Unknown dump format {:?}, expected hastebin or 0x0", name)
+        }
+    }
+}
+
+fn db_error<E: error::Error + Send + Sync + 'static>(err: E) -> DumpError {
+    DumpError::Db(Box::new(err))
+}
+
+/// Writes all the pastes of the database to `output`.
+///
+/// Pastes are enumerated with `DbInterface::list`, so the ones stored while the export is running
+/// might be missed, and the ones removed in the meantime are skipped. Returns the number of
+/// exported pastes.
+pub fn export<D, W>(db: &D, output: W) -> Result<u64, DumpError>
+    where D: DbInterface + ?Sized,
+          W: Write
+{
+    export_filtered(db, output, |_| true)
+}
+
+/// Exports the pastes whose summaries match the `filter`.
+fn export_filtered<D, W, F>(db: &D, mut output: W, filter: F) -> Result<u64, DumpError>
+    where D: DbInterface + ?Sized,
+          W: Write,
+          F: Fn(&PasteSummary) -> bool
+{
+    let mut exported = 0;
+    let mut offset = 0;
+    loop {
+        let batch = db.list(offset, BATCH_SIZE).map_err(db_error)?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len();
+        for summary in batch.into_iter().filter(|summary| filter(summary)) {
+            let entry = match db.load_data(summary.id).map_err(db_error)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            writeln!(output, "{}", to_json(summary.id, &entry))?;
+            exported += 1;
+        }
+    }
+    output.flush()?;
+    Ok(exported)
+}
+
+/// Writes the given pastes to `output`, in the order they are given.
+///
+/// The pastes that are gone in the meantime are skipped. Returns the number of exported pastes.
+pub fn export_pastes<D, W>(db: &D, ids: &[PasteId], mut output: W) -> Result<u64, DumpError>
+    where D: DbInterface + ?Sized,
+          W: Write
+{
+    let mut exported = 0;
+    for &id in ids {
+        if let Some(entry) = db.load_data(id).map_err(db_error)? {
+            writeln!(output, "{}", to_json(id, &entry))?;
+            exported += 1;
+        }
+    }
+    output.flush()?;
+    Ok(exported)
+}
+
+/// Reads pastes written by [export](fn.export.html) and stores them into the database with
+/// `DbInterface::insert_data`, so existing pastes with the same IDs are replaced.
+///
+/// Empty lines are skipped. Returns the number of imported pastes, the ones before a malformed
+/// line stay imported.
+pub fn import<D, R>(db: &D, input: R) -> Result<u64, DumpError>
+    where D: DbInterface + ?Sized,
+          R: BufRead
+{
+    let mut imported = 0;
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, entry) = from_json(&line).map_err(|reason| DumpError::Malformed(n + 1, reason))?;
+        db.insert_data(id, entry).map_err(db_error)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Writes a backup of all the pastes into a new file in `dir`, see [export](fn.export.html).
+///
+/// Backups are named after the moment they are started at, like `pastes-20180501T123000Z.ndjson`.
+/// An incremental backup (`pastes-20180501T123000Z-incremental.ndjson`) only holds the pastes
+/// created since the latest backup in the directory (it is a full one if there is none). Mind that
+/// changes of existing pastes (like new data or views) only get into full backups. To restore
+/// the pastes import the latest full backup and then the incremental ones that follow it.
+///
+/// Returns the path of the backup and the number of pastes in it.
+pub fn backup<D>(db: &D, dir: &Path, incremental: bool) -> Result<(PathBuf, u64), DumpError>
+    where D: DbInterface + ?Sized
+{
+    let since = if incremental {
+        latest_backup(dir)?
+    } else {
+        None
+    };
+    let name = format!("{}{}{}.ndjson",
+                       BACKUP_PREFIX,
+                       Utc::now().format(BACKUP_DATE_FORMAT),
+                       if since.is_some() { "-incremental" } else { "" });
+    let path = dir.join(name);
+    // Write into a temporary file first, so a backup is never seen half-written.
+    let tmp = path.with_extension("tmp");
+    let output = BufWriter::new(File::create(&tmp)?);
+    let exported = match since {
+        Some(since) => export_filtered(db, output, |summary| summary.created_at >= since)?,
+        None => export(db, output)?,
+    };
+    fs::rename(tmp, &path)?;
+    Ok((path, exported))
+}
+
+/// Finds out when the latest backup in the directory has been started, if there are any.
+fn latest_backup(dir: &Path) -> io::Result<Option<DateTime<Utc>>> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let date = name.to_str()
+                       .filter(|name| name.starts_with(BACKUP_PREFIX) && name.ends_with(".ndjson"))
+                       .and_then(|name| name[BACKUP_PREFIX.len()..].get(..16))
+                       .and_then(|date| {
+                                     NaiveDateTime::parse_from_str(date, BACKUP_DATE_FORMAT).ok()
+                                 })
+                       .map(|date| DateTime::from_utc(date, Utc));
+        latest = latest.max(date);
+    }
+    Ok(latest)
+}
+
+fn to_json(id: PasteId, entry: &PasteEntry) -> Value {
+    json!({
+        "id": id.to_string(),
+        "file_name": entry.file_name,
+        "mime_type": entry.mime_type.as_str(),
+        "created_at": entry.created_at.to_rfc3339(),
+        "best_before": entry.best_before.map(|date| date.to_rfc3339()),
+        "views": entry.views,
+        "creator": entry.creator,
+        "deletion_token": entry.deletion_token,
+        "language": entry.language,
+        "revision": entry.revision,
+        "views_left": entry.views_left,
+        "public": entry.public,
+        "data": base64::encode(&entry.data),
+    })
+}
+
+fn from_json(line: &str) -> Result<(PasteId, PasteEntry), String> {
+    let value: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let string = |field: &str| match value[field] {
+        Value::Null => Ok(None),
+        Value::String(ref s) => Ok(Some(s.clone())),
+        _ => Err(format!("`{}` is not a string", field)),
+    };
+    let required = |field: &str| string(field)?.ok_or_else(|| format!("`{}` is missing", field));
+    let date = |field: &str, date: String| {
+        DateTime::parse_from_rfc3339(&date).map(|date| date.with_timezone(&Utc))
+                                           .map_err(|e| format!("`{}`: {}", field, e))
+    };
+    let id = required("id")?.parse::<PasteId>()
+                            .map_err(|e| e.to_string())?;
+    let best_before = match string("best_before")? {
+        Some(best_before) => Some(date("best_before", best_before)?),
+        None => None,
+    };
+    let number = |field: &str| match value[field] {
+        Value::Null => Ok(0),
+        ref number => number.as_u64()
+                            .ok_or_else(|| format!("`{}` is not a number", field)),
+    };
+    let views_left = match value["views_left"] {
+        Value::Null => None,
+        ref number => Some(number.as_u64()
+                                 .ok_or_else(|| "`views_left` is not a number".to_string())?),
+    };
+    let public = match value["public"] {
+        Value::Null => false,
+        Value::Bool(public) => public,
+        _ => return Err("`public` is not a boolean".to_string()),
+    };
+    let data = base64::decode(&required("data")?).map_err(|e| e.to_string())?;
+    let mime_type = MimeType::new(required("mime_type")?).map_err(|e| e.to_string())?;
+    let entry = PasteEntry { data,
+                             file_name: string("file_name")?,
+                             mime_type,
+                             created_at: date("created_at", required("created_at")?)?,
+                             best_before,
+                             views: number("views")?,
+                             creator: string("creator")?,
+                             deletion_token: string("deletion_token")?,
+                             language: string("language")?,
+                             revision: number("revision")?,
+                             views_left,
+                             public, };
+    Ok((id, entry))
+}
@@ -0,0 +1,205 @@
+//! Compressing responses with gzip or brotli, and decompressing gzipped uploads.
+
+use Error;
+use MimeType;
+use brotli::CompressorWriter;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use iron::{status, AfterMiddleware, IronError, IronResult, Request, Response};
+use iron::headers::{AcceptEncoding, ContentEncoding, ContentLength, ContentRange, ContentType,
+                    ETag, Encoding, EntityTag};
+use limits::{split_rules, MimeRules};
+use std::io::{self, Read, Write};
+
+/// Mime type patterns of responses compressed by default.
+const DEFAULT_MIME_TYPES: &str = "text/*, application/json, application/javascript, \
+                                  application/xml, application/x-yaml";
+
+/// Responses smaller than this are not compressed by default, since the savings don't pay for the
+/// effort.
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Brotli quality level: the higher levels are too slow to compress responses on the fly.
+const BROTLI_QUALITY: u32 = 5;
+
+/// Brotli window size (base 2 logarithm), the one recommended by the reference implementation.
+const BROTLI_WINDOW: u32 = 22;
+
+/// Response header that tells caches a response depends on the request's `Accept-Encoding`.
+const VARY_HEADER: &str = "Vary";
+
+/// Response header that sets a cookie.
+const SET_COOKIE_HEADER: &str = "Set-Cookie";
+
+/// Content codings responses are compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+/// Transparent compression of responses, see
+/// [PastebinBuilder::compress_responses](struct.PastebinBuilder.html#method.compress_responses).
+///
+/// A response is compressed when the client accepts gzip or brotli (the latter is preferred on
+/// equal terms), its mime type matches one of the patterns (text, JSON, JavaScript, XML and YAML
+/// by default) and it is at least as big as the minimum size (1 KiB by default).
+///
+/// Responses that set cookies are never compressed. Those are the pages with forms, which carry
+/// CSRF tokens, and compressing a secret along with text an attacker could reflect into the page
+/// lets the secret be guessed from the sizes of the responses (the BREACH attack).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseCompression {
+    min_size: usize,
+    mime_types: MimeRules<bool>,
+}
+
+impl Default for ResponseCompression {
+    fn default() -> Self {
+        ResponseCompression::new()
+    }
+}
+
+impl ResponseCompression {
+    /// Creates a policy with the default minimum size and mime types.
+    pub fn new() -> Self {
+        let mut mime_types = MimeRules::default();
+        for pattern in split_rules(DEFAULT_MIME_TYPES) {
+            mime_types.set(pattern, true)
+                      .expect("Default mime type patterns are valid");
+        }
+        ResponseCompression { min_size: DEFAULT_MIN_SIZE,
+                              mime_types, }
+    }
+
+    /// Sets the size of the smallest response to compress, in bytes.
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Sets the mime types of responses to compress, replacing the default ones.
+    ///
+    /// Patterns are the ones of [SizeLimits](../struct.SizeLimits.html), separated by commas or
+    /// whitespace, like `text/*, application/json`.
+    pub fn mime_types(mut self, patterns: &str) -> Result<Self, Error> {
+        let mut mime_types = MimeRules::default();
+        for pattern in split_rules(patterns) {
+            mime_types.set(pattern, true)
+                      .map_err(Error::InvalidMimeType)?;
+        }
+        self.mime_types = mime_types;
+        Ok(self)
+    }
+
+    /// Checks whether a response might be compressed, depending on what the client accepts.
+    fn is_compressible(&self, res: &Response) -> bool {
+        let uncompressed = res.headers.get::<ContentEncoding>().is_none()
+                           && res.headers.get::<ContentRange>().is_none();
+        let has_secrets = res.headers.get_raw(SET_COOKIE_HEADER).is_some();
+        let has_body = match res.status {
+            Some(status::NoContent) | Some(status::NotModified) => false,
+            _ => res.body.is_some(),
+        };
+        let mime_type = res.headers
+                           .get::<ContentType>()
+                           .and_then(|content_type| MimeType::new(content_type.to_string()).ok());
+        let is_listed = mime_type.and_then(|mime_type| self.mime_types.get(&mime_type))
+                                 .unwrap_or(false);
+        uncompressed && !has_secrets && has_body && is_listed
+    }
+}
+
+impl AfterMiddleware for ResponseCompression {
+    fn after(&self, req: &mut Request, mut res: Response) -> IronResult<Response> {
+        if !self.is_compressible(&res) {
+            return Ok(res);
+        }
+        res.headers
+           .set_raw(VARY_HEADER, vec![b"Accept-Encoding".to_vec()]);
+        let coding = match negotiate(req) {
+            Some(coding) => coding,
+            None => return Ok(res),
+        };
+        // Bodies are written out to know their sizes, since not every one has a `Content-Length`.
+        let mut data = Vec::new();
+        if let Some(mut body) = res.body.take() {
+            body.write_body(&mut data)
+                .map_err(|e| IronError::new(e, status::InternalServerError))?;
+        }
+        if data.len() < self.min_size {
+            res.body = Some(Box::new(data));
+            return Ok(res);
+        }
+        let compressed =
+            compress(coding, &data).map_err(|e| IronError::new(e, status::InternalServerError))?;
+        let encoding = match coding {
+            Coding::Brotli => Encoding::EncodingExt("br".into()),
+            Coding::Gzip => Encoding::Gzip,
+        };
+        res.headers.set(ContentEncoding(vec![encoding]));
+        res.headers.set(ContentLength(compressed.len() as u64));
+        // The compressed representation is not byte-for-byte the tagged one, but it is still
+        // semantically equivalent.
+        let weak_tag = res.headers
+                          .get::<ETag>()
+                          .map(|tag| EntityTag::weak(tag.tag().to_string()));
+        if let Some(tag) = weak_tag {
+            res.headers.set(ETag(tag));
+        }
+        res.body = Some(Box::new(compressed));
+        Ok(res)
+    }
+}
+
+/// Picks the content coding the client prefers, if it accepts any.
+fn negotiate(req: &Request) -> Option<Coding> {
+    let accepted = &req.headers.get::<AcceptEncoding>()?.0;
+    // An explicitly listed coding takes precedence over the `*` wildcard.
+    let quality = |name: &str| {
+        let find = |name: &str| {
+            accepted.iter()
+                    .find(|item| item.item.to_string() == name)
+                    .map(|item| item.quality.0)
+        };
+        find(name).or_else(|| find("*")).unwrap_or(0)
+    };
+    let (brotli, gzip) = (quality("br"), quality("gzip"));
+    if brotli > 0 && brotli >= gzip {
+        Some(Coding::Brotli)
+    } else if gzip > 0 {
+        Some(Coding::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Compresses data with a content coding.
+fn compress(coding: Coding, data: &[u8]) -> io::Result<Vec<u8>> {
+    match coding {
+        Coding::Brotli => {
+            let mut writer = CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+            writer.write_all(data)?;
+            Ok(writer.into_inner())
+        }
+        Coding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Decompresses a gzipped upload, giving up as soon as it turns out to be bigger than `limit`, so
+/// a few kilobytes can't blow up into gigabytes (a zip bomb).
+pub fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(data).take(limit as u64 + 1)
+                             .read_to_end(&mut decompressed)
+                             .map_err(|e| Error::InvalidEncodedBody(e.to_string()))?;
+    if decompressed.len() > limit {
+        return Err(Error::TooBig(limit, None));
+    }
+    Ok(decompressed)
+}
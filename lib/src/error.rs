@@ -1,8 +1,13 @@
 //! Library erros.
 
+use Tombstone;
 use base64;
+use chrono::{DateTime, Utc};
 use iron::IronError;
 use iron::status;
+use limits::HumanSize;
+use mime::MimeType;
+use std::error;
 use std::io;
 use tera;
 
@@ -15,19 +20,238 @@ quick_error!{
             from()
             cause(err)
         }
-        /// Data limit exceeded.
-        TooBig {
+        /// Data limit exceeded, for pastes of the given mime type (if it is known yet).
+        TooBig(limit: usize, mime_type: Option<MimeType>) {
             description("Too large paste")
+            display("{} limited to {}",
+                    match *mime_type {
+                        Some(ref mime_type) => format!("Pastes of type {} are", mime_type),
+                        None => "Pastes are".to_string(),
+                    },
+                    HumanSize(*limit))
+        }
+        /// Malformed size limit rule.
+        InvalidSizeLimit(rule: String) {
+            description("Invalid size limit")
+            display("Invalid size limit {:?}", rule)
+        }
+        /// Malformed retention rule.
+        InvalidRetention(rule: String) {
+            description("Invalid retention rule")
+            display("Invalid retention rule {:?}", rule)
+        }
+        /// Malformed JSON request body.
+        InvalidJson(reason: String) {
+            description("Invalid JSON")
+            display("Invalid JSON: {}", reason)
+        }
+        /// A body that can't be decoded according to its `Content-Encoding`.
+        InvalidEncodedBody(reason: String) {
+            description("Invalid encoded body")
+            display("Can't decode the body: {}", reason)
+        }
+        /// A `Content-Encoding` of a body that is not supported.
+        UnsupportedEncoding(encoding: String) {
+            description("Unsupported content encoding")
+            display("Content encoding {:?} is not supported, expected gzip", encoding)
+        }
+        /// Malformed `multipart/form-data` body.
+        InvalidMultipart(reason: String) {
+            description("Invalid multipart body")
+            display("Invalid multipart/form-data body: {}", reason)
+        }
+        /// No converter of a paste of the mime type into the format.
+        UnsupportedConversion(mime_type: MimeType, format: String) {
+            description("Unsupported conversion")
+            display("Pastes of type {} can't be converted into {:?}", mime_type, format)
+        }
+        /// A paste can't be converted.
+        ConversionFailed(reason: String) {
+            description("Conversion failed")
+            display("Conversion failed: {}", reason)
+        }
+        /// Malformed address or network of a trusted proxy.
+        InvalidProxy(network: String) {
+            description("Invalid trusted proxy")
+            display("Invalid trusted proxy {:?}, expected an address or a network like 10.0.0.0/8",
+                    network)
+        }
+        /// Malformed Basic authentication users.
+        InvalidBasicAuth(reason: String) {
+            description("Invalid Basic authentication users")
+            display("Invalid Basic authentication users: {}", reason)
+        }
+        /// Malformed announcement banner.
+        InvalidBanner(reason: String) {
+            description("Invalid banner")
+            display("Invalid banner: {}", reason)
+        }
+        /// Malformed extra page configuration.
+        InvalidPage(reason: String) {
+            description("Invalid extra page")
+            display("Invalid extra page: {}", reason)
         }
         /// Malformed URI (no ID).
         NoIdSegment {
             description("ID segment not found in the URL")
         }
-        /// Unknown ID.
-        IdNotFound(id: u64) {
+        /// A request to an endpoint that doesn't exist.
+        UnknownEndpoint {
+            description("Unknown endpoint")
+        }
+        /// Unknown ID (encoded the way the service shows IDs, since the message reaches clients).
+        IdNotFound(id: String) {
             description("ID not found")
             display("Id {} not found", id)
         }
+        /// The paste with the given (encoded) ID has been removed, but its tombstone is still kept.
+        Removed(id: String, tombstone: Tombstone) {
+            description("Paste has been removed")
+            display("{}", describe_removal(id, tombstone))
+        }
+        /// Malformed ID.
+        InvalidId(id: String) {
+            description("Invalid ID")
+            display("Invalid ID {}", id)
+        }
+        /// An alphabet of IDs that is too short, or has characters that are not allowed or are
+        /// repeated.
+        InvalidAlphabet(digits: String) {
+            description("Invalid ID alphabet")
+            display("Invalid ID alphabet {:?}, expected at least two distinct letters, digits, \
+                     dashes or underscores",
+                    digits)
+        }
+        /// Malformed content hash of a permalink.
+        InvalidHash(hash: String) {
+            description("Invalid hash")
+            display("Invalid hash {:?}, expected 64 lowercase hex digits", hash)
+        }
+        /// No paste with the content hash.
+        HashNotFound(hash: String) {
+            description("Hash not found")
+            display("No paste with hash {}", hash)
+        }
+        /// Malformed expiration date.
+        InvalidExpires(expires: String) {
+            description("Invalid expiration date")
+            display("Invalid expiration date {:?}, expected a Unix timestamp, a lifetime like 1h \
+                     or never",
+                    expires)
+        }
+        /// An expiration date that has already passed.
+        ExpiresInPast(expires_at: DateTime<Utc>) {
+            description("Expiration date in the past")
+            display("Expiration date {} is in the past",
+                    expires_at.format("%Y-%m-%d %H:%M:%S UTC"))
+        }
+        /// Malformed language name.
+        InvalidLanguage(language: String) {
+            description("Invalid language")
+            display("Invalid language {:?}, expected a name like rust or cpp", language)
+        }
+        /// Malformed view limit.
+        InvalidMaxViews(max_views: String) {
+            description("Invalid view limit")
+            display("Invalid view limit {:?}, expected a positive number", max_views)
+        }
+        /// Malformed spam feedback label.
+        InvalidLabel(label: String) {
+            description("Invalid label")
+            display("Invalid label {:?}, expected spam or ham", label)
+        }
+        /// The upload has been rejected by the spam filter.
+        Spam {
+            description("Rejected as spam")
+            display("The paste has been rejected as spam")
+        }
+        /// Malformed timestamp.
+        InvalidTimestamp(timestamp: String) {
+            description("Invalid timestamp")
+            display("Invalid timestamp {:?}, expected a Unix timestamp", timestamp)
+        }
+        /// Malformed listing cursor.
+        InvalidCursor(cursor: String) {
+            description("Invalid cursor")
+            display("Invalid cursor {:?}", cursor)
+        }
+        /// Malformed revision of a paste.
+        InvalidRevision(revision: String) {
+            description("Invalid revision")
+            display("Invalid revision {:?}, expected a number", revision)
+        }
+        /// Malformed slug of a paste, along with the reason.
+        InvalidSlug(slug: String, reason: &'static str) {
+            description("Invalid slug")
+            display("Invalid slug {:?}: {}", slug, reason)
+        }
+        /// No existing paste has claimed the slug.
+        SlugNotFound(slug: String) {
+            description("Slug not found")
+            display("No paste with slug {}", slug)
+        }
+        /// The slug is claimed by another paste.
+        SlugTaken(slug: String) {
+            description("Slug is taken")
+            display("Slug {:?} is taken", slug)
+        }
+        /// The paste with the given (encoded) ID has been updated since the revision an update is
+        /// based on.
+        Conflict(id: String, expected: u64, current: u64) {
+            description("Conflicting update")
+            display("Paste {} has been updated since revision {}, it is at revision {} now",
+                    id,
+                    expected,
+                    current)
+        }
+        /// Every ID the ID generator has come up with for a new paste is taken.
+        NoFreeId(attempts: usize) {
+            description("No free ID")
+            display("No free ID has been found in {} attempts", attempts)
+        }
+        /// Malformed number of entries on a page.
+        InvalidLimit(limit: String) {
+            description("Invalid limit")
+            display("Invalid limit {:?}, expected a positive number", limit)
+        }
+        /// A required URI argument is not provided.
+        MissingArgument(name: &'static str) {
+            description("Missing argument")
+            display("Argument {} is missing", name)
+        }
+        /// A bulk removal doesn't say which pastes to remove.
+        NoFilter {
+            description("No filter")
+            display("At least one of creator, mime_type, since and until is required")
+        }
+        /// A deletion token chosen by an uploader is too easy to guess.
+        WeakToken(min_length: usize) {
+            description("Weak token")
+            display("The token has to be at least {} characters of URL-safe base64", min_length)
+        }
+        /// A request on behalf of an owner names someone else than the caller.
+        UnknownOwner(owner: String) {
+            description("Unknown owner")
+            display("Unknown owner {:?}, only me is supported", owner)
+        }
+        /// The request requires an authorization it doesn't have.
+        Unauthorized {
+            description("Unauthorized")
+        }
+        /// The request is not allowed to do what it asks for.
+        Forbidden {
+            description("Forbidden")
+        }
+        /// A form is submitted by a browser without the CSRF token of its session.
+        InvalidCsrfToken {
+            description("Invalid CSRF token")
+            display("The form has expired, please reload the page and submit it again")
+        }
+        /// Malformed mime type.
+        InvalidMimeType(mime_type: String) {
+            description("Invalid mime type")
+            display("Invalid mime type {}", mime_type)
+        }
         /// ID decoder error.
         IdDecode(err: base64::DecodeError) {
             from()
@@ -43,19 +267,154 @@ quick_error!{
             description("Can't parse URL")
             display("Can't parse URL: {}", err)
         }
-        /// We expect a `ContentLength` header for incoming requests.
+        /// We expect either a `ContentLength` header or a chunked body for incoming requests.
         NoContentLength {
             description("No content-length header provided")
         }
+        /// The upload has been aborted after receiving some of the announced bytes (if the length
+        /// has been announced at all), either because the deadline has passed (`timed_out`) or
+        /// because the client has disconnected.
+        UploadAborted(received: usize, expected: Option<usize>, timed_out: bool) {
+            description("Upload aborted")
+            display("Upload aborted after {}{} bytes: {}",
+                    received,
+                    match *expected {
+                        Some(expected) => format!(" of {}", expected),
+                        None => String::new(),
+                    },
+                    if *timed_out {
+                        "the deadline has passed"
+                    } else {
+                        "the client has disconnected"
+                    })
+        }
+        /// Database failure.
+        Db(err: Box<error::Error + Send + Sync>) {
+            description("Database failure")
+            cause(&**err)
+        }
+        /// An error with a description of what was going on when it happened.
+        ///
+        /// The description is displayed, while the original error is available as the cause.
+        Context(context: String, err: Box<Error>) {
+            description("Error with a context")
+            display("{}", context)
+            cause(&**err)
+        }
+    }
+}
+
+impl Error {
+    /// Wraps the error with a description of what was going on.
+    pub fn context<C: Into<String>>(self, context: C) -> Error {
+        Error::Context(context.into(), Box::new(self))
+    }
+
+    /// Returns an HTTP status code corresponding to the error.
+    pub fn status(&self) -> status::Status {
+        match *self {
+            Error::IdNotFound(_) |
+            Error::HashNotFound(_) |
+            Error::SlugNotFound(_) |
+            Error::UnknownEndpoint => status::NotFound,
+            Error::TooBig(..) => status::PayloadTooLarge,
+            Error::Unauthorized => status::Unauthorized,
+            Error::UnsupportedConversion(..) | Error::UnsupportedEncoding(_) => {
+                status::UnsupportedMediaType
+            }
+            Error::ConversionFailed(_) => status::UnprocessableEntity,
+            Error::Forbidden | Error::InvalidCsrfToken | Error::Spam => status::Forbidden,
+            Error::Removed(..) => status::Gone,
+            Error::Conflict(..) | Error::SlugTaken(_) => status::Conflict,
+            Error::UploadAborted(_, _, true) => status::RequestTimeout,
+            Error::Db(_) | Error::Tera(_) | Error::Url(_) | Error::NoFreeId(_) => {
+                status::InternalServerError
+            }
+            Error::Context(_, ref err) => err.status(),
+            _ => status::BadRequest,
+        }
     }
 }
 
 impl From<Error> for IronError {
     fn from(err: Error) -> IronError {
+        let status = err.status();
         match err {
-            e @ Error::IdNotFound(_) => IronError::new(e, status::NotFound),
-            e @ Error::TooBig => IronError::new(e, status::PayloadTooLarge),
-            e => IronError::new(e, status::BadRequest),
+            Error::TooBig(..) |
+            Error::Removed(..) |
+            Error::Conflict(..) |
+            Error::InvalidSlug(..) |
+            Error::SlugTaken(_) |
+            Error::Spam |
+            Error::InvalidCsrfToken => {
+                // Let the client know what the limit is or what has happened to the paste (or the
+                // form).
+                let message = format!("{}\n", err);
+                IronError::new(err, (status, message))
+            }
+            _ => IronError::new(err, status),
         }
     }
 }
+
+/// Describes a removed paste, like `Paste 1y has been removed by its uploader on 2018-05-01
+/// 12:30:00 UTC`.
+fn describe_removal(id: &str, tombstone: &Tombstone) -> String {
+    let actor = if tombstone.actor == "uploader" {
+        "its uploader"
+    } else {
+        "an admin"
+    };
+    format!("Paste {} has been removed by {} on {}",
+            id,
+            actor,
+            tombstone.removed_at.format("%Y-%m-%d %H:%M:%S UTC"))
+}
+
+/// Renders an error together with all its causes, like `reading data: Broken pipe`.
+pub fn display_chain(err: &error::Error) -> String {
+    let mut result = err.to_string();
+    let mut cause = err.cause();
+    while let Some(err) = cause {
+        result.push_str(": ");
+        result.push_str(&err.to_string());
+        cause = err.cause();
+    }
+    result
+}
+
+/// Attaching a context to errors.
+pub trait ResultExt<T> {
+    /// Converts an error into the library's `Error` and wraps it with a (lazily computed) context.
+    fn context<F, C>(self, f: F) -> Result<T, Error>
+        where F: FnOnce() -> C,
+              C: Into<String>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn context<F, C>(self, f: F) -> Result<T, Error>
+        where F: FnOnce() -> C,
+              C: Into<String>
+    {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+/// Attaching a context to database errors.
+pub trait DbResultExt<T> {
+    /// Converts a database error into `Error::Db` and wraps it with a (lazily computed) context.
+    fn db_context<F, C>(self, f: F) -> Result<T, Error>
+        where F: FnOnce() -> C,
+              C: Into<String>;
+}
+
+impl<T, E> DbResultExt<T> for Result<T, E>
+    where E: error::Error + Send + Sync + 'static
+{
+    fn db_context<F, C>(self, f: F) -> Result<T, Error>
+        where F: FnOnce() -> C,
+              C: Into<String>
+    {
+        self.map_err(|e| Error::Db(Box::new(e)).context(f()))
+    }
+}
@@ -0,0 +1,111 @@
+//! Importing pastes from dumps of other pastebins.
+//!
+//! The supported pastebins keep pastes as plain files in a directory, one file per paste:
+//!
+//! * [hastebin](https://github.com/seejohnrun/haste-server) with the file storage: the files are
+//!   named after hashes of document keys and hold the text of the documents;
+//! * [0x0](https://git.0x0.st/mia/0x0): the upload directory (`up`) holds the uploaded files as
+//!   they are, while their original names live in 0x0's database and are lost.
+//!
+//! Neither file names keep the links to the pastes, so the imported pastes get new IDs, and the
+//! modification times of the files become their creation dates. Expiration dates are not carried
+//! over either, the imported pastes are kept until they are removed.
+//!
+//! PrivateBin dumps can't be imported: pastes are encrypted in the browser, and the server never
+//! has the keys.
+
+use {DbInterface, PasteEntry};
+use chrono::{DateTime, Utc};
+use dump::DumpError;
+use mime::{self, MimeType};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A pastebin whose dumps could be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A hastebin file storage directory, text documents only.
+    Hastebin,
+    /// A 0x0 upload directory, files of any kind.
+    ZeroX0,
+}
+
+impl FromStr for Format {
+    type Err = DumpError;
+
+    fn from_str(s: &str) -> Result<Self, DumpError> {
+        match s {
+            "hastebin" => Ok(Format::Hastebin),
+            "0x0" => Ok(Format::ZeroX0),
+            _ => Err(DumpError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        Format::Hastebin => "hastebin",
+                        Format::ZeroX0 => "0x0",
+                    })
+    }
+}
+
+/// Stores the pastes of a dump in the directory `dir` into the database with
+/// `DbInterface::store_data`, so they get new IDs.
+///
+/// Files are imported in the order of their names; subdirectories and hidden files are skipped,
+/// and so are files too large for the database, with a warning. Returns the number of imported
+/// pastes, the ones imported before a failure stay imported.
+pub fn import<D>(db: &D, format: Format, dir: &Path) -> Result<u64, DumpError>
+    where D: DbInterface + ?Sized
+{
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let hidden = entry.file_name()
+                          .to_str()
+                          .map_or(false, |name| name.starts_with('.'));
+        if !hidden && entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+
+    let mut imported = 0;
+    for path in paths {
+        let data = fs::read(&path)?;
+        if data.len() > db.max_data_size() {
+            warn!("Skipping {}: {} bytes is too much for the database",
+                  path.display(),
+                  data.len());
+            continue;
+        }
+        let created_at = fs::metadata(&path)?.modified()
+                                             .map(DateTime::<Utc>::from)
+                                             .unwrap_or_else(|_| Utc::now());
+        let mime_type = match format {
+            Format::Hastebin => MimeType::text_plain(),
+            Format::ZeroX0 => mime::data_mime_type(None::<&str>, &data),
+        };
+        let entry = PasteEntry { data,
+                                 file_name: None,
+                                 mime_type,
+                                 created_at,
+                                 best_before: None,
+                                 views: 0,
+                                 creator: None,
+                                 deletion_token: None,
+                                 language: None,
+                                 revision: 0,
+                                 views_left: None,
+                                 public: false, };
+        let id = db.store_data(entry)
+                   .map_err(|e| DumpError::Db(Box::new(e)))?;
+        debug!("Imported {} as {}", path.display(), id);
+        imported += 1;
+    }
+    Ok(imported)
+}
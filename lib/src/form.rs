@@ -0,0 +1,55 @@
+//! Uploads of classic HTML forms, submitted as `application/x-www-form-urlencoded`.
+//!
+//! The text of the paste comes in the `content` field, along with the optional `expires`,
+//! `filename`, `public` (a checkbox) and `csrf_token` (see the `csrf` module) fields, so the
+//! upload page works without JavaScript. Other fields are ignored.
+
+use Error;
+use api::Upload;
+use iron::headers::ContentType;
+use iron::mime::{Mime, SubLevel, TopLevel};
+use iron::prelude::*;
+use iron::url::form_urlencoded;
+
+/// Checks whether a request has a form body, according to its `Content-Type`.
+pub fn is_urlencoded(req: &Request) -> bool {
+    match req.headers.get::<ContentType>() {
+        Some(&ContentType(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, _))) => true,
+        _ => false,
+    }
+}
+
+/// Returns the value of a field of a form body, if it is there and not empty.
+pub fn field(body: &[u8], field: &str) -> Option<String> {
+    form_urlencoded::parse(body).find(|pair| pair.0 == field)
+                                .map(|(_, value)| value.into_owned())
+                                .filter(|value| !value.is_empty())
+}
+
+/// Parses a form body into an upload.
+pub fn parse_upload(body: &[u8]) -> Result<Upload, Error> {
+    let mut content = None;
+    let mut file_name = None;
+    let mut expires = None;
+    let mut public = false;
+    let mut csrf_token = None;
+    for (name, value) in form_urlencoded::parse(body) {
+        // Fields left empty are as good as missing.
+        let value = Some(value.into_owned()).filter(|value| !value.is_empty());
+        match &*name {
+            "content" => content = value,
+            "filename" => file_name = value,
+            "expires" => expires = value,
+            "public" => public = value.is_some(),
+            "csrf_token" => csrf_token = value,
+            _ => {}
+        }
+    }
+    let content = content.ok_or(Error::MissingArgument("content"))?;
+    // Browsers submit line breaks of a text area as CRLF, whatever the text has been.
+    Ok(Upload { data: content.replace("\r\n", "\n").into_bytes(),
+                file_name,
+                expires,
+                public,
+                csrf_token, })
+}
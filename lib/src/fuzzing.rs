@@ -0,0 +1,6 @@
+//! Parsers of untrusted input, exposed for the fuzzing harness in the `fuzz` directory.
+//!
+//! This is not a part of the public API, it might change at any moment.
+
+pub use mime::data_mime_type;
+pub use request::parse_expires;
@@ -0,0 +1,71 @@
+//! Human-friendly sizes and durations for the web pages and JSON responses.
+//!
+//! Both are available in templates as filters: `{{ size | filesize }}` turns a number of bytes
+//! into something like `1.5 KiB`, and `{{ expires_in | humantime }}` turns a number of seconds
+//! into something like `3 days`.
+
+use std::collections::HashMap;
+use tera::{self, Tera, Value};
+
+/// Units of sizes, each one is 1024 times bigger than the previous one.
+const SIZE_UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+/// Units of durations, in seconds, from the biggest one.
+const TIME_UNITS: &[(u64, &str)] = &[(365 * 24 * 60 * 60, "year"),
+                                     (24 * 60 * 60, "day"),
+                                     (60 * 60, "hour"),
+                                     (60, "minute"),
+                                     (1, "second")];
+
+/// Formats a size like `1.5 KiB`, sizes below a kibibyte are shown in bytes.
+pub fn filesize(bytes: u64) -> String {
+    if bytes < 1024 {
+        return plural(bytes, "byte");
+    }
+    let mut size = bytes as f64 / 1024.;
+    let mut unit = 0;
+    while size >= 1024. && unit + 1 < SIZE_UNITS.len() {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, SIZE_UNITS[unit])
+}
+
+/// Formats a duration in seconds in the biggest unit that fits, like `3 days` (rounded down).
+pub fn humantime(seconds: u64) -> String {
+    let &(unit, name) = TIME_UNITS.iter()
+                                  .find(|&&(unit, _)| seconds >= unit)
+                                  .unwrap_or(&(1, "second"));
+    plural(seconds / unit, name)
+}
+
+fn plural(n: u64, name: &str) -> String {
+    if n == 1 {
+        format!("1 {}", name)
+    } else {
+        format!("{} {}s", n, name)
+    }
+}
+
+fn filesize_filter(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    match value.as_u64() {
+        Some(bytes) => Ok(Value::String(filesize(bytes))),
+        None => Err(format!("Filter `filesize` expects a number of bytes, got {}", value).into()),
+    }
+}
+
+fn humantime_filter(value: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
+    match value.as_u64() {
+        Some(seconds) => Ok(Value::String(humantime(seconds))),
+        None => {
+            Err(format!("Filter `humantime` expects a number of seconds, got {}", value).into())
+        }
+    }
+}
+
+/// Registers the `filesize` and `humantime` filters.
+pub fn register_filters(templates: &mut Tera) {
+    templates.register_filter("filesize", filesize_filter);
+    templates.register_filter("humantime", humantime_filter);
+}
+
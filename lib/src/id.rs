@@ -1,7 +1,14 @@
 //! Short ID generator/decoder, based on `base64` (url-safe, no-padding version).
+//!
+//! Paste IDs are plain `u64` numbers internally (wrapped into `PasteId`), while in URLs they are
+//! represented by short strings produced by `encode_id`, or by an `IdEncoding` with another
+//! alphabet. The numbers of new pastes are chosen by an `IdGenerator`.
 
 use base64;
 use error::Error;
+use rand::{thread_rng, Rng};
+use std::fmt;
+use std::str::FromStr;
 
 /// Combines `u8` numbers into one `u64`. Will panic if there are more than 8 elements provided.
 fn combine_bits(buf: &[u8]) -> u64 {
@@ -33,11 +40,317 @@ fn trim(b: &[u8]) -> &[u8] {
 }
 
 /// Encodes a given `u64` number into a string as short as possible.
+///
+/// It is the default [IdEncoding](struct.IdEncoding.html), see there for other alphabets.
 pub fn encode_id(id: u64) -> String {
     base64::encode_config(trim(&split_into_bits(id)), base64::URL_SAFE_NO_PAD)
 }
 
 /// Converts a string created with `encode_id` function back into a number.
 pub fn decode_id(id: &str) -> Result<u64, Error> {
-    Ok(combine_bits(&base64::decode_config(id, base64::URL_SAFE_NO_PAD)?))
+    let bits = base64::decode_config(id, base64::URL_SAFE_NO_PAD)?;
+    if bits.len() > 8 {
+        return Err(Error::InvalidId(id.into()));
+    }
+    Ok(combine_bits(&bits))
+}
+
+/// Digits of base58 IDs, as Bitcoin has them: neither `0` and `O` nor `I` and `l` could be
+/// mistaken for one another.
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Digits of base62 IDs: letters and digits only, so a double click selects a whole ID.
+const BASE62_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// How paste IDs are written in URLs, see
+/// [PastebinBuilder::id_encoding](web/struct.PastebinBuilder.html#method.id_encoding).
+///
+/// By default IDs are the bytes of the numbers in base64, like `encode_id` makes them. Otherwise
+/// an ID is the number written in the digits of an alphabet, like base58, which leaves out the
+/// characters that look alike, or base62, which leaves out `-` and `_`. IDs could be padded to a
+/// minimum length, so the first pastes don't get one-character URLs:
+///
+/// ```
+/// # extern crate pastebin;
+/// use pastebin::IdEncoding;
+///
+/// # fn main() {
+/// let encoding = "base58".parse::<IdEncoding>().unwrap().min_length(4);
+/// assert_eq!(encoding.encode(57), "111z");
+/// assert_eq!(encoding.decode("111z").unwrap(), 57);
+/// assert_eq!(encoding.decode("z").unwrap(), 57);
+/// # }
+/// ```
+///
+/// Padded IDs are decoded with the padding left out as well, so switching to another minimum
+/// length keeps the old URLs working. Switching to another alphabet doesn't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdEncoding {
+    /// Digits of IDs, or `None` for the bytes of IDs in base64.
+    alphabet: Option<Vec<u8>>,
+    min_length: usize,
+}
+
+impl Default for IdEncoding {
+    fn default() -> Self {
+        IdEncoding::base64()
+    }
+}
+
+impl IdEncoding {
+    /// The bytes of IDs in url-safe base64, as `encode_id` makes them.
+    ///
+    /// They are padded with zero bytes, so a padded ID might come out a character longer than the
+    /// minimum, and no ID is longer than 11 characters.
+    pub fn base64() -> Self {
+        IdEncoding { alphabet: None,
+                     min_length: 0, }
+    }
+
+    /// Numbers in base58, without characters that look alike.
+    pub fn base58() -> Self {
+        IdEncoding { alphabet: Some(BASE58_ALPHABET.into()),
+                     min_length: 0, }
+    }
+
+    /// Numbers in base62, letters and digits only.
+    pub fn base62() -> Self {
+        IdEncoding { alphabet: Some(BASE62_ALPHABET.into()),
+                     min_length: 0, }
+    }
+
+    /// Numbers written in the given digits, the zero first.
+    ///
+    /// Digits are letters, digits, dashes and underscores (anything else might clash with the
+    /// routes), at least two of them and none twice.
+    pub fn alphabet(digits: &str) -> Result<Self, Error> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        // Allowed digits are ASCII, so they are single bytes.
+        let unique = || {
+            digits.bytes()
+                  .enumerate()
+                  .all(|(index, digit)| !digits.as_bytes()[index + 1..].contains(&digit))
+        };
+        if digits.len() < 2 || !digits.chars().all(allowed) || !unique() {
+            return Err(Error::InvalidAlphabet(digits.into()));
+        }
+        Ok(IdEncoding { alphabet: Some(digits.into()),
+                        min_length: 0, })
+    }
+
+    /// Pads IDs shorter than `min_length` characters (with zeros, so the numbers stay the same).
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Writes an ID down.
+    pub fn encode(&self, id: u64) -> String {
+        let digits = match self.alphabet {
+            Some(ref digits) => digits,
+            None => return self.encode_base64(id),
+        };
+        let base = digits.len() as u64;
+        let mut encoded = Vec::new();
+        let mut rest = id;
+        while rest > 0 || encoded.is_empty() {
+            encoded.push(digits[(rest % base) as usize]);
+            rest /= base;
+        }
+        while encoded.len() < self.min_length {
+            encoded.push(digits[0]);
+        }
+        encoded.reverse();
+        String::from_utf8(encoded).expect("the digits are ASCII")
+    }
+
+    /// Reads an ID written by `encode`, with or without the padding.
+    pub fn decode(&self, id: &str) -> Result<u64, Error> {
+        let digits = match self.alphabet {
+            Some(ref digits) => digits,
+            None => return decode_id(id),
+        };
+        let invalid = || Error::InvalidId(id.into());
+        if id.is_empty() {
+            return Err(invalid());
+        }
+        let mut number = 0u64;
+        for byte in id.bytes() {
+            let digit = digits.iter()
+                              .position(|&digit| digit == byte)
+                              .ok_or_else(invalid)?;
+            number = number.checked_mul(digits.len() as u64)
+                           .and_then(|number| number.checked_add(digit as u64))
+                           .ok_or_else(invalid)?;
+        }
+        Ok(number)
+    }
+
+    /// Checks whether a string is exactly how `encode` writes some ID down.
+    pub fn is_id(&self, s: &str) -> bool {
+        self.decode(s)
+            .map(|id| self.encode(id) == s)
+            .unwrap_or(false)
+    }
+
+    fn encode_base64(&self, id: u64) -> String {
+        let bytes = split_into_bits(id);
+        let mut start = bytes.len() - trim(&bytes).len();
+        let mut encoded = encode_id(id);
+        while encoded.len() < self.min_length && start > 0 {
+            start -= 1;
+            encoded = base64::encode_config(&bytes[start..], base64::URL_SAFE_NO_PAD);
+        }
+        encoded
+    }
+}
+
+impl FromStr for IdEncoding {
+    type Err = Error;
+
+    /// Parses `base64`, `base58`, `base62` or the digits of another alphabet.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "base64" => Ok(IdEncoding::base64()),
+            "base58" => Ok(IdEncoding::base58()),
+            "base62" => Ok(IdEncoding::base62()),
+            digits => IdEncoding::alphabet(digits),
+        }
+    }
+}
+
+/// Number of attempts a `RandomIds` generator draws IDs of the configured length before it falls
+/// back to the longest ones.
+const RANDOM_ID_ATTEMPTS: usize = 8;
+
+/// Chooses the IDs of new pastes, see
+/// [PastebinBuilder::id_generator](web/struct.PastebinBuilder.html#method.id_generator).
+///
+/// A generator either leaves it to the database, which issues sequential IDs, or picks an ID by
+/// itself. In the latter case the paste is only stored if the ID is not taken (by a removed paste
+/// either), otherwise the generator is asked again. Custom generators could be implemented as
+/// well:
+///
+/// ```
+/// # extern crate pastebin;
+/// use pastebin::IdGenerator;
+/// use std::time::{SystemTime, UNIX_EPOCH};
+///
+/// /// IDs made of the upload time, in microseconds.
+/// struct TimeIds;
+///
+/// impl IdGenerator for TimeIds {
+///     fn generate(&self, attempt: usize) -> Option<u64> {
+///         let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+///         Some(now.as_secs() * 1_000_000 + u64::from(now.subsec_micros()) + attempt as u64)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+pub trait IdGenerator: Send + Sync {
+    /// Returns the ID to store a new paste under, or `None` to leave it to the database.
+    ///
+    /// `attempt` is the number of IDs returned for the paste before that turned out to be taken.
+    fn generate(&self, attempt: usize) -> Option<u64>;
+}
+
+/// Sequential IDs issued by the database, which is the default.
+///
+/// They are as short as IDs get, but anyone could walk through all the pastes (`/AQ`, `/Ag`, ...),
+/// listed or not.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequentialIds;
+
+impl IdGenerator for SequentialIds {
+    fn generate(&self, _attempt: usize) -> Option<u64> {
+        None
+    }
+}
+
+/// Random IDs of a given number of bytes, which can't be enumerated.
+///
+/// Mind that there are only 256 IDs of a byte, 65536 of two bytes and so on: once an ID of the
+/// configured length has turned out to be taken several times in a row, longer ones are drawn.
+/// The sign bit is never set, since some databases store IDs as signed numbers, so IDs of 8 bytes
+/// are actually 63-bit ones (11 characters in URLs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomIds {
+    bytes: usize,
+}
+
+impl Default for RandomIds {
+    fn default() -> Self {
+        RandomIds::new(8)
+    }
+}
+
+impl RandomIds {
+    /// Creates a generator of IDs of `bytes` bytes, from 1 to 8.
+    pub fn new(bytes: usize) -> Self {
+        let bytes = match bytes {
+            0 => 1,
+            bytes if bytes > 8 => 8,
+            bytes => bytes,
+        };
+        RandomIds { bytes }
+    }
+}
+
+impl IdGenerator for RandomIds {
+    fn generate(&self, attempt: usize) -> Option<u64> {
+        let bytes = if attempt < RANDOM_ID_ATTEMPTS { self.bytes } else { 8 };
+        let bits = (bytes * 8).min(63);
+        let mut rng = thread_rng();
+        loop {
+            let id = rng.gen::<u64>() >> (64 - bits);
+            if id != 0 {
+                return Some(id);
+            }
+        }
+    }
+}
+
+/// A paste ID.
+///
+/// It is a plain number internally, which is displayed (and parsed from a string) in the short
+/// form produced by [encode_id](fn.encode_id.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PasteId(u64);
+
+impl PasteId {
+    /// Wraps a numeric ID.
+    pub fn new(id: u64) -> Self {
+        PasteId(id)
+    }
+
+    /// Returns the numeric ID.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for PasteId {
+    fn from(id: u64) -> Self {
+        PasteId(id)
+    }
+}
+
+impl From<PasteId> for u64 {
+    fn from(id: PasteId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for PasteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&encode_id(self.0))
+    }
+}
+
+impl FromStr for PasteId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        decode_id(s).map(PasteId)
+    }
 }
@@ -0,0 +1,25 @@
+//! Background jobs.
+
+use chrono::Duration;
+use std::thread;
+use std::time;
+
+/// Spawns a detached thread that runs the `job` every `interval`.
+///
+/// The first run happens after the first `interval` passes. Non-positive intervals are treated as
+/// one second so the thread doesn't spin.
+pub fn spawn_periodic<F>(name: &str, interval: Duration, job: F)
+    where F: Fn() + Send + 'static
+{
+    let interval = interval.to_std()
+                           .ok()
+                           .filter(|interval| *interval > time::Duration::from_secs(0))
+                           .unwrap_or_else(|| time::Duration::from_secs(1));
+    let runner = move || loop {
+        thread::sleep(interval);
+        job();
+    };
+    if let Err(e) = thread::Builder::new().name(name.into()).spawn(runner) {
+        error!("Can't spawn a `{}` thread: {}", name, e);
+    }
+}
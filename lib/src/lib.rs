@@ -8,9 +8,60 @@
 //! [Iron](https://github.com/iron/iron) is used as a web-backend, so all its features could be
 //! utilized (at least theoretically). The actual code is in the [web](web/index.html) module,
 //! useful examples are also there.
+//!
+//! # Public API
+//!
+//! The types most embedders need are collected in the [prelude](prelude/index.html) module. The
+//! request handler itself ([Pastebin](struct.Pastebin.html)) is public as well, so it could be
+//! mounted into an existing Iron chain instead of running a dedicated server.
+//!
+//! # Cargo features
+//!
+//! Heavyweight subsystems are optional, so embedders who only need the core handler can keep the
+//! build lean:
+//!
+//! * `magic` (enabled by default): content-based mime type detection. Without it mime types are
+//! guessed by file names only.
+//! * `compression`: transparent compression of the stored data, see
+//! [db::CompressedDb](db/struct.CompressedDb.html).
+//! * `encryption`: encryption of the stored data at rest, see
+//! [db::EncryptedDb](db/struct.EncryptedDb.html).
+//! * `http-compression`: compressing responses with gzip or brotli, see
+//! [web::ResponseCompression](web/struct.ResponseCompression.html), and decompressing gzipped
+//! uploads.
+//! * `basic-auth`: HTTP Basic authentication of users, see the [auth](auth/index.html) module.
+//! * `dedup`: storing identical pastes only once, see [db::DedupDb](db/struct.DedupDb.html).
+//! * `signing`: signing pastes with a server key, see [db::SignedDb](db/struct.SignedDb.html).
+//! * `vault`: loading secrets from HashiCorp Vault, see the [secrets](secrets/index.html) module.
+//! * `copy`: copying pastes between instances, see the [copy](copy/index.html) module.
+//! * `yaml`: converting YAML pastes into JSON and back, see the [convert](convert/index.html)
+//! module.
+//! * `markdown`: rendering Markdown pastes into HTML (at `/<id>/html` as well), see the
+//! [convert](convert/index.html) module.
+//! * `metrics`: request and database metrics in the Prometheus text format at `/metrics`, see
+//! the [metrics](metrics/index.html) module and
+//! [db::InstrumentedDb](db/struct.InstrumentedDb.html).
+//! * `full`: enables all the optional subsystems.
+//!
+//! Syntax highlighting is done by highlight.js in the browser, so there is nothing to gate on the
+//! server: the crate only guesses the language names for it. The server doesn't process images
+//! either (thumbnails are the images themselves), and it has no gRPC or S3 support.
+
+// The `Error` enumeration is too long for `quick_error!` with the default limit.
+#![recursion_limit = "512"]
 
+extern crate backtrace;
 extern crate base64;
+#[cfg(feature = "basic-auth")]
+extern crate bcrypt;
+#[cfg(feature = "dedup")]
+extern crate blake2_rfc;
+#[cfg(feature = "http-compression")]
+extern crate brotli;
 extern crate chrono;
+#[cfg(feature = "http-compression")]
+extern crate flate2;
+extern crate hyper;
 #[macro_use]
 extern crate iron;
 #[macro_use]
@@ -18,44 +69,252 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate mime_guess;
+#[cfg(feature = "markdown")]
+extern crate pulldown_cmark;
 #[macro_use]
 extern crate quick_error;
 extern crate rand;
 extern crate serde;
 #[macro_use]
 extern crate serde_json;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
 extern crate tera;
+#[cfg(feature = "magic")]
 extern crate tree_magic;
+#[cfg(feature = "compression")]
+extern crate zstd;
+#[cfg(any(feature = "encryption", feature = "signing"))]
+extern crate ring;
+#[cfg(feature = "signing")]
+extern crate untrusted;
 
+pub mod analytics;
+#[cfg(feature = "basic-auth")]
+pub mod auth;
+pub mod convert;
+#[cfg(feature = "copy")]
+pub mod copy;
+pub mod db;
+pub mod dump;
+pub mod foreign;
+#[doc(hidden)]
+pub mod fuzzing;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod prelude;
+pub mod redact;
+pub mod secrets;
+pub mod spam;
 pub mod web;
 
+mod api;
+mod banner;
+mod csrf;
+mod drain;
+#[cfg(feature = "http-compression")]
+mod encoding;
 mod error;
+mod form;
+mod human;
 mod id;
+mod jobs;
+mod limits;
+mod middleware;
 mod mime;
+mod multipart;
+mod pages;
+mod pagination;
 mod pastebin;
+mod proxy;
 mod read;
 mod request;
+mod security;
+mod spec;
+mod summary;
 #[cfg(test)]
 mod test;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "copy", feature = "vault"))]
 extern crate reqwest;
 
 use chrono::{DateTime, Utc};
+pub use banner::{Banner, Severity};
 pub use error::Error;
+pub use id::{decode_id, encode_id, IdEncoding, IdGenerator, PasteId, RandomIds, SequentialIds};
+pub use limits::{Retention, RetentionPolicy, SizeLimits};
+pub use mime::MimeType;
+pub use pages::{ExtraPage, ExtraPages};
+pub use pastebin::Pastebin;
+pub use summary::{count_lines, guess_language};
 use iron::error::HttpResult;
+use std::fmt;
+use std::io;
 
 /// A paste representation. As simple as that.
-#[derive(Debug, Clone)]
+///
+/// The `Debug` representation doesn't include the data itself, see the [redact](redact/index.html)
+/// module.
+#[derive(Clone)]
 pub struct PasteEntry {
     /// Raw paste data.
     pub data: Vec<u8>,
     /// File name associated with the pate, if any.
     pub file_name: Option<String>,
     /// Mime type of the paste.
-    pub mime_type: String,
+    pub mime_type: MimeType,
+    /// Creation date.
+    pub created_at: DateTime<Utc>,
     /// Expiration date, if any.
     pub best_before: Option<DateTime<Utc>>,
+    /// Number of times the paste has been viewed.
+    pub views: u64,
+    /// Who has uploaded the paste (a client IP address, for instance), if recorded.
+    ///
+    /// This is only kept for a while to deal with abuse reports, see
+    /// `DbInterface::scrub_creators`.
+    pub creator: Option<String>,
+    /// A secret the uploader has to present to remove the paste, if any.
+    ///
+    /// Pastes uploaded with the same token belong to the same owner, see
+    /// `DbInterface::remove_by_owner`.
+    pub deletion_token: Option<String>,
+    /// Language of the paste (in terms of highlight.js) as the uploader has given it, if any.
+    ///
+    /// It takes precedence over the language guessed from the file name and the mime type, see
+    /// `PasteSummary::language`. Databases that store the guessed language along with a paste
+    /// might return it here as well. Replacing the data of a paste guesses a stored language
+    /// anew, but keeps the one the uploader has given.
+    pub language: Option<String>,
+    /// Number of times the data of the paste has been replaced, see `DbInterface::update_data`.
+    pub revision: u64,
+    /// Number of times the paste could still be viewed before it is removed, if it is limited.
+    ///
+    /// See `DbInterface::take_view`.
+    pub views_left: Option<u64>,
+    /// Whether the paste is listed at `/browse`; unlisted pastes are only reachable by their IDs.
+    pub public: bool,
+}
+
+impl fmt::Debug for PasteEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PasteEntry")
+         .field("data", &redact::redact(&self.data))
+         .field("file_name", &self.file_name)
+         .field("mime_type", &self.mime_type)
+         .field("created_at", &self.created_at)
+         .field("best_before", &self.best_before)
+         .field("views", &self.views)
+         .field("creator", &self.creator)
+         .field("deletion_token", &self.deletion_token.as_ref().map(|_| "<redacted>"))
+         .field("language", &self.language)
+         .field("revision", &self.revision)
+         .field("views_left", &self.views_left)
+         .field("public", &self.public)
+         .finish()
+    }
+}
+
+/// A short description of a paste, without its data.
+///
+/// Used for enumerating pastes and for metadata lookups, see `DbInterface::list` and
+/// `DbInterface::load_metadata`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasteSummary {
+    /// Paste ID.
+    pub id: PasteId,
+    /// File name associated with the paste, if any.
+    pub file_name: Option<String>,
+    /// Mime type of the paste.
+    pub mime_type: MimeType,
+    /// Size of the paste data in bytes.
+    pub size: usize,
+    /// Number of lines in the paste data.
+    pub lines: usize,
+    /// Language of the paste (in terms of highlight.js): the one given by the uploader, or an
+    /// estimated one, if any.
+    pub language: Option<String>,
+    /// Creation date.
+    pub created_at: DateTime<Utc>,
+    /// Expiration date, if any.
+    pub best_before: Option<DateTime<Utc>>,
+    /// Number of times the paste has been viewed.
+    pub views: u64,
+    /// Who has uploaded the paste, if recorded.
+    pub creator: Option<String>,
+    /// Number of times the data of the paste has been replaced.
+    pub revision: u64,
+    /// Number of times the paste could still be viewed, if it is limited.
+    pub views_left: Option<u64>,
+    /// Whether the paste is listed publicly.
+    pub public: bool,
+}
+
+/// A record of a removed paste.
+///
+/// Tombstones outlive the pastes they describe, so a request for a removed paste could be told
+/// apart from a request for a paste that has never existed, and removals could be audited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tombstone {
+    /// ID of the removed paste.
+    pub id: PasteId,
+    /// Removal date.
+    pub removed_at: DateTime<Utc>,
+    /// Who has removed the paste: `admin` or `uploader`.
+    pub actor: String,
+}
+
+/// Outcome of `DbInterface::update_data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    /// The paste has been updated, and it is at the given revision now.
+    Updated(u64),
+    /// There is no paste with such ID.
+    NotFound,
+    /// The paste is at the given revision rather than at the expected one, so it is kept intact.
+    Conflict(u64),
+}
+
+/// Storage statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageStats {
+    /// Number of stored pastes.
+    pub pastes: u64,
+    /// Total size of the stored data in bytes.
+    pub total_bytes: u64,
+}
+
+/// An error of a database that doesn't support an operation.
+///
+/// This is what the optional methods of [DbInterface](trait.DbInterface.html) return unless a
+/// database implements them, so the error type of every database has to be convertible from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported {
+    /// Name of the unsupported method, like `list`.
+    pub operation: &'static str,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The database doesn't support {}", self.operation)
+    }
+}
+
+impl std::error::Error for Unsupported {
+    fn description(&self) -> &str {
+        "unsupported database operation"
+    }
+}
+
+impl From<Unsupported> for io::Error {
+    fn from(err: Unsupported) -> Self {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+}
+
+/// The default implementation of an optional method of `DbInterface`.
+fn unsupported<T, E: From<Unsupported>>(operation: &'static str) -> Result<T, E> {
+    Err(Unsupported { operation }.into())
 }
 
 /// Interface to a database.
@@ -72,41 +331,297 @@ pub struct PasteEntry {
 /// # Errors handling
 ///
 /// An implementation must provide an `Error` type, which must be thread safe as well and also
-/// have a `'static` lifetime.
+/// have a `'static` lifetime, and which could be made of an [Unsupported](struct.Unsupported.html)
+/// error.
 ///
 /// Should some method return an error it will be logged by the web server, but will not be send to
 /// an http client, it will just receive an internal server error:
 /// [500](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/500).
+///
+/// # Optional methods
+///
+/// Only storing, loading and removing pastes has to be implemented. The rest of the methods back
+/// features the service could do without (view counters and limits, edits, the trash, tombstones,
+/// slugs, listing, statistics and such), and by default they fail with `Unsupported`, so a
+/// database that doesn't implement some of them can't be used with the corresponding features
+/// enabled. The exceptions are `load_metadata`, which falls back to `load_data`, and
+/// `health_check`, which succeeds.
 pub trait DbInterface: Send + Sync {
-    type Error: Send + Sync + std::error::Error + 'static;
+    type Error: Send + Sync + std::error::Error + From<Unsupported> + 'static;
 
-    /// Stores the data into the database and returns a unique ID that should be used later to
-    /// access the data.
+    /// Stores a new paste into the database and returns a unique ID that should be used later to
+    /// access the paste.
+    ///
+    /// The entry is provided by the caller, so it is the same across all the layers and replicas,
+    /// and should be stored as is, except that a new paste has no views and is at revision 0:
+    /// `PasteEntry::views` and `PasteEntry::revision` are ignored.
     ///
     /// # Return value
     ///
     /// The function is expected to return a unique ID.
-    fn store_data(&self,
-                  data: Vec<u8>,
-                  file_name: Option<String>,
-                  mime_type: String,
-                  best_before: Option<DateTime<Utc>>)
-                  -> Result<u64, Self::Error>;
+    fn store_data(&self, entry: PasteEntry) -> Result<PasteId, Self::Error>;
+
+    /// Stores a paste under the given ID, replacing an existing paste with the same ID if any.
+    ///
+    /// Unlike with `store_data` the ID is chosen by the caller, which is needed to copy pastes
+    /// between databases (see [db::MirroredDb](db/struct.MirroredDb.html)). An implementation
+    /// that generates IDs by itself must make sure it won't generate IDs of inserted pastes later.
+    fn insert_data(&self, _id: PasteId, _entry: PasteEntry) -> Result<(), Self::Error> {
+        unsupported("insert_data")
+    }
+
+    /// Stores a new paste under the given ID, unless the ID is taken by another paste (a removed
+    /// one included). Returns whether the paste has been stored.
+    ///
+    /// This is how pastes are stored when their IDs are picked by an
+    /// [IdGenerator](trait.IdGenerator.html) rather than by the database, so the check should be
+    /// atomic if the database allows it. A new paste has no views and is at revision 0.
+    fn store_new(&self, _id: PasteId, _entry: PasteEntry) -> Result<bool, Self::Error> {
+        unsupported("store_new")
+    }
 
     /// Loads data from the database.
     ///
     /// Returns corresponding data if found, `None` otherwise.
-    fn load_data(&self, id: u64) -> Result<Option<PasteEntry>, Self::Error>;
+    fn load_data(&self, id: PasteId) -> Result<Option<PasteEntry>, Self::Error>;
 
-    /// Gets a file name of a paste (if any).
-    fn get_file_name(&self, id: u64) -> Result<Option<String>, Self::Error>;
+    /// Loads a summary of a paste without its data.
+    ///
+    /// Returns `None` if there is no such paste. This is used whenever the data itself is not
+    /// needed (redirects, `HEAD` requests and such), so an implementation is expected to avoid
+    /// loading the data from the database.
+    fn load_metadata(&self, id: PasteId) -> Result<Option<PasteSummary>, Self::Error> {
+        Ok(self.load_data(id)?.map(|entry| PasteSummary::from_entry(id, &entry)))
+    }
+
+    /// Loads the deletion token of a paste without its data.
+    ///
+    /// Returns `None` if there is no such paste or if it has no token. Like `load_metadata`, an
+    /// implementation is expected to avoid loading the data from the database.
+    fn load_deletion_token(&self, id: PasteId) -> Result<Option<String>, Self::Error> {
+        Ok(self.load_data(id)?.and_then(|entry| entry.deletion_token))
+    }
+
+    /// Replaces data and a mime type of an existing paste, keeping the rest intact, and
+    /// increments its revision.
+    ///
+    /// If `expected_revision` is given, the paste is only updated while it is still at that
+    /// revision, otherwise a `Conflict` with the current revision is returned. This is what keeps
+    /// concurrent edits from overwriting each other, so an implementation has to check the
+    /// revision and update the paste atomically.
+    fn update_data(&self,
+                   _id: PasteId,
+                   _expected_revision: Option<u64>,
+                   _data: Vec<u8>,
+                   _mime_type: MimeType)
+                   -> Result<UpdateOutcome, Self::Error> {
+        unsupported("update_data")
+    }
+
+    /// Increments the number of views of a paste.
+    ///
+    /// Does nothing if there is no paste with such ID. A popular paste is viewed concurrently, so
+    /// an implementation should increment the counter atomically rather than load and store it.
+    fn increment_views(&self, _id: PasteId) -> Result<(), Self::Error> {
+        unsupported("increment_views")
+    }
+
+    /// Uses up one of the views left of a paste with a view limit (see
+    /// `PasteEntry::views_left`), returns the number of views left after it.
+    ///
+    /// Returns `None` if there is no such paste, it has no views left, or it is not limited at
+    /// all. Concurrent requests race for the last view, so an implementation has to check and
+    /// decrement the counter atomically. Removing the paste once it is used up is up to the
+    /// caller.
+    fn take_view(&self, _id: PasteId) -> Result<Option<u64>, Self::Error> {
+        unsupported("take_view")
+    }
 
     /// Removes data from the database.
     ///
     /// Normally we don't care whether an object exists in the database or not, so an
     /// implementation doesn't have to check that fact, and usually databases are okay with
     /// attempts to remove something that doesn't exist.
-    fn remove_data(&self, id: u64) -> Result<(), Self::Error>;
+    fn remove_data(&self, id: PasteId) -> Result<(), Self::Error>;
+
+    /// Moves a paste to the trash, marking it as deleted at the given moment.
+    ///
+    /// A trashed paste stays in the database, but it is neither loaded nor listed, as if it has
+    /// been removed, until it is either brought back with `restore` or removed for good with
+    /// `purge_trash`.
+    ///
+    /// Returns `false` if there is no such paste or it is in the trash already.
+    fn soft_delete(&self, _id: PasteId, _deleted_at: DateTime<Utc>) -> Result<bool, Self::Error> {
+        unsupported("soft_delete")
+    }
+
+    /// Brings a paste back from the trash.
+    ///
+    /// Returns `false` if there is no such paste in the trash.
+    fn restore(&self, _id: PasteId) -> Result<bool, Self::Error> {
+        unsupported("restore")
+    }
+
+    /// Removes all the pastes that have been put into the trash before the given moment.
+    ///
+    /// Returns the number of removed pastes. Like `purge_expired` this is called periodically.
+    fn purge_trash(&self, _deleted_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        unsupported("purge_trash")
+    }
+
+    /// Stores a tombstone of a removed paste, replacing the previous tombstone with the same ID if
+    /// any.
+    ///
+    /// Tombstones are kept apart from the pastes: neither removing a paste nor `purge_expired`
+    /// affects them, they are only removed with `purge_tombstones`.
+    fn store_tombstone(&self, _tombstone: Tombstone) -> Result<(), Self::Error> {
+        unsupported("store_tombstone")
+    }
+
+    /// Loads a tombstone of a paste.
+    ///
+    /// Returns `None` if there is no tombstone with such ID.
+    fn load_tombstone(&self, _id: PasteId) -> Result<Option<Tombstone>, Self::Error> {
+        unsupported("load_tombstone")
+    }
+
+    /// Lists the tombstones of pastes that have been removed since the given moment, the most
+    /// recent removals first.
+    fn list_tombstones(&self,
+                       _removed_since: DateTime<Utc>)
+                       -> Result<Vec<Tombstone>, Self::Error> {
+        unsupported("list_tombstones")
+    }
+
+    /// Removes all the tombstones of pastes that have been removed before the given moment.
+    ///
+    /// Returns the number of removed tombstones. This is called periodically.
+    fn purge_tombstones(&self, _removed_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        unsupported("purge_tombstones")
+    }
+
+    /// Removes all the pastes that have expired by the given moment (i.e. whose `best_before` is
+    /// earlier than `now`).
+    ///
+    /// Returns the number of removed pastes. This is intended to be called periodically, so an
+    /// implementation should make it as cheap as the database allows (e.g. a single bulk delete).
+    fn purge_expired(&self, _now: DateTime<Utc>) -> Result<u64, Self::Error> {
+        unsupported("purge_expired")
+    }
+
+    /// Forgets creators of all the pastes that have been created before the given moment, leaving
+    /// the pastes themselves intact.
+    ///
+    /// Returns the number of scrubbed pastes. Like `purge_expired` this is called periodically.
+    fn scrub_creators(&self, _created_before: DateTime<Utc>) -> Result<u64, Self::Error> {
+        unsupported("scrub_creators")
+    }
+
+    /// Removes all the pastes of the given `creator` that have been created since the given moment
+    /// (inclusive), which is how spam waves are dealt with.
+    ///
+    /// Returns the number of removed pastes. Pastes whose creators have been scrubbed already are
+    /// left intact.
+    fn remove_by_creator(&self,
+                         _creator: &str,
+                         _since: DateTime<Utc>)
+                         -> Result<u64, Self::Error> {
+        unsupported("remove_by_creator")
+    }
+
+    /// Removes all the pastes of an owner, that is, the pastes whose deletion token is `token`,
+    /// trashed ones included.
+    ///
+    /// Returns the number of removed pastes.
+    fn remove_by_owner(&self, _token: &str) -> Result<u64, Self::Error> {
+        unsupported("remove_by_owner")
+    }
+
+    /// Lists IDs of the pastes of an owner, the ones whose deletion token is `token`, in no
+    /// particular order. Trashed pastes are not listed.
+    fn list_by_owner(&self, _token: &str) -> Result<Vec<PasteId>, Self::Error> {
+        unsupported("list_by_owner")
+    }
+
+    /// Remembers a hash of a paste's content, see [db::DedupDb](db/struct.DedupDb.html).
+    ///
+    /// A hash is an opaque string computed over the original data of a paste, it only has to be
+    /// kept as long as the paste itself. A paste has at most one hash, a new one replaces the old.
+    fn store_hash(&self, _id: PasteId, _hash: &str) -> Result<(), Self::Error> {
+        unsupported("store_hash")
+    }
+
+    /// Looks a paste up by a hash stored with `store_hash`.
+    ///
+    /// If several pastes share the hash, any of them could be returned. The paste might be gone
+    /// already, so a caller has to check it.
+    fn find_by_hash(&self, _hash: &str) -> Result<Option<PasteId>, Self::Error> {
+        unsupported("find_by_hash")
+    }
+
+    /// Claims a slug for a paste: a human-readable name it could be found by in place of its
+    /// ID, like `release-notes`.
+    ///
+    /// A slug belongs to at most one paste: if another paste has claimed it already, nothing is
+    /// changed and `false` is returned. The check has to be atomic, so two uploads could never
+    /// both get the same slug. A slug is released once its paste is removed.
+    fn store_slug(&self, _id: PasteId, _slug: &str) -> Result<bool, Self::Error> {
+        unsupported("store_slug")
+    }
+
+    /// Looks a paste up by a slug stored with `store_slug`.
+    ///
+    /// The paste might be gone already, so a caller has to check it.
+    fn find_by_slug(&self, _slug: &str) -> Result<Option<PasteId>, Self::Error> {
+        unsupported("find_by_slug")
+    }
+
+    /// Remembers a signature of a paste, see [db::SignedDb](db/struct.SignedDb.html).
+    ///
+    /// A signature is an opaque string that has to be kept as long as the paste itself. A paste
+    /// has at most one signature, a new one replaces the old.
+    fn store_signature(&self, _id: PasteId, _signature: &str) -> Result<(), Self::Error> {
+        unsupported("store_signature")
+    }
+
+    /// Loads a signature stored with `store_signature`, if any.
+    fn load_signature(&self, _id: PasteId) -> Result<Option<String>, Self::Error> {
+        unsupported("load_signature")
+    }
+
+    /// Lists stored pastes.
+    ///
+    /// Pastes are ordered by their IDs, at most `limit` entries are returned starting from the
+    /// `offset`-th one. An empty vector means there are no more pastes to list.
+    fn list(&self, _offset: usize, _limit: usize) -> Result<Vec<PasteSummary>, Self::Error> {
+        unsupported("list")
+    }
+
+    /// Lists public pastes (see `PasteEntry::public`), the newest first.
+    ///
+    /// At most `limit` entries are returned starting from the `offset`-th one, pastes created at
+    /// the same moment are ordered by their IDs (descending). Expired pastes that haven't been
+    /// removed yet are listed as well. A database is expected to keep an index for this, so
+    /// listing doesn't go through every paste. An empty vector means there are no more pastes to
+    /// list.
+    fn list_public(&self,
+                   _offset: usize,
+                   _limit: usize)
+                   -> Result<Vec<PasteSummary>, Self::Error> {
+        unsupported("list_public")
+    }
+
+    /// Checks whether the database is reachable and operational.
+    ///
+    /// Any error returned from this method means the service is not able to serve requests.
+    fn health_check(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Collects storage statistics.
+    fn stats(&self) -> Result<StorageStats, Self::Error> {
+        unsupported("stats")
+    }
 
     /// Returns the maximum data size that could be handled.
     ///
@@ -0,0 +1,338 @@
+//! Limits on the size and the lifetime of pastes.
+
+use DbInterface;
+use Error;
+use MimeType;
+use chrono::{DateTime, Duration, Utc};
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// Maximum sizes of pastes depending on their mime types.
+///
+/// A limit applies to a mime type pattern, which is either an exact mime type
+/// (`application/json`), a top-level type (`text/*`), or `*` for everything. The most specific
+/// pattern wins. Pastes of types no pattern matches are only limited by the database.
+///
+/// Limits could be parsed from a string of `pattern=size` rules, separated by commas or
+/// whitespace, where a size is a number of bytes with an optional `K`, `M` or `G` suffix
+/// (powers of 1024). Everything after a `#` up to the end of a line is a comment, so a list of
+/// rules could be easily kept in a file:
+///
+/// ```
+/// # extern crate pastebin;
+/// use pastebin::{MimeType, SizeLimits};
+///
+/// # fn main() {
+/// let limits: SizeLimits = "text/*=1M, image/*=10M, *=256K".parse().unwrap();
+/// assert_eq!(limits.limit_for(&"image/png".parse().unwrap()), Some(10 * 1024 * 1024));
+/// assert_eq!(limits.limit_for(&MimeType::octet_stream()), Some(256 * 1024));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeLimits {
+    rules: MimeRules<usize>,
+}
+
+impl SizeLimits {
+    /// Creates an empty policy, which doesn't limit anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a limit for a mime type pattern, replacing the previous limit for the pattern if any.
+    pub fn limit(mut self, pattern: &str, size: usize) -> Result<Self, Error> {
+        self.rules
+            .set(pattern, size)
+            .map_err(Error::InvalidSizeLimit)?;
+        Ok(self)
+    }
+
+    /// Returns the limit for a mime type, if any.
+    pub fn limit_for(&self, mime_type: &MimeType) -> Option<usize> {
+        self.rules.get(mime_type)
+    }
+
+    /// Returns the biggest limit of all, if every mime type is limited.
+    ///
+    /// This is used to reject pastes before receiving them, when the mime type is not yet known.
+    pub fn max_limit(&self) -> Option<usize> {
+        let default = self.rules.default?;
+        Some(self.rules
+                 .exact
+                 .iter()
+                 .chain(self.rules.top_level.iter())
+                 .map(|&(_, size)| size)
+                 .fold(default, cmp::max))
+    }
+}
+
+impl FromStr for SizeLimits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut limits = SizeLimits::new();
+        for rule in split_rules(s) {
+            let mut parts = rule.splitn(2, '=');
+            let pattern = parts.next().unwrap_or("");
+            let size = parts.next()
+                            .and_then(parse_size)
+                            .ok_or_else(|| Error::InvalidSizeLimit(rule.to_string()))?;
+            limits = limits.limit(pattern, size)?;
+        }
+        Ok(limits)
+    }
+}
+
+/// Pastes are enumerated in batches of this size when sweeping.
+const SWEEP_BATCH_SIZE: usize = 100;
+
+/// Maximum lifetimes of pastes depending on their mime types.
+///
+/// Patterns and rules are the same as the ones of [SizeLimits](struct.SizeLimits.html), except
+/// that a lifetime is a number of minutes, hours, days or weeks, like `12h`, `90d` or `2w`:
+///
+/// ```
+/// # extern crate chrono;
+/// # extern crate pastebin;
+/// use chrono::Duration;
+/// use pastebin::RetentionPolicy;
+///
+/// # fn main() {
+/// let policy: RetentionPolicy = "image/*=7d, *=90d".parse().unwrap();
+/// assert_eq!(policy.max_ttl_for(&"image/png".parse().unwrap()), Some(Duration::days(7)));
+/// assert_eq!(policy.max_ttl_for(&"text/plain".parse().unwrap()), Some(Duration::days(90)));
+/// # }
+/// ```
+///
+/// A policy is evaluated on upload (see `apply`), so no paste is stored with an expiration date
+/// beyond its maximum lifetime, and enforced by `sweep`, which also catches the pastes stored
+/// before the policy has been set or tightened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    rules: MimeRules<Duration>,
+}
+
+/// The expiration date of a new paste as a retention policy has decided it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// The effective expiration date, if the paste expires at all.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The maximum lifetime of pastes of the type, if it is limited.
+    pub max_ttl: Option<Duration>,
+    /// Whether the requested expiration date has been brought closer by the policy.
+    pub capped: bool,
+}
+
+impl RetentionPolicy {
+    /// Creates an empty policy, which lets pastes live as long as they are asked to.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a maximum lifetime for a mime type pattern, replacing the previous one for the
+    /// pattern if any.
+    pub fn max_ttl(mut self, pattern: &str, ttl: Duration) -> Result<Self, Error> {
+        self.rules
+            .set(pattern, ttl)
+            .map_err(Error::InvalidRetention)?;
+        Ok(self)
+    }
+
+    /// Returns the maximum lifetime of pastes of a mime type, if any.
+    pub fn max_ttl_for(&self, mime_type: &MimeType) -> Option<Duration> {
+        self.rules.get(mime_type)
+    }
+
+    /// Decides on the expiration date of a paste created at `created_at` that is asked to expire
+    /// at `requested` (`None` for never).
+    pub fn apply(&self,
+                 mime_type: &MimeType,
+                 created_at: DateTime<Utc>,
+                 requested: Option<DateTime<Utc>>)
+                 -> Retention {
+        let max_ttl = self.max_ttl_for(mime_type);
+        // A lifetime too long to be represented as a date is as good as no limit.
+        let deadline = max_ttl.and_then(|max_ttl| created_at.checked_add_signed(max_ttl));
+        let expires_at = match (requested, deadline) {
+            (Some(requested), Some(deadline)) => Some(cmp::min(requested, deadline)),
+            (requested, deadline) => requested.or(deadline),
+        };
+        Retention { expires_at,
+                    max_ttl,
+                    capped: expires_at != requested, }
+    }
+
+    /// Removes the pastes that have outlived their maximum lifetimes by `now`, returns the number
+    /// of removed pastes.
+    ///
+    /// Pastes are enumerated with `DbInterface::list`, so a sweep takes a pass over all of them.
+    pub fn sweep<D>(&self, db: &D, now: DateTime<Utc>) -> Result<u64, D::Error>
+        where D: DbInterface + ?Sized
+    {
+        if self.rules.is_empty() {
+            return Ok(0);
+        }
+        // Removing pastes while listing them would shift the offsets, so they are removed after.
+        let mut overdue = Vec::new();
+        let mut offset = 0;
+        loop {
+            let batch = db.list(offset, SWEEP_BATCH_SIZE)?;
+            if batch.is_empty() {
+                break;
+            }
+            offset += batch.len();
+            overdue.extend(batch.into_iter()
+                                .filter(|summary| {
+                                    self.max_ttl_for(&summary.mime_type)
+                                        .and_then(|ttl| summary.created_at.checked_add_signed(ttl))
+                                        .map_or(false, |deadline| deadline <= now)
+                                })
+                                .map(|summary| summary.id));
+        }
+        for &id in &overdue {
+            db.remove_data(id)?;
+        }
+        Ok(overdue.len() as u64)
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut policy = RetentionPolicy::new();
+        for rule in split_rules(s) {
+            let mut parts = rule.splitn(2, '=');
+            let pattern = parts.next().unwrap_or("");
+            let ttl = parts.next()
+                           .and_then(parse_ttl)
+                           .ok_or_else(|| Error::InvalidRetention(rule.to_string()))?;
+            policy = policy.max_ttl(pattern, ttl)?;
+        }
+        Ok(policy)
+    }
+}
+
+/// Splits a list of rules separated by commas or whitespace, dropping `#` comments.
+pub fn split_rules(s: &str) -> Vec<&str> {
+    s.lines()
+     .map(|line| line.split('#').next().unwrap_or(""))
+     .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+     .filter(|rule| !rule.is_empty())
+     .collect()
+}
+
+/// Values for mime type patterns: exact mime types (`application/json`), top-level types
+/// (`text/*`) and `*` for everything. The most specific pattern wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeRules<T> {
+    exact: Vec<(String, T)>,
+    top_level: Vec<(String, T)>,
+    default: Option<T>,
+}
+
+impl<T> Default for MimeRules<T> {
+    fn default() -> Self {
+        MimeRules { exact: Vec::new(),
+                    top_level: Vec::new(),
+                    default: None, }
+    }
+}
+
+impl<T: Copy> MimeRules<T> {
+    /// Sets a value for a pattern, replacing the previous value for the pattern if any.
+    ///
+    /// A malformed pattern is returned back as an error.
+    pub fn set(&mut self, pattern: &str, value: T) -> Result<(), String> {
+        let pattern = pattern.trim().to_lowercase();
+        if pattern == "*" || pattern == "*/*" {
+            self.default = Some(value);
+            return Ok(());
+        }
+        let (top, sub) = match pattern.find('/') {
+            Some(slash) => (&pattern[..slash], &pattern[slash + 1..]),
+            None => return Err(pattern.clone()),
+        };
+        if top.is_empty() || top.contains('*') || sub.is_empty() {
+            return Err(pattern.clone());
+        }
+        let (rules, key) = if sub == "*" {
+            (&mut self.top_level, top.to_string())
+        } else if sub.contains('*') {
+            return Err(pattern.clone());
+        } else {
+            (&mut self.exact, pattern.clone())
+        };
+        rules.retain(|&(ref existing, _)| *existing != key);
+        rules.push((key, value));
+        Ok(())
+    }
+
+    /// Returns the value of the most specific pattern the mime type matches, if any.
+    pub fn get(&self, mime_type: &MimeType) -> Option<T> {
+        let essence = mime_type.as_str()
+                               .split(';')
+                               .next()
+                               .unwrap_or("")
+                               .trim()
+                               .to_lowercase();
+        let top = essence.split('/').next().unwrap_or("");
+        self.exact
+            .iter()
+            .find(|&&(ref pattern, _)| *pattern == essence)
+            .or_else(|| self.top_level.iter().find(|&&(ref pattern, _)| pattern == top))
+            .map(|&(_, value)| value)
+            .or(self.default)
+    }
+
+    /// Checks whether there are no rules at all.
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.top_level.is_empty() && self.default.is_none()
+    }
+}
+
+/// Parses a size like `256K`.
+fn parse_size(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last()?.to_ascii_uppercase() {
+        'K' => (&size[..size.len() - 1], 1 << 10),
+        'M' => (&size[..size.len() - 1], 1 << 20),
+        'G' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses a lifetime like `90d`: a positive number of minutes (`m`), hours (`h`), days (`d`) or
+/// weeks (`w`).
+pub fn parse_ttl(ttl: &str) -> Option<Duration> {
+    let ttl = ttl.trim();
+    let minutes = match ttl.chars().last()?.to_ascii_lowercase() {
+        'm' => 1,
+        'h' => 60,
+        'd' => 60 * 24,
+        'w' => 60 * 24 * 7,
+        _ => return None,
+    };
+    // A 32-bit number of weeks is still in the range of a `Duration`.
+    match ttl[..ttl.len() - 1].parse::<u32>().ok()? {
+        0 => None,
+        number => Some(Duration::minutes(i64::from(number) * minutes)),
+    }
+}
+
+/// Displays a size in a human-friendly way, like `256 KiB`.
+pub struct HumanSize(pub usize);
+
+impl fmt::Display for HumanSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = self.0;
+        for &(unit, name) in &[(1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")] {
+            if size >= unit && size % unit == 0 {
+                return write!(f, "{} {}", size / unit, name);
+            }
+        }
+        write!(f, "{} bytes", size)
+    }
+}
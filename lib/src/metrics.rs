@@ -0,0 +1,189 @@
+//! Operational metrics in the Prometheus text format.
+//!
+//! A [Metrics](struct.Metrics.html) registry collects two kinds of measurements:
+//!
+//! * database calls by `DbInterface` method: number of calls, number of failed ones and a
+//! histogram of latencies, recorded by [db::InstrumentedDb](../db/struct.InstrumentedDb.html);
+//! * requests by endpoint and HTTP method: number of requests and total sizes of request and
+//! response bodies (as reported by their `Content-Length` headers), recorded by the server once
+//! the registry is given to
+//! [PastebinBuilder::metrics](../web/struct.PastebinBuilder.html#method.metrics).
+//!
+//! Comparing the latencies of the database calls with the amount of traffic tells whether the
+//! database or the web layer is the bottleneck. The registry is served at `/metrics`.
+
+use middleware;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1., 5.];
+
+/// Statistics of calls of a database method.
+#[derive(Debug, Default)]
+struct CallStats {
+    calls: u64,
+    errors: u64,
+    latency_sum: f64,
+    /// Number of calls that took no longer than the bucket bounds, one per `LATENCY_BUCKETS`.
+    buckets: Vec<u64>,
+}
+
+/// Statistics of requests to an endpoint.
+#[derive(Debug, Default)]
+struct RequestStats {
+    requests: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    db_calls: BTreeMap<&'static str, CallStats>,
+    requests: BTreeMap<(&'static str, String), RequestStats>,
+}
+
+/// A registry of metrics.
+///
+/// Clones share the same registry, so one of them could be given to the database decorator and
+/// another one to the server.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records a call of a database method.
+    pub fn record_db_call(&self, method: &'static str, elapsed: Duration, failed: bool) {
+        let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        let mut registry = self.registry.lock().unwrap();
+        let stats = registry.db_calls.entry(method).or_insert_with(Default::default);
+        stats.calls += 1;
+        if failed {
+            stats.errors += 1;
+        }
+        stats.latency_sum += seconds;
+        stats.buckets.resize(LATENCY_BUCKETS.len(), 0);
+        for (bucket, &bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Records a request to an endpoint.
+    pub fn record_request(&self,
+                          endpoint: &'static str,
+                          method: &str,
+                          request_bytes: u64,
+                          response_bytes: u64) {
+        let mut registry = self.registry.lock().unwrap();
+        let stats = registry.requests
+                            .entry((endpoint, method.to_string()))
+                            .or_insert_with(Default::default);
+        stats.requests += 1;
+        stats.request_bytes += request_bytes;
+        stats.response_bytes += response_bytes;
+    }
+
+    /// Renders all the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+        header(&mut out, "pastebin_db_calls_total", "counter", "Database calls by method.");
+        for (method, stats) in &registry.db_calls {
+            sample(&mut out, "pastebin_db_calls_total", &[("method", method)], stats.calls);
+        }
+        header(&mut out, "pastebin_db_errors_total", "counter", "Failed database calls by method.");
+        for (method, stats) in &registry.db_calls {
+            sample(&mut out, "pastebin_db_errors_total", &[("method", method)], stats.errors);
+        }
+        header(&mut out,
+               "pastebin_db_latency_seconds",
+               "histogram",
+               "Latencies of database calls by method.");
+        for (method, stats) in &registry.db_calls {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&stats.buckets) {
+                sample(&mut out,
+                       "pastebin_db_latency_seconds_bucket",
+                       &[("method", method), ("le", &bound.to_string())],
+                       count);
+            }
+            sample(&mut out,
+                   "pastebin_db_latency_seconds_bucket",
+                   &[("method", method), ("le", "+Inf")],
+                   stats.calls);
+            sample(&mut out,
+                   "pastebin_db_latency_seconds_sum",
+                   &[("method", method)],
+                   stats.latency_sum);
+            sample(&mut out,
+                   "pastebin_db_latency_seconds_count",
+                   &[("method", method)],
+                   stats.calls);
+        }
+        header(&mut out,
+               "pastebin_http_requests_total",
+               "counter",
+               "Requests by endpoint and method.");
+        for (&(endpoint, ref method), stats) in &registry.requests {
+            sample(&mut out,
+                   "pastebin_http_requests_total",
+                   &[("endpoint", endpoint), ("method", method)],
+                   stats.requests);
+        }
+        header(&mut out,
+               "pastebin_http_request_bytes_total",
+               "counter",
+               "Total size of request bodies by endpoint and method.");
+        for (&(endpoint, ref method), stats) in &registry.requests {
+            sample(&mut out,
+                   "pastebin_http_request_bytes_total",
+                   &[("endpoint", endpoint), ("method", method)],
+                   stats.request_bytes);
+        }
+        header(&mut out,
+               "pastebin_http_response_bytes_total",
+               "counter",
+               "Total size of response bodies by endpoint and method.");
+        for (&(endpoint, ref method), stats) in &registry.requests {
+            sample(&mut out,
+                   "pastebin_http_response_bytes_total",
+                   &[("endpoint", endpoint), ("method", method)],
+                   stats.response_bytes);
+        }
+        header(&mut out, "pastebin_panics_total", "counter", "Caught panics.");
+        sample(&mut out, "pastebin_panics_total", &[], middleware::panics_caught());
+        out
+    }
+}
+
+/// Writes the description of a metric.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+}
+
+/// Writes a sample of a metric, like `name{label="value"} 42`.
+fn sample<V: ToString>(out: &mut String, name: &str, labels: &[(&str, &str)], value: V) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels = labels.iter()
+                           .map(|&(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                           .collect::<Vec<_>>();
+        out.push_str(&format!("{{{}}}", labels.join(",")));
+    }
+    out.push_str(&format!(" {}\n", value.to_string()));
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\")
+         .replace('"', "\\\"")
+         .replace('\n', "\\n")
+}
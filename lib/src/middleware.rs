@@ -0,0 +1,155 @@
+//! Iron middlewares used by the server.
+
+use backtrace::Backtrace;
+use error::display_chain;
+use iron::{status, AfterMiddleware, AroundMiddleware, Handler, IronError, IronResult, Request,
+           Response};
+#[cfg(feature = "metrics")]
+use iron::headers::ContentLength;
+#[cfg(feature = "metrics")]
+use metrics::Metrics;
+#[cfg(feature = "metrics")]
+use request::RequestExt;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+/// Total number of panics caught by `PanicCatcher`.
+static PANICS_CAUGHT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Request IDs generator, used to match log messages with responses.
+static NEXT_REQUEST_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+thread_local! {
+    /// Whether the current thread is handling a request guarded by `PanicCatcher`.
+    static GUARDED: Cell<bool> = Cell::new(false);
+    /// Backtrace of the last panic on the current thread.
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = RefCell::new(None);
+}
+
+/// Returns how many panics have been caught by the server so far.
+pub fn panics_caught() -> usize {
+    PANICS_CAUGHT.load(Ordering::Relaxed)
+}
+
+/// Logs errors returned by the handler, including the whole chain of their causes.
+///
+/// Errors are turned into responses here, so Iron doesn't log them once again (Iron's own message
+/// dumps the whole request, including the client's address).
+pub struct ErrorLogger;
+
+impl AfterMiddleware for ErrorLogger {
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        let is_server_error = err.response
+                                 .status
+                                 .map(|status| status.is_server_error())
+                                 .unwrap_or(true);
+        if is_server_error {
+            error!("{} {}: {}", req.method, req.url, display_chain(&*err.error));
+        } else {
+            debug!("{} {}: {}", req.method, req.url, display_chain(&*err.error));
+        }
+        Ok(err.response)
+    }
+}
+
+/// Records requests and sizes of their bodies in a metrics registry.
+///
+/// Has to be linked after `ErrorLogger`, so failed requests are recorded as well.
+#[cfg(feature = "metrics")]
+pub struct RequestMetrics(pub Metrics);
+
+#[cfg(feature = "metrics")]
+impl AfterMiddleware for RequestMetrics {
+    fn after(&self, req: &mut Request, res: Response) -> IronResult<Response> {
+        let response_bytes = res.headers.get::<ContentLength>().map_or(0, |length| length.0);
+        self.0.record_request(endpoint(req),
+                              req.method.as_ref(),
+                              req.get_length().unwrap_or(0),
+                              response_bytes);
+        Ok(res)
+    }
+}
+
+/// Names the endpoint a request is made to, as it is labeled in the metrics.
+///
+/// Everything that is not a service route is considered to be a paste (or a static file).
+#[cfg(feature = "metrics")]
+fn endpoint(req: &Request) -> &'static str {
+    match req.url_segment_n(0) {
+        None => "index",
+        Some("paste.sh") => "paste.sh",
+        Some("healthz") => "healthz",
+        Some("readyz") => "readyz",
+        Some("stats") => "stats",
+        Some("metrics") => "metrics",
+        Some("signing-key") => "signing-key",
+        Some("readme") => "readme",
+        Some("raw") => "raw",
+        Some("dl") => "download",
+        Some("h") => "permalink",
+        Some("admin") => "admin",
+        Some("api") => "api",
+        Some(_) => "paste",
+    }
+}
+
+/// Catches panics in the handler, logs them and responds with `500 Internal Server Error`
+/// instead of tearing down the worker thread.
+pub struct PanicCatcher;
+
+impl AroundMiddleware for PanicCatcher {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        install_panic_hook();
+        Box::new(PanicCatchingHandler { handler })
+    }
+}
+
+/// Installs a panic hook that stores backtraces of panics that happen in guarded threads, while
+/// the rest of the panics are handled by the previous hook.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if GUARDED.with(|guarded| guarded.get()) {
+                LAST_BACKTRACE.with(|last| *last.borrow_mut() = Some(Backtrace::new()));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// A handler wrapped by `PanicCatcher`.
+struct PanicCatchingHandler {
+    handler: Box<Handler>,
+}
+
+impl Handler for PanicCatchingHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        GUARDED.with(|guarded| guarded.set(true));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.handler.handle(req)));
+        GUARDED.with(|guarded| guarded.set(false));
+        let payload = match result {
+            Ok(result) => return result,
+            Err(payload) => payload,
+        };
+        PANICS_CAUGHT.fetch_add(1, Ordering::Relaxed);
+        let message = payload.downcast_ref::<&str>()
+                             .map(|s| s.to_string())
+                             .or_else(|| payload.downcast_ref::<String>().cloned())
+                             .unwrap_or_else(|| "unknown panic".into());
+        let backtrace = LAST_BACKTRACE.with(|last| last.borrow_mut().take());
+        error!("Request #{} ({} {}) panicked: {}\n{:?}",
+               request_id,
+               req.method,
+               req.url,
+               message,
+               backtrace);
+        Ok(Response::with((status::InternalServerError,
+                          format!("Internal server error (request #{})\n", request_id))))
+    }
+}
@@ -1,46 +1,145 @@
 //! A helper module for MIME and ContentType related stuff.
 
+use error::Error;
 use iron::headers::ContentType;
+use iron::mime::Mime;
 use mime_guess;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
+#[cfg(feature = "magic")]
 use tree_magic;
 
-/// Checks whether a given mime type represents some text.
-pub fn is_text(mime_type: &str) -> bool {
-    match mime_type {
-        "application/x-sh" => true,
-        s if s.starts_with("text/") => true,
-        _ => false,
+/// A mime type of a paste, like `text/plain`.
+///
+/// Only syntactically valid mime types (`type/subtype`, optionally followed by parameters) could
+/// be constructed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MimeType(String);
+
+impl MimeType {
+    /// Validates a mime type.
+    pub fn new<S: Into<String>>(mime_type: S) -> Result<Self, Error> {
+        let mime_type = mime_type.into();
+        match mime_type.parse::<Mime>() {
+            Ok(_) => Ok(MimeType(mime_type)),
+            Err(()) => Err(Error::InvalidMimeType(mime_type)),
+        }
+    }
+
+    /// `application/octet-stream`, a mime type of arbitrary binary data.
+    pub fn octet_stream() -> Self {
+        MimeType("application/octet-stream".into())
+    }
+
+    /// `text/plain`.
+    pub fn text_plain() -> Self {
+        MimeType("text/plain".into())
+    }
+
+    /// Returns the mime type as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks whether the mime type represents some text.
+    pub fn is_text(&self) -> bool {
+        match self.as_str() {
+            "application/x-sh" => true,
+            s if s.starts_with("text/") => true,
+            _ => false,
+        }
+    }
+
+    /// Converts the mime type into a content type.
+    pub fn to_content_type(&self) -> ContentType {
+        match self.0.parse() {
+            Ok(mime) => ContentType(mime),
+            Err(()) => ContentType::plaintext(),
+        }
+    }
+
+    /// Wraps a guessed mime type, falling back to `application/octet-stream` for invalid ones.
+    fn guessed(mime_type: String) -> Self {
+        Self::new(mime_type).unwrap_or_else(|_| Self::octet_stream())
     }
 }
 
-/// Converts a given mime type into a content type.
-pub fn to_content_type(mime_type: String) -> ContentType {
-    match mime_type.parse() {
-        Ok(mime) => ContentType(mime),
-        Err(()) => ContentType::plaintext(),
+impl fmt::Display for MimeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for MimeType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::new(s)
+    }
+}
+
+impl AsRef<str> for MimeType {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<MimeType> for String {
+    fn from(mime_type: MimeType) -> Self {
+        mime_type.0
     }
 }
 
 /// Guesses mime type of a file.
 fn mime_from_file_name<P: AsRef<Path>>(name: P) -> Option<&'static str> {
-    name.as_ref().extension()
-        .and_then(|s| s.to_str())
-        .and_then(mime_guess::get_mime_type_str)
+    let extension = name.as_ref().extension().and_then(|s| s.to_str())?;
+    match extension.to_lowercase().as_str() {
+        // `mime_guess` knows it by a name browsers don't play.
+        "m4a" => Some("audio/mp4"),
+        _ => mime_guess::get_mime_type_str(extension),
+    }
+}
+
+/// Detects mime type of a file by its contents.
+#[cfg(feature = "magic")]
+fn mime_from_file_contents(path: &Path) -> String {
+    tree_magic::from_filepath(path)
+}
+
+/// Without the `magic` feature nothing is known about the file.
+#[cfg(not(feature = "magic"))]
+fn mime_from_file_contents(_path: &Path) -> String {
+    "application/octet-stream".into()
+}
+
+/// Detects mime type of data by its contents.
+#[cfg(feature = "magic")]
+fn mime_from_data(data: &[u8]) -> String {
+    tree_magic::from_u8(data)
+}
+
+/// Without the `magic` feature the only distinction is whether the data is a valid UTF-8 text.
+#[cfg(not(feature = "magic"))]
+fn mime_from_data(data: &[u8]) -> String {
+    match ::std::str::from_utf8(data) {
+        Ok(_) => "text/plain".into(),
+        Err(_) => "application/octet-stream".into(),
+    }
 }
 
 /// Guesses a file's content type.
 pub fn file_content_type<P: AsRef<Path>>(p: P) -> ContentType {
-    let mime_type =
-        mime_from_file_name(&p).map(Into::into)
-                               .unwrap_or_else(|| tree_magic::from_filepath(p.as_ref()));
-    to_content_type(mime_type)
+    let mime_type = mime_from_file_name(&p).map(Into::into)
+                                           .unwrap_or_else(|| mime_from_file_contents(p.as_ref()));
+    MimeType::guessed(mime_type).to_content_type()
 }
 
 /// Guesses a content type of given data and its file name (if any).
-pub fn data_mime_type<P: AsRef<Path>>(file_name: Option<P>, data: &[u8]) -> String {
-    file_name.as_ref()
-             .and_then(mime_from_file_name)
-             .map(Into::into)
-             .unwrap_or_else(|| tree_magic::from_u8(data))
+pub fn data_mime_type<P: AsRef<Path>>(file_name: Option<P>, data: &[u8]) -> MimeType {
+    let mime_type = file_name.as_ref()
+                             .and_then(mime_from_file_name)
+                             .map(Into::into)
+                             .unwrap_or_else(|| mime_from_data(data));
+    MimeType::guessed(mime_type)
 }
@@ -0,0 +1,182 @@
+//! Uploads of `multipart/form-data` bodies, as browsers submit forms with files.
+//!
+//! The file is taken from the first part that carries a file name (an `<input type="file">`),
+//! or from the `data` field if there is no such part (a `<textarea name="data">`). The file name
+//! is used for mime guessing just like a file name given in the URL. Besides the file, the
+//! `file_name`, `expires`, `public` (a checkbox, which is only submitted if checked) and
+//! `csrf_token` (see the `csrf` module) fields are recognized, the rest are ignored.
+
+use Error;
+use api::Upload;
+use iron::headers::ContentType;
+use iron::mime::{Attr, Mime, SubLevel, TopLevel};
+use iron::prelude::*;
+use std::str::from_utf8;
+
+/// A part of a multipart body.
+struct Part {
+    /// Name of the form field.
+    name: String,
+    /// File name of an uploaded file, if any.
+    file_name: Option<String>,
+    /// Contents of the part.
+    data: Vec<u8>,
+}
+
+/// Returns the boundary of a `multipart/form-data` request, or `None` if the request has a body
+/// of some other type.
+pub fn boundary(req: &Request) -> Option<String> {
+    match req.headers.get::<ContentType>() {
+        Some(&ContentType(ref mime @ Mime(TopLevel::Multipart, SubLevel::FormData, _))) => {
+            mime.get_param(Attr::Boundary)
+                .map(|boundary| boundary.to_string().trim_matches('"').to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Parses a `multipart/form-data` body into an upload, see the [module](index.html)
+/// documentation.
+pub fn parse_upload(body: &[u8], boundary: &str) -> Result<Upload, Error> {
+    let mut file = None;
+    let mut data_field = None;
+    let mut file_name = None;
+    let mut expires = None;
+    let mut public = false;
+    let mut csrf_token = None;
+    for part in parse(body, boundary)? {
+        match (part.name.as_str(), part.file_name.is_some()) {
+            (_, true) if file.is_none() => file = Some(part),
+            ("data", false) => data_field = Some(part),
+            ("file_name", false) => file_name = Some(text_field(part)?),
+            ("expires", false) => expires = Some(text_field(part)?),
+            ("public", false) => public = true,
+            ("csrf_token", false) => csrf_token = Some(text_field(part)?),
+            _ => {}
+        }
+    }
+    let file = file.or(data_field)
+                   .ok_or_else(|| Error::InvalidMultipart("no file is uploaded".into()))?;
+    // Fields left empty are as good as missing.
+    let non_empty = |value: Option<String>| value.filter(|value| !value.is_empty());
+    Ok(Upload { data: file.data,
+                file_name: non_empty(file_name).or(file.file_name),
+                expires: non_empty(expires),
+                public,
+                csrf_token, })
+}
+
+/// Decodes a text field.
+fn text_field(part: Part) -> Result<String, Error> {
+    let Part { name, data, .. } = part;
+    String::from_utf8(data).map_err(|_| {
+                               Error::InvalidMultipart(format!("`{}` is not a UTF-8 text", name))
+                           })
+}
+
+/// Finds the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Splits a body into parts.
+fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, Error> {
+    let invalid = |reason: &str| Error::InvalidMultipart(reason.to_string());
+    // Anything before the first delimiter is a preamble to be ignored.
+    let first = format!("--{}", boundary).into_bytes();
+    let mut rest = match find(body, &first) {
+        Some(position) => &body[position + first.len()..],
+        None => return Err(invalid("no boundary found")),
+    };
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    loop {
+        // The last delimiter is followed by `--`, and an epilogue to be ignored.
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(invalid("malformed boundary line"));
+        }
+        rest = &rest[2..];
+        let end = find(rest, &delimiter).ok_or_else(|| invalid("unterminated part"))?;
+        parts.push(parse_part(&rest[..end])?);
+        rest = &rest[end + delimiter.len()..];
+    }
+}
+
+/// Parses the headers and the contents of a part.
+fn parse_part(part: &[u8]) -> Result<Part, Error> {
+    let invalid = |reason: &str| Error::InvalidMultipart(reason.to_string());
+    let (headers, data) = if part.starts_with(b"\r\n") {
+        (&part[..0], &part[2..])
+    } else {
+        let end = find(part, b"\r\n\r\n").ok_or_else(|| invalid("unterminated part headers"))?;
+        (&part[..end], &part[end + 4..])
+    };
+    let headers = from_utf8(headers).map_err(|_| invalid("part headers are not UTF-8"))?;
+    let mut disposition = None;
+    for header in headers.split("\r\n") {
+        let mut header = header.splitn(2, ':');
+        let name = header.next().unwrap_or_default().trim();
+        if name.eq_ignore_ascii_case("content-disposition") {
+            disposition = header.next().map(parse_disposition);
+        }
+    }
+    let (name, file_name) =
+        disposition.ok_or_else(|| invalid("a part has no Content-Disposition"))?;
+    let name = name.ok_or_else(|| invalid("a part has no name"))?;
+    Ok(Part { name,
+              file_name,
+              data: data.to_vec(), })
+}
+
+/// Parses a `Content-Disposition` header like `form-data; name="file"; filename="main.rs"` into
+/// the name and the file name.
+///
+/// An empty file name (which browsers send when no file is chosen) is taken as no file name, and
+/// a path (which some browsers send) is cut to its last component.
+fn parse_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut file_name = None;
+    let mut chars = value.chars().peekable();
+    // The disposition type itself.
+    while chars.next().map(|c| c != ';').unwrap_or(false) {}
+    loop {
+        let key = chars.by_ref()
+                       .take_while(|&c| c != '=')
+                       .collect::<String>()
+                       .trim_matches(|c: char| c == ';' || c.is_whitespace())
+                       .to_lowercase();
+        if key.is_empty() {
+            break;
+        }
+        let mut param = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => param.extend(chars.next()),
+                    c => param.push(c),
+                }
+            }
+            // Skip up to the next parameter.
+            while chars.next().map(|c| c != ';').unwrap_or(false) {}
+        } else {
+            param = chars.by_ref().take_while(|&c| c != ';').collect();
+            param = param.trim().to_string();
+        }
+        match key.as_str() {
+            "name" => name = Some(param),
+            "filename" => {
+                file_name = param.rsplit(|c| c == '/' || c == '\\')
+                                 .next()
+                                 .filter(|file_name| !file_name.is_empty())
+                                 .map(str::to_string)
+            }
+            _ => {}
+        }
+    }
+    (name, file_name)
+}
@@ -0,0 +1,119 @@
+//! Extra pages rendered from templates.
+
+use Error;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Paths served by the handler itself, which neither pages, slugs nor IDs of pastes can take.
+const RESERVED_PATHS: &[&str] = &["admin",
+                                  "api",
+                                  "browse",
+                                  "dl",
+                                  "h",
+                                  "healthz",
+                                  "metrics",
+                                  "paste.sh",
+                                  "raw",
+                                  "readme",
+                                  "readyz",
+                                  "s",
+                                  "signing-key",
+                                  "stats"];
+
+/// Checks whether a path of a single segment is served by the handler itself.
+pub fn is_reserved(path: &str) -> bool {
+    RESERVED_PATHS.contains(&path)
+}
+
+/// An HTML page rendered from a template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraPage {
+    /// Name of the template, without the templates extension (like `about.html`).
+    pub template: String,
+    /// Values available to the template, besides `prefix` (the URL prefix of the service).
+    pub context: Map<String, Value>,
+}
+
+/// Pages an instance serves in addition to the built-in ones, like `/about` or `/privacy`.
+///
+/// Every page is served at a path of a single segment, which neither the handler's own routes
+/// (`/readme`, `/stats` and so on) nor other pages use. A page takes precedence over a static
+/// file or a paste with the same name.
+///
+/// Pages could be parsed from a JSON object, which maps paths to templates and their contexts,
+/// so they could be kept in a configuration file:
+///
+/// ```
+/// # extern crate pastebin;
+/// use pastebin::ExtraPages;
+///
+/// # fn main() {
+/// let pages: ExtraPages = r#"{
+///     "about": {"template": "about.html", "context": {"email": "admin@example.com"}},
+///     "/privacy": {"template": "privacy.html"}
+/// }"#.parse().unwrap();
+/// assert_eq!(pages.get("about").unwrap().context["email"], "admin@example.com");
+/// assert_eq!(pages.get("privacy").unwrap().template, "privacy.html");
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtraPages {
+    pages: BTreeMap<String, ExtraPage>,
+}
+
+impl ExtraPages {
+    /// Creates an empty set of pages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a page, replacing the previous page at the path if any.
+    ///
+    /// A leading slash of the path is optional.
+    pub fn page(mut self, path: &str, page: ExtraPage) -> Result<Self, Error> {
+        let path = path.trim_left_matches('/');
+        if path.is_empty() || path.contains('/') || is_reserved(path) {
+            let reason = format!("{:?} is not a free path of a single segment", path);
+            return Err(Error::InvalidPage(reason));
+        }
+        self.pages.insert(path.to_string(), page);
+        Ok(self)
+    }
+
+    /// Returns the page at a path (without the leading slash), if any.
+    pub fn get(&self, path: &str) -> Option<&ExtraPage> {
+        self.pages.get(path)
+    }
+
+    /// Checks if there are no pages.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+impl FromStr for ExtraPages {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let config: Map<String, Value> =
+            serde_json::from_str(s).map_err(|e| Error::InvalidPage(e.to_string()))?;
+        let mut pages = ExtraPages::new();
+        for (path, page) in config {
+            let template = match page.get("template").and_then(Value::as_str) {
+                Some(template) => template.to_string(),
+                None => return Err(Error::InvalidPage(format!("{:?} has no template", path))),
+            };
+            let context = match page.get("context") {
+                Some(&Value::Object(ref context)) => context.clone(),
+                Some(_) => {
+                    let reason = format!("context of {:?} is not an object", path);
+                    return Err(Error::InvalidPage(reason));
+                }
+                None => Map::new(),
+            };
+            pages = pages.page(&path, ExtraPage { template, context })?;
+        }
+        Ok(pages)
+    }
+}
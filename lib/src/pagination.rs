@@ -0,0 +1,291 @@
+//! Paging through listings of pastes.
+//!
+//! Listings are ordered by paste IDs (see `DbInterface::list`), or the newest first for public
+//! pastes (see `DbInterface::list_public`), and split into pages of at most
+//! `limit` pastes (20 by default, 100 at most). Pages are chained with opaque cursors: a JSON
+//! listing looks like `{"pastes": [...], "next_cursor": "..."}`, and the next page is requested
+//! with `?cursor=<next_cursor>`. `next_cursor` is `null` on the last page.
+//!
+//! A cursor points right past the last paste of a page rather than at an offset, so pastes
+//! stored or removed in between don't make the next page skip or repeat pastes.
+//!
+//! Admin listings are narrowed down with a [PasteFilter](struct.PasteFilter.html), which also
+//! picks the pastes to remove in bulk.
+
+use chrono::{DateTime, Utc};
+use error::Error;
+use iron::Request;
+use limits::{split_rules, MimeRules};
+use request::{parse_timestamp, RequestExt};
+use serde_json::Value;
+use {DbInterface, PasteId, PasteSummary};
+
+/// Number of pastes on a page unless asked otherwise.
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Maximum number of pastes on a page.
+pub const MAX_LIMIT: usize = 100;
+
+/// Number of pastes listed at once.
+const BATCH_SIZE: usize = 100;
+
+/// A position in a listing: the last listed paste and the offset right past it.
+///
+/// The offset is only a hint where to resume, the ID is what counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    last: PasteId,
+    offset: usize,
+}
+
+impl Cursor {
+    /// Encodes the cursor, like `AQAAAAAAAAA.20`.
+    pub fn encode(&self) -> String {
+        format!("{}.{}", self.last, self.offset)
+    }
+
+    /// Decodes a cursor made by `encode`.
+    pub fn decode(cursor: &str) -> Result<Cursor, Error> {
+        let invalid = || Error::InvalidCursor(cursor.to_string());
+        let dot = cursor.rfind('.').ok_or_else(invalid)?;
+        Ok(Cursor { last: cursor[..dot].parse().map_err(|_| invalid())?,
+                    offset: cursor[dot + 1..].parse().map_err(|_| invalid())?, })
+    }
+}
+
+/// A request for a page of a listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    after: Option<Cursor>,
+    limit: usize,
+}
+
+impl PageRequest {
+    /// Takes the page from the `cursor` and `limit` arguments of a request, the limit is capped
+    /// at `MAX_LIMIT`.
+    pub fn from_request(req: &Request) -> Result<PageRequest, Error> {
+        let after = match req.get_arg("cursor") {
+            Some(cursor) => Some(Cursor::decode(&cursor)?),
+            None => None,
+        };
+        let limit = match req.get_arg("limit") {
+            Some(limit) => limit.parse::<usize>()
+                                .map_err(|_| Error::InvalidLimit(limit.to_string()))?,
+            None => DEFAULT_LIMIT,
+        };
+        if limit == 0 {
+            return Err(Error::InvalidLimit(limit.to_string()));
+        }
+        Ok(PageRequest { after,
+                         limit: limit.min(MAX_LIMIT), })
+    }
+}
+
+/// Pastes an admin is looking for, all the given conditions have to hold.
+#[derive(Debug, Clone, Default)]
+pub struct PasteFilter {
+    creator: Option<String>,
+    mime_types: Option<MimeRules<bool>>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+impl PasteFilter {
+    /// Takes the filter from the arguments of a request: `creator` (an IP address), `mime_type`
+    /// (patterns like `image/*`, separated by commas), and `since` and `until` (Unix timestamps,
+    /// inclusive) bounding the creation date.
+    pub fn from_request(req: &Request) -> Result<PasteFilter, Error> {
+        let mime_types = match req.get_arg("mime_type") {
+            Some(patterns) => {
+                let mut rules = MimeRules::default();
+                for pattern in split_rules(&patterns) {
+                    rules.set(pattern, true).map_err(Error::InvalidMimeType)?;
+                }
+                Some(rules)
+            }
+            None => None,
+        };
+        let timestamp = |name| match req.get_arg(name) {
+            Some(timestamp) => parse_timestamp(&timestamp).map(Some),
+            None => Ok(None),
+        };
+        Ok(PasteFilter { creator: req.get_arg("creator").map(|creator| creator.into_owned()),
+                         mime_types,
+                         since: timestamp("since")?,
+                         until: timestamp("until")?, })
+    }
+
+    /// Checks whether the filter lets every paste through.
+    pub fn is_empty(&self) -> bool {
+        self.creator.is_none() && self.mime_types.is_none() && self.since.is_none()
+        && self.until.is_none()
+    }
+
+    /// Returns the creator and the moment a filter by `creator` and `since` alone asks for, which
+    /// `DbInterface::remove_by_creator` handles without listing every paste.
+    pub fn creator_since(&self) -> Option<(&str, DateTime<Utc>)> {
+        if self.mime_types.is_some() || self.until.is_some() {
+            return None;
+        }
+        match (self.creator.as_ref(), self.since) {
+            (Some(creator), Some(since)) => Some((creator, since)),
+            _ => None,
+        }
+    }
+
+    /// Checks whether a paste passes the filter.
+    pub fn matches(&self, summary: &PasteSummary) -> bool {
+        let creator = self.creator.is_none() || summary.creator == self.creator;
+        let mime_type = self.mime_types.as_ref().map_or(true, |rules| {
+                                                    rules.get(&summary.mime_type).unwrap_or(false)
+                                                });
+        creator && mime_type && self.since.map_or(true, |since| summary.created_at >= since)
+        && self.until.map_or(true, |until| summary.created_at <= until)
+    }
+}
+
+/// A page of a listing.
+#[derive(Debug)]
+pub struct Page {
+    /// Pastes on the page.
+    pub pastes: Vec<PasteSummary>,
+    /// Where the next page starts, unless this one is the last.
+    pub next: Option<Cursor>,
+}
+
+impl Page {
+    /// Renders the page as `{"pastes": [...], "next_cursor": ...}`, with every paste described by
+    /// `describe`.
+    pub fn to_json<F>(&self, describe: F) -> Value
+        where F: Fn(&PasteSummary) -> Value
+    {
+        json!({
+            "pastes": self.pastes.iter().map(describe).collect::<Vec<_>>(),
+            "next_cursor": self.next.map(|cursor| cursor.encode()),
+        })
+    }
+}
+
+/// Lists a page of the pastes that match the `filter`.
+///
+/// Mind that a filter which matches few pastes makes the whole database to be listed.
+pub fn page<D, F>(db: &D, request: &PageRequest, filter: F) -> Result<Page, D::Error>
+    where D: DbInterface + ?Sized,
+          F: Fn(&PasteSummary) -> bool
+{
+    let mut offset = match request.after {
+        Some(after) => resume_offset(db, after)?,
+        None => 0,
+    };
+    // One more paste tells whether there is a next page.
+    let mut pastes = Vec::with_capacity(request.limit + 1);
+    let mut next_offset = offset;
+    'listing: loop {
+        let batch = db.list(offset, BATCH_SIZE)?;
+        if batch.is_empty() {
+            break;
+        }
+        for (position, summary) in (offset..).zip(batch.iter()) {
+            if request.after.map_or(false, |after| summary.id <= after.last) || !filter(summary) {
+                continue;
+            }
+            pastes.push(summary.clone());
+            if pastes.len() > request.limit {
+                break 'listing;
+            }
+            next_offset = position + 1;
+        }
+        offset += batch.len();
+    }
+    let next = if pastes.len() > request.limit {
+        pastes.truncate(request.limit);
+        pastes.last().map(|last| Cursor { last: last.id,
+                                          offset: next_offset, })
+    } else {
+        None
+    };
+    Ok(Page { pastes, next })
+}
+
+/// Lists a page of the public pastes that match the `filter`, the newest first.
+///
+/// New pastes push the older ones further down the listing, so the page starts right past the
+/// last listed paste, which is looked for from the offset of the cursor on.
+pub fn public_page<D, F>(db: &D, request: &PageRequest, filter: F) -> Result<Page, D::Error>
+    where D: DbInterface + ?Sized,
+          F: Fn(&PasteSummary) -> bool
+{
+    // The last listed paste is right before the offset, unless something has changed since.
+    let mut offset = request.after.map_or(0, |after| after.offset.saturating_sub(1));
+    let mut pastes = Vec::with_capacity(request.limit + 1);
+    let mut next_offset = offset;
+    let mut first_batch = true;
+    'listing: loop {
+        let batch = db.list_public(offset, BATCH_SIZE)?;
+        if batch.is_empty() {
+            break;
+        }
+        let skip = match request.after {
+            Some(after) if first_batch => {
+                batch.iter()
+                     .position(|summary| summary.id == after.last)
+                     .map_or(0, |position| position + 1)
+            }
+            _ => 0,
+        };
+        first_batch = false;
+        for (position, summary) in (offset..).zip(batch.iter()).skip(skip) {
+            if !filter(summary) {
+                continue;
+            }
+            pastes.push(summary.clone());
+            if pastes.len() > request.limit {
+                break 'listing;
+            }
+            next_offset = position + 1;
+        }
+        offset += batch.len();
+    }
+    let next = if pastes.len() > request.limit {
+        pastes.truncate(request.limit);
+        pastes.last().map(|last| Cursor { last: last.id,
+                                          offset: next_offset, })
+    } else {
+        None
+    };
+    Ok(Page { pastes, next })
+}
+
+/// Lists all the pastes that match the `filter`, going through the whole database.
+pub fn all<D, F>(db: &D, filter: F) -> Result<Vec<PasteSummary>, D::Error>
+    where D: DbInterface + ?Sized,
+          F: Fn(&PasteSummary) -> bool
+{
+    let mut pastes = Vec::new();
+    let mut offset = 0;
+    loop {
+        let batch = db.list(offset, BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(pastes);
+        }
+        offset += batch.len();
+        pastes.extend(batch.into_iter().filter(|summary| filter(summary)));
+    }
+}
+
+/// Finds an offset at or before the paste that follows the cursor.
+///
+/// Pastes removed since the cursor has been made shift the rest of them back, so the listing
+/// steps back until it sees the last listed paste (or anything before it).
+fn resume_offset<D>(db: &D, after: Cursor) -> Result<usize, D::Error>
+    where D: DbInterface + ?Sized
+{
+    let mut offset = after.offset.saturating_sub(1);
+    while offset > 0 {
+        match db.list(offset, 1)?.first() {
+            Some(first) if first.id <= after.last => break,
+            _ => offset = offset.saturating_sub(BATCH_SIZE),
+        }
+    }
+    Ok(offset)
+}
@@ -1,53 +1,757 @@
 use DbInterface;
 use Error;
+use ExtraPage;
+use ExtraPages;
+use IdEncoding;
+use IdGenerator;
+use error::{DbResultExt, ResultExt};
+use form;
+use human::{self, filesize};
+use PasteEntry;
+use PasteId;
+use PasteSummary;
+use Retention;
+use RetentionPolicy;
+use SequentialIds;
+use SizeLimits;
+use Tombstone;
+use Unsupported;
+use UpdateOutcome;
+use analytics::{Analytics, NoAnalytics, Visitor};
+use api;
+use banner::{Banner, Severity};
+use base64;
 use chrono::{DateTime, Duration, NaiveDateTime, Utc};
-use id::{decode_id, encode_id};
+use convert::{self, Converters};
+use csrf;
+#[cfg(feature = "dedup")]
+use db::content_hash;
+#[cfg(feature = "http-compression")]
+use encoding;
 use iron::{status, Handler, Url};
-use iron::headers::ContentType;
+use iron::headers::{AcceptRanges, Authorization, Basic, Bearer, ByteRangeSpec, ContentEncoding,
+                    ContentLength, ContentRange, ContentRangeSpec, ContentType, ETag, Encoding,
+                    EntityTag, IfModifiedSince, IfNoneMatch, IfRange, Location, Range, RangeUnit};
+use dump;
 use iron::method::Method;
+use iron::mime::{Mime, SubLevel, TopLevel};
 use iron::modifiers::Redirect;
 use iron::prelude::*;
-use iron::response::BodyReader;
-use mime;
+use iron::response::{BodyReader, WriteBody};
+#[cfg(feature = "metrics")]
+use metrics::Metrics;
+use middleware;
+use mime::{self, MimeType};
+use multipart;
+use pages;
+use pagination::{self, PageRequest, PasteFilter};
+use rand::{thread_rng, Rng};
 use read::load_data;
-use request::RequestExt;
+use redact::redact;
+use request::{parse_expires, parse_language, parse_max_views, parse_timestamp, RequestExt};
+use secrets::Secret;
 use serde_json;
+use spam::{SpamFilter, Verdict};
+use spec;
 use std;
 use std::borrow::Cow;
+use std::cmp;
 use std::fs::File;
-use std::ops::Add;
+use std::io::{self, Write};
 use std::path::PathBuf;
 use std::str::from_utf8;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 use tera::{escape_html, Tera};
 
-/// An intermediate structure that handles information about a MongoDB connection and web templates
-/// engine.
+/// Response header with the creation date of a paste.
+const CREATED_AT_HEADER: &str = "X-Created-At";
+
+/// Sets the creation date header (in RFC 3339 format, like `2018-05-01T12:30:00Z`).
+fn set_created_at(response: &mut Response, created_at: DateTime<Utc>) {
+    let value = created_at.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    response.headers
+            .set_raw(CREATED_AT_HEADER, vec![value.into_bytes()]);
+}
+
+/// Response header with the expiration date of a new paste.
+const EXPIRES_AT_HEADER: &str = "X-Expires-At";
+
+/// Response header with the maximum lifetime (in seconds) of pastes of the type of a new paste.
+const MAX_TTL_HEADER: &str = "X-Max-TTL";
+
+/// Sets the headers that let an uploader know when the new paste expires (in RFC 3339 format,
+/// like the creation date) and whether the retention policy limits its lifetime.
+fn set_retention(response: &mut Response, retention: &Retention) {
+    if let Some(expires_at) = retention.expires_at {
+        let value = expires_at.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        response.headers
+                .set_raw(EXPIRES_AT_HEADER, vec![value.into_bytes()]);
+    }
+    if let Some(max_ttl) = retention.max_ttl {
+        let value = max_ttl.num_seconds().to_string();
+        response.headers
+                .set_raw(MAX_TTL_HEADER, vec![value.into_bytes()]);
+    }
+}
+
+/// Response header with the content-addressed permalink of a paste.
+const PERMALINK_HEADER: &str = "X-Permalink";
+
+/// Algorithm prefix of the hashes permalinks are resolved by, see `db::content_hash`.
+const PERMALINK_HASH_PREFIX: &str = "blake2b:";
+
+/// Response header with the number of views of a paste.
+const VIEWS_HEADER: &str = "X-Views";
+
+/// Sets the number of views header.
+fn set_views(response: &mut Response, views: u64) {
+    response.headers
+            .set_raw(VIEWS_HEADER, vec![views.to_string().into_bytes()]);
+}
+
+/// Response header with the number of times a paste with a view limit could still be viewed.
+const VIEWS_LEFT_HEADER: &str = "X-Views-Left";
+
+/// Sets the views left header if the paste has a view limit.
+fn set_views_left(response: &mut Response, views_left: Option<u64>) {
+    if let Some(views_left) = views_left {
+        response.headers
+                .set_raw(VIEWS_LEFT_HEADER, vec![views_left.to_string().into_bytes()]);
+    }
+}
+
+/// Response header with the revision of a paste, which an update could be based on.
+const REVISION_HEADER: &str = "X-Revision";
+
+/// Sets the revision header.
+fn set_revision(response: &mut Response, revision: u64) {
+    response.headers
+            .set_raw(REVISION_HEADER, vec![revision.to_string().into_bytes()]);
+}
+
+/// Tags the data of a paste at a revision: updates bump the revision and IDs are not reused, so
+/// the tag changes whenever the data does.
+fn entity_tag(id: PasteId, revision: u64) -> EntityTag {
+    EntityTag::strong(format!("{}-{}", id, revision))
+}
+
+/// Tells when the data of a paste has changed for the last time.
+///
+/// Only the creation date is stored, so replaced pastes (beyond revision 0) can't tell.
+fn last_modified(created_at: DateTime<Utc>, revision: u64) -> Option<DateTime<Utc>> {
+    if revision == 0 {
+        Some(created_at)
+    } else {
+        None
+    }
+}
+
+/// Response header with the date the data of a paste has changed for the last time.
+const LAST_MODIFIED_HEADER: &str = "Last-Modified";
+
+/// Sets the last modification date header (in HTTP format, like `Tue, 01 May 2018 12:30:00 GMT`)
+/// if the date is known.
+fn set_last_modified(response: &mut Response, last_modified: Option<DateTime<Utc>>) {
+    if let Some(date) = last_modified {
+        let value = date.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        response.headers
+                .set_raw(LAST_MODIFIED_HEADER, vec![value.into_bytes()]);
+    }
+}
+
+/// Checks whether the client's copy is current: the `If-None-Match` header matches the tag, or,
+/// without one, the `If-Modified-Since` date is not earlier than the `last_modified` one.
+fn is_not_modified(req: &Request, tag: &EntityTag, last_modified: Option<DateTime<Utc>>) -> bool {
+    match req.headers.get::<IfNoneMatch>() {
+        Some(&IfNoneMatch::Any) => return true,
+        Some(&IfNoneMatch::Items(ref tags)) => {
+            return tags.iter().any(|given| given.weak_eq(tag));
+        }
+        None => {}
+    }
+    let since = req.headers
+                   .get::<IfModifiedSince>()
+                   .and_then(|since| DateTime::parse_from_rfc2822(&since.0.to_string()).ok());
+    match (last_modified, since) {
+        // HTTP dates are precise to a second.
+        (Some(modified), Some(since)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// A part of a paste's data a request asks for with the `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// The whole data.
+    Full,
+    /// Bytes from the first to the last one, inclusive.
+    Partial(u64, u64),
+    /// A range that starts past the end of the data.
+    Unsatisfiable,
+}
+
+/// Finds the part of `length` bytes of data a request asks for.
+///
+/// Only a single range of bytes is served as a part, a request for several ones gets the whole
+/// data. So does a request with an `If-Range` that doesn't match the tag (or the `last_modified`
+/// date): the part the client already has is stale.
+fn byte_range(req: &Request,
+              tag: &EntityTag,
+              last_modified: Option<DateTime<Utc>>,
+              length: u64)
+              -> ByteRange {
+    let spec = match req.headers.get::<Range>() {
+        Some(&Range::Bytes(ref specs)) if specs.len() == 1 => specs[0].clone(),
+        _ => return ByteRange::Full,
+    };
+    let current = match req.headers.get::<IfRange>() {
+        Some(&IfRange::EntityTag(ref given)) => given.strong_eq(tag),
+        Some(&IfRange::Date(ref date)) => {
+            let date = DateTime::parse_from_rfc2822(&date.to_string()).ok();
+            match (last_modified, date) {
+                (Some(modified), Some(date)) => modified.timestamp() <= date.timestamp(),
+                _ => false,
+            }
+        }
+        None => true,
+    };
+    if !current {
+        return ByteRange::Full;
+    }
+    let (first, last) = match spec {
+        ByteRangeSpec::FromTo(first, last) if first <= last => (first, last),
+        ByteRangeSpec::FromTo(..) => return ByteRange::Full,
+        ByteRangeSpec::AllFrom(first) => (first, u64::max_value()),
+        ByteRangeSpec::Last(0) => return ByteRange::Unsatisfiable,
+        ByteRangeSpec::Last(count) => (length.saturating_sub(count), u64::max_value()),
+    };
+    if first >= length {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(first, cmp::min(last, length - 1))
+    }
+}
+
+/// Response header with a signature of a paste, see [db::SignedDb](db/struct.SignedDb.html).
+const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Response header that confines converted pastes (which might turn out to be HTML), so they
+/// can't run scripts on the service's pages.
+const CONTENT_SECURITY_POLICY_HEADER: &str = "Content-Security-Policy";
+
+/// Response header that makes a paste a download rather than something to display.
+const CONTENT_DISPOSITION_HEADER: &str = "Content-Disposition";
+
+/// Response header with a token that allows to remove a freshly stored paste.
+const DELETION_TOKEN_HEADER: &str = "X-Deletion-Token";
+
+/// Response header that asks a client for credentials.
+pub const WWW_AUTHENTICATE_HEADER: &str = "WWW-Authenticate";
+
+/// Number of the most recent uploads shown on the admin dashboard.
+const DASHBOARD_RECENT: usize = 20;
+
+/// Maximum length of a slug of a paste.
+const MAX_SLUG_LENGTH: usize = 64;
+
+/// How many IDs of the ID generator are tried for a new paste before giving up.
+const MAX_ID_ATTEMPTS: usize = 16;
+
+/// Minimum length of a deletion token chosen by an uploader, that of a random one.
+const MIN_TOKEN_LENGTH: usize = 22;
+
+/// Generates a random deletion token.
+fn deletion_token() -> String {
+    let mut bytes = [0u8; 16];
+    thread_rng().fill(&mut bytes);
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Returns the deletion token of an upload: the one given as the `token` argument, so pastes
+/// uploaded with the same token belong to the same owner (see `DbInterface::remove_by_owner`),
+/// or a random one.
+fn upload_token(req: &Request) -> Result<String, Error> {
+    match req.get_arg("token") {
+        Some(token) => {
+            check_owner_token(&token)?;
+            Ok(token.into_owned())
+        }
+        None => Ok(deletion_token()),
+    }
+}
+
+/// Checks that a token chosen by a client looks no easier to guess than a random one: URL-safe
+/// base64 at least as long.
+fn check_owner_token(token: &str) -> Result<(), Error> {
+    let url_safe = token.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if token.len() < MIN_TOKEN_LENGTH || !url_safe {
+        return Err(Error::WeakToken(MIN_TOKEN_LENGTH));
+    }
+    Ok(())
+}
+
+/// Returns the token of the owner an `owner=me` request is made on behalf of: the deletion token
+/// given as the `token` argument.
+fn caller_token(req: &Request) -> Result<String, Error> {
+    match req.get_arg("owner") {
+        Some(ref owner) if owner == "me" => (),
+        Some(owner) => return Err(Error::UnknownOwner(owner.into_owned())),
+        None => return Err(Error::MissingArgument("owner")),
+    }
+    let token = req.get_arg("token").ok_or(Error::MissingArgument("token"))?;
+    check_owner_token(&token)?;
+    Ok(token.into_owned())
+}
+
+/// Builds a `Content-Disposition` header that makes a paste a download named `file_name`.
+///
+/// The plain `filename` parameter keeps to printable ASCII, while names with anything else are
+/// given in full by the RFC 5987 `filename*` parameter as well.
+fn attachment(file_name: &str) -> Vec<u8> {
+    let fallback: String = file_name.chars()
+                                    .map(|c| match c {
+                                             ' '..='~' if c != '"' && c != '\\' => c,
+                                             _ => '_',
+                                         })
+                                    .collect();
+    let mut value = format!("attachment; filename=\"{}\"", fallback);
+    if fallback != file_name {
+        value.push_str("; filename*=UTF-8''");
+        for &byte in file_name.as_bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'!' | b'#' | b'$' | b'&' | b'+' |
+                b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => value.push(byte as char),
+                _ => value.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+    }
+    value.into_bytes()
+}
+
+/// Checks the syntax of a slug: letters, digits, dashes and underscores.
+fn check_slug_syntax(slug: &str) -> Result<(), Error> {
+    let invalid = |reason| Err(Error::InvalidSlug(slug.into(), reason));
+    if slug.is_empty() || slug.len() > MAX_SLUG_LENGTH {
+        return invalid("a slug takes from 1 to 64 characters");
+    }
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !slug.chars().all(allowed) {
+        return invalid("a slug takes letters, digits, dashes and underscores only");
+    }
+    Ok(())
+}
+
+/// Returns the slug an upload claims: the one in its path (`/s/<slug>`) or the `slug` argument.
+fn requested_slug(req: &Request) -> Option<String> {
+    match req.url_segment_n(0) {
+        Some("s") => req.url_segment_n(1).map(String::from),
+        _ => req.get_arg("slug").map(Cow::into_owned),
+    }
+}
+
+/// An export of pastes, written while they are loaded, see `dump::export_pastes`.
+struct Export<E> {
+    db: Arc<DbInterface<Error = E>>,
+    ids: Vec<PasteId>,
+}
+
+impl<E> WriteBody for Export<E>
+    where E: Send + Sync + std::error::Error + From<Unsupported> + 'static
+{
+    fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+        // The response has been started already, so a failure only cuts the export short.
+        match dump::export_pastes(&*self.db, &self.ids, res) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Export of {} pastes has failed: {}", self.ids.len(), e);
+                Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+            }
+        }
+    }
+}
+
+/// A paste that has just been stored.
+struct Stored {
+    id: PasteId,
+    /// The slug the paste has claimed, if any.
+    slug: Option<String>,
+    /// The policy decision on the expiration date of the paste.
+    retention: Retention,
+    deletion_token: String,
+}
+
+impl Stored {
+    /// Returns the path of the paste (after the URL prefix): its slug or its ID.
+    fn path(&self, id_encoding: &IdEncoding) -> String {
+        match self.slug {
+            Some(ref slug) => format!("s/{}", slug),
+            None => id_encoding.encode(self.id.into()),
+        }
+    }
+}
+
+/// How a paste is served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    /// Depending on the paste and the client: browsers get text pastes wrapped into a page, and
+    /// file names are redirected to.
+    Auto,
+    /// As it is, in a sandbox (`/raw/<id>`).
+    Raw,
+    /// As a file to save (`/dl/<id>`), named after the paste or its ID.
+    Download,
+}
+
+/// Checks whether a paste is an image browsers could show.
+fn is_image(summary: &PasteSummary) -> bool {
+    summary.mime_type.as_str().starts_with("image/")
+}
+
+/// Checks whether a paste is a recording browsers could play.
+fn is_playable(summary: &PasteSummary) -> bool {
+    let mime_type = summary.mime_type.as_str();
+    mime_type.starts_with("audio/") || mime_type.starts_with("video/")
+}
+
+/// Checks whether a paste is a Markdown document, by its language.
+///
+/// The language is guessed from the file name (`.md`, `.markdown`) or the mime type
+/// (`text/markdown`) unless the uploader gives it, in which case the given one wins.
+fn is_markdown(summary: &PasteSummary) -> bool {
+    summary.language.as_ref().map(String::as_str) == Some("markdown")
+}
+
+/// Renders a Markdown paste into HTML (see `convert::render_markdown`), `None` for other pastes.
+#[cfg(feature = "markdown")]
+fn render_markdown(summary: &PasteSummary, data: &[u8]) -> Option<String> {
+    if !is_markdown(summary) {
+        return None;
+    }
+    Some(convert::render_markdown(&String::from_utf8_lossy(data)))
+}
+
+#[cfg(not(feature = "markdown"))]
+fn render_markdown(_: &PasteSummary, _: &[u8]) -> Option<String> {
+    None
+}
+
+/// Number of seconds left before a paste expires, if it ever does.
+fn expires_in(best_before: Option<DateTime<Utc>>) -> Option<i64> {
+    best_before.map(|date| cmp::max((date - Utc::now()).num_seconds(), 0))
+}
+
+/// The pastebin request handler.
+///
+/// Holds a database wrapper and web templates engine and implements Iron's `Handler`. Normally it
+/// is created and launched by [PastebinBuilder](web/struct.PastebinBuilder.html) (or
+/// [run_web](web/fn.run_web.html)), but it can be used directly as a part of a custom Iron
+/// chain, in which case no background jobs are started.
 pub struct Pastebin<E> {
-    db: Box<DbInterface<Error = E>>,
+    db: Arc<DbInterface<Error = E>>,
     templates: Tera,
     url_prefix: String,
     default_ttl: Duration,
     static_path: PathBuf,
+    size_limits: SizeLimits,
+    retention: RetentionPolicy,
+    signing_key: Option<String>,
+    count_views: bool,
+    record_creators: bool,
+    admin_token: Option<Secret>,
+    hotlink_allowed_hosts: Option<Vec<String>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    extra_pages: ExtraPages,
+    analytics: Arc<Analytics>,
+    id_generator: Arc<IdGenerator>,
+    id_encoding: IdEncoding,
+    use_trash: bool,
+    tombstones: bool,
+    upload_timeout: Option<Duration>,
+    converters: Converters,
+    content_permalinks: bool,
+    csrf_protection: bool,
+    banner: RwLock<Option<Banner>>,
+    spam_filter: Option<SpamFilter>,
 }
 
 impl<E> Pastebin<E>
-    where E: Send + Sync + std::error::Error + 'static
+    where E: Send + Sync + std::error::Error + From<Unsupported> + 'static
 {
-    /// Initializes a pastebin web server with a database interface.
-    pub fn new(db: Box<DbInterface<Error = E>>,
-               templates: Tera,
+    /// Initializes a pastebin request handler with a database interface.
+    ///
+    /// Please refer to the [run_web](web/fn.run_web.html) documentation for the details on the
+    /// arguments. Unlike there, `url_prefix` is used as is, so it should end with a slash.
+    pub fn new(db: Arc<DbInterface<Error = E>>,
+               mut templates: Tera,
                url_prefix: String,
                default_ttl: Duration,
                static_path: String)
                -> Self {
+        human::register_filters(&mut templates);
         Pastebin { db,
                    templates,
                    url_prefix,
                    default_ttl,
-                   static_path: static_path.into(), }
+                   static_path: static_path.into(),
+                   size_limits: SizeLimits::new(),
+                   retention: RetentionPolicy::new(),
+                   signing_key: None,
+                   count_views: true,
+                   record_creators: false,
+                   admin_token: None,
+                   hotlink_allowed_hosts: None,
+                   #[cfg(feature = "metrics")]
+                   metrics: None,
+                   extra_pages: ExtraPages::new(),
+                   analytics: Arc::new(NoAnalytics),
+                   id_generator: Arc::new(SequentialIds),
+                   id_encoding: IdEncoding::default(),
+                   use_trash: false,
+                   tombstones: false,
+                   upload_timeout: None,
+                   converters: Converters::default(),
+                   content_permalinks: false,
+                   csrf_protection: true,
+                   banner: RwLock::new(None),
+                   spam_filter: None, }
+    }
+
+    /// Sets limits on the size of pastes, on top of the database's `max_data_size`.
+    pub fn size_limits(mut self, size_limits: SizeLimits) -> Self {
+        self.size_limits = size_limits;
+        self
+    }
+
+    /// Sets maximum lifetimes of pastes, which cap the expiration dates of uploads.
+    ///
+    /// Sweeping the pastes that have outlived the policy is up to the
+    /// [builder](web/struct.PastebinBuilder.html#method.retention).
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = policy;
+        self
+    }
+
+    /// Sets the public key pastes are signed with, see [db::SignedDb](db/struct.SignedDb.html).
+    ///
+    /// The key is served at `/signing-key`, and signatures of pastes are returned in the
+    /// `X-Signature` header.
+    pub fn signing_key(mut self, public_key: String) -> Self {
+        self.signing_key = Some(public_key);
+        self
+    }
+
+    /// Enables or disables counting views of pastes (enabled by default).
+    ///
+    /// When disabled, the counters are neither updated nor shown.
+    pub fn count_views(mut self, count_views: bool) -> Self {
+        self.count_views = count_views;
+        self
+    }
+
+    /// Enables or disables content-addressed permalinks of pastes (disabled by default).
+    ///
+    /// The permalinks (`/h/<hash>`) are resolved through the hashes stored by `db::DedupDb`, so
+    /// the database has to be wrapped into one, and they are only given out with the `dedup`
+    /// feature.
+    pub fn content_permalinks(mut self, enabled: bool) -> Self {
+        self.content_permalinks = enabled;
+        self
+    }
+
+    /// Enables or disables the protection of the upload form against cross-site request
+    /// forgery (enabled by default), see the `csrf` module.
+    ///
+    /// When enabled, uploads and forks submitted by browsers have to carry the token of the page
+    /// they are submitted from.
+    pub fn csrf_protection(mut self, enabled: bool) -> Self {
+        self.csrf_protection = enabled;
+        self
+    }
+
+    /// Enables or disables recording client IP addresses of uploaders (disabled by default).
+    ///
+    /// Addresses are stored as `PasteEntry::creator` and are never shown to users.
+    pub fn record_creators(mut self, record_creators: bool) -> Self {
+        self.record_creators = record_creators;
+        self
+    }
+
+    /// Enables the admin API with a token requests have to bear, see
+    /// [PastebinBuilder::admin_token](web/struct.PastebinBuilder.html#method.admin_token).
+    pub fn admin_token(mut self, token: Secret) -> Self {
+        self.admin_token = Some(token);
+        self
+    }
+
+    /// Enables hotlink protection, so binary pastes could only be embedded into pages of the
+    /// service itself and of the allowed hosts, see the
+    /// [builder](web/struct.PastebinBuilder.html#method.hotlink_protection).
+    pub fn hotlink_protection(mut self, allowed_hosts: Vec<String>) -> Self {
+        self.hotlink_allowed_hosts = Some(allowed_hosts);
+        self
+    }
+
+    /// Serves the metrics registry at `/metrics`, see the [metrics](metrics/index.html) module.
+    ///
+    /// Requests are not recorded by the handler itself, that's what the
+    /// [builder](web/struct.PastebinBuilder.html#method.metrics) adds a middleware for.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sets extra pages rendered from templates, like `/about`.
+    pub fn extra_pages(mut self, extra_pages: ExtraPages) -> Self {
+        self.extra_pages = extra_pages;
+        self
+    }
+
+    /// Sets analytics to report views and uploads of pastes to, see the
+    /// [analytics](analytics/index.html) module.
+    pub fn analytics(mut self, analytics: Arc<Analytics>) -> Self {
+        self.analytics = analytics;
+        self
+    }
+
+    /// Sets the generator of IDs of new pastes (the database's sequential IDs by default), see
+    /// [IdGenerator](trait.IdGenerator.html).
+    pub fn id_generator(mut self, id_generator: Arc<IdGenerator>) -> Self {
+        self.id_generator = id_generator;
+        self
+    }
+
+    /// Sets how IDs are written in URLs (url-safe base64 by default), see
+    /// [IdEncoding](struct.IdEncoding.html).
+    pub fn id_encoding(mut self, id_encoding: IdEncoding) -> Self {
+        self.id_encoding = id_encoding;
+        self
+    }
+
+    /// Enables or disables moving removed pastes to the trash instead of removing them (disabled
+    /// by default).
+    ///
+    /// Trashed pastes could be restored with the admin API, purging the trash is up to the
+    /// [builder](web/struct.PastebinBuilder.html#method.trash).
+    pub fn use_trash(mut self, use_trash: bool) -> Self {
+        self.use_trash = use_trash;
+        self
+    }
+
+    /// Enables or disables keeping tombstones of removed pastes (disabled by default).
+    ///
+    /// A request for a paste with a tombstone is answered with `410 Gone` rather than `404 Not
+    /// Found`, and admins could list recent removals at `/admin/tombstones`. Purging old
+    /// tombstones is up to the [builder](web/struct.PastebinBuilder.html#method.tombstones).
+    pub fn tombstones(mut self, tombstones: bool) -> Self {
+        self.tombstones = tombstones;
+        self
+    }
+
+    /// Sets the time an upload has to be received in (unlimited by default).
+    ///
+    /// An upload that doesn't make it in time, just like one whose client disconnects midway, is
+    /// aborted and logged, and nothing of it is stored.
+    pub fn upload_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets converters pastes are served converted with at `/<id>/as/<format>`, see the
+    /// [convert](convert/index.html) module. All the built-in converters are used by default.
+    pub fn converters(mut self, converters: Converters) -> Self {
+        self.converters = converters;
+        self
+    }
+
+    /// Sets an announcement banner shown on top of every page, until it expires or is replaced
+    /// with the admin API.
+    pub fn banner(self, banner: Banner) -> Self {
+        *self.banner.write().unwrap() = Some(banner);
+        self
+    }
+
+    /// Sets a spam filter text uploads are checked with, see the [spam](spam/index.html) module.
+    pub fn spam_filter(mut self, filter: SpamFilter) -> Self {
+        self.spam_filter = Some(filter);
+        self
+    }
+
+    /// Returns the banner if there is one and it hasn't expired yet.
+    fn active_banner(&self) -> Option<Banner> {
+        self.banner
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|banner| banner.is_active(Utc::now()))
+            .cloned()
+    }
+
+    /// Sets the signature header if signing is enabled and the paste is signed.
+    fn set_signature(&self, response: &mut Response, id: PasteId) -> IronResult<()> {
+        if self.signing_key.is_none() {
+            return Ok(());
+        }
+        let signature = self.db
+                            .load_signature(id)
+                            .db_context(|| format!("loading a signature of paste {}", id))?;
+        if let Some(signature) = signature {
+            response.headers
+                    .set_raw(SIGNATURE_HEADER, vec![signature.into_bytes()]);
+        }
+        Ok(())
+    }
+
+    /// Combines a size limit of the policy (if any) with the database limit.
+    fn effective_limit(&self, limit: Option<usize>) -> usize {
+        let db_limit = self.db.max_data_size();
+        limit.map(|limit| cmp::min(limit, db_limit))
+             .unwrap_or(db_limit)
+    }
+
+    /// Checks a paste against the limit for its mime type.
+    fn check_size(&self, data: &[u8], mime_type: &MimeType) -> Result<(), Error> {
+        let limit = self.effective_limit(self.size_limits.limit_for(mime_type));
+        if data.len() > limit {
+            return Err(Error::TooBig(limit, Some(mime_type.clone())));
+        }
+        Ok(())
+    }
+
+    /// Checks a text upload with the spam filter (if any), rejecting spam.
+    ///
+    /// Returns the score of the upload if it is to be flagged once stored.
+    fn check_spam(&self, data: &[u8], mime_type: &MimeType) -> Result<Option<f64>, Error> {
+        let filter = match self.spam_filter {
+            Some(ref filter) if mime_type.is_text() => filter,
+            _ => return Ok(None),
+        };
+        let (score, verdict) = filter.classify(&String::from_utf8_lossy(data));
+        match verdict {
+            Verdict::Allow => Ok(None),
+            Verdict::Flag => Ok(Some(score)),
+            Verdict::Reject => {
+                info!("Rejected {} as spam (score {:.2})", redact(data), score);
+                Err(Error::Spam)
+            }
+        }
+    }
+
+    /// Flags a stored paste for review if the spam filter has asked for it.
+    fn flag_spam(&self, id: PasteId, flagged: Option<f64>) {
+        if let (Some(filter), Some(score)) = (self.spam_filter.as_ref(), flagged) {
+            warn!("Paste {} is flagged as spam (score {:.2})", id, score);
+            filter.flag(id, score);
+        }
     }
 
     /// Render a template.
+    ///
+    /// The active banner (if any) is added to the data as `banner_message` and `banner_style`
+    /// (a UIkit alert style).
     fn render_template(&self,
                        name: &str,
                        content_type: ContentType,
@@ -55,56 +759,677 @@ impl<E> Pastebin<E>
                        -> IronResult<Response> {
         let mut response = Response::new();
         response.headers.set(content_type);
-        response.set_mut(itry!(self.templates.render(&format!("{}.tera", name), data,)))
+        let mut data = data.clone();
+        if let (Some(banner), Some(object)) = (self.active_banner(), data.as_object_mut()) {
+            let style = match banner.severity {
+                Severity::Info => "primary",
+                Severity::Warning => "warning",
+                Severity::Critical => "danger",
+            };
+            object.insert("banner_message".into(), escape_html(&banner.message).into());
+            object.insert("banner_style".into(), style.into());
+        }
+        let body = self.templates
+                       .render(&format!("{}.tera", name), &data)
+                       .context(|| format!("rendering template {}", name))?;
+        response.set_mut(body)
                 .set_mut(status::Ok);
         Ok(response)
     }
 
-    /// Serves data in a form of HTML.
+    /// Renders an HTML page with a form, which gets the CSRF token of the client (or a new one,
+    /// along with its cookie) as `csrf_token` unless the protection is disabled.
+    fn render_form(&self,
+                   req: &Request,
+                   name: &str,
+                   mut data: serde_json::Value)
+                   -> IronResult<Response> {
+        if !self.csrf_protection {
+            return self.render_template(name, ContentType::html(), &data);
+        }
+        let token = csrf::token(req);
+        if let Some(object) = data.as_object_mut() {
+            object.insert("csrf_token".into(), token.clone().into());
+        }
+        let mut response = self.render_template(name, ContentType::html(), &data)?;
+        csrf::set_cookie(&mut response, &token);
+        Ok(response)
+    }
+
+    /// Serves data in a form of HTML, highlighted as the `language` if it is given.
     fn serve_data_html(&self,
-                       id: u64,
-                       mime: &str,
-                       file_name: Option<String>,
-                       data: &[u8])
+                       req: &Request,
+                       id: PasteId,
+                       paste: PasteEntry,
+                       views: Option<u64>,
+                       language: Option<String>)
                        -> IronResult<Response> {
-        self.render_template(
+        let summary = PasteSummary::from_entry(id, &paste);
+        let public_id = self.public_id(id);
+        let rendered_url = if cfg!(feature = "markdown") && is_markdown(&summary) {
+            Some(escape_html(&format!("{}{}/html", self.url_prefix, public_id)))
+        } else {
+            None
+        };
+        self.render_form(
+            req,
             "show.html",
+            json!({
+                    "id": public_id,
+                    "mime": escape_html(paste.mime_type.as_str()),
+                    "file_name": paste.file_name.map(|s| escape_html(&s)),
+                    "size": summary.size,
+                    "lines": summary.lines,
+                    "language": language.or(summary.language),
+                    "created_at": summary.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    "expires_in": expires_in(summary.best_before),
+                    "views": views,
+                    "raw_url": escape_html(&format!("{}raw/{}", self.url_prefix, public_id)),
+                    "download_url": escape_html(&format!("{}dl/{}", self.url_prefix, public_id)),
+                    "fork_url": escape_html(&format!("{}{}/fork?edit", self.url_prefix, public_id)),
+                    "rendered_url": rendered_url,
+                    "permalink": self.permalink(&paste.data).map(|url| escape_html(&url)),
+                    "data": escape_html(itry!(from_utf8(&paste.data)))
+                }),
+        )
+    }
+
+    /// Builds a content-addressed permalink of a paste with the given data, if permalinks are
+    /// enabled.
+    #[cfg(feature = "dedup")]
+    fn permalink(&self, data: &[u8]) -> Option<String> {
+        if !self.content_permalinks {
+            return None;
+        }
+        let hash = content_hash(data);
+        Some(format!("{}h/{}", self.url_prefix, hash.trim_left_matches(PERMALINK_HASH_PREFIX)))
+    }
+
+    #[cfg(not(feature = "dedup"))]
+    fn permalink(&self, _: &[u8]) -> Option<String> {
+        None
+    }
+
+    /// Redirects a content-addressed permalink (`/h/<hash>`) to the paste the hash is stored for.
+    ///
+    /// If several pastes share the content, the one the hash has been stored for the latest wins.
+    fn resolve_permalink(&self, hash: &str) -> IronResult<Response> {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_digit(16) && !c.is_uppercase()) {
+            return Err(Error::InvalidHash(hash.to_string()).into());
+        }
+        let id = self.db
+                     .find_by_hash(&format!("{}{}", PERMALINK_HASH_PREFIX, hash))
+                     .db_context(|| format!("looking up hash {}", hash))?
+                     .ok_or_else(|| Error::HashNotFound(hash.to_string()))?;
+        let metadata = self.load_paste_metadata(id)?;
+        let url = Url::parse(&self.paste_url(&metadata)).map_err(|e| Error::Url(e))?;
+        Ok(Response::with((status::Found, Redirect(url))))
+    }
+
+    /// Writes the ID of a paste down the way it is in URLs.
+    fn public_id(&self, id: PasteId) -> String {
+        self.id_encoding.encode(id.into())
+    }
+
+    /// Builds a link to a paste, with its file name if it has one.
+    fn paste_url(&self, metadata: &PasteSummary) -> String {
+        let id = self.public_id(metadata.id);
+        match metadata.file_name {
+            Some(ref name) => format!("{}{}/{}", self.url_prefix, id, name),
+            None => format!("{}{}", self.url_prefix, id),
+        }
+    }
+
+    /// Serves a Markdown paste rendered into an HTML page (`/<id>/html`), along with links to its
+    /// source. Other pastes are answered with `415 Unsupported Media Type`.
+    ///
+    /// The rendered HTML is sanitized (see `convert::render_markdown`), since unlike the
+    /// conversions at `/<id>/as/html` the page is not sandboxed. A `HEAD` request
+    /// (`headers_only`) doesn't count as a view.
+    fn serve_rendered(&self,
+                      req: &Request,
+                      str_id: &str,
+                      headers_only: bool)
+                      -> IronResult<Response> {
+        let id = self.resolve_id(str_id)?;
+        let paste = self.load_paste(id)?;
+        if paste.data.len() > convert::MAX_INPUT_SIZE {
+            let reason = format!("pastes bigger than {} bytes are not rendered",
+                                 convert::MAX_INPUT_SIZE);
+            return Err(Error::ConversionFailed(reason).into());
+        }
+        let summary = PasteSummary::from_entry(id, &paste);
+        let html = render_markdown(&summary, &paste.data).ok_or_else(|| {
+                       Error::UnsupportedConversion(paste.mime_type.clone(), "html".into())
+                   })?;
+        let (views_left, views) = self.view(req, id, &paste, headers_only)?;
+        let public_id = self.public_id(id);
+        let mut response = self.render_template(
+            "markdown.html",
             ContentType::html(),
             &json!({
-                    "id": id,
-                    "mime": escape_html(mime),
-                    "file_name": file_name.map(|s| escape_html(&s)),
-                    "data": escape_html(itry!(from_utf8(data)))
+                    "id": public_id,
+                    "file_name": summary.file_name.map(|s| escape_html(&s)),
+                    "size": summary.size,
+                    "created_at": summary.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    "expires_in": expires_in(summary.best_before),
+                    "views": views,
+                    "source_url": escape_html(&format!("{}{}", self.url_prefix, public_id)),
+                    "raw_url": escape_html(&format!("{}raw/{}", self.url_prefix, public_id)),
+                    "html": html,
+                }),
+        )?;
+        set_created_at(&mut response, paste.created_at);
+        set_views_left(&mut response, views_left);
+        if let Some(views) = views {
+            set_views(&mut response, views);
+        }
+        Ok(response)
+    }
+
+    /// Serves a page about a binary paste (an image is shown on it), with links to the paste
+    /// itself.
+    fn serve_binary_page(&self, metadata: PasteSummary) -> IronResult<Response> {
+        let public_id = self.public_id(metadata.id);
+        let raw_url = format!("{}raw/{}", self.url_prefix, public_id);
+        self.render_template(
+            "binary.html",
+            ContentType::html(),
+            &json!({
+                    "id": public_id,
+                    "mime": escape_html(metadata.mime_type.as_str()),
+                    "is_image": is_image(&metadata),
+                    "is_audio": metadata.mime_type.as_str().starts_with("audio/"),
+                    "is_video": metadata.mime_type.as_str().starts_with("video/"),
+                    "file_name": metadata.file_name.map(|s| escape_html(&s)),
+                    "size": metadata.size,
+                    "created_at": metadata.created_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    "expires_in": expires_in(metadata.best_before),
+                    "views": if self.count_views { Some(metadata.views) } else { None },
+                    "raw_url": escape_html(&raw_url),
+                    "download_url": escape_html(&format!("{}dl/{}", self.url_prefix, public_id)),
                 }),
         )
     }
 
+    /// Serves a page of public pastes, the newest first (see the `pagination` module), with their
+    /// titles, mime types and ages. Expired pastes are left out.
+    fn browse(&self, req: &mut Request) -> IronResult<Response> {
+        let request = PageRequest::from_request(req)?;
+        let now = Utc::now();
+        let page = pagination::public_page(&*self.db, &request, |summary| {
+                       summary.best_before.map_or(true, |date| date > now)
+                   }).db_context(|| "listing public pastes")?;
+        let pastes = page.pastes
+                         .iter()
+                         .map(|summary| self.browse_entry(summary, now))
+                         .collect::<Vec<_>>();
+        let next_url = page.next
+                           .map(|cursor| cursor.encode())
+                           .map(|cursor| format!("{}browse?cursor={}", self.url_prefix, cursor));
+        self.render_template("browse.html",
+                             ContentType::html(),
+                             &json!({
+                                 "pastes": pastes,
+                                 "next_url": next_url.map(|url| escape_html(&url)),
+                             }))
+    }
+
+    /// Describes a paste on the `/browse` page, its age is given in seconds.
+    fn browse_entry(&self, summary: &PasteSummary, now: DateTime<Utc>) -> serde_json::Value {
+        let id = self.public_id(summary.id);
+        let title = summary.file_name.clone().unwrap_or_else(|| id.clone());
+        // Pastes that are limited in views would run out of them because of the listing.
+        let thumbnail_url = if is_image(summary) && summary.views_left.is_none() {
+            Some(escape_html(&format!("{}raw/{}", self.url_prefix, id)))
+        } else {
+            None
+        };
+        json!({
+            "url": escape_html(&format!("{}{}", self.url_prefix, id)),
+            "title": escape_html(&title),
+            "mime": escape_html(summary.mime_type.as_str()),
+            "age": cmp::max((now - summary.created_at).num_seconds(), 0),
+            "thumbnail_url": thumbnail_url,
+        })
+    }
+
+    /// Checks whether a request is an attempt to embed a paste into a page of another site.
+    ///
+    /// Requests without a `Referer` (direct downloads, command line clients) are fine, and so are
+    /// the ones from the service itself and from the allowed hosts.
+    fn is_hotlinked(&self, req: &Request) -> bool {
+        let allowed_hosts = match self.hotlink_allowed_hosts {
+            Some(ref hosts) => hosts,
+            None => return false,
+        };
+        match req.referer_host() {
+            Some(host) => {
+                req.url.as_ref().host_str() != Some(&host)
+                && !allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(&host))
+            }
+            None => false,
+        }
+    }
+
     /// Loads a paste from the database.
+    ///
+    /// If `headers_only` is set, only the metadata of the paste is loaded and a response with an
+    /// empty body is produced (the data is always described as-is, no matter the user agent).
+    ///
+    /// Binary pastes are described with an HTML page instead if the `page` argument is given, or
+    /// if they are hotlinked while hotlink protection is enabled. So are images, audio and video
+    /// opened in a browser, the page shows the image itself or a player (from `/raw/<id>`).
+    ///
+    /// The `lang` argument overrides the language a text paste is highlighted as on its page.
+    ///
+    /// A `Raw` paste (`/raw/<id>`) is always served as it is, whatever the user agent: there are
+    /// no redirects nor pages, and hotlinked binary pastes are forbidden instead. It is served in
+    /// a sandbox, so an HTML paste can't run scripts on behalf of the service. A `Download` is
+    /// served just the same, but as an attachment named after the paste's file name (or its ID,
+    /// like `<id>.txt`).
+    ///
+    /// The data (as opposed to a page) comes with an `ETag` and a `Last-Modified` date (unless
+    /// the paste has been replaced), and a request with a matching `If-None-Match` (or, without
+    /// one, an `If-Modified-Since` that is not earlier) is answered with `304 Not Modified`
+    /// without loading the data or taking a view.
+    ///
+    /// The data of pastes that are not limited in views could also be fetched in parts, with a
+    /// single range of bytes in the `Range` header (`206 Partial Content`), so players could
+    /// seek through recordings. Only a part starting at the beginning counts as a view.
     fn get_paste(&self,
+                 req: &Request,
                  str_id: &str,
-                 is_browser: bool,
-                 name_provided: bool)
+                 headers_only: bool,
+                 delivery: Delivery)
                  -> IronResult<Response> {
-        let id = itry!(decode_id(str_id));
-        if !name_provided {
-            if let Some(name) = itry!(self.db.get_file_name(id)) {
+        let id = self.resolve_id(str_id)?;
+        let language = parse_language(req.get_arg("lang").as_ref().map(|lang| lang.as_ref()))?;
+        // The data is loaded right away unless only the headers are asked for: databases like the
+        // encrypted one can't describe a paste without loading (and decrypting) it anyway.
+        let (metadata, paste) = if headers_only {
+            (self.load_paste_metadata(id)?, None)
+        } else {
+            let paste = self.load_paste(id)?;
+            (PasteSummary::from_entry(id, &paste), Some(paste))
+        };
+        let raw = delivery != Delivery::Auto;
+        let disposition = if delivery == Delivery::Download {
+            Some(attachment(&match metadata.file_name {
+                                 Some(ref name) => name.clone(),
+                                 None if metadata.mime_type.is_text() => {
+                                     format!("{}.txt", self.public_id(id))
+                                 }
+                                 None => self.public_id(id),
+                             }))
+        } else {
+            None
+        };
+        if !raw && req.url_segment_n(1).is_none() {
+            if let Some(name) = metadata.file_name {
                 let new_url =
                     Url::parse(&format!("{}{}/{}", self.url_prefix, str_id, name))
                         .map_err(|e| Error::Url(e))?;
                 return Ok(Response::with((status::MovedPermanently, Redirect(new_url))));
             }
         }
-        let paste = itry!(self.db.load_data(id)).ok_or(Error::IdNotFound(id))?;
-        if mime::is_text(&paste.mime_type) && is_browser {
-            self.serve_data_html(id, &paste.mime_type, paste.file_name, &paste.data)
+        if !metadata.mime_type.is_text() {
+            if !raw && req.get_arg("page").is_some() {
+                return self.serve_binary_page(metadata);
+            }
+            if !raw && (is_image(&metadata) || is_playable(&metadata)) && req.is_browser()
+               && req.accepts_html()
+            {
+                return self.serve_binary_page(metadata);
+            }
+            if self.is_hotlinked(req) {
+                debug!("Paste {} is hotlinked from {:?}", id, req.referer_host());
+                if raw {
+                    return Err(Error::Forbidden.into());
+                }
+                let page_url = Url::parse(&format!("{}?page", self.paste_url(&metadata)))
+                    .map_err(|e| Error::Url(e))?;
+                return Ok(Response::with((status::Found, Redirect(page_url))));
+            }
+        }
+        // Pages show view counters and expiration countdowns, so only the data itself is tagged.
+        let tagged = raw || !metadata.mime_type.is_text() || !req.is_browser();
+        if tagged {
+            let tag = entity_tag(id, metadata.revision);
+            let modified = last_modified(metadata.created_at, metadata.revision);
+            if is_not_modified(req, &tag, modified) {
+                let mut response = Response::with(status::NotModified);
+                response.headers.set(ETag(tag));
+                set_last_modified(&mut response, modified);
+                return Ok(response);
+            }
+        }
+        if headers_only {
+            let mut response = Response::with((status::Ok, Vec::new()));
+            response.headers.set(metadata.mime_type.to_content_type());
+            if tagged {
+                response.headers.set(ETag(entity_tag(id, metadata.revision)));
+                set_last_modified(&mut response,
+                                  last_modified(metadata.created_at, metadata.revision));
+                if metadata.views_left.is_none() {
+                    response.headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+                }
+            }
+            response.headers.set(ContentLength(metadata.size as u64));
+            if let Some(disposition) = disposition {
+                response.headers
+                        .set_raw(CONTENT_DISPOSITION_HEADER, vec![disposition]);
+            }
+            set_created_at(&mut response, metadata.created_at);
+            set_revision(&mut response, metadata.revision);
+            set_views_left(&mut response, metadata.views_left);
+            if self.count_views {
+                set_views(&mut response, metadata.views);
+            }
+            self.set_signature(&mut response, id)?;
+            return Ok(response);
+        }
+        let paste = match paste {
+            Some(paste) => paste,
+            None => self.load_paste(id)?,
+        };
+        let length = paste.data.len() as u64;
+        // A paste that could be viewed a few times only is given out as a whole.
+        let range = if tagged && paste.views_left.is_none() {
+            byte_range(req,
+                       &entity_tag(id, paste.revision),
+                       last_modified(paste.created_at, paste.revision),
+                       length)
+        } else {
+            ByteRange::Full
+        };
+        if range == ByteRange::Unsatisfiable {
+            let mut response = Response::with(status::RangeNotSatisfiable);
+            response.headers.set(ContentRange(ContentRangeSpec::Bytes { range: None,
+                                                                        instance_length:
+                                                                            Some(length), }));
+            return Ok(response);
+        }
+        // Players fetch media in parts, only the first one counts as a view.
+        let opening = match range {
+            ByteRange::Partial(first, _) => first == 0,
+            _ => true,
+        };
+        let views_left = self.take_view(id, paste.views_left)?;
+        if opening {
+            self.report_view(req);
+        }
+        let created_at = paste.created_at;
+        let revision = paste.revision;
+        let permalink = self.permalink(&paste.data);
+        let views = if opening {
+            self.count_view(id, paste.views)
+        } else if self.count_views {
+            Some(paste.views)
+        } else {
+            None
+        };
+        let mut response = if !tagged {
+            self.serve_data_html(req, id, paste, views, language)?
         } else {
             let mut response = Response::new();
-            response.headers.set(mime::to_content_type(paste.mime_type));
-            response.set_mut((status::Ok, paste.data));
-            Ok(response)
+            response.headers.set(paste.mime_type.to_content_type());
+            response.headers.set(ETag(entity_tag(id, revision)));
+            set_last_modified(&mut response, last_modified(created_at, revision));
+            if views_left.is_none() {
+                response.headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+            }
+            match range {
+                ByteRange::Partial(first, last) => {
+                    let range = ContentRangeSpec::Bytes { range: Some((first, last)),
+                                                          instance_length: Some(length), };
+                    response.headers.set(ContentRange(range));
+                    let part = paste.data[first as usize..last as usize + 1].to_vec();
+                    response.set_mut((status::PartialContent, part));
+                }
+                _ => {
+                    response.set_mut((status::Ok, paste.data));
+                }
+            }
+            response
+        };
+        if raw {
+            response.headers
+                    .set_raw(CONTENT_SECURITY_POLICY_HEADER, vec![b"sandbox".to_vec()]);
+        }
+        if let Some(disposition) = disposition {
+            response.headers
+                    .set_raw(CONTENT_DISPOSITION_HEADER, vec![disposition]);
+        }
+        if let Some(permalink) = permalink {
+            response.headers
+                    .set_raw(PERMALINK_HEADER, vec![permalink.into_bytes()]);
+        }
+        set_created_at(&mut response, created_at);
+        set_revision(&mut response, revision);
+        set_views_left(&mut response, views_left);
+        if let Some(views) = views {
+            set_views(&mut response, views);
+        }
+        self.set_signature(&mut response, id)?;
+        Ok(response)
+    }
+
+    /// Serves a paste converted into the format given by the third URI segment, like
+    /// `/<id>/as/yaml`. A `HEAD` request (`headers_only`) doesn't count as a view.
+    fn convert_paste(&self,
+                     req: &Request,
+                     str_id: &str,
+                     headers_only: bool)
+                     -> IronResult<Response> {
+        let id = self.resolve_id(str_id)?;
+        let format = req.url_segment_n(2).ok_or(Error::UnknownEndpoint)?;
+        let paste = self.load_paste(id)?;
+        let (data, mime_type) = self.converters
+                                    .convert(format, &paste.data, &paste.mime_type)
+                                    .context(|| format!("converting paste {}", id))?;
+        let (views_left, views) = self.view(req, id, &paste, headers_only)?;
+        let mut response = Response::with((status::Ok, data));
+        response.headers.set(mime_type.to_content_type());
+        response.headers
+                .set_raw(CONTENT_SECURITY_POLICY_HEADER, vec![b"sandbox".to_vec()]);
+        set_created_at(&mut response, paste.created_at);
+        set_views_left(&mut response, views_left);
+        if let Some(views) = views {
+            set_views(&mut response, views);
+        }
+        Ok(response)
+    }
+
+    /// Takes, reports and counts a view of a paste, unless only the headers are asked for.
+    ///
+    /// Returns the number of views left (see `take_view`) and the number of views (see
+    /// `count_view`).
+    fn view(&self,
+            req: &Request,
+            id: PasteId,
+            paste: &PasteEntry,
+            headers_only: bool)
+            -> Result<(Option<u64>, Option<u64>), Error> {
+        if headers_only {
+            let views = if self.count_views {
+                Some(paste.views)
+            } else {
+                None
+            };
+            return Ok((paste.views_left, views));
+        }
+        let views_left = self.take_view(id, paste.views_left)?;
+        self.report_view(req);
+        Ok((views_left, self.count_view(id, paste.views)))
+    }
+
+    /// Reports a view of a paste to the analytics, along with the site it is linked from.
+    fn report_view(&self, req: &Request) {
+        let referrer = req.referer_host()
+                          .filter(|host| req.url.as_ref().host_str() != Some(host));
+        let visitor = Visitor::from_ip(req.client_ip());
+        self.analytics.page_view(visitor, referrer.as_ref().map(String::as_str));
+    }
+
+    /// Counts a view of a paste (if views are counted), returns the updated number of views.
+    ///
+    /// `views` is the number of views the paste has been loaded with, before the counter is
+    /// incremented, so the view is added here.
+    fn count_view(&self, id: PasteId, views: u64) -> Option<u64> {
+        if !self.count_views {
+            return None;
+        }
+        if let Err(e) = self.db.increment_views(id) {
+            error!("Can't count a view of paste {}: {}", id, e);
+        }
+        Some(views + 1)
+    }
+
+    /// Finds the paste a path segment refers to, the ID of it. Paths of the service are never
+    /// IDs.
+    ///
+    /// Slugs have paths of their own (`/s/<slug>`), so they are never confused with IDs.
+    fn resolve_id(&self, segment: &str) -> Result<PasteId, Error> {
+        if self.is_service_path(segment) {
+            return Err(Error::InvalidId(segment.into()));
+        }
+        self.id_encoding
+            .decode(segment)
+            .map(PasteId::new)
+            .context(|| format!("decoding id {}", segment))
+    }
+
+    /// Checks whether a path segment is served by the service itself: it is a route, an extra
+    /// page or a static file.
+    fn is_service_path(&self, segment: &str) -> bool {
+        pages::is_reserved(segment) || self.extra_pages.get(segment).is_some() ||
+        self.static_path.join(segment).is_file()
+    }
+
+    /// Finds the existing paste that has claimed a slug.
+    fn find_slug_owner(&self, slug: &str) -> Result<Option<PasteSummary>, Error> {
+        let owner = self.db
+                        .find_by_slug(slug)
+                        .db_context(|| format!("looking up slug {}", slug))?;
+        match owner {
+            Some(id) => self.db
+                            .load_metadata(id)
+                            .db_context(|| format!("loading metadata of paste {}", id)),
+            None => Ok(None),
         }
     }
 
+    /// Checks whether a slug could be claimed by a new paste: it is well-formed and no existing
+    /// paste has claimed it.
+    ///
+    /// It is only a quick check before an upload is stored, the claim itself is settled by the
+    /// database (see `DbInterface::store_slug`).
+    fn check_slug(&self, slug: &str) -> Result<(), Error> {
+        check_slug_syntax(slug)?;
+        if self.find_slug_owner(slug)?.is_some() {
+            return Err(Error::SlugTaken(slug.into()));
+        }
+        Ok(())
+    }
+
+    /// Handles requests to slugs (`/s/<slug>`).
+    ///
+    /// `GET` and `HEAD` requests are redirected to the paste that has claimed the slug, along
+    /// with the rest of the path (so `/s/<slug>/as/md` leads to `/<id>/as/md`). A `PUT` request
+    /// with the deletion token (or the admin token) of that paste updates it, otherwise `PUT` and
+    /// `POST` requests upload a new paste that claims the slug.
+    fn slug(&self, req: &mut Request) -> IronResult<Response> {
+        let slug = req.url_segment_n(1).unwrap_or_default().to_string();
+        match req.method {
+            Method::Get | Method::Head => {
+                let id = self.find_slug_owner(&slug)?
+                             .ok_or_else(|| Error::SlugNotFound(slug.clone()))?
+                             .id;
+                let metadata = self.load_paste_metadata(id)?;
+                let rest: Vec<_> = req.url.path().into_iter().skip(2).collect();
+                let url = if rest.is_empty() {
+                    self.paste_url(&metadata)
+                } else {
+                    format!("{}{}/{}", self.url_prefix, self.public_id(id), rest.join("/"))
+                };
+                let mut response = Response::with(status::Found);
+                response.headers.set(Location(url));
+                Ok(response)
+            }
+            Method::Put => {
+                let authorized =
+                    req.get_arg("token").is_some() || self.check_admin_token(req).is_ok();
+                match self.find_slug_owner(&slug)? {
+                    Some(metadata) if authorized => self.update(req, metadata),
+                    _ => self.post(req),
+                }
+            }
+            Method::Post => self.post(req),
+            _ => Ok(Response::with(status::MethodNotAllowed)),
+        }
+    }
+
+    /// Loads a paste, treating an expired one as a missing one (see `check_expiry`).
+    fn load_paste(&self, id: PasteId) -> Result<PasteEntry, Error> {
+        let paste = self.db
+                        .load_data(id)
+                        .db_context(|| format!("loading paste {}", id))?
+                        .ok_or_else(|| self.not_found(id))?;
+        self.check_expiry(id, paste.best_before)?;
+        Ok(paste)
+    }
+
+    /// Loads metadata of a paste, treating an expired one as a missing one (see `check_expiry`).
+    fn load_paste_metadata(&self, id: PasteId) -> Result<PasteSummary, Error> {
+        let metadata = self.db
+                           .load_metadata(id)
+                           .db_context(|| format!("loading metadata of paste {}", id))?
+                           .ok_or_else(|| self.not_found(id))?;
+        self.check_expiry(id, metadata.best_before)?;
+        Ok(metadata)
+    }
+
+    /// Reports a paste that has expired as not found.
+    ///
+    /// Databases purge expired pastes on their own schedule (if at all), so they might still
+    /// have them. Such a paste is removed right away, a failure to is only logged though.
+    fn check_expiry(&self, id: PasteId, best_before: Option<DateTime<Utc>>) -> Result<(), Error> {
+        match best_before {
+            Some(date) if date <= Utc::now() => {
+                info!("Paste {} has expired on {}, removing it", id, date);
+                if let Err(e) = self.db.remove_data(id) {
+                    error!("Can't remove expired paste {}: {}", id, e);
+                }
+                Err(Error::IdNotFound(self.public_id(id)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Uses up a view of a paste with a view limit (if it has one, given by `views_left`),
+    /// returns the number of views left after it.
+    ///
+    /// The paste is removed once its last view is taken. If concurrent requests have taken all
+    /// the views already, the paste is reported as not found.
+    fn take_view(&self, id: PasteId, views_left: Option<u64>) -> Result<Option<u64>, Error> {
+        if views_left.is_none() {
+            return Ok(None);
+        }
+        let views_left = self.db
+                             .take_view(id)
+                             .db_context(|| format!("taking a view of paste {}", id))?
+                             .ok_or_else(|| self.not_found(id))?;
+        if views_left == 0 {
+            info!("Paste {} has been viewed for the last time, removing it", id);
+            // The view has been taken, so the paste is served even if it can't be removed now.
+            if let Err(e) = self.db.remove_data(id) {
+                error!("Can't remove used up paste {}: {}", id, e);
+            }
+        }
+        Ok(Some(views_left))
+    }
+
     /// Serves a static file.
     fn serve_static(&self, file_name: &str) -> IronResult<Response> {
         let path = self.static_path.join(file_name);
@@ -115,66 +1440,997 @@ impl<E> Pastebin<E>
         Ok(response)
     }
 
-    /// Handles `GET` requests.
+    /// Checks the database connection, so the service is only given traffic it can handle.
+    fn readiness_check(&self) -> IronResult<Response> {
+        match self.db.health_check() {
+            Ok(()) => {
+                let body = json!({ "status": "ready", "database": "ok" });
+                Ok(api::json_response(status::Ok, &body))
+            }
+            Err(e) => {
+                error!("Readiness check failed: {}", e);
+                let body = json!({ "status": "unavailable", "database": "unreachable" });
+                Ok(api::json_response(status::ServiceUnavailable, &body))
+            }
+        }
+    }
+
+    /// Serves storage statistics.
+    fn stats(&self) -> IronResult<Response> {
+        let stats = self.db.stats().db_context(|| "collecting statistics")?;
+        let mut body = json!({
+            "pastes": stats.pastes,
+            "total_bytes": stats.total_bytes,
+            "total_size": filesize(stats.total_bytes),
+            "panics": middleware::panics_caught(),
+        });
+        if let Some(summary) = self.analytics.summary() {
+            body["analytics"] = summary;
+        }
+        let mut response = Response::with((status::Ok, body.to_string()));
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    /// Renders an extra page, see [ExtraPages](struct.ExtraPages.html).
+    fn render_extra_page(&self, page: &ExtraPage) -> IronResult<Response> {
+        let mut context = page.context.clone();
+        context.insert("prefix".into(), self.url_prefix.clone().into());
+        self.render_template(&page.template, ContentType::html(), &context.into())
+    }
+
+    /// Serves the metrics in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    fn serve_metrics(&self) -> IronResult<Response> {
+        let body = self.metrics.as_ref().map(Metrics::render).unwrap_or_default();
+        let mut response = Response::with((status::Ok, body));
+        response.headers.set(ContentType::plaintext());
+        Ok(response)
+    }
+
+    /// Serves the public key pastes are signed with.
+    fn serve_signing_key(&self) -> IronResult<Response> {
+        let key = self.signing_key.as_ref().map(String::as_str).unwrap_or_default();
+        Ok(Response::with((status::Ok, format!("{}\n", key))))
+    }
+
+    /// Handles `GET` and `HEAD` requests.
     ///
     /// If a URI segment is not provided then the upload form is rendered, otherwise the first
     /// segment is considered to be a paste ID, and hence the paste is fetched from the DB.
-    fn get(&self, req: &mut Request) -> IronResult<Response> {
+    ///
+    /// For `HEAD` requests (`headers_only`) the body of the response is still generated (except
+    /// for pastes), so it has to be stripped by the caller.
+    fn get(&self, req: &mut Request, headers_only: bool) -> IronResult<Response> {
+        // Pages can't take the paths of the built-in routes, so they could be looked up first.
+        if let Some(page) = req.url_segment_n(0).and_then(|path| self.extra_pages.get(path)) {
+            return self.render_extra_page(page);
+        }
         match req.url_segment_n(0) {
-            None => self.render_template("upload.html", ContentType::html(), &json!({})),
+            None => {
+                self.render_form(req, "upload.html", json!({"content": "", "file_name": ""}))
+            }
             Some("paste.sh") => self.render_template("paste.sh",
                                                      ContentType::plaintext(),
                                                      &json!({"prefix": &self.url_prefix})),
+            Some("healthz") => Ok(api::json_response(status::Ok, &json!({ "status": "alive" }))),
+            Some("readyz") => self.readiness_check(),
+            Some("browse") => self.browse(req),
+            Some("stats") => self.stats(),
+            #[cfg(feature = "metrics")]
+            Some("metrics") if self.metrics.is_some() => self.serve_metrics(),
+            Some("signing-key") if self.signing_key.is_some() => self.serve_signing_key(),
             Some("readme") => self.render_template("readme.html",
                                                    ContentType::html(),
                                                    &json!({"prefix": &self.url_prefix})),
+            Some("h") if self.content_permalinks && req.url_segment_n(1).is_some() => {
+                self.resolve_permalink(req.url_segment_n(1).unwrap_or_default())
+            }
+            Some("raw") if req.url_segment_n(1).is_some() => {
+                let id = req.url_segment_n(1).unwrap_or_default();
+                self.get_paste(req, id, headers_only, Delivery::Raw)
+            }
+            Some("dl") if req.url_segment_n(1).is_some() => {
+                let id = req.url_segment_n(1).unwrap_or_default();
+                self.get_paste(req, id, headers_only, Delivery::Download)
+            }
             Some(file_name) if self.static_path.join(file_name).is_file() => {
                 self.serve_static(file_name)
             }
-            Some(id) => self.get_paste(id, req.is_browser(), req.url_segment_n(1).is_some()),
+            Some(id) if req.url_segment_n(1) == Some("as") && req.url_segment_n(2).is_some() => {
+                self.convert_paste(req, id, headers_only)
+            }
+            Some(id) if req.url_segment_n(1) == Some("html") && req.url_segment_n(2).is_none() => {
+                self.serve_rendered(req, id, headers_only)
+            }
+            Some(id) => self.get_paste(req, id, headers_only, Delivery::Auto),
+        }
+    }
+
+    /// Handles `HEAD` requests.
+    fn head(&self, req: &mut Request) -> IronResult<Response> {
+        let mut response = self.get(req, true)?;
+        if response.headers.has::<ContentLength>() {
+            // Keep the length of the original body.
+            response.body = Some(Box::new(Vec::new()));
+        } else {
+            response.body = None;
         }
+        Ok(response)
     }
 
-    /// Handles `POST` and `PUT` requests.
+    /// Loads data of an incoming paste.
+    ///
+    /// The mime type is not known yet, so only the biggest of the limits is checked here: up
+    /// front against the announced length, and against the received data as it arrives (be the
+    /// length spoofed or not announced at all, like for a chunked body).
+    fn load_body(&self, req: &mut Request) -> Result<Vec<u8>, Error> {
+        let limit = self.effective_limit(self.size_limits.max_limit());
+        let data_length = match req.get_length() {
+            Some(data_length) if data_length > limit as u64 => {
+                return Err(Error::TooBig(limit, None))
+            }
+            Some(data_length) => Some(data_length),
+            None if req.is_chunked() => None,
+            None => return Err(Error::NoContentLength),
+        };
+        let deadline = self.upload_timeout
+                           .and_then(|timeout| timeout.to_std().ok())
+                           .map(|timeout| Instant::now() + timeout);
+        let body = load_data(&mut req.body, data_length, limit, deadline).map_err(|e| {
+            if let Error::UploadAborted(..) = e {
+                warn!("Upload aborted: {}", e);
+            }
+            e
+        })?;
+        self.decode_body(req, body)
+    }
+
+    /// Undoes the `Content-Encoding` of an incoming paste, if any.
+    ///
+    /// Only gzip is supported (with the `http-compression` feature). The decompressed data is
+    /// checked against the biggest of the limits as it goes, so a zip bomb is rejected early.
+    fn decode_body(&self, req: &Request, body: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let codings: Vec<&Encoding> = match req.headers.get::<ContentEncoding>() {
+            Some(codings) => codings.iter()
+                                    .filter(|&coding| *coding != Encoding::Identity)
+                                    .collect(),
+            None => return Ok(body),
+        };
+        match codings.as_slice() {
+            [] => Ok(body),
+            #[cfg(feature = "http-compression")]
+            [&Encoding::Gzip] => {
+                let limit = self.effective_limit(self.size_limits.max_limit());
+                encoding::gunzip(&body, limit)
+            }
+            _ => {
+                let names: Vec<String> = codings.iter().map(|coding| coding.to_string()).collect();
+                Err(Error::UnsupportedEncoding(names.join(", ")))
+            }
+        }
+    }
+
+    /// Handles `POST` requests (and `PUT` requests that do not refer an existing paste).
+    ///
+    /// The body is either the paste itself or a form: a `multipart/form-data` one with a file
+    /// (see the `multipart` module) or a classic urlencoded one (see the `form` module). A file
+    /// name in the URL and the `expires` argument take precedence over the ones of a form.
+    ///
+    /// A classic form is submitted by a browser without JavaScript, so it is redirected to the
+    /// new paste right away. Browsers have to pass the CSRF token of their session (see the
+    /// `csrf` module), in a form field or a header.
     fn post(&self, req: &mut Request) -> IronResult<Response> {
-        let file_name = req.url_segment_n(0).map(|s| s.to_string());
-        debug!("File name: {:?}", file_name);
-        let data_length = req.get_length().ok_or(Error::NoContentLength)?;
-        if data_length > self.db.max_data_size() as u64 {
-            return Err(Error::TooBig.into());
+        let body = self.load_body(req)?;
+        let from_form = form::is_urlencoded(req);
+        let upload = match multipart::boundary(req) {
+            Some(boundary) => multipart::parse_upload(&body, &boundary)?,
+            None if from_form => form::parse_upload(&body)?,
+            None => api::Upload { data: body,
+                                  file_name: None,
+                                  expires: None,
+                                  public: false,
+                                  csrf_token: None, },
+        };
+        if self.csrf_protection {
+            csrf::check(req, upload.csrf_token.as_ref().map(String::as_str))?;
+        }
+        // Uploads to slugs (`/s/<slug>`) don't take file names from their paths.
+        let file_name = req.url_segment_n(0)
+                           .filter(|&s| s != "s")
+                           .map(|s| s.to_string())
+                           .or(upload.file_name);
+        let expires = req.get_arg("expires")
+                         .map(Cow::into_owned)
+                         .or(upload.expires);
+        let stored = self.store_paste(req,
+                                      upload.data,
+                                      file_name,
+                                      expires.as_ref().map(String::as_str),
+                                      upload.public,
+                                      None)?;
+        Ok(self.uploaded(stored, from_form))
+    }
+
+    /// Responds to an upload with the URL of the new paste: a classic form is redirected to it.
+    fn uploaded(&self, stored: Stored, from_form: bool) -> Response {
+        let url = format!("{}{}", self.url_prefix, stored.path(&self.id_encoding));
+        let mut response = if from_form {
+            let mut response = Response::with((status::SeeOther, format!("{}\n", url)));
+            response.headers.set(Location(url));
+            response
+        } else {
+            Response::with((status::Created, format!("{}\n", url)))
+        };
+        response.headers
+                .set_raw(DELETION_TOKEN_HEADER, vec![stored.deletion_token.into_bytes()]);
+        set_retention(&mut response, &stored.retention);
+        response
+    }
+
+    /// Handles `POST /<id>/fork`: copies a paste into a new one, so it could be changed without
+    /// touching the original.
+    ///
+    /// The copy keeps the data, the file name and the language of the paste, while its
+    /// expiration date, view limit and listing are given by the arguments, just like for an
+    /// upload. Reading the paste uses one of its views up (if they are limited).
+    ///
+    /// With the `edit` argument nothing is stored yet: the upload page is served pre-filled with
+    /// the paste instead, and the copy is stored once the form is submitted.
+    ///
+    /// Browsers have to pass the CSRF token of their session, just like for an upload.
+    fn fork(&self, req: &mut Request) -> IronResult<Response> {
+        if self.csrf_protection {
+            let submitted = if form::is_urlencoded(req) {
+                form::field(&self.load_body(req)?, csrf::CSRF_TOKEN_NAME)
+            } else {
+                None
+            };
+            csrf::check(req, submitted.as_ref().map(String::as_str))?;
+        }
+        let str_id = req.url_segment_n(0).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        let paste = self.load_paste(id)?;
+        self.take_view(id, paste.views_left)?;
+        if req.get_arg("edit").is_some() {
+            let content = from_utf8(&paste.data).map_err(|_| {
+                              Error::UnsupportedConversion(paste.mime_type.clone(), "edit".into())
+                          })?;
+            let file_name = paste.file_name.as_ref().map(String::as_str).unwrap_or_default();
+            return self.render_form(req,
+                                    "upload.html",
+                                    json!({
+                                        "content": escape_html(content),
+                                        "file_name": escape_html(file_name),
+                                    }));
+        }
+        let expires = req.get_arg("expires").map(Cow::into_owned);
+        let stored = self.store_paste(req,
+                                      paste.data,
+                                      paste.file_name,
+                                      expires.as_ref().map(String::as_str),
+                                      false,
+                                      paste.language)?;
+        debug!("Paste {} is forked into {}", id, stored.id);
+        Ok(self.uploaded(stored, form::is_urlencoded(req)))
+    }
+
+    /// Stores an uploaded paste, `expires` being the value of the `expires` argument (if any).
+    ///
+    /// The expiration date is capped by the retention policy. The paste is listed publicly if
+    /// either the upload says so (`public`) or the `public` argument is given. The `lang`
+    /// argument takes precedence over the given `language`. The paste claims the slug given in
+    /// the `slug` argument, if any, and takes the deletion token given in the `token` one.
+    fn store_paste(&self,
+                   req: &Request,
+                   data: Vec<u8>,
+                   file_name: Option<String>,
+                   expires: Option<&str>,
+                   public: bool,
+                   language: Option<String>)
+                   -> Result<Stored, Error> {
+        debug!("Received {} (file name: {:?})", redact(&data), file_name);
+        let slug = requested_slug(req);
+        if let Some(ref slug) = slug {
+            self.check_slug(slug)?;
         }
-        let data = load_data(&mut req.body, data_length)?;
         let mime_type = mime::data_mime_type(file_name.as_ref(), &data);
-        let expires_at = match req.get_arg("expires") {
-            Some(Cow::Borrowed("never")) => None,
-            Some(x) => {
-                Some(DateTime::from_utc(NaiveDateTime::from_timestamp(itry!(x.parse()), 0), Utc))
-            }
-            _ => Some(Utc::now().add(self.default_ttl)),
+        self.check_size(&data, &mime_type)?;
+        let flagged = self.check_spam(&data, &mime_type)?;
+        let created_at = Utc::now();
+        let requested = parse_expires(expires, created_at, self.default_ttl)?;
+        let retention = self.retention.apply(&mime_type, created_at, requested);
+        if retention.capped {
+            debug!("Expiration date {:?} is capped to {:?}", requested, retention.expires_at);
+        }
+        let language = parse_language(req.get_arg("lang").as_ref().map(|lang| lang.as_ref()))?
+                           .or(language);
+        let max_views = parse_max_views(req.get_arg("max_views").as_ref().map(|arg| arg.as_ref()))?;
+        let public = public || req.get_arg("public").is_some();
+        let creator = if self.record_creators {
+            Some(req.client_ip().to_string())
+        } else {
+            None
         };
-        let id = itry!(self.db.store_data(data, file_name, mime_type, expires_at));
+        let size = data.len();
+        let token = upload_token(req)?;
+        let id = self.store_new_paste(PasteEntry { data,
+                                                   file_name,
+                                                   mime_type,
+                                                   created_at,
+                                                   best_before: retention.expires_at,
+                                                   views: 0,
+                                                   creator,
+                                                   deletion_token: Some(token.clone()),
+                                                   language,
+                                                   revision: 0,
+                                                   views_left: max_views,
+                                                   public, })?;
         debug!("Generated id: {}", id);
-        Ok(Response::with((status::Created,
-                          format!("{}{}\n",
-                                   self.url_prefix,
-                                   encode_id(id)))))
+        if let Some(ref slug) = slug {
+            let claimed = self.db
+                              .store_slug(id, slug)
+                              .db_context(|| format!("storing slug {} of paste {}", slug, id))?;
+            if !claimed {
+                // Another upload has claimed the slug since it was checked.
+                if let Err(e) = self.db.remove_data(id) {
+                    error!("Can't remove paste {} that hasn't got its slug: {}", id, e);
+                }
+                return Err(Error::SlugTaken(slug.clone()));
+            }
+        }
+        self.flag_spam(id, flagged);
+        self.analytics.upload(Visitor::from_ip(req.client_ip()), size);
+        Ok(Stored { id,
+                    slug,
+                    retention,
+                    deletion_token: token, })
+    }
+
+    /// Stores a new paste under an ID of the ID generator, or under the one the database issues
+    /// if the generator leaves it to the database.
+    ///
+    /// IDs that are written the same way as paths of the service (like `raw`) are skipped, so a
+    /// paste never hides behind a route.
+    fn store_new_paste(&self, entry: PasteEntry) -> Result<PasteId, Error> {
+        for attempt in 0..MAX_ID_ATTEMPTS {
+            let id = match self.id_generator.generate(attempt) {
+                Some(id) => PasteId::new(id),
+                None => {
+                    let id = self.db
+                                 .store_data(entry.clone())
+                                 .db_context(|| "storing a paste")?;
+                    if !self.is_service_path(&self.public_id(id)) {
+                        return Ok(id);
+                    }
+                    // The database moves on to the next ID once this one is given up.
+                    debug!("ID {} is a path of the service, storing the paste anew", id);
+                    self.db
+                        .remove_data(id)
+                        .db_context(|| format!("removing paste {}", id))?;
+                    continue;
+                }
+            };
+            if self.is_service_path(&self.public_id(id)) {
+                debug!("ID {} is a path of the service", id);
+                continue;
+            }
+            if self.db
+                   .store_new(id, entry.clone())
+                   .db_context(|| format!("storing a paste as {}", id))?
+            {
+                return Ok(id);
+            }
+            debug!("ID {} is taken", id);
+        }
+        Err(Error::NoFreeId(MAX_ID_ATTEMPTS))
+    }
+
+    /// Handles `PUT` requests.
+    ///
+    /// If the first URI segment is an ID (or a slug) of an existing paste and the request comes
+    /// with a deletion token (the `token` argument) or the admin token, then the paste is
+    /// updated. Otherwise the request is treated as a `POST` one, so a file uploaded under a name
+    /// that happens to be an ID makes a new paste rather than replacing someone else's one.
+    fn put(&self, req: &mut Request) -> IronResult<Response> {
+        let authorized = req.get_arg("token").is_some() || self.check_admin_token(req).is_ok();
+        let segment = req.url_segment_n(0).filter(|_| authorized);
+        let existing = match segment.and_then(|s| self.resolve_id(s).ok()) {
+            Some(id) => {
+                self.db
+                    .load_metadata(id)
+                    .db_context(|| format!("loading metadata of paste {}", id))?
+            }
+            None => None,
+        };
+        match existing {
+            Some(metadata) => self.update(req, metadata),
+            None => self.post(req),
+        }
+    }
+
+    /// Replaces data of an existing paste.
+    ///
+    /// Just like a removal, an update takes either the admin token or the deletion token of the
+    /// paste (as the `token` argument).
+    ///
+    /// If the `rev` argument is given, the paste is only updated if it is still at that revision,
+    /// so an update based on a stale copy of the paste doesn't overwrite someone else's one.
+    fn update(&self, req: &mut Request, metadata: PasteSummary) -> IronResult<Response> {
+        if self.check_admin_token(req).is_err() {
+            self.check_deletion_token(req, metadata.id)?;
+        }
+        let expected_revision = match req.get_arg("rev") {
+            Some(rev) => Some(rev.parse::<u64>()
+                                 .map_err(|_| Error::InvalidRevision(rev.to_string()))?),
+            None => None,
+        };
+        let data = self.load_body(req)?;
+        debug!("Received {} to update paste {}", redact(&data), metadata.id);
+        let mime_type = mime::data_mime_type(metadata.file_name.as_ref(), &data);
+        self.check_size(&data, &mime_type)?;
+        let flagged = self.check_spam(&data, &mime_type)?;
+        let outcome = self.db
+                          .update_data(metadata.id, expected_revision, data, mime_type)
+                          .db_context(|| format!("updating paste {}", metadata.id))?;
+        let revision = match outcome {
+            UpdateOutcome::Updated(revision) => revision,
+            UpdateOutcome::NotFound => {
+                return Err(Error::IdNotFound(self.public_id(metadata.id)).into())
+            }
+            UpdateOutcome::Conflict(current) => {
+                let expected = expected_revision.unwrap_or_default();
+                return Err(Error::Conflict(self.public_id(metadata.id), expected, current).into());
+            }
+        };
+        debug!("Updated id: {} to revision {}", metadata.id, revision);
+        self.flag_spam(metadata.id, flagged);
+        let mut response =
+            Response::with((status::Ok,
+                            format!("{}{}\n", self.url_prefix, self.public_id(metadata.id))));
+        set_revision(&mut response, revision);
+        Ok(response)
     }
 
     /// Handles `DELETE` requests.
+    ///
+    /// A paste is removed either with the admin token or with the deletion token it has been
+    /// given on upload (as the `token` argument). If the trash is used, the paste is moved there
+    /// instead.
     fn remove(&self, req: &mut Request) -> IronResult<Response> {
-        let id = itry!(decode_id(&req.url_segment_n(0).ok_or(Error::NoIdSegment)?));
-        itry!(self.db.remove_data(id));
+        let str_id = req.url_segment_n(0).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        self.remove_paste(req, id)?;
         Ok(Response::with(status::Ok))
     }
+
+    /// Removes (or trashes) a paste if the request is allowed to, see `remove`.
+    ///
+    /// If tombstones are kept, the removal is recorded along with who has done it.
+    fn remove_paste(&self, req: &Request, id: PasteId) -> Result<(), Error> {
+        let actor = if self.check_admin_token(req).is_ok() {
+            if self.tombstones && !self.use_trash {
+                // Tombstones are only written for pastes that do exist.
+                self.db
+                    .load_metadata(id)
+                    .db_context(|| format!("loading metadata of paste {}", id))?
+                    .ok_or_else(|| self.not_found(id))?;
+            }
+            "admin"
+        } else {
+            self.check_deletion_token(req, id)?;
+            "uploader"
+        };
+        self.discard(id, actor)
+    }
+
+    /// Checks that a request bears the deletion token of a paste (as the `token` argument).
+    ///
+    /// Pastes stored without a token are only modified by the admin.
+    fn check_deletion_token(&self, req: &Request, id: PasteId) -> Result<(), Error> {
+        let expected = self.db
+                           .load_deletion_token(id)
+                           .db_context(|| format!("loading the deletion token of paste {}", id))?;
+        match (req.get_arg("token"), expected) {
+            (Some(given), Some(expected)) if tokens_equal(&given, &expected) => Ok(()),
+            (_, Some(_)) => Err(Error::Forbidden),
+            (_, None) => {
+                // Either there is no such paste, or it can't be removed with a token.
+                self.db
+                    .load_metadata(id)
+                    .db_context(|| format!("loading metadata of paste {}", id))?
+                    .ok_or_else(|| self.not_found(id))?;
+                Err(Error::Forbidden)
+            }
+        }
+    }
+
+    /// Removes (or trashes) a paste on behalf of an `actor`, leaving a tombstone if they are kept.
+    fn discard(&self, id: PasteId, actor: &str) -> Result<(), Error> {
+        if self.use_trash {
+            let trashed = self.db
+                              .soft_delete(id, Utc::now())
+                              .db_context(|| format!("trashing paste {}", id))?;
+            if !trashed {
+                return Err(Error::IdNotFound(self.public_id(id)));
+            }
+        } else {
+            self.db
+                .remove_data(id)
+                .db_context(|| format!("removing paste {}", id))?;
+        }
+        if self.tombstones {
+            let tombstone = Tombstone { id,
+                                        removed_at: Utc::now(),
+                                        actor: actor.to_string(), };
+            self.db
+                .store_tombstone(tombstone)
+                .db_context(|| format!("storing a tombstone of paste {}", id))?;
+        }
+        Ok(())
+    }
+
+    /// Describes a paste that is not found: as a removed one if there is a tombstone of it.
+    ///
+    /// A failure to look the tombstone up is only logged, the paste is reported as unknown then.
+    fn not_found(&self, id: PasteId) -> Error {
+        if !self.tombstones {
+            return Error::IdNotFound(self.public_id(id));
+        }
+        match self.db.load_tombstone(id) {
+            Ok(Some(tombstone)) => Error::Removed(self.public_id(id), tombstone),
+            Ok(None) => Error::IdNotFound(self.public_id(id)),
+            Err(e) => {
+                error!("Can't load a tombstone of paste {}: {}", id, e);
+                Error::IdNotFound(self.public_id(id))
+            }
+        }
+    }
+
+    /// Checks that a request bears the admin token, either as a bearer token or, for browsers, as
+    /// the password of HTTP Basic authentication (the user name doesn't matter).
+    ///
+    /// Without a token the admin API is disabled, so all the requests are rejected.
+    fn check_admin_token(&self, req: &Request) -> Result<(), Error> {
+        let expected = self.admin_token.as_ref().ok_or(Error::Unauthorized)?;
+        if let Some(auth) = req.headers.get::<Authorization<Bearer>>() {
+            if tokens_equal(&auth.token, expected.expose()) {
+                return Ok(());
+            }
+        }
+        match req.headers.get::<Authorization<Basic>>() {
+            Some(&Authorization(Basic { password: Some(ref password), .. }))
+                if tokens_equal(password, expected.expose()) => Ok(()),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    /// Handles admin requests (everything under `/admin/`).
+    ///
+    /// Requests from browsers that change anything and are authenticated with HTTP Basic have to
+    /// carry the CSRF token of the dashboard too, no matter whether the upload form is protected.
+    fn admin(&self, req: &mut Request) -> IronResult<Response> {
+        if req.method == Method::Get && req.url_segment_n(1).is_none() {
+            return self.dashboard(req);
+        }
+        self.check_admin_token(req)?;
+        // Browsers resend the password of HTTP Basic authentication by themselves, so a request
+        // that changes anything has to carry the token of the dashboard as well (see the `csrf`
+        // module). A bearer token is never sent by a browser on its own.
+        if req.method != Method::Get && req.headers.get::<Authorization<Bearer>>().is_none() {
+            csrf::check(req, None)?;
+        }
+        match (req.method.clone(), req.url_segment_n(1)) {
+            (Method::Get, Some("pastes")) if req.url_segment_n(2).is_some() => self.inspect(req),
+            (Method::Get, Some("pastes")) => self.list_pastes(req),
+            (Method::Delete, Some("pastes")) if req.url_segment_n(2).is_some() => {
+                self.force_remove(req)
+            }
+            (Method::Delete, Some("pastes")) => self.remove_matching(req),
+            (Method::Post, Some("restore")) => self.restore(req),
+            (Method::Get, Some("tombstones")) => self.list_tombstones(req),
+            (Method::Put, Some("banner")) => self.set_banner(req),
+            (Method::Delete, Some("banner")) => self.remove_banner(),
+            (Method::Get, Some("spam")) => self.list_flagged(),
+            (Method::Post, Some("spam")) => self.spam_feedback(req),
+            _ => Ok(Response::with(status::NotFound)),
+        }
+    }
+
+    /// Handles API requests (everything under `/api/`), see the `api` module.
+    ///
+    /// The description of all the routes is served at `/api/spec.json`, see the `spec` module.
+    fn api(&self, req: &mut Request) -> IronResult<Response> {
+        if req.method == Method::Get && req.url_segment_n(1) == Some("spec.json") {
+            let spec = spec::openapi(&self.url_prefix);
+            return Ok(api::json_response(status::Ok, &spec));
+        }
+        if req.method == Method::Get && req.url_segment_n(1) == Some("v1") &&
+           req.url_segment_n(2) == Some("instance")
+        {
+            return Ok(api::json_response(status::Ok, &self.instance_json()));
+        }
+        if req.method == Method::Get && req.url_segment_n(1) == Some("v1") &&
+           req.url_segment_n(2) == Some("export")
+        {
+            return self.api_export(req).map_err(api::error);
+        }
+        if req.url_segment_n(1) != Some("v1") || req.url_segment_n(2) != Some("pastes") {
+            return Err(api::error(Error::UnknownEndpoint));
+        }
+        let id = req.url_segment_n(3).map(String::from);
+        let result = match (req.method.clone(), id) {
+            (Method::Post, None) => self.api_post(req),
+            (Method::Get, Some(id)) => self.api_get(&id),
+            (Method::Delete, Some(id)) => self.api_delete(req, &id),
+            (Method::Delete, None) => self.api_delete_owned(req),
+            _ => Err(Error::UnknownEndpoint),
+        };
+        result.map_err(api::error)
+    }
+
+    /// Describes the instance: its version, URL, the maximum size of pastes and the active
+    /// announcement banner (or `null`).
+    fn instance_json(&self) -> serde_json::Value {
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "url": self.url_prefix,
+            "max_paste_size": self.effective_limit(self.size_limits.max_limit()),
+            "banner": self.active_banner().map(|banner| banner.to_json()),
+        })
+    }
+
+    /// Stores a paste given either as a JSON object (see `api::Upload`) or as a raw body, along
+    /// with the `file_name` and `expires` arguments.
+    fn api_post(&self, req: &mut Request) -> Result<Response, Error> {
+        let body = self.load_body(req)?;
+        let upload = if api::is_json(req) {
+            api::Upload::from_json(&body)?
+        } else {
+            api::Upload { data: body,
+                          file_name: req.get_arg("file_name").map(Cow::into_owned),
+                          expires: req.get_arg("expires").map(Cow::into_owned),
+                          public: false,
+                          csrf_token: None, }
+        };
+        let expires = upload.expires.as_ref().map(String::as_str);
+        let stored =
+            self.store_paste(req, upload.data, upload.file_name, expires, upload.public, None)?;
+        let retention = &stored.retention;
+        let body = json!({
+            "id": self.public_id(stored.id),
+            "slug": stored.slug,
+            "url": format!("{}{}", self.url_prefix, stored.path(&self.id_encoding)),
+            "expires_at": retention.expires_at.map(|date| date.to_rfc3339()),
+            "delete_token": stored.deletion_token,
+            "retention": retention.max_ttl.map(|max_ttl| json!({
+                "max_ttl": max_ttl.num_seconds(),
+                "capped": retention.capped,
+            })),
+        });
+        Ok(api::json_response(status::Created, &body))
+    }
+
+    /// Describes a paste along with its data.
+    fn api_get(&self, str_id: &str) -> Result<Response, Error> {
+        let id = self.resolve_id(str_id)?;
+        let mut paste = self.load_paste(id)?;
+        paste.views_left = self.take_view(id, paste.views_left)?;
+        let views = self.count_view(id, paste.views);
+        let public_id = self.public_id(id);
+        let url = format!("{}{}", self.url_prefix, public_id);
+        let body = api::paste_json(id, public_id, &paste, url, views);
+        Ok(api::json_response(status::Ok, &body))
+    }
+
+    /// Removes a paste, just like a `DELETE` request to the paste itself does.
+    fn api_delete(&self, req: &Request, str_id: &str) -> Result<Response, Error> {
+        let id = self.resolve_id(str_id)?;
+        self.remove_paste(req, id)?;
+        Ok(Response::with(status::NoContent))
+    }
+
+    /// Removes all the pastes of the caller (`owner=me`), the ones uploaded with the deletion
+    /// token given as the `token` argument.
+    ///
+    /// Like the admin's removals of pastes of a creator, this is left to
+    /// `DbInterface::remove_by_owner`, so the pastes are gone for good: they are neither trashed
+    /// nor leave tombstones.
+    fn api_delete_owned(&self, req: &Request) -> Result<Response, Error> {
+        let token = caller_token(req)?;
+        let removed = self.db
+                          .remove_by_owner(&token)
+                          .db_context(|| "removing pastes of an owner")?;
+        info!("Removed {} pastes on behalf of their owner", removed);
+        Ok(api::json_response(status::Ok, &json!({ "removed": removed })))
+    }
+
+    /// Exports all the pastes of the caller (`owner=me`, see `api_delete_owned`) along with all
+    /// their metadata, as a dump (see the `dump` module) that is written while the pastes are
+    /// loaded. Trashed pastes are left out.
+    fn api_export(&self, req: &Request) -> Result<Response, Error> {
+        let token = caller_token(req)?;
+        let ids = self.db
+                      .list_by_owner(&token)
+                      .db_context(|| "listing pastes of an owner")?;
+        info!("Exporting {} pastes on behalf of their owner", ids.len());
+        let mut response = Response::with(status::Ok);
+        let ndjson = SubLevel::Ext("x-ndjson".into());
+        response.headers
+                .set(ContentType(Mime(TopLevel::Application, ndjson, vec![])));
+        response.headers
+                .set_raw(CONTENT_DISPOSITION_HEADER, vec![attachment("pastes.ndjson")]);
+        response.body = Some(Box::new(Export { db: self.db.clone(),
+                                               ids, }));
+        Ok(response)
+    }
+
+    /// Serves the admin dashboard (`/admin/`), rendered from the `admin.html` template: storage
+    /// statistics, the most recent uploads with buttons to remove them through the admin API,
+    /// and how many pastes are about to expire.
+    ///
+    /// Browsers are asked for the admin token as a password, since they can't send bearer tokens
+    /// on their own. Mind that the whole database is listed to find the recent uploads.
+    fn dashboard(&self, req: &mut Request) -> IronResult<Response> {
+        if let Err(e) = self.check_admin_token(req) {
+            let mut response = Response::with((status::Unauthorized, "Authentication required\n"));
+            response.headers
+                    .set_raw(WWW_AUTHENTICATE_HEADER, vec![b"Basic realm=\"admin\"".to_vec()]);
+            return Err(IronError { error: Box::new(e),
+                                   response, });
+        }
+        let stats = self.db.stats().db_context(|| "collecting statistics")?;
+        let mut pastes = pagination::all(&*self.db, |_| true).db_context(|| "listing pastes")?;
+        let now = Utc::now();
+        let expiring_within = |period: Duration| {
+            pastes.iter()
+                  .filter_map(|summary| summary.best_before)
+                  .filter(|&date| date > now && date <= now + period)
+                  .count()
+        };
+        let expiring = json!([
+            { "within": "an hour", "count": expiring_within(Duration::hours(1)) },
+            { "within": "a day", "count": expiring_within(Duration::days(1)) },
+            { "within": "a week", "count": expiring_within(Duration::weeks(1)) },
+        ]);
+        pastes.sort_by_key(|summary| cmp::Reverse(summary.created_at));
+        let recent = pastes.iter()
+                           .take(DASHBOARD_RECENT)
+                           .map(|summary| self.dashboard_entry(summary, now))
+                           .collect::<Vec<_>>();
+        // Admin requests from browsers are always checked, see `admin`.
+        let token = csrf::token(req);
+        let mut response = self.render_template("admin.html",
+                                                ContentType::html(),
+                                                &json!({
+                                                    "pastes": stats.pastes,
+                                                    "total_size": filesize(stats.total_bytes),
+                                                    "expiring": expiring,
+                                                    "recent": recent,
+                                                    "csrf_token": token,
+                                                }))?;
+        csrf::set_cookie(&mut response, &token);
+        Ok(response)
+    }
+
+    /// Describes a paste on the admin dashboard, its age is given in seconds.
+    fn dashboard_entry(&self, summary: &PasteSummary, now: DateTime<Utc>) -> serde_json::Value {
+        let mut entry = self.browse_entry(summary, now);
+        entry["id"] = self.public_id(summary.id).into();
+        entry["size"] = filesize(summary.size as u64).into();
+        entry["creator"] = summary.creator.as_ref().map(|creator| escape_html(creator)).into();
+        let delete_url = format!("{}admin/pastes/{}", self.url_prefix, self.public_id(summary.id));
+        entry["delete_url"] = escape_html(&delete_url).into();
+        entry
+    }
+
+    /// Lists pastes page by page (see the `pagination` module), only the ones that pass the
+    /// filter given by the arguments (see `PasteFilter::from_request`).
+    fn list_pastes(&self, req: &mut Request) -> IronResult<Response> {
+        let request = PageRequest::from_request(req)?;
+        let filter = PasteFilter::from_request(req)?;
+        let page = pagination::page(&*self.db, &request, |summary| filter.matches(summary))
+            .db_context(|| "listing pastes")?;
+        let body = page.to_json(|summary| summary_json(summary, &self.id_encoding));
+        let mut response = Response::with((status::Ok, body.to_string()));
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    /// Describes a paste, given by the segment after `/admin/pastes/`, including what is never
+    /// shown to users (like its creator).
+    fn inspect(&self, req: &mut Request) -> IronResult<Response> {
+        let str_id = req.url_segment_n(2).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        let summary = self.db
+                          .load_metadata(id)
+                          .db_context(|| format!("loading metadata of paste {}", id))?
+                          .ok_or_else(|| self.not_found(id))?;
+        Ok(api::json_response(status::Ok, &summary_json(&summary, &self.id_encoding)))
+    }
+
+    /// Removes a paste, given by the segment after `/admin/pastes/`, whoever has uploaded it.
+    fn force_remove(&self, req: &mut Request) -> IronResult<Response> {
+        let str_id = req.url_segment_n(2).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        self.db
+            .load_metadata(id)
+            .db_context(|| format!("loading metadata of paste {}", id))?
+            .ok_or_else(|| self.not_found(id))?;
+        self.discard(id, "admin")?;
+        info!("Paste {} is removed by an admin", id);
+        Ok(Response::with(status::NoContent))
+    }
+
+    /// Removes all the pastes that pass the filter given by the arguments (see
+    /// `PasteFilter::from_request`), at least one condition is required.
+    ///
+    /// Pastes of a creator created since a moment (when nothing else is asked for) are left to
+    /// `DbInterface::remove_by_creator`, the rest of the filters go through the whole database.
+    fn remove_matching(&self, req: &mut Request) -> IronResult<Response> {
+        let filter = PasteFilter::from_request(req)?;
+        if filter.is_empty() {
+            return Err(Error::NoFilter.into());
+        }
+        let removed = match filter.creator_since() {
+            Some((creator, since)) => {
+                self.db
+                    .remove_by_creator(creator, since)
+                    .db_context(|| format!("removing pastes of {}", creator))?
+            }
+            None => {
+                let matching = pagination::all(&*self.db, |summary| filter.matches(summary))
+                    .db_context(|| "listing pastes")?;
+                for summary in &matching {
+                    self.discard(summary.id, "admin")?;
+                }
+                matching.len() as u64
+            }
+        };
+        info!("Removed {} pastes matching {:?}", removed, filter);
+        let mut response = Response::with((status::Ok, json!({ "removed": removed }).to_string()));
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    /// Restores a trashed paste, given by the segment after `/admin/restore/`.
+    fn restore(&self, req: &mut Request) -> IronResult<Response> {
+        let str_id = req.url_segment_n(2).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        let restored = self.db
+                           .restore(id)
+                           .db_context(|| format!("restoring paste {}", str_id))?;
+        if !restored {
+            return Err(Error::IdNotFound(self.public_id(id)).into());
+        }
+        info!("Restored paste {} from the trash", id);
+        Ok(Response::with((status::Ok, format!("{}{}\n", self.url_prefix, self.public_id(id)))))
+    }
+
+    /// Lists tombstones of pastes removed since a moment, given by the optional `since` argument
+    /// (all the kept tombstones by default).
+    fn list_tombstones(&self, req: &mut Request) -> IronResult<Response> {
+        let since = match req.get_arg("since") {
+            Some(since) => parse_timestamp(&since)?,
+            None => DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(0, 0), Utc),
+        };
+        let tombstones = self.db
+                             .list_tombstones(since)
+                             .db_context(|| "listing tombstones")?;
+        let tombstones = tombstones.iter()
+                               .map(|tombstone| tombstone_json(tombstone, &self.id_encoding))
+                               .collect::<Vec<_>>();
+        let body = json!({ "tombstones": tombstones });
+        let mut response = Response::with((status::Ok, body.to_string()));
+        response.headers.set(ContentType::json());
+        Ok(response)
+    }
+
+    /// Replaces the announcement banner with the one given as JSON, see
+    /// [Banner::from_json](struct.Banner.html#method.from_json).
+    fn set_banner(&self, req: &mut Request) -> IronResult<Response> {
+        let banner = Banner::from_json(&self.load_body(req)?)?;
+        info!("Banner set: {:?}", banner.message);
+        let body = banner.to_json();
+        *self.banner.write().unwrap() = Some(banner);
+        Ok(api::json_response(status::Ok, &body))
+    }
+
+    /// Takes the announcement banner down.
+    fn remove_banner(&self) -> IronResult<Response> {
+        *self.banner.write().unwrap() = None;
+        info!("Banner removed");
+        Ok(Response::with(status::NoContent))
+    }
+
+    /// Lists pastes flagged by the spam filter that haven't been reviewed yet.
+    fn list_flagged(&self) -> IronResult<Response> {
+        let filter = self.spam_filter.as_ref().ok_or(Error::UnknownEndpoint)?;
+        let flagged = filter.flagged()
+                            .into_iter()
+                            .map(|flagged| {
+                                     json!({
+                                         "id": self.public_id(flagged.id),
+                                         "score": flagged.score,
+                                         "flagged_at": flagged.flagged_at.to_rfc3339(),
+                                     })
+                                 })
+                            .collect::<Vec<_>>();
+        Ok(api::json_response(status::Ok, &json!({ "flagged": flagged })))
+    }
+
+    /// Records an admin's judgement of a paste, given by the segment after `/admin/spam/`, for
+    /// the spam filter. The `label` argument is either `spam` or `ham`.
+    fn spam_feedback(&self, req: &mut Request) -> IronResult<Response> {
+        let filter = self.spam_filter.as_ref().ok_or(Error::UnknownEndpoint)?;
+        let str_id = req.url_segment_n(2).ok_or(Error::NoIdSegment)?;
+        let id = self.resolve_id(str_id)?;
+        let is_spam = match req.get_arg("label") {
+            Some(ref label) if label == "spam" => true,
+            Some(ref label) if label == "ham" => false,
+            Some(label) => return Err(Error::InvalidLabel(label.into_owned()).into()),
+            None => return Err(Error::MissingArgument("label").into()),
+        };
+        let paste = self.db
+                        .load_data(id)
+                        .db_context(|| format!("loading paste {}", id))?
+                        .ok_or_else(|| self.not_found(id))?;
+        let score = filter.feedback(id, &String::from_utf8_lossy(&paste.data), is_spam)
+                          .context(|| format!("recording spam feedback on paste {}", id))?;
+        info!("Paste {} is reported as {}", id, if is_spam { "spam" } else { "ham" });
+        let body = json!({ "id": self.public_id(id), "spam": is_spam, "score": score });
+        Ok(api::json_response(status::Ok, &body))
+    }
+}
+
+/// Describes a tombstone in JSON listings.
+fn tombstone_json(tombstone: &Tombstone, id_encoding: &IdEncoding) -> serde_json::Value {
+    json!({
+        "id": id_encoding.encode(tombstone.id.into()),
+        "removed_at": tombstone.removed_at.to_rfc3339(),
+        "actor": tombstone.actor,
+    })
+}
+
+/// Describes a paste in JSON listings.
+fn summary_json(summary: &PasteSummary, id_encoding: &IdEncoding) -> serde_json::Value {
+    json!({
+        "id": id_encoding.encode(summary.id.into()),
+        "file_name": summary.file_name,
+        "mime_type": summary.mime_type.as_str(),
+        "size": summary.size,
+        "lines": summary.lines,
+        "language": summary.language,
+        "created_at": summary.created_at.to_rfc3339(),
+        "best_before": summary.best_before.map(|date| date.to_rfc3339()),
+        "views": summary.views,
+        "creator": summary.creator,
+        "revision": summary.revision,
+        "views_left": summary.views_left,
+        "public": summary.public,
+    })
+}
+
+/// Compares tokens in a time that doesn't depend on how much of them matches.
+pub fn tokens_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
 }
 
 impl<E> Handler for Pastebin<E>
-    where E: Send + Sync + std::error::Error + 'static
+    where E: Send + Sync + std::error::Error + From<Unsupported> + 'static
 {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
         match req.method {
-            Method::Get => self.get(req),
-            Method::Post | Method::Put => self.post(req),
+            _ if req.url_segment_n(0) == Some("admin") => self.admin(req),
+            _ if req.url_segment_n(0) == Some("api") => self.api(req),
+            _ if req.url_segment_n(0) == Some("s") && req.url_segment_n(1).is_some() => {
+                self.slug(req)
+            }
+            Method::Get => self.get(req, false),
+            Method::Head => self.head(req),
+            Method::Post if req.url_segment_n(1) == Some("fork") => self.fork(req),
+            Method::Post => self.post(req),
+            Method::Put => self.put(req),
             Method::Delete => self.remove(req),
             _ => Ok(Response::with(status::MethodNotAllowed)),
         }
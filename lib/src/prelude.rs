@@ -0,0 +1,15 @@
+//! A curated set of the items most embedders need.
+//!
+//! ```
+//! use pastebin::prelude::*;
+//! ```
+//!
+//! Everything re-exported here is a part of the stable public API of the library: it will only be
+//! changed (or removed) with a version bump that is considered breaking by semver.
+
+pub use {DbInterface, Error, PasteEntry, PasteSummary, StorageStats, Tombstone, Unsupported,
+         UpdateOutcome};
+pub use {MimeType, PasteId, RetentionPolicy, SizeLimits};
+pub use id::{decode_id, encode_id, IdEncoding, IdGenerator, RandomIds, SequentialIds};
+pub use pastebin::Pastebin;
+pub use web::{run_web, PastebinBuilder};
@@ -0,0 +1,227 @@
+//! Telling client addresses apart from the addresses of reverse proxies in front of the service,
+//! and hiding them from the rest of it.
+
+use Error;
+use chrono::{Date, Utc};
+use iron::{BeforeMiddleware, IronResult, Request};
+use iron::typemap::Key;
+use limits::split_rules;
+use request::RequestExt;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::str::{self, FromStr};
+use std::sync::Mutex;
+
+/// Request header with the addresses a request has been forwarded for, the client first.
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Request header with the address of the client, as nginx sets it.
+const REAL_IP_HEADER: &str = "X-Real-IP";
+
+/// Request extension with the address of the client found by `TrustedProxies`, see
+/// `RequestExt::client_ip`.
+pub struct ClientIp;
+
+impl Key for ClientIp {
+    type Value = IpAddr;
+}
+
+/// An address along with the length of the network prefix, like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Checks whether an address belongs to the network.
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    /// Parses a network like `10.0.0.0/8` or `fd00::/8`, or a single address like `127.0.0.1`.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidProxy(s.into());
+        let mut parts = s.splitn(2, '/');
+        let address = canonical(parts.next()
+                                     .unwrap_or("")
+                                     .parse::<IpAddr>()
+                                     .map_err(|_| invalid())?);
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+        Ok(Network { address, prefix })
+    }
+}
+
+/// Checks whether the leading `prefix` bits of two addresses are the same.
+fn prefix_matches(a: &[u8], b: &[u8], prefix: u8) -> bool {
+    let bytes = (prefix / 8) as usize;
+    let bits = prefix % 8;
+    a[..bytes] == b[..bytes] && (bits == 0 || (a[bytes] ^ b[bytes]) >> (8 - bits) == 0)
+}
+
+/// Turns IPv4 addresses mapped to IPv6 (`::ffff:10.0.0.1`, as a dual-stack socket reports IPv4
+/// peers) back into IPv4 ones.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+            let octets = v6.octets();
+            IpAddr::from([octets[12], octets[13], octets[14], octets[15]])
+        }
+        _ => ip,
+    }
+}
+
+/// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are believed, see
+/// [PastebinBuilder::trusted_proxies](struct.PastebinBuilder.html#method.trusted_proxies).
+///
+/// Each proxy appends the address it has got a request from to `X-Forwarded-For`, so the header
+/// is read from the end: the client is the last address that is not a trusted proxy. Whatever
+/// comes before it is made up by the client itself and is ignored. Without `X-Forwarded-For`,
+/// `X-Real-IP` is taken. Requests that don't come from a trusted proxy are taken as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+}
+
+impl TrustedProxies {
+    /// Creates an empty list, which trusts no one.
+    pub fn new() -> Self {
+        TrustedProxies::default()
+    }
+
+    /// Trusts proxies of a network like `10.0.0.0/8`, or a single proxy like `127.0.0.1`.
+    pub fn add(mut self, network: &str) -> Result<Self, Error> {
+        self.networks.push(network.trim().parse()?);
+        Ok(self)
+    }
+
+    /// Checks whether an address is the one of a trusted proxy.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Finds the address of the client a request has been made by.
+    pub fn client_ip(&self, req: &Request) -> IpAddr {
+        let peer = canonical(req.remote_addr.ip());
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        let forwarded = match req.headers.get_raw(FORWARDED_FOR_HEADER) {
+            Some(lines) => lines.iter()
+                                .filter_map(|line| str::from_utf8(line).ok())
+                                .flat_map(|line| line.split(','))
+                                .map(|address| address.trim().parse::<IpAddr>().map(canonical))
+                                .collect::<Vec<_>>(),
+            None => return self.real_ip(req).unwrap_or(peer),
+        };
+        let mut client = peer;
+        for address in forwarded.into_iter().rev() {
+            match address {
+                Ok(address) if self.is_trusted(address) => client = address,
+                Ok(address) => return address,
+                // Nothing before a malformed address could be told apart from a made up one.
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    /// Takes the client address from the `X-Real-IP` header, if it is there.
+    fn real_ip(&self, req: &Request) -> Option<IpAddr> {
+        req.headers
+           .get_raw(REAL_IP_HEADER)
+           .and_then(|lines| lines.first())
+           .and_then(|line| str::from_utf8(line).ok())
+           .and_then(|address| address.trim().parse().ok())
+           .map(canonical)
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = Error;
+
+    /// Parses networks and addresses separated by commas or whitespace, like
+    /// `127.0.0.1, 10.0.0.0/8`.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        split_rules(s).into_iter()
+                      .fold(Ok(TrustedProxies::new()), |proxies, network| proxies?.add(network))
+    }
+}
+
+impl BeforeMiddleware for TrustedProxies {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let client = self.client_ip(req);
+        req.extensions.insert::<ClientIp>(client);
+        Ok(())
+    }
+}
+
+/// Replaces the address of the client with a pseudonym before anything else gets to see it, see
+/// [PastebinBuilder::anonymize_clients](struct.PastebinBuilder.html#method.anonymize_clients).
+///
+/// Has to be linked right after `TrustedProxies`, if there are any. Both the `ClientIp` extension
+/// and `remote_addr` (which Iron logs along with failed requests) are replaced, and the headers
+/// the client address could be forwarded in are dropped.
+pub struct AnonymizeClients;
+
+impl BeforeMiddleware for AnonymizeClients {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let client = pseudonym(req.client_ip());
+        req.extensions.insert::<ClientIp>(client);
+        req.remote_addr = SocketAddr::new(client, req.remote_addr.port());
+        req.headers.remove_raw(FORWARDED_FOR_HEADER);
+        req.headers.remove_raw(REAL_IP_HEADER);
+        Ok(())
+    }
+}
+
+/// Turns an address into a salted hash of it within `100::/64`, the IPv6 discard prefix, so it is
+/// never mistaken for a real one.
+///
+/// The salt is random, is replaced every day and is never stored, so requests of a client could
+/// be told apart from the others within a day, but the address itself can't be recovered.
+fn pseudonym(ip: IpAddr) -> IpAddr {
+    lazy_static! {
+        static ref SALT: Mutex<(Date<Utc>, RandomState)> =
+            Mutex::new((Utc::today(), RandomState::new()));
+    }
+    let hash = {
+        let mut salt = SALT.lock().unwrap();
+        let today = Utc::today();
+        if salt.0 != today {
+            *salt = (today, RandomState::new());
+        }
+        let mut hasher = salt.1.build_hasher();
+        canonical(ip).hash(&mut hasher);
+        hasher.finish()
+    };
+    IpAddr::V6(Ipv6Addr::new(0x100,
+                             0,
+                             0,
+                             0,
+                             (hash >> 48) as u16,
+                             (hash >> 32) as u16,
+                             (hash >> 16) as u16,
+                             hash as u16))
+}
@@ -1,14 +1,54 @@
 //! Reading from stream helper.
 
 use Error;
-use std::io::Read;
+use std::cmp;
+use std::io::{ErrorKind, Read};
+use std::time::Instant;
 
-/// Loads data from stream either in portions of 1024 bytes until an end of data or the limit is
-/// reached or an exact amount of bytes if `data_length` is not `None`.
+/// Size of the blocks a stream is read in.
+const BLOCK_SIZE: usize = 8 * 1024;
+
+/// Loads a stream of `data_length` bytes, or up to its end if the length is not known (like a
+/// chunked request body), giving up once it grows bigger than `limit` or the deadline (if any)
+/// passes.
+///
+/// The stream is read block by block and the buffer grows as the data arrives, so neither a
+/// spoofed length nor a body over the limit makes it allocate more than the limit: the latter is
+/// rejected with `Error::TooBig` as soon as the limit is crossed, without receiving the rest of it.
 ///
-/// If a limit is reached Error::TooBig is returned.
-pub fn load_data<R: Read>(stream: &mut R, data_length: u64) -> Result<Vec<u8>, Error> {
-    let mut data = vec![0u8; data_length as usize];
-    stream.read_exact(&mut data)?;
+/// A stream that ends prematurely or fails (the client has disconnected mid-upload), or that
+/// doesn't make it in time, fails with `Error::UploadAborted`, so a truncated paste never makes it
+/// to the database. The deadline is checked between reads, so a single read is bounded by the
+/// socket's read timeout rather than by the deadline itself.
+pub fn load_data<R: Read>(stream: &mut R,
+                          data_length: Option<u64>,
+                          limit: usize,
+                          deadline: Option<Instant>)
+                          -> Result<Vec<u8>, Error> {
+    let expected = data_length.map(|length| length as usize);
+    let mut data = Vec::new();
+    let mut block = [0u8; BLOCK_SIZE];
+    // One byte past the limit is enough to know it is crossed.
+    let wanted = cmp::min(expected.unwrap_or(usize::max_value()), limit.saturating_add(1));
+    while data.len() < wanted {
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            return Err(Error::UploadAborted(data.len(), expected, true));
+        }
+        let size = cmp::min(block.len(), wanted - data.len());
+        match stream.read(&mut block[..size]) {
+            Ok(0) if expected.is_none() => break,
+            Ok(0) => return Err(Error::UploadAborted(data.len(), expected, false)),
+            Ok(read) => data.extend_from_slice(&block[..read]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                return Err(Error::UploadAborted(data.len(), expected, true))
+            }
+            // Whatever else goes wrong with the connection, the rest of the data is not coming.
+            Err(_) => return Err(Error::UploadAborted(data.len(), expected, false)),
+        }
+    }
+    if data.len() > limit {
+        return Err(Error::TooBig(limit, None));
+    }
     Ok(data)
 }
@@ -0,0 +1,71 @@
+//! Safe logging of pastes data.
+//!
+//! People paste secrets, so pastes data must never end up in logs. Every piece of code that wants
+//! to mention the data in a log message should do it through [redact](fn.redact.html), which
+//! only reveals the size of the data, and optionally a short prefix of it (see
+//! [set_log_prefix_len](fn.set_log_prefix_len.html)).
+
+use std::cmp::min;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+/// Number of leading bytes of the data that are allowed to be logged.
+static LOG_PREFIX_LEN: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Sets how many leading bytes of pastes data [redact](fn.redact.html) reveals.
+///
+/// The setting is global and defaults to zero, i.e. only the size of the data is logged. A
+/// non-zero value is meant for debugging only.
+pub fn set_log_prefix_len(len: usize) {
+    LOG_PREFIX_LEN.store(len, Ordering::Relaxed);
+}
+
+/// Returns the number of leading bytes of pastes data that are allowed to be logged.
+pub fn log_prefix_len() -> usize {
+    LOG_PREFIX_LEN.load(Ordering::Relaxed)
+}
+
+/// Wraps pastes data to be safely logged.
+///
+/// The returned value implements both `Display` and `Debug`, which print the size of the data
+/// followed by its prefix (if enabled by [set_log_prefix_len](fn.set_log_prefix_len.html)), like
+/// `5 bytes` or `5 bytes ("Aha"...)`.
+pub fn redact(data: &[u8]) -> Redacted {
+    Redacted::new(data, log_prefix_len())
+}
+
+/// Pastes data prepared for logging, see [redact](fn.redact.html).
+pub struct Redacted<'a> {
+    data: &'a [u8],
+    prefix_len: usize,
+}
+
+impl<'a> Redacted<'a> {
+    /// Wraps the data revealing at most `prefix_len` bytes of it.
+    pub fn new(data: &'a [u8], prefix_len: usize) -> Self {
+        Redacted { data,
+                   prefix_len: min(prefix_len, data.len()), }
+    }
+}
+
+impl<'a> fmt::Display for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", self.data.len())?;
+        if self.prefix_len == 0 {
+            return Ok(());
+        }
+        write!(f,
+               " ({:?}",
+               String::from_utf8_lossy(&self.data[..self.prefix_len]))?;
+        if self.prefix_len < self.data.len() {
+            write!(f, "...")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<'a> fmt::Debug for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
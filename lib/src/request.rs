@@ -1,7 +1,13 @@
 //! Request helpers.
 
+use Error;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use iron::{self, Request};
+use iron::mime::{SubLevel, TopLevel};
+use limits::parse_ttl;
+use proxy::ClientIp;
 use std::borrow::Cow;
+use std::net::IpAddr;
 
 /// Convenience functions for a `Request`.
 pub trait RequestExt {
@@ -9,14 +15,31 @@ pub trait RequestExt {
     /// (like wget or curl).
     fn is_browser(&self) -> bool;
 
+    /// Checks whether the client asks for an HTML page (`text/html` is in the `Accept` header),
+    /// as browsers do when a page is opened, as opposed to an image embedded into one.
+    fn accepts_html(&self) -> bool;
+
     /// Retrieves data from the `ContentLength` header if it is provided.
     fn get_length(&self) -> Option<u64>;
 
+    /// Checks if the body of a request is sent in chunks (`Transfer-Encoding: chunked`), so its
+    /// length is not known up front.
+    fn is_chunked(&self) -> bool;
+
     /// Tries to obtain an `n`-th segment of the URI.
     fn url_segment_n(&self, n: usize) -> Option<&str>;
 
     /// Extracts value of an argument (a URI part after `?`).
     fn get_arg(&self, arg: &str) -> Option<Cow<str>>;
+
+    /// Returns the host of the page the request has been made from (the `Referer` header), if
+    /// it is known.
+    fn referer_host(&self) -> Option<String>;
+
+    /// Returns the address of the client: the one the request comes from, unless it comes from a
+    /// trusted proxy which tells the client's one (see `web::TrustedProxies`). With
+    /// `PastebinBuilder::anonymize_clients`, it is a daily pseudonym instead.
+    fn client_ip(&self) -> IpAddr;
 }
 
 impl<'a, 'b> RequestExt for Request<'a, 'b> {
@@ -33,6 +56,14 @@ impl<'a, 'b> RequestExt for Request<'a, 'b> {
             .unwrap_or(false)
     }
 
+    fn accepts_html(&self) -> bool {
+        self.headers.get::<iron::headers::Accept>()
+            .map_or(false, |accept| {
+                accept.iter()
+                      .any(|item| item.item.0 == TopLevel::Text && item.item.1 == SubLevel::Html)
+            })
+    }
+
     fn get_length(&self) -> Option<u64> {
         self.headers.get::<iron::headers::ContentLength>()
             .map(|length_header| {
@@ -42,6 +73,11 @@ impl<'a, 'b> RequestExt for Request<'a, 'b> {
                  })
     }
 
+    fn is_chunked(&self) -> bool {
+        self.headers.get::<iron::headers::TransferEncoding>()
+            .map_or(false, |codings| codings.contains(&iron::headers::Encoding::Chunked))
+    }
+
     fn url_segment_n(&self, n: usize) -> Option<&str> {
         self.url.as_ref()
             .path_segments()
@@ -61,4 +97,87 @@ impl<'a, 'b> RequestExt for Request<'a, 'b> {
             .find(|&(ref name, _)| name == arg)
             .map(|(_, value)| value)
     }
+
+    fn referer_host(&self) -> Option<String> {
+        self.headers.get::<iron::headers::Referer>()
+            .and_then(|referer| iron::url::Url::parse(referer).ok())
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+    }
+
+    fn client_ip(&self) -> IpAddr {
+        self.extensions
+            .get::<ClientIp>()
+            .cloned()
+            .unwrap_or_else(|| self.remote_addr.ip())
+    }
+}
+
+/// Parses the `expires` argument of an upload: a Unix timestamp, a lifetime like `10m`, `1h`,
+/// `2d` or `1w`, or `never`.
+///
+/// Pastes without the argument expire after `default_ttl` from `now`. A timestamp that is not
+/// after `now` is rejected, since the paste would have expired before it is stored.
+pub fn parse_expires(arg: Option<&str>,
+                     now: DateTime<Utc>,
+                     default_ttl: Duration)
+                     -> Result<Option<DateTime<Utc>>, Error> {
+    let expires = match arg {
+        Some("never") => return Ok(None),
+        Some(expires) => expires,
+        None => return Ok(Some(now + default_ttl)),
+    };
+    if let Some(ttl) = parse_ttl(expires) {
+        return Ok(Some(now + ttl));
+    }
+    let expires_at =
+        timestamp_from_str(expires).ok_or_else(|| Error::InvalidExpires(expires.into()))?;
+    if expires_at <= now {
+        return Err(Error::ExpiresInPast(expires_at));
+    }
+    Ok(Some(expires_at))
+}
+
+/// Parses the `lang` argument: a language name in terms of highlight.js, like `rust` or `cpp`.
+///
+/// An empty argument is as good as a missing one. Names are lowercased, and anything but short
+/// names made of letters, digits, `+`, `#`, `-` and `_` is rejected, since names end up in pages.
+pub fn parse_language(arg: Option<&str>) -> Result<Option<String>, Error> {
+    let language = match arg {
+        Some(language) if !language.is_empty() => language,
+        _ => return Ok(None),
+    };
+    let is_valid = language.len() <= 32
+                   && language.chars()
+                              .all(|c| c.is_ascii_alphanumeric() || "+#-_".contains(c));
+    if is_valid {
+        Ok(Some(language.to_ascii_lowercase()))
+    } else {
+        Err(Error::InvalidLanguage(language.into()))
+    }
+}
+
+/// Parses the `max_views` argument of an upload: the number of views a paste is removed after.
+///
+/// An empty argument is as good as a missing one, zero is rejected.
+pub fn parse_max_views(arg: Option<&str>) -> Result<Option<u64>, Error> {
+    match arg {
+        Some(max_views) if !max_views.is_empty() => match max_views.parse() {
+            Ok(0) | Err(_) => Err(Error::InvalidMaxViews(max_views.into())),
+            Ok(max_views) => Ok(Some(max_views)),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Parses a Unix timestamp argument.
+pub fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, Error> {
+    timestamp_from_str(timestamp).ok_or_else(|| Error::InvalidTimestamp(timestamp.into()))
+}
+
+fn timestamp_from_str(timestamp: &str) -> Option<DateTime<Utc>> {
+    // Out of range timestamps are rejected as well, `NaiveDateTime::from_timestamp` panics on them.
+    timestamp.parse()
+             .ok()
+             .and_then(|secs| NaiveDateTime::from_timestamp_opt(secs, 0))
+             .map(|date| DateTime::from_utc(date, Utc))
 }
@@ -0,0 +1,287 @@
+//! Secrets management: encryption keys, tokens and credentials.
+//!
+//! Secrets are looked up by name (like `encryption-keys` or `db-uri`) through a
+//! [SecretProvider](trait.SecretProvider.html), so they don't have to be passed on the command
+//! line, where every user of the system could see them. The providers always return the current
+//! value of a secret, and [watch](fn.watch.html) keeps a [Rotating](struct.Rotating.html) value
+//! up to date, so secrets could be rotated without a restart.
+//!
+//! The following providers are available:
+//!
+//! * [EnvSecrets](struct.EnvSecrets.html) reads environment variables;
+//! * [FileSecrets](struct.FileSecrets.html) reads files from a directory, which is how Docker and
+//! Kubernetes pass secrets to containers;
+//! * `VaultSecrets` reads a HashiCorp Vault KV secret (requires the `vault` feature).
+//!
+//! Several providers could be combined with [SecretChain](struct.SecretChain.html).
+
+use chrono::Duration;
+use jobs;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+#[cfg(feature = "vault")]
+mod vault;
+
+#[cfg(feature = "vault")]
+pub use self::vault::VaultSecrets;
+
+quick_error! {
+    /// Secrets loading errors.
+    #[derive(Debug)]
+    pub enum SecretError {
+        /// A required secret doesn't exist.
+        NotFound(name: String) {
+            description("Secret not found")
+            display("Secret {} not found", name)
+        }
+        /// Can't read a secret.
+        Io(err: io::Error) {
+            cause(err)
+            from()
+        }
+        /// A secrets backend has failed.
+        Backend(reason: String) {
+            description("Secrets backend failure")
+            display("Secrets backend failure: {}", reason)
+        }
+    }
+}
+
+/// A secret value.
+///
+/// It is never revealed by `Debug`, so it can't leak into logs by accident.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wraps a value.
+    pub fn new<S: Into<String>>(value: S) -> Self {
+        Secret(value.into())
+    }
+
+    /// Returns the value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// A source of secrets.
+pub trait SecretProvider: Send + Sync {
+    /// Looks up the current value of a secret.
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError>;
+
+    /// Loads a secret that must exist.
+    fn get(&self, name: &str) -> Result<Secret, SecretError> {
+        self.find(name)?
+            .ok_or_else(|| SecretError::NotFound(name.into()))
+    }
+}
+
+impl<P: SecretProvider + ?Sized> SecretProvider for Box<P> {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        (**self).find(name)
+    }
+}
+
+impl<P: SecretProvider + ?Sized> SecretProvider for Arc<P> {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        (**self).find(name)
+    }
+}
+
+/// Reads secrets from environment variables.
+///
+/// A name is upper-cased, with dashes and dots replaced by underscores, and prefixed, so with a
+/// `PASTEBIN_` prefix the `encryption-keys` secret is read from `PASTEBIN_ENCRYPTION_KEYS`.
+pub struct EnvSecrets {
+    prefix: String,
+}
+
+impl EnvSecrets {
+    /// Creates a provider for variables with the given prefix.
+    pub fn new<S: Into<String>>(prefix: S) -> Self {
+        EnvSecrets { prefix: prefix.into() }
+    }
+
+    /// Returns the name of the variable that holds a secret.
+    pub fn var_name(&self, name: &str) -> String {
+        let name: String = name.chars()
+                               .map(|c| match c {
+                                        '-' | '.' => '_',
+                                        c => c.to_ascii_uppercase(),
+                                    })
+                               .collect();
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+impl SecretProvider for EnvSecrets {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        let var = self.var_name(name);
+        match env::var(&var) {
+            Ok(value) => Ok(Some(Secret(value))),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(env::VarError::NotUnicode(_)) => {
+                Err(SecretError::Backend(format!("{} is not valid unicode", var)))
+            }
+        }
+    }
+}
+
+/// Reads secrets from files in a directory, a file per secret named after it.
+///
+/// A trailing newline is stripped, since most editors add one.
+pub struct FileSecrets {
+    dir: PathBuf,
+}
+
+impl FileSecrets {
+    /// Creates a provider for a directory.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FileSecrets { dir: dir.into() }
+    }
+}
+
+impl SecretProvider for FileSecrets {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        if name.is_empty() || name.contains(|c| c == '/' || c == '\\') || name.starts_with('.') {
+            return Err(SecretError::Backend(format!("invalid secret name {:?}", name)));
+        }
+        match fs::read_to_string(self.dir.join(name)) {
+            Ok(mut value) => {
+                if value.ends_with('\n') {
+                    value.pop();
+                    if value.ends_with('\r') {
+                        value.pop();
+                    }
+                }
+                Ok(Some(Secret(value)))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Looks secrets up in several providers, returning the first one found.
+#[derive(Default)]
+pub struct SecretChain {
+    providers: Vec<Box<SecretProvider>>,
+}
+
+impl SecretChain {
+    /// Creates an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a provider to the chain.
+    pub fn with<P: SecretProvider + 'static>(mut self, provider: P) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+}
+
+impl SecretProvider for SecretChain {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        for provider in &self.providers {
+            if let Some(secret) = provider.find(name)? {
+                return Ok(Some(secret));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A value that could be replaced while it is in use, like a keyring built from a secret.
+///
+/// Clones share the value, so one of them could be handed over to [watch](fn.watch.html) while
+/// the others are used to read it.
+pub struct Rotating<T> {
+    current: Arc<RwLock<Arc<T>>>,
+}
+
+impl<T> Rotating<T> {
+    /// Wraps an initial value.
+    pub fn new(value: T) -> Self {
+        Rotating { current: Arc::new(RwLock::new(Arc::new(value))) }
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> Arc<T> {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Replaces the value. Those who already hold the previous one keep using it.
+    pub fn set(&self, value: T) {
+        let value = Arc::new(value);
+        match self.current.write() {
+            Ok(mut current) => *current = value,
+            Err(poisoned) => *poisoned.into_inner() = value,
+        }
+    }
+}
+
+impl<T> Clone for Rotating<T> {
+    fn clone(&self) -> Self {
+        Rotating { current: self.current.clone() }
+    }
+}
+
+/// Reloads a secret every `interval` in the background, and once it changes replaces the
+/// `target` with a value built by `parse`.
+///
+/// The `initial` secret is the one the `target` has been built from. Failures are logged and the
+/// old value is kept, so a botched rotation doesn't take the server down.
+pub fn watch<P, T, F, E>(provider: P,
+                         name: &str,
+                         initial: Secret,
+                         interval: Duration,
+                         target: Rotating<T>,
+                         parse: F)
+    where P: SecretProvider + 'static,
+          T: Send + Sync + 'static,
+          F: Fn(&Secret) -> Result<T, E> + Send + 'static,
+          E: fmt::Display
+{
+    let name = name.to_string();
+    let last = Mutex::new(initial);
+    jobs::spawn_periodic(&format!("secret {}", name), interval, move || {
+        let secret = match provider.get(&name) {
+            Ok(secret) => secret,
+            Err(e) => {
+                error!("Can't reload secret {}: {}", name, e);
+                return;
+            }
+        };
+        let mut last = match last.lock() {
+            Ok(last) => last,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if *last == secret {
+            return;
+        }
+        match parse(&secret) {
+            Ok(value) => {
+                target.set(value);
+                *last = secret;
+                info!("Secret {} has been rotated", name);
+            }
+            Err(e) => error!("Rotated secret {} is invalid, keeping the old one: {}", name, e),
+        }
+    });
+}
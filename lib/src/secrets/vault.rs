@@ -0,0 +1,61 @@
+//! HashiCorp Vault secrets.
+
+use super::{Secret, SecretError, SecretProvider};
+use reqwest::{self, StatusCode};
+use reqwest::header::Headers;
+use serde_json::{self, Value};
+
+/// Reads secrets from a HashiCorp Vault key/value secret.
+///
+/// All the secrets live in a single Vault secret, one key per secret name. Both versions of the
+/// key/value engine are supported, mind that the second one needs `data` in the path, like
+/// `secret/data/pastebin`. The secret is fetched anew on every lookup, so rotations in Vault are
+/// picked up right away.
+pub struct VaultSecrets {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl VaultSecrets {
+    /// Creates a provider for a Vault server at `addr` (like `https://vault:8200`).
+    pub fn new(addr: &str, token: &str, path: &str) -> Self {
+        VaultSecrets { client: reqwest::Client::new(),
+                       url: format!("{}/v1/{}",
+                                    addr.trim_right_matches('/'),
+                                    path.trim_left_matches('/')),
+                       token: token.to_string(), }
+    }
+}
+
+impl SecretProvider for VaultSecrets {
+    fn find(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        let backend = |e: reqwest::Error| SecretError::Backend(format!("Vault: {}", e));
+        let mut headers = Headers::new();
+        headers.set_raw("X-Vault-Token", vec![self.token.clone().into_bytes()]);
+        let response = self.client
+                           .get(&self.url)
+                           .headers(headers)
+                           .send()
+                           .map_err(backend)?;
+        match response.status() {
+            StatusCode::Ok => {}
+            StatusCode::NotFound => return Ok(None),
+            status => {
+                return Err(SecretError::Backend(format!("Vault responded with {}", status)));
+            }
+        }
+        let body: Value = serde_json::from_reader(response)
+            .map_err(|e| SecretError::Backend(format!("Vault response: {}", e)))?;
+        // Version 2 of the engine wraps the values in yet another `data` along with `metadata`.
+        let data = match body["data"]["data"] {
+            Value::Object(_) => &body["data"]["data"],
+            _ => &body["data"],
+        };
+        match data[name] {
+            Value::String(ref value) => Ok(Some(Secret::new(value.clone()))),
+            Value::Null => Ok(None),
+            _ => Err(SecretError::Backend(format!("Vault secret {} is not a string", name))),
+        }
+    }
+}
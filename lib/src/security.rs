@@ -0,0 +1,121 @@
+//! Hardening headers sent along with every response.
+
+use iron::{AfterMiddleware, IronResult, Request, Response};
+
+/// Response header that restricts what a page is allowed to load and run.
+const CONTENT_SECURITY_POLICY_HEADER: &str = "Content-Security-Policy";
+
+/// Response header that keeps browsers from guessing content types other than the given one.
+const CONTENT_TYPE_OPTIONS_HEADER: &str = "X-Content-Type-Options";
+
+/// Response header that tells browsers what to put into `Referer` of requests made from a page.
+const REFERRER_POLICY_HEADER: &str = "Referrer-Policy";
+
+/// Response header that tells browsers whether other sites might put a page into a frame.
+const FRAME_OPTIONS_HEADER: &str = "X-Frame-Options";
+
+/// Policy of the service's own pages: scripts and styles come from the service itself and the
+/// CDNs the shipped templates use (inline ones included), and no one frames the pages.
+const DEFAULT_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'self'; \
+     script-src 'self' 'unsafe-inline' https://cdnjs.cloudflare.com https://cdn.jsdelivr.net; \
+     style-src 'self' 'unsafe-inline' https://cdnjs.cloudflare.com https://cdn.jsdelivr.net; \
+     img-src 'self' data:; \
+     frame-ancestors 'none'";
+
+/// Referrer policy by default: paste URLs are secrets of a sort, so they are never passed on.
+const DEFAULT_REFERRER_POLICY: &str = "no-referrer";
+
+/// Framing policy by default.
+const DEFAULT_FRAME_OPTIONS: &str = "DENY";
+
+/// Security headers added to every response, see
+/// [PastebinBuilder::security_headers](struct.PastebinBuilder.html#method.security_headers).
+///
+/// By default responses get a `Content-Security-Policy` that fits the shipped templates,
+/// `X-Content-Type-Options: nosniff`, `Referrer-Policy: no-referrer` and `X-Frame-Options:
+/// DENY`. Every header could be replaced or left out. Responses that set a header by themselves
+/// (like raw pastes, which are sandboxed) keep their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityHeaders {
+    content_security_policy: Option<String>,
+    nosniff: bool,
+    referrer_policy: Option<String>,
+    frame_options: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders::new()
+    }
+}
+
+impl SecurityHeaders {
+    /// Creates the default set of headers.
+    pub fn new() -> Self {
+        SecurityHeaders { content_security_policy: Some(DEFAULT_CONTENT_SECURITY_POLICY.into()),
+                          nosniff: true,
+                          referrer_policy: Some(DEFAULT_REFERRER_POLICY.into()),
+                          frame_options: Some(DEFAULT_FRAME_OPTIONS.into()), }
+    }
+
+    /// Creates an empty set, which adds no headers.
+    pub fn none() -> Self {
+        SecurityHeaders { content_security_policy: None,
+                          nosniff: false,
+                          referrer_policy: None,
+                          frame_options: None, }
+    }
+
+    /// Sets the `Content-Security-Policy` (`None` leaves it out).
+    ///
+    /// Custom templates that load anything from other sites have to be allowed to here.
+    pub fn content_security_policy(mut self, policy: Option<&str>) -> Self {
+        self.content_security_policy = policy.map(String::from);
+        self
+    }
+
+    /// Enables or disables `X-Content-Type-Options: nosniff`.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    /// Sets the `Referrer-Policy`, like `same-origin` (`None` leaves it out).
+    pub fn referrer_policy(mut self, policy: Option<&str>) -> Self {
+        self.referrer_policy = policy.map(String::from);
+        self
+    }
+
+    /// Sets the `X-Frame-Options`, like `SAMEORIGIN` (`None` leaves it out).
+    pub fn frame_options(mut self, options: Option<&str>) -> Self {
+        self.frame_options = options.map(String::from);
+        self
+    }
+}
+
+impl AfterMiddleware for SecurityHeaders {
+    fn after(&self, _: &mut Request, mut res: Response) -> IronResult<Response> {
+        let nosniff = if self.nosniff { Some("nosniff") } else { None };
+        set_default(&mut res,
+                    CONTENT_SECURITY_POLICY_HEADER,
+                    self.content_security_policy.as_ref().map(String::as_str));
+        set_default(&mut res, CONTENT_TYPE_OPTIONS_HEADER, nosniff);
+        set_default(&mut res,
+                    REFERRER_POLICY_HEADER,
+                    self.referrer_policy.as_ref().map(String::as_str));
+        set_default(&mut res,
+                    FRAME_OPTIONS_HEADER,
+                    self.frame_options.as_ref().map(String::as_str));
+        Ok(res)
+    }
+}
+
+/// Sets a header unless it is left out or the response already has it.
+fn set_default(res: &mut Response, name: &'static str, value: Option<&str>) {
+    if let Some(value) = value {
+        if res.headers.get_raw(name).is_none() {
+            res.headers.set_raw(name, vec![value.as_bytes().to_vec()]);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Spam filtering of text uploads.
+//!
+//! A [SpamFilter](struct.SpamFilter.html) given to
+//! [PastebinBuilder::spam_filter](../web/struct.PastebinBuilder.html#method.spam_filter) asks its
+//! [SpamClassifier](trait.SpamClassifier.html) to score every text upload (and update), and acts
+//! on the score: uploads scoring at least the rejection threshold are answered with `403
+//! Forbidden`, the ones scoring at least the flagging threshold are stored, but flagged for
+//! admins to review (`GET /admin/spam`). Binary uploads are not classified.
+//!
+//! Admins tell the filter about its mistakes with `POST /admin/spam/<id>?label=spam` (or `ham`).
+//! The judgement is passed to the classifier, so it could learn on the fly, and appended to the
+//! feedback file (if any), so it could be retrained later. Unlike logs, the feedback file holds
+//! the texts of the judged pastes.
+//!
+//! Classifiers are meant to be provided by the operators of public instances, be it a set of
+//! heuristics or a client of an external service. The only built-in one,
+//! [LinkClassifier](struct.LinkClassifier.html), is a crude heuristic against link spam.
+
+use Error;
+use PasteId;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of flagged pastes a `SpamFilter` keeps track of, the oldest ones are forgotten
+/// first.
+const MAX_FLAGGED: usize = 1000;
+
+/// A scorer of texts.
+pub trait SpamClassifier: Send + Sync {
+    /// Scores a text from 0 (surely not spam) to 1 (surely spam).
+    fn score(&self, text: &str) -> f64;
+
+    /// Learns from an admin's judgement of a text, which is spam if `is_spam` is set. Does
+    /// nothing by default.
+    fn feedback(&self, _text: &str, _is_spam: bool) {}
+}
+
+/// A classifier that counts links: a text with `max_links` links (or more) is surely spam.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkClassifier {
+    max_links: usize,
+}
+
+impl LinkClassifier {
+    /// Creates a classifier that treats `max_links` links as too many.
+    pub fn new(max_links: usize) -> Self {
+        LinkClassifier { max_links }
+    }
+}
+
+impl SpamClassifier for LinkClassifier {
+    fn score(&self, text: &str) -> f64 {
+        let links = text.matches("http://").count() + text.matches("https://").count();
+        if links >= self.max_links {
+            1.
+        } else {
+            links as f64 / self.max_links as f64
+        }
+    }
+}
+
+/// What is done with an upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The upload is stored as usual.
+    Allow,
+    /// The upload is stored, but flagged for review.
+    Flag,
+    /// The upload is rejected.
+    Reject,
+}
+
+/// A paste flagged for review.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flagged {
+    /// ID of the paste.
+    pub id: PasteId,
+    /// The score of the paste.
+    pub score: f64,
+    /// When the paste has been flagged.
+    pub flagged_at: DateTime<Utc>,
+}
+
+/// A classifier along with the thresholds of the actions on its scores.
+pub struct SpamFilter {
+    classifier: Arc<SpamClassifier>,
+    flag_at: f64,
+    reject_at: f64,
+    feedback_file: Option<PathBuf>,
+    flagged: Mutex<VecDeque<Flagged>>,
+}
+
+impl SpamFilter {
+    /// Creates a filter that flags uploads scoring at least 0.5 and rejects the ones scoring at
+    /// least 0.9.
+    pub fn new(classifier: Arc<SpamClassifier>) -> Self {
+        SpamFilter { classifier,
+                     flag_at: 0.5,
+                     reject_at: 0.9,
+                     feedback_file: None,
+                     flagged: Mutex::new(VecDeque::new()), }
+    }
+
+    /// Sets the score uploads are flagged at.
+    pub fn flag_at(mut self, score: f64) -> Self {
+        self.flag_at = score;
+        self
+    }
+
+    /// Sets the score uploads are rejected at.
+    pub fn reject_at(mut self, score: f64) -> Self {
+        self.reject_at = score;
+        self
+    }
+
+    /// Sets the file admin feedback is appended to, as JSON lines like `{"id": "...", "spam":
+    /// false, "score": 0.6, "reported_at": "...", "text": "..."}`.
+    pub fn feedback_file(mut self, path: PathBuf) -> Self {
+        self.feedback_file = Some(path);
+        self
+    }
+
+    /// Scores a text and decides what to do with it.
+    pub fn classify(&self, text: &str) -> (f64, Verdict) {
+        let score = self.classifier.score(text);
+        let verdict = if score >= self.reject_at {
+            Verdict::Reject
+        } else if score >= self.flag_at {
+            Verdict::Flag
+        } else {
+            Verdict::Allow
+        };
+        (score, verdict)
+    }
+
+    /// Flags a paste for review.
+    pub fn flag(&self, id: PasteId, score: f64) {
+        let mut flagged = self.flagged.lock().unwrap();
+        flagged.retain(|flagged| flagged.id != id);
+        if flagged.len() == MAX_FLAGGED {
+            flagged.pop_front();
+        }
+        flagged.push_back(Flagged { id,
+                                    score,
+                                    flagged_at: Utc::now(), });
+    }
+
+    /// Returns the flagged pastes that haven't been reviewed yet, the oldest first.
+    pub fn flagged(&self) -> Vec<Flagged> {
+        self.flagged.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Records an admin's judgement of a paste: passes it to the classifier, appends it to the
+    /// feedback file (if any) and takes the paste off the review list. Returns the current score
+    /// of the text.
+    pub fn feedback(&self, id: PasteId, text: &str, is_spam: bool) -> Result<f64, Error> {
+        let score = self.classifier.score(text);
+        if let Some(ref path) = self.feedback_file {
+            let line = json!({
+                "id": id.to_string(),
+                "spam": is_spam,
+                "score": score,
+                "reported_at": Utc::now().to_rfc3339(),
+                "text": text,
+            });
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        self.classifier.feedback(text, is_spam);
+        self.flagged
+            .lock()
+            .unwrap()
+            .retain(|flagged| flagged.id != id);
+        Ok(score)
+    }
+}
@@ -0,0 +1,687 @@
+//! Machine-readable description of the routes, served at `/api/spec.json`.
+//!
+//! The document follows the OpenAPI 3.0 format, so clients and tooling could be generated from
+//! it. Optional routes (like `/metrics` or the admin API) are described even if the instance
+//! doesn't serve them, they respond with `404 Not Found` then.
+
+use serde_json::{Map, Value};
+
+/// Describes a parameter.
+fn param(name: &str, location: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": location,
+        "description": description,
+        "required": location == "path",
+        "schema": {"type": "string"},
+    })
+}
+
+/// Refers a parameter described in the components.
+fn param_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/parameters/{}", name) })
+}
+
+/// Describes a response without a body.
+fn empty(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+/// Describes a response with a body of a content type.
+fn body(description: &str, content_type: &str, schema: Value) -> Value {
+    let mut content = Map::new();
+    content.insert(content_type.to_string(), json!({ "schema": schema }));
+    json!({ "description": description, "content": content })
+}
+
+/// Describes a plain text response.
+fn text(description: &str) -> Value {
+    body(description, "text/plain", json!({"type": "string"}))
+}
+
+/// Describes an API error response, which carries `{"error": "<message>"}`.
+fn api_error(description: &str) -> Value {
+    body(description,
+         "application/json",
+         json!({"$ref": "#/components/schemas/Error"}))
+}
+
+/// Describes an operation with its responses, given as pairs of status codes and responses.
+fn operation(summary: &str, parameters: Vec<Value>, responses: Vec<(&str, Value)>) -> Value {
+    let responses = responses.into_iter()
+                             .map(|(status, response)| (status.to_string(), response))
+                             .collect::<Map<_, _>>();
+    json!({
+        "summary": summary,
+        "parameters": parameters,
+        "responses": responses,
+    })
+}
+
+/// Adds a request body to an operation.
+fn with_body(mut operation: Value, content_types: &[&str]) -> Value {
+    let mut content = Map::new();
+    for content_type in content_types {
+        let schema = if *content_type == "application/json" {
+            json!({"$ref": "#/components/schemas/Upload"})
+        } else if *content_type == "multipart/form-data" {
+            json!({"$ref": "#/components/schemas/Form"})
+        } else if *content_type == "application/x-www-form-urlencoded" {
+            json!({"$ref": "#/components/schemas/TextForm"})
+        } else {
+            json!({"type": "string", "format": "binary"})
+        };
+        content.insert(content_type.to_string(), json!({ "schema": schema }));
+    }
+    operation["requestBody"] = json!({ "required": true, "content": content });
+    operation
+}
+
+/// Marks an operation as requiring the admin token, as a bearer token or as a password.
+fn admin(mut operation: Value) -> Value {
+    operation["security"] = json!([{"adminToken": []}, {"adminPassword": []}]);
+    operation
+}
+
+/// Builds the OpenAPI document of a service at a URL prefix.
+pub fn openapi(url_prefix: &str) -> Value {
+    let uploaded = || {
+        let mut response = text("The URL of the new paste");
+        response["headers"] = json!({
+            "X-Deletion-Token": {
+                "description": "Token the paste could be removed with",
+                "schema": {"type": "string"},
+            },
+            "X-Expires-At": {
+                "description": "Expiration date of the paste, if it expires",
+                "schema": {"type": "string", "format": "date-time"},
+            },
+            "X-Max-TTL": {
+                "description": "Maximum lifetime of pastes of the type in seconds, if limited",
+                "schema": {"type": "integer"},
+            },
+        });
+        response
+    };
+    let upload = |summary: &str, mut parameters: Vec<Value>| {
+        parameters.push(param_ref("expires"));
+        parameters.push(param_ref("lang"));
+        parameters.push(param_ref("max_views"));
+        parameters.push(param_ref("public"));
+        parameters.push(param_ref("slug"));
+        parameters.push(param_ref("owner_token"));
+        parameters.push(param_ref("content_encoding"));
+        parameters.push(param_ref("csrf_token"));
+        with_body(operation(summary,
+                            parameters,
+                            vec![("201", uploaded()),
+                                 ("303", empty("Redirect to the new paste (for a classic form)")),
+                                 ("400", text("Malformed arguments")),
+                                 ("403", text("Rejected as spam, or no CSRF token")),
+                                 ("408", text("The upload has taken too long")),
+                                 ("409", text("The slug is taken")),
+                                 ("413", text("The paste is too big")),
+                                 ("415", text("Unsupported content encoding"))]),
+                  &["application/octet-stream",
+                    "multipart/form-data",
+                    "application/x-www-form-urlencoded"])
+    };
+    let paste = |summary: &str, mut parameters: Vec<Value>| {
+        parameters.push(param("page",
+                              "query",
+                              "Describe a binary paste with an HTML page instead (no value)"));
+        parameters.push(param("lang",
+                              "query",
+                              "Language to highlight a text paste as on its page, like rust"));
+        parameters.push(param_ref("if_none_match"));
+        parameters.push(param_ref("if_modified_since"));
+        parameters.push(param_ref("range"));
+        parameters.push(param_ref("if_range"));
+        operation(summary,
+                  parameters,
+                  vec![("200", body("The data of the paste", "*/*", json!({}))),
+                       ("206", body("A part of the data of the paste", "*/*", json!({}))),
+                       ("304", empty("The data of the paste hasn't changed")),
+                       ("301", empty("Redirect to the URL with the file name of the paste")),
+                       ("302", empty("Redirect to the page of a hotlinked binary paste")),
+                       ("404", text("No such paste")),
+                       ("410", text("The paste has been removed")),
+                       ("416", empty("The range starts past the end of the data"))])
+    };
+    let mut paths = Map::new();
+    let form = body("Upload form", "text/html", json!({"type": "string"}));
+    paths.insert("/".into(), json!({
+        "get": operation("Upload form", vec![], vec![("200", form)]),
+        "post": upload("Upload a paste", vec![]),
+        "put": upload("Upload a paste", vec![]),
+    }));
+    // A single segment is an ID of a paste, except for uploads, which take it as a file name.
+    paths.insert("/{id}".into(), json!({
+        "get": paste("Get a paste", vec![param_ref("id")]),
+        "post": upload("Upload a paste with a file name",
+                       vec![param("id", "path", "File name of the new paste")]),
+        "put": with_body(operation("Replace the data of a paste (with its deletion token or the \
+                                    admin token, otherwise a new paste is uploaded)",
+                                   vec![param_ref("id"),
+                                        param_ref("token"),
+                                        param_ref("rev"),
+                                        param_ref("content_encoding")],
+                                   vec![("200", text("The URL of the paste")),
+                                        ("403", text("Wrong deletion token, or rejected as spam")),
+                                        ("408", text("The upload has taken too long")),
+                                        ("409",
+                                         text("The paste is not at the given revision anymore")),
+                                        ("413", text("The paste is too big")),
+                                        ("415", text("Unsupported content encoding"))]),
+                         &["application/octet-stream"]),
+        "delete": operation("Remove a paste (with its deletion token or the admin token)",
+                            vec![param_ref("id"), param_ref("token")],
+                            vec![("200", empty("The paste has been removed")),
+                                 ("403", text("Wrong deletion token")),
+                                 ("404", text("No such paste"))]),
+    }));
+    paths.insert("/{id}/{file_name}".into(), json!({
+        "get": paste("Get a paste", vec![param_ref("id"), param_ref("file_name")]),
+    }));
+    paths.insert("/{id}/fork".into(), json!({
+        "post": operation("Copy a paste into a new one (keeping its file name and language)",
+                          vec![param_ref("id"),
+                               param("edit",
+                                     "query",
+                                     "Serve the upload page pre-filled with the paste instead \
+                                      of storing the copy (takes no value)"),
+                               param_ref("expires"),
+                               param_ref("lang"),
+                               param_ref("max_views"),
+                               param_ref("public"),
+                               param_ref("slug"),
+                               param_ref("csrf_token")],
+                          vec![("201", uploaded()),
+                               ("200", body("Upload page", "text/html", json!({"type": "string"}))),
+                               ("303", empty("Redirect to the copy (for a classic form)")),
+                               ("403", text("Rejected as spam, or no CSRF token")),
+                               ("404", text("No such paste")),
+                               ("409", text("The slug is taken")),
+                               ("410", text("The paste has been removed")),
+                               ("415", text("A binary paste can't be edited"))]),
+    }));
+    paths.insert("/h/{hash}".into(), json!({
+        "get": operation("Find a paste by the hash of its content (a permalink)",
+                         vec![param("hash", "path", "Hash from the X-Permalink header")],
+                         vec![("302", empty("Redirect to the paste")),
+                              ("400", text("Malformed hash")),
+                              ("404", text("No paste with such content")),
+                              ("410", text("The paste has been removed"))]),
+    }));
+    let slug = || param("slug", "path", "Slug a paste has claimed");
+    paths.insert("/s/{slug}".into(), json!({
+        "get": operation("Find a paste by its slug",
+                         vec![slug()],
+                         vec![("302", empty("Redirect to the paste")),
+                              ("404", text("No paste has claimed the slug")),
+                              ("410", text("The paste has been removed"))]),
+        "post": upload("Upload a paste that claims the slug", vec![slug()]),
+        "put": upload("Replace the data of the paste that has claimed the slug (with its \
+                       deletion token or the admin token), otherwise upload a paste that claims \
+                       it",
+                      vec![slug(), param_ref("token"), param_ref("rev")]),
+    }));
+    paths.insert("/raw/{id}".into(), json!({
+        "get": operation("Get the data of a paste as it is, whatever the user agent",
+                         vec![param_ref("id"),
+                              param_ref("if_none_match"),
+                              param_ref("if_modified_since"),
+                              param_ref("range"),
+                              param_ref("if_range")],
+                         vec![("200", body("The data of the paste", "*/*", json!({}))),
+                              ("206", body("A part of the data of the paste", "*/*", json!({}))),
+                              ("304", empty("The data of the paste hasn't changed")),
+                              ("403", text("A hotlinked binary paste")),
+                              ("404", text("No such paste")),
+                              ("410", text("The paste has been removed")),
+                              ("416", empty("The range starts past the end of the data"))]),
+    }));
+    paths.insert("/dl/{id}".into(), json!({
+        "get": operation("Download a paste as a file named after it (or its ID)",
+                         vec![param_ref("id"),
+                              param_ref("if_none_match"),
+                              param_ref("if_modified_since"),
+                              param_ref("range"),
+                              param_ref("if_range")],
+                         vec![("200", body("The data of the paste", "*/*", json!({}))),
+                              ("206", body("A part of the data of the paste", "*/*", json!({}))),
+                              ("304", empty("The data of the paste hasn't changed")),
+                              ("403", text("A hotlinked binary paste")),
+                              ("404", text("No such paste")),
+                              ("410", text("The paste has been removed")),
+                              ("416", empty("The range starts past the end of the data"))]),
+    }));
+    paths.insert("/{id}/as/{format}".into(), json!({
+        "get": operation("Get a paste converted into another format",
+                         vec![param_ref("id"),
+                              param("format",
+                                    "path",
+                                    "hexdump, base64-decoded, json, yaml or html")],
+                         vec![("200", body("The converted paste", "*/*", json!({}))),
+                              ("404", text("No such paste")),
+                              ("410", text("The paste has been removed")),
+                              ("415", text("The format doesn't apply to the paste")),
+                              ("422", text("The paste can't be converted"))]),
+    }));
+    paths.insert("/{id}/html".into(), json!({
+        "get": operation("Get a page with a Markdown paste rendered into HTML",
+                         vec![param_ref("id")],
+                         vec![("200",
+                               body("Rendered paste", "text/html", json!({"type": "string"}))),
+                              ("404", text("No such paste")),
+                              ("410", text("The paste has been removed")),
+                              ("415", text("Not a Markdown paste")),
+                              ("422", text("The paste is too big to be rendered"))]),
+    }));
+    paths.insert("/api/v1/pastes".into(), json!({
+        "post": with_body(operation("Upload a paste",
+                                    vec![param_ref("file_name_arg"),
+                                         param_ref("expires"),
+                                         param_ref("lang"),
+                                         param_ref("max_views"),
+                                         param_ref("public"),
+                                         param_ref("slug"),
+                                         param_ref("owner_token")],
+                                    vec![("201",
+                                          body("The new paste",
+                                               "application/json",
+                                               json!({"$ref": "#/components/schemas/Created"}))),
+                                         ("400", api_error("Malformed request")),
+                                         ("403", api_error("Rejected as spam")),
+                                         ("408", api_error("The upload has taken too long")),
+                                         ("409", api_error("The slug is taken")),
+                                         ("413", api_error("The paste is too big"))]),
+                          &["application/json", "application/octet-stream"]),
+        "delete": operation("Remove all the pastes of the caller, the ones uploaded with the \
+                             token",
+                            vec![param("owner", "query", "Whose pastes to remove, only me"),
+                                 param_ref("token")],
+                            vec![("200",
+                                  body("The number of removed pastes",
+                                       "application/json",
+                                       json!({
+                                           "type": "object",
+                                           "properties": {"removed": {"type": "integer"}},
+                                       }))),
+                                 ("400", api_error("Malformed token, or not me"))]),
+    }));
+    paths.insert("/api/v1/pastes/{id}".into(), json!({
+        "get": operation("Get a paste",
+                         vec![param_ref("id")],
+                         vec![("200",
+                               body("The paste",
+                                    "application/json",
+                                    json!({"$ref": "#/components/schemas/Paste"}))),
+                              ("400", api_error("Malformed ID")),
+                              ("404", api_error("No such paste")),
+                              ("410", api_error("The paste has been removed"))]),
+        "delete": operation("Remove a paste (with its deletion token or the admin token)",
+                            vec![param_ref("id"), param_ref("token")],
+                            vec![("204", empty("The paste has been removed")),
+                                 ("403", api_error("Wrong deletion token")),
+                                 ("404", api_error("No such paste"))]),
+    }));
+    paths.insert("/api/v1/export".into(), json!({
+        "get": operation("Export all the pastes of the caller, the ones uploaded with the token",
+                         vec![param("owner", "query", "Whose pastes to export, only me"),
+                              param_ref("token")],
+                         vec![("200",
+                               body("The pastes with their metadata, one JSON object per line",
+                                    "application/x-ndjson",
+                                    json!({"type": "string"}))),
+                              ("400", api_error("Malformed token, or not me"))]),
+    }));
+    paths.insert("/api/v1/instance".into(), json!({
+        "get": operation("Describe the instance",
+                         vec![],
+                         vec![("200",
+                               body("The instance",
+                                    "application/json",
+                                    json!({"$ref": "#/components/schemas/Instance"})))]),
+    }));
+    paths.insert("/api/spec.json".into(), json!({
+        "get": operation("This document",
+                         vec![],
+                         vec![("200", body("OpenAPI document", "application/json", json!({})))]),
+    }));
+    paths.insert("/healthz".into(), json!({
+        "get": operation("Check that the service is alive",
+                         vec![],
+                         vec![("200", body("The service is alive", "application/json", json!({}))),
+                              ("503", body("The service is draining",
+                                           "application/json",
+                                           json!({})))]),
+    }));
+    paths.insert("/readyz".into(), json!({
+        "get": operation("Check that the service is ready for traffic (the database is reachable)",
+                         vec![],
+                         vec![("200", body("The service is ready", "application/json", json!({}))),
+                              ("503", body("The database is unavailable or the service is \
+                                            draining",
+                                           "application/json",
+                                           json!({})))]),
+    }));
+    paths.insert("/stats".into(), json!({
+        "get": operation("Storage statistics",
+                         vec![],
+                         vec![("200", body("Statistics", "application/json", json!({})))]),
+    }));
+    paths.insert("/metrics".into(), json!({
+        "get": operation("Metrics in the Prometheus text format (if enabled)",
+                         vec![],
+                         vec![("200", text("Metrics"))]),
+    }));
+    paths.insert("/signing-key".into(), json!({
+        "get": operation("The public key pastes are signed with (if signing is enabled)",
+                         vec![],
+                         vec![("200", text("Base64 encoded Ed25519 public key"))]),
+    }));
+    paths.insert("/browse".into(), json!({
+        "get": operation("Page through public pastes, the oldest first",
+                         vec![param("cursor", "query", "Cursor of the next page"),
+                              param("limit", "query", "Number of pastes on a page")],
+                         vec![("200", body("A page of public pastes",
+                                           "text/html",
+                                           json!({"type": "string"}))),
+                              ("400", text("Malformed cursor or limit"))]),
+    }));
+    paths.insert("/paste.sh".into(), json!({
+        "get": operation("Command line client script",
+                         vec![],
+                         vec![("200", text("Shell script"))]),
+    }));
+    paths.insert("/admin/".into(), json!({
+        "get": admin(operation("Admin dashboard",
+                               vec![],
+                               vec![("200", body("Storage statistics and recent uploads",
+                                                 "text/html",
+                                                 json!({"type": "string"}))),
+                                    ("401", text("No admin token"))])),
+    }));
+    paths.insert("/admin/pastes".into(), json!({
+        "get": admin(operation("List pastes page by page",
+                               vec![param("cursor", "query", "Cursor of the next page"),
+                                    param("limit", "query", "Number of pastes on a page"),
+                                    param("creator", "query", "IP address of the creator"),
+                                    param("mime_type", "query", "Mime type patterns"),
+                                    param("since", "query", "Unix timestamp"),
+                                    param("until", "query", "Unix timestamp")],
+                               vec![("200", body("A page of pastes",
+                                                 "application/json",
+                                                 json!({}))),
+                                    ("400", text("Malformed arguments")),
+                                    ("401", text("No admin token"))])),
+        "delete": admin(operation("Remove pastes by creator, mime type and creation date",
+                                  vec![param("creator", "query", "IP address of the creator"),
+                                       param("mime_type", "query", "Mime type patterns"),
+                                       param("since", "query", "Unix timestamp"),
+                                       param("until", "query", "Unix timestamp")],
+                                  vec![("200", text("Number of removed pastes")),
+                                       ("400", text("Missing or malformed arguments")),
+                                       ("401", text("No admin token"))])),
+    }));
+    paths.insert("/admin/pastes/{id}".into(), json!({
+        "get": admin(operation("Describe a paste",
+                               vec![param_ref("id")],
+                               vec![("200", body("The paste", "application/json", json!({}))),
+                                    ("401", text("No admin token")),
+                                    ("404", text("No such paste"))])),
+        "delete": admin(operation("Remove a paste",
+                                  vec![param_ref("id")],
+                                  vec![("204", empty("The paste has been removed")),
+                                       ("401", text("No admin token")),
+                                       ("404", text("No such paste"))])),
+    }));
+    paths.insert("/admin/restore/{id}".into(), json!({
+        "post": admin(operation("Restore a paste from the trash",
+                                vec![param_ref("id")],
+                                vec![("200", text("The URL of the paste")),
+                                     ("401", text("No admin token")),
+                                     ("404", text("No such paste in the trash"))])),
+    }));
+    paths.insert("/admin/tombstones".into(), json!({
+        "get": admin(operation("List tombstones of removed pastes, the most recent first",
+                               vec![param("since", "query", "Unix timestamp")],
+                               vec![("200", body("Tombstones", "application/json", json!({}))),
+                                    ("400", text("Malformed arguments")),
+                                    ("401", text("No admin token"))])),
+    }));
+    let banner = json!({"$ref": "#/components/schemas/Banner"});
+    let mut set_banner = admin(operation("Set the announcement banner",
+                                         vec![],
+                                         vec![("200",
+                                               body("The banner",
+                                                    "application/json",
+                                                    banner.clone())),
+                                              ("400", text("Malformed banner")),
+                                              ("401", text("No admin token"))]));
+    set_banner["requestBody"] = json!({
+        "required": true,
+        "content": {"application/json": {"schema": banner}},
+    });
+    paths.insert("/admin/banner".into(), json!({
+        "put": set_banner,
+        "delete": admin(operation("Remove the announcement banner",
+                                  vec![],
+                                  vec![("204", empty("The banner has been removed")),
+                                       ("401", text("No admin token"))])),
+    }));
+    paths.insert("/admin/spam".into(), json!({
+        "get": admin(operation("List pastes flagged by the spam filter, the oldest first",
+                               vec![],
+                               vec![("200", body("Flagged pastes", "application/json", json!({}))),
+                                    ("401", text("No admin token")),
+                                    ("404", text("No spam filter"))])),
+    }));
+    paths.insert("/admin/spam/{id}".into(), json!({
+        "post": admin(operation("Report whether a paste is spam to the spam filter",
+                                vec![param_ref("id"),
+                                     param("label", "query", "Either spam or ham")],
+                                vec![("200", body("The paste, the label and the current score",
+                                                  "application/json",
+                                                  json!({}))),
+                                     ("400", text("Missing or malformed label")),
+                                     ("401", text("No admin token")),
+                                     ("404", text("No such paste or no spam filter"))])),
+    }));
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "Pastebin",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Errors of the API routes are described with {\"error\": \
+                            \"<message>\"}, errors of the other routes with plain text. \
+                            Internal errors are answered with 500.",
+        },
+        "servers": [{"url": url_prefix}],
+        "paths": paths,
+        "components": components(),
+    })
+}
+
+/// Parameters, schemas and security schemes referred by the paths.
+fn components() -> Value {
+    let string = || json!({"type": "string"});
+    let nullable_string = || json!({"type": "string", "nullable": true});
+    let mut expires = param("expires",
+                            "query",
+                            "Unix timestamp the paste expires at (in the future), a lifetime \
+                             like 10m, 1h, 2d or 1w, or never; the server's default time to live \
+                             if not given");
+    expires["schema"] = json!({
+        "oneOf": [{"type": "integer"},
+                  {"type": "string", "pattern": "^[0-9]+[mhdwMHDW]$"},
+                  {"type": "string", "enum": ["never"]}],
+    });
+    json!({
+        "parameters": {
+            "id": param("id", "path", "ID of a paste"),
+            "file_name": param("file_name", "path", "File name of a paste"),
+            "file_name_arg": param("file_name", "query", "File name of a paste"),
+            "token": param("token", "query", "Deletion token given on upload"),
+            "owner_token": param("token",
+                                 "query",
+                                 "Deletion token of the new paste, at least 22 characters of \
+                                  URL-safe base64; pastes uploaded with the same token belong to \
+                                  the same owner (a random token if not given)"),
+            "expires": expires,
+            "rev": param("rev",
+                         "query",
+                         "Revision the update is based on (the X-Revision header of the paste); \
+                          the paste is only updated if it is still at it"),
+            "lang": param("lang",
+                          "query",
+                          "Language of the paste in terms of highlight.js, like rust; guessed \
+                           from the file name and the mime type if not given"),
+            "if_none_match": param("If-None-Match",
+                                   "header",
+                                   "ETag of the data of the paste the client has (pages are not \
+                                    tagged)"),
+            "if_modified_since": param("If-Modified-Since",
+                                       "header",
+                                       "Date of the copy of the data the client has, from the \
+                                        Last-Modified header (replaced pastes have none)"),
+            "range": param("Range",
+                           "header",
+                           "A single range of bytes of the data, like bytes=0-1023 (pastes \
+                            limited in views are always served as a whole)"),
+            "if_range": param("If-Range",
+                              "header",
+                              "ETag or Last-Modified date of the part the client has; the whole \
+                               data is served if it has changed"),
+            "content_encoding": param("Content-Encoding",
+                                      "header",
+                                      "gzip for a gzipped body, which is decompressed (up to the \
+                                       size limit) before it is stored"),
+            "csrf_token": param("X-CSRF-Token",
+                                "header",
+                                "Token of the upload page (the csrf_token cookie), required from \
+                                 browsers unless it is given in the csrf_token form field"),
+            "slug": param("slug",
+                          "query",
+                          "Human-readable name to find the paste by at /s/{slug}, like \
+                           release-notes (letters, digits, dashes and underscores, up to 64); \
+                           it has to be free"),
+            "max_views": param("max_views",
+                               "query",
+                               "Number of views the paste is removed after; the number left is \
+                                given in the X-Views-Left header"),
+            "public": param("public",
+                            "query",
+                            "List the paste at /browse (takes no value); pastes are unlisted by \
+                             default"),
+        },
+        "securitySchemes": {
+            "adminToken": {"type": "http", "scheme": "bearer"},
+            "adminPassword": {
+                "type": "http",
+                "scheme": "basic",
+                "description": "The admin token as the password, for browsers",
+            },
+        },
+        "schemas": {
+            "Error": {
+                "type": "object",
+                "properties": {"error": string()},
+            },
+            "Upload": {
+                "type": "object",
+                "required": ["data"],
+                "properties": {
+                    "data": string(),
+                    "encoding": {"type": "string", "enum": ["utf-8", "base64"]},
+                    "file_name": nullable_string(),
+                    "expires": {"oneOf": [{"type": "integer"}, {"type": "string"}]},
+                    "public": {"type": "boolean"},
+                },
+            },
+            "Form": {
+                "type": "object",
+                "properties": {
+                    "file": {"type": "string", "format": "binary"},
+                    "data": string(),
+                    "file_name": string(),
+                    "expires": string(),
+                    "public": string(),
+                },
+            },
+            "TextForm": {
+                "type": "object",
+                "required": ["content"],
+                "properties": {
+                    "content": string(),
+                    "filename": string(),
+                    "expires": string(),
+                    "public": string(),
+                },
+            },
+            "Created": {
+                "type": "object",
+                "properties": {
+                    "id": string(),
+                    "slug": nullable_string(),
+                    "url": string(),
+                    "expires_at": nullable_string(),
+                    "delete_token": string(),
+                    "retention": {
+                        "type": "object",
+                        "nullable": true,
+                        "description": "Maximum lifetime of pastes of the type (in seconds), \
+                                        and whether the expiration date has been brought \
+                                        closer to fit it",
+                        "properties": {
+                            "max_ttl": {"type": "integer"},
+                            "capped": {"type": "boolean"},
+                        },
+                    },
+                },
+            },
+            "Paste": {
+                "type": "object",
+                "properties": {
+                    "id": string(),
+                    "url": string(),
+                    "file_name": nullable_string(),
+                    "mime_type": string(),
+                    "size": {"type": "integer"},
+                    "lines": {"type": "integer"},
+                    "language": nullable_string(),
+                    "created_at": string(),
+                    "expires_at": nullable_string(),
+                    "views": {"type": "integer", "nullable": true},
+                    "revision": {"type": "integer"},
+                    "views_left": {"type": "integer", "nullable": true},
+                    "public": {"type": "boolean"},
+                    "data": {"type": "string", "format": "byte"},
+                },
+            },
+            "Banner": {
+                "type": "object",
+                "required": ["message"],
+                "properties": {
+                    "message": string(),
+                    "severity": {"type": "string", "enum": ["info", "warning", "critical"]},
+                    "expires_at": {
+                        "oneOf": [{"type": "integer"}, {"type": "string"}],
+                        "nullable": true,
+                        "description": "Unix timestamp when set, RFC 3339 date when described",
+                    },
+                },
+            },
+            "Instance": {
+                "type": "object",
+                "properties": {
+                    "version": string(),
+                    "url": string(),
+                    "max_paste_size": {"type": "integer"},
+                    "banner": {"$ref": "#/components/schemas/Banner"},
+                },
+            },
+        },
+    })
+}
@@ -0,0 +1,95 @@
+//! Helpers to compute the summary fields of a paste.
+
+use PasteEntry;
+use PasteId;
+use PasteSummary;
+use std::path::Path;
+
+/// Counts lines in the data.
+///
+/// A trailing line without a line break is counted as well.
+pub fn count_lines(data: &[u8]) -> usize {
+    let breaks = data.iter().filter(|&&byte| byte == b'\n').count();
+    match data.last() {
+        None | Some(&b'\n') => breaks,
+        Some(_) => breaks + 1,
+    }
+}
+
+/// Estimates a (programming or markup) language of a paste by its file name and mime type.
+///
+/// The returned names are the ones understood by [highlight.js](https://highlightjs.org/).
+pub fn guess_language(file_name: Option<&str>, mime_type: &str) -> Option<&'static str> {
+    file_name.and_then(|name| Path::new(name).extension())
+             .and_then(|ext| ext.to_str())
+             .and_then(|ext| language_from_extension(&ext.to_lowercase()))
+             .or_else(|| language_from_mime(mime_type))
+}
+
+/// Maps a file extension into a language name.
+fn language_from_extension(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+             "rs" => "rust",
+             "c" | "h" => "c",
+             "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+             "go" => "go",
+             "java" => "java",
+             "js" => "javascript",
+             "ts" => "typescript",
+             "py" => "python",
+             "rb" => "ruby",
+             "php" => "php",
+             "sh" | "bash" | "zsh" => "bash",
+             "json" => "json",
+             "yml" | "yaml" => "yaml",
+             "toml" | "ini" | "cfg" => "ini",
+             "md" | "markdown" => "markdown",
+             "html" | "htm" | "xml" | "svg" => "xml",
+             "css" => "css",
+             "sql" => "sql",
+             "diff" | "patch" => "diff",
+             _ => return None,
+         })
+}
+
+/// Maps a mime type into a language name.
+fn language_from_mime(mime_type: &str) -> Option<&'static str> {
+    Some(match mime_type {
+             "application/x-sh" | "text/x-shellscript" => "bash",
+             "application/json" => "json",
+             "application/javascript" | "text/javascript" => "javascript",
+             "text/html" | "text/xml" | "application/xml" => "xml",
+             "text/css" => "css",
+             "text/x-rust" => "rust",
+             "text/x-python" => "python",
+             "text/x-c" => "c",
+             "text/x-c++" => "cpp",
+             "text/markdown" => "markdown",
+             _ => return None,
+         })
+}
+
+impl PasteSummary {
+    /// Computes a summary of a paste.
+    ///
+    /// Database implementations that store summary fields alongside the data might use it at
+    /// the storing time.
+    pub fn from_entry(id: PasteId, entry: &PasteEntry) -> Self {
+        PasteSummary { id,
+                       file_name: entry.file_name.clone(),
+                       mime_type: entry.mime_type.clone(),
+                       size: entry.data.len(),
+                       lines: count_lines(&entry.data),
+                       language: entry.language.clone().or_else(|| {
+                           guess_language(entry.file_name.as_ref().map(|s| s.as_str()),
+                                          entry.mime_type.as_str()).map(Into::into)
+                       }),
+                       created_at: entry.created_at,
+                       best_before: entry.best_before,
+                       views: entry.views,
+                       creator: entry.creator.clone(),
+                       revision: entry.revision,
+                       views_left: entry.views_left,
+                       public: entry.public, }
+    }
+}
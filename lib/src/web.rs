@@ -1,20 +1,53 @@
 //! Module that deals with a web server.
 //!
-//! See [run_web](fn.run_web.html) documentation for details.
+//! See [run_web](fn.run_web.html) documentation for details, and
+//! [PastebinBuilder](struct.PastebinBuilder.html) for more configuration options.
 
 use DbInterface;
+use ExtraPages;
+use analytics::Analytics;
+#[cfg(feature = "basic-auth")]
+use auth::BasicAuth;
+use Banner;
+use dump;
 use HttpResult;
-use chrono::Duration;
-use iron::Listening;
+use IdEncoding;
+use IdGenerator;
+use RetentionPolicy;
+use SizeLimits;
+use Unsupported;
+use chrono::{Duration, Utc};
+use convert::Converters;
+use hyper::net::HttpListener;
 use iron::prelude::*;
+use iron::{Listening, Protocol};
+use jobs;
+#[cfg(feature = "metrics")]
+use metrics::Metrics;
+use middleware::{ErrorLogger, PanicCatcher};
+#[cfg(feature = "metrics")]
+use middleware::RequestMetrics;
 use pastebin::Pastebin;
-use std::net::ToSocketAddrs;
+use redact;
+use secrets::Secret;
+use spam::SpamFilter;
+use std;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
 use tera::Tera;
 
+pub use drain::{inherit_listener, Drain};
+#[cfg(feature = "http-compression")]
+pub use encoding::ResponseCompression;
+pub use proxy::TrustedProxies;
+use proxy::AnonymizeClients;
+pub use security::SecurityHeaders;
+
 /// Runs a web server.
 ///
 /// This is the main function of the library. Starts a web server and serves the
-/// following HTTP requests: `GET`, `POST`, `PUT` and `DELETE`.
+/// following HTTP requests: `GET`, `HEAD`, `POST`, `PUT` and `DELETE`.
 ///
 /// Basically it is just a layer between an `Iron` web server and a `DbInterface` implementation.
 ///
@@ -56,13 +89,68 @@ use tera::Tera;
 /// following files:
 ///
 /// * `show.html.tera`: expects `id` (a paste id), `mime` (mime-type string), `file_name` (`null`
-/// if there is no file name associated with the paste), and `data` which is actually the paste
-/// itself.
-/// * `upload.html.tera`: no parameters.
+/// if there is no file name associated with the paste), `size` (in bytes), `lines` (number of
+/// lines), `language` (the language given with the `lang` argument on upload or view, or an
+/// estimated one, or `null`), `created_at` (formatted date), `expires_in` (seconds left before
+/// the paste expires or `null`), `views` (`null` unless views are counted), `raw_url`,
+/// `download_url`, `fork_url`, `rendered_url` (`null` unless the paste is rendered at
+/// `/<id>/html`), `permalink` (`null` unless content permalinks are enabled), `csrf_token` and
+/// `data` which is actually the paste itself.
+/// * `binary.html.tera`: a page about a paste that is not text, expects `id`, `mime`, `is_image`,
+/// `is_audio`, `is_video`, `file_name`, `size`, `created_at`, `expires_in`, `views`, `raw_url`
+/// and `download_url`.
+/// * `upload.html.tera`: expects `csrf_token`, `content` and `file_name` (empty, unless an
+/// existing paste is edited in a fork).
+/// * `browse.html.tera`: expects `pastes`, a list of objects with `url`, `title`, `mime`, `age`
+/// (in seconds) and `thumbnail_url` (`null` unless the paste is an image), and `next_url` (`null`
+/// on the last page).
+/// * `admin.html.tera`: expects `pastes` (number of pastes), `total_size`, `expiring` (a list of
+/// objects with `within` and `count`), `recent` (entries like the `browse.html.tera` ones, plus
+/// `id`, `size`, `creator` and `delete_url`) and `csrf_token`.
 /// * `paste.sh.tera`: expects `prefix`, see `url_prefix` argument.
 /// * `readme.html.tera`: also expects `prefix`.
+/// * `markdown.html.tera`: expects `id`, `file_name`, `size`, `created_at`, `expires_in`,
+/// `views`, `source_url`, `raw_url` and `html`, which is a Markdown paste rendered into
+/// (sanitized) HTML.
+///
+/// `csrf_token` is only given if CSRF protection is enabled, except for the admin dashboard,
+/// which always gets it. Every template also gets `banner_message` and `banner_style` while a
+/// banner is active. Pages extend `base.html.tera`.
+///
+/// All these files are provided with the service (`/templates/`). Extra pages (see
+/// [PastebinBuilder::extra_pages](struct.PastebinBuilder.html#method.extra_pages)) bring
+/// templates of their own, which get their context along with `prefix`.
+///
+/// Two filters are available to the templates: `filesize` formats a number of bytes like `1.5
+/// KiB`, and `humantime` formats a number of seconds like `3 days`.
+///
+/// # Health checks
+///
+/// A `GET` request on `/healthz` tells whether the service is alive (`{"status": "alive"}`) without
+/// touching the database, and one on `/readyz` checks the database connection (see
+/// `DbInterface::health_check`), responding either with `200 OK` or with `503 Service
+/// Unavailable` and a JSON status. Container orchestrators restart the service when the former
+/// fails and stop routing requests to it while the latter does.
+///
+/// # Statistics
+///
+/// A `GET` request on `/stats` returns storage statistics (see `DbInterface::stats`) as a JSON
+/// object: `{"pastes": <number of pastes>, "total_bytes": <total size of the pastes>,
+/// "total_size": <the same size formatted like "1.5 KiB">, "panics": <number of caught panics>}`.
+/// With [analytics](struct.PastebinBuilder.html#method.analytics) the object also holds a summary
+/// of them as `analytics`.
+///
+/// # Panics
 ///
-/// All these files are provided with the service (`/templates/`).
+/// A panic while handling a request doesn't kill the worker thread: it is logged (together with
+/// a backtrace and an ID of the request) and a `500 Internal Server Error` response with the same
+/// request ID is sent back. The number of caught panics is reported by `/stats`.
+///
+/// # Metrics
+///
+/// With [PastebinBuilder::metrics](struct.PastebinBuilder.html#method.metrics) a `GET` request
+/// on `/metrics` returns request and database metrics in the Prometheus text format, see the
+/// [metrics](../metrics/index.html) module. This needs the `metrics` cargo feature.
 ///
 /// # Notice
 ///
@@ -74,11 +162,16 @@ use tera::Tera;
 /// # `PUT` vs `POST`
 ///
 /// While [REST](https://en.wikipedia.org/wiki/Representational_state_transfer) differentiates
-/// between those two request kinds, there is no difference in this service. Why? Well, just
+/// between those two request kinds, there is almost no difference in this service. Why? Well, just
 /// because some CLI clients tend to use `POST` requests by default for sending data and some use
 /// `PUT`, so that's why the service do not care. If you have any argument why this shouldn't be
 /// the case please fill free to post an issue on github.
 ///
+/// The only exception is a `PUT` request to an address of an existing paste (`/<id>`) along with
+/// the paste's deletion token (`?token=<token>`) or the admin token: in this case the paste's data
+/// is replaced, while its ID (and hence its URL) stays the same. Without a token such a request is
+/// an upload of a new paste, named after the segment.
+///
 /// # Example
 ///
 /// Let's say you have some kind of a database wrapper implemented (`DbImplementation`) and you
@@ -88,27 +181,19 @@ use tera::Tera;
 /// # extern crate pastebin;
 /// # extern crate bson;
 /// # extern crate chrono;
-/// # use pastebin::{DbInterface, PasteEntry};
+/// # use pastebin::{DbInterface, PasteEntry, PasteId};
 /// # use std::io;
-/// # use chrono::{DateTime, Duration, Utc};
+/// # use chrono::Duration;
 /// # struct DbImplementation;
 /// # impl DbInterface for DbImplementation {
 ///   # type Error = io::Error;
-///   # fn store_data(&self,
-///   #               _data: Vec<u8>,
-///   #               _file_name: Option<String>,
-///   #               _mime_type: String,
-///   #               _best_before: Option<DateTime<Utc>>)
-///   #               -> Result<u64, Self::Error> {
-///   #   unimplemented!()
-///   # }
-///   # fn load_data(&self, _: u64) -> Result<Option<PasteEntry>, Self::Error> {
+///   # fn store_data(&self, _: PasteEntry) -> Result<PasteId, Self::Error> {
 ///   #   unimplemented!()
 ///   # }
-///   # fn get_file_name(&self, _: u64) -> Result<Option<String>, Self::Error> {
+///   # fn load_data(&self, _: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
 ///   #   unimplemented!()
 ///   # }
-///   # fn remove_data(&self, _: u64) -> Result<(), Self::Error> {
+///   # fn remove_data(&self, _: PasteId) -> Result<(), Self::Error> {
 ///   #   unimplemented!()
 ///   # }
 ///   # fn max_data_size(&self) -> usize {
@@ -141,27 +226,19 @@ use tera::Tera;
 /// # extern crate pastebin;
 /// # extern crate bson;
 /// # extern crate chrono;
-/// # use pastebin::{DbInterface, PasteEntry};
+/// # use pastebin::{DbInterface, PasteEntry, PasteId};
 /// # use std::io;
-/// # use chrono::{DateTime, Duration, Utc};
+/// # use chrono::Duration;
 /// # struct DbImplementation;
 /// # impl DbInterface for DbImplementation {
 ///   # type Error = io::Error;
-///   # fn store_data(&self,
-///   #               _data: Vec<u8>,
-///   #               _file_name: Option<String>,
-///   #               _mime_type: String,
-///   #               _best_before: Option<DateTime<Utc>>)
-///   #               -> Result<u64, Self::Error> {
-///   #   unimplemented!()
-///   # }
-///   # fn load_data(&self, _: u64) -> Result<Option<PasteEntry>, Self::Error> {
+///   # fn store_data(&self, _: PasteEntry) -> Result<PasteId, Self::Error> {
 ///   #   unimplemented!()
 ///   # }
-///   # fn get_file_name(&self, _: u64) -> Result<Option<String>, Self::Error> {
+///   # fn load_data(&self, _: PasteId) -> Result<Option<PasteEntry>, Self::Error> {
 ///   #   unimplemented!()
 ///   # }
-///   # fn remove_data(&self, _: u64) -> Result<(), Self::Error> {
+///   # fn remove_data(&self, _: PasteId) -> Result<(), Self::Error> {
 ///   #   unimplemented!()
 ///   # }
 ///   # fn max_data_size(&self) -> usize {
@@ -194,12 +271,694 @@ pub fn run_web<Db, A>(db_wrapper: Db,
     where Db: DbInterface + 'static,
           A: ToSocketAddrs
 {
-    // Make sure there is only one trailing slash.
-    let url_prefix = format!("{}/", url_prefix.trim_right_matches('/'));
-    let pastebin = Pastebin::new(Box::new(db_wrapper),
-                                 templates,
-                                 url_prefix,
-                                 default_ttl,
-                                 static_files_path);
-    Iron::new(pastebin).http(addr)
+    PastebinBuilder::new(db_wrapper).templates(templates)
+                                    .url_prefix(url_prefix)
+                                    .default_ttl(default_ttl)
+                                    .static_files_path(static_files_path)
+                                    .run(addr)
+}
+
+/// A builder for a pastebin web server.
+///
+/// It is a more flexible alternative to the [run_web](fn.run_web.html) function: besides the
+/// arguments of `run_web` (which have the same meaning here) it allows to tune optional features
+/// of the server. Every setting has a reasonable default, so only the database has to be provided:
+///
+/// ```no_run
+/// # extern crate chrono;
+/// # extern crate pastebin;
+/// # extern crate tera;
+/// # use chrono::Duration;
+/// # use pastebin::DbInterface;
+/// # fn run<Db: DbInterface + 'static>(db: Db, templates: tera::Tera) {
+/// use pastebin::web::PastebinBuilder;
+///
+/// PastebinBuilder::new(db).templates(templates)
+///                         .url_prefix("https://example.com/")
+///                         .stats_log_interval(Duration::minutes(10))
+///                         .run("0.0.0.0:8000")
+///                         .unwrap();
+/// # }
+/// # fn main() {}
+/// ```
+pub struct PastebinBuilder<E> {
+    db: Arc<DbInterface<Error = E>>,
+    templates: Tera,
+    url_prefix: String,
+    default_ttl: Duration,
+    static_files_path: String,
+    stats_log_interval: Option<Duration>,
+    purge_expired_interval: Option<Duration>,
+    log_body_prefix: usize,
+    drain: Drain,
+    size_limits: SizeLimits,
+    retention: Option<RetentionPolicy>,
+    signing_key: Option<String>,
+    count_views: bool,
+    record_creators: bool,
+    creator_retention: Option<Duration>,
+    backups: Option<(PathBuf, Duration, bool)>,
+    admin_token: Option<Secret>,
+    hotlink_allowed_hosts: Option<Vec<String>>,
+    trusted_proxies: Option<TrustedProxies>,
+    anonymize_clients: bool,
+    security_headers: SecurityHeaders,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    extra_pages: ExtraPages,
+    analytics: Option<Arc<Analytics>>,
+    id_generator: Option<Arc<IdGenerator>>,
+    id_encoding: IdEncoding,
+    trash_grace_period: Option<Duration>,
+    tombstone_retention: Option<Duration>,
+    upload_timeout: Option<Duration>,
+    converters: Converters,
+    content_permalinks: bool,
+    csrf_protection: bool,
+    banner: Option<Banner>,
+    spam_filter: Option<SpamFilter>,
+    #[cfg(feature = "http-compression")]
+    compression: Option<ResponseCompression>,
+    #[cfg(feature = "basic-auth")]
+    basic_auth: Option<BasicAuth>,
+}
+
+impl<E> PastebinBuilder<E>
+    where E: Send + Sync + std::error::Error + From<Unsupported> + 'static
+{
+    /// Starts building a server around a database wrapper.
+    pub fn new<Db>(db_wrapper: Db) -> Self
+        where Db: DbInterface<Error = E> + 'static
+    {
+        PastebinBuilder { db: Arc::new(db_wrapper),
+                          templates: Tera::default(),
+                          url_prefix: "/".into(),
+                          default_ttl: Duration::days(7),
+                          static_files_path: String::new(),
+                          stats_log_interval: None,
+                          purge_expired_interval: None,
+                          log_body_prefix: 0,
+                          drain: Drain::new(),
+                          size_limits: SizeLimits::new(),
+                          retention: None,
+                          signing_key: None,
+                          count_views: true,
+                          record_creators: false,
+                          creator_retention: None,
+                          backups: None,
+                          admin_token: None,
+                          hotlink_allowed_hosts: None,
+                          trusted_proxies: None,
+                          anonymize_clients: false,
+                          security_headers: SecurityHeaders::new(),
+                          #[cfg(feature = "metrics")]
+                          metrics: None,
+                          extra_pages: ExtraPages::new(),
+                          analytics: None,
+                          id_generator: None,
+                          id_encoding: IdEncoding::default(),
+                          trash_grace_period: None,
+                          tombstone_retention: None,
+                          upload_timeout: None,
+                          converters: Converters::default(),
+                          content_permalinks: false,
+                          csrf_protection: true,
+                          banner: None,
+                          spam_filter: None,
+                          #[cfg(feature = "http-compression")]
+                          compression: None,
+                          #[cfg(feature = "basic-auth")]
+                          basic_auth: None, }
+    }
+
+    /// Sets the templates engine.
+    pub fn templates(mut self, templates: Tera) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Sets the URL prefix for the links generated by the server.
+    pub fn url_prefix(mut self, url_prefix: &str) -> Self {
+        // Make sure there is only one trailing slash.
+        self.url_prefix = format!("{}/", url_prefix.trim_right_matches('/'));
+        self
+    }
+
+    /// Sets the default expiration time of pastes.
+    pub fn default_ttl(mut self, default_ttl: Duration) -> Self {
+        self.default_ttl = default_ttl;
+        self
+    }
+
+    /// Sets the path to the static files.
+    pub fn static_files_path(mut self, static_files_path: String) -> Self {
+        self.static_files_path = static_files_path;
+        self
+    }
+
+    /// Enables periodic logging (on the `info` level) of the storage statistics.
+    pub fn stats_log_interval(mut self, interval: Duration) -> Self {
+        self.stats_log_interval = Some(interval);
+        self
+    }
+
+    /// Enables a background job that removes expired pastes (see
+    /// [DbInterface::purge_expired](../trait.DbInterface.html#tymethod.purge_expired)) every
+    /// `interval`.
+    ///
+    /// Expired pastes are never served, but databases without a native expiration of their own
+    /// would keep them forever otherwise.
+    pub fn purge_expired(mut self, interval: Duration) -> Self {
+        self.purge_expired_interval = Some(interval);
+        self
+    }
+
+    /// Allows logging of the first `bytes` bytes of pastes data (zero by default).
+    ///
+    /// The setting is process-wide, see the [redact](../redact/index.html) module. Beware that
+    /// pastes might contain secrets, so only use it for debugging.
+    pub fn log_body_prefix(mut self, bytes: usize) -> Self {
+        self.log_body_prefix = bytes;
+        self
+    }
+
+    /// Sets limits on the size of pastes depending on their mime types.
+    ///
+    /// The database's `max_data_size` is always respected on top of them.
+    pub fn size_limits(mut self, size_limits: SizeLimits) -> Self {
+        self.size_limits = size_limits;
+        self
+    }
+
+    /// Sets maximum lifetimes of pastes depending on their mime types.
+    ///
+    /// Uploads are never stored with an expiration date beyond their maximum lifetime, and the
+    /// effective expiration date is reported back to the uploader (in the `X-Expires-At` header,
+    /// or in the API response). A background job removes the pastes that have outlived the policy
+    /// every hour, including the ones stored before the policy has been set or tightened.
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Sets the public key pastes are signed with, so it is served at `/signing-key` and
+    /// signatures are returned along with pastes. The database is expected to be wrapped into a
+    /// [SignedDb](../db/struct.SignedDb.html) with the matching private key.
+    pub fn signing_key(mut self, public_key: String) -> Self {
+        self.signing_key = Some(public_key);
+        self
+    }
+
+    /// Enables or disables counting views of pastes (enabled by default).
+    ///
+    /// Privacy-conscious installations might not want to track how often pastes are read. When
+    /// disabled, the counters are neither updated nor shown.
+    pub fn count_views(mut self, count_views: bool) -> Self {
+        self.count_views = count_views;
+        self
+    }
+
+    /// Enables or disables content-addressed permalinks of pastes (disabled by default).
+    ///
+    /// Every paste is also linked as `/h/<hash>` of its content (see the `X-Permalink` header),
+    /// which redirects to the paste and keeps working when the paste is migrated under another
+    /// ID, and identical pastes share it. The links are resolved through the hashes stored by
+    /// [DedupDb](../db/struct.DedupDb.html), so the database has to be wrapped into one, and they
+    /// are only given out with the `dedup` feature.
+    pub fn content_permalinks(mut self, enabled: bool) -> Self {
+        self.content_permalinks = enabled;
+        self
+    }
+
+    /// Enables or disables the protection of the upload form against cross-site request forgery
+    /// (enabled by default).
+    ///
+    /// The upload page and the fork button of a paste carry a random token, which is also set as
+    /// the `csrf_token` cookie of the browser's session, and uploads and forks submitted by
+    /// browsers are rejected with `403 Forbidden` unless they pass the token back (in the
+    /// `csrf_token` field or the `X-CSRF-Token` header). So other sites can't make a visitor's
+    /// browser create pastes, using the visitor's credentials or address. Other clients, like
+    /// `curl`, are not affected. Custom upload templates have to submit the `csrf_token` they are
+    /// given.
+    pub fn csrf_protection(mut self, enabled: bool) -> Self {
+        self.csrf_protection = enabled;
+        self
+    }
+
+    /// Sets an announcement banner shown on top of every page (none by default).
+    ///
+    /// The banner lets users know about things like upcoming maintenance windows without editing
+    /// the templates (they get it as `banner_message` and `banner_style`). It is also given by
+    /// the instance API (`GET /api/v1/instance`), goes away by itself once it expires, and could
+    /// be replaced (`PUT /admin/banner`) or removed (`DELETE /admin/banner`) at runtime with the
+    /// [admin API](#method.admin_token).
+    pub fn banner(mut self, banner: Banner) -> Self {
+        self.banner = Some(banner);
+        self
+    }
+
+    /// Checks text uploads with a spam filter, see the [spam](../spam/index.html) module.
+    ///
+    /// Uploads the filter rejects are answered with `403 Forbidden`, the ones it flags are listed
+    /// for review with the [admin API](#method.admin_token).
+    pub fn spam_filter(mut self, filter: SpamFilter) -> Self {
+        self.spam_filter = Some(filter);
+        self
+    }
+
+    /// Compresses responses with gzip or brotli for the clients that accept them (disabled by
+    /// default), which is a big saving on large text pastes like build logs.
+    ///
+    /// Compressed responses are buffered in memory, and their `ETag`s are turned weak, so cached
+    /// copies are still revalidated. Pages that set cookies (the ones with CSRF tokens) are sent
+    /// as they are, see `ResponseCompression`.
+    #[cfg(feature = "http-compression")]
+    pub fn compress_responses(mut self, compression: ResponseCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Asks for a user name and a password before letting anyone in, see the
+    /// [auth](../auth/index.html) module.
+    ///
+    /// Only uploads, updates and removals are protected, unless the policy protects everything.
+    #[cfg(feature = "basic-auth")]
+    pub fn basic_auth(mut self, auth: BasicAuth) -> Self {
+        self.basic_auth = Some(auth);
+        self
+    }
+
+    /// Enables or disables recording client IP addresses of uploaders (disabled by default).
+    ///
+    /// The addresses are stored along with pastes (see `PasteEntry::creator`) so abuse reports
+    /// could be dealt with, but they are never shown to users. Behind a reverse proxy, see
+    /// [trusted_proxies](#method.trusted_proxies). With
+    /// [anonymize_clients](#method.anonymize_clients), pseudonyms are recorded instead.
+    pub fn record_creators(mut self, record_creators: bool) -> Self {
+        self.record_creators = record_creators;
+        self
+    }
+
+    /// Sets for how long creators of pastes are kept.
+    ///
+    /// A background job scrubs creators of pastes older than `retention` every hour. By default
+    /// creators are kept as long as the pastes themselves.
+    pub fn creator_retention(mut self, retention: Duration) -> Self {
+        self.creator_retention = Some(retention);
+        self
+    }
+
+    /// Sets converters pastes are served converted with at `/<id>/as/<format>`, see the
+    /// [convert](../convert/index.html) module. By default all the built-in converters are
+    /// available.
+    pub fn converters(mut self, converters: Converters) -> Self {
+        self.converters = converters;
+        self
+    }
+
+    /// Moves removed pastes to the trash instead of removing them right away.
+    ///
+    /// A trashed paste is not served anymore, but it could be brought back with the admin API
+    /// (see [admin_token](#method.admin_token)) for the `grace_period`. A background job purges
+    /// pastes trashed longer ago than that every hour.
+    pub fn trash(mut self, grace_period: Duration) -> Self {
+        self.trash_grace_period = Some(grace_period);
+        self
+    }
+
+    /// Keeps tombstones of removed pastes for the `retention` period, so requests for them are
+    /// answered with `410 Gone` and a note on when they have been removed.
+    ///
+    /// Tombstones also allow admins to audit removals, see [admin_token](#method.admin_token). A
+    /// background job purges tombstones older than the `retention` every hour.
+    pub fn tombstones(mut self, retention: Duration) -> Self {
+        self.tombstone_retention = Some(retention);
+        self
+    }
+
+    /// Sets the time an upload has to be received in (unlimited by default).
+    ///
+    /// Uploads that don't make it in time are answered with `408 Request Timeout`. Either way,
+    /// an upload is only stored once it has been received completely, so neither a slow client
+    /// nor one that disconnects midway leaves a truncated paste behind.
+    pub fn upload_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_timeout = Some(timeout);
+        self
+    }
+
+    /// Enables periodic backups of all the pastes into the directory, see
+    /// [dump::backup](../dump/fn.backup.html).
+    ///
+    /// With `incremental` set only the first backup is a full one, the following ones hold new
+    /// pastes only. Old backups are not removed.
+    pub fn backups(mut self, dir: PathBuf, interval: Duration, incremental: bool) -> Self {
+        self.backups = Some((dir, interval, incremental));
+        self
+    }
+
+    /// Enables the admin API, which is only available to requests with an
+    /// `Authorization: Bearer <token>` header (or, for browsers, with the token as the password
+    /// of HTTP Basic authentication).
+    ///
+    /// The API consists of:
+    ///
+    /// * `GET /admin/`, a dashboard rendered from the `admin.html` template: storage statistics,
+    /// how many pastes expire within an hour, a day and a week, and the most recent uploads with
+    /// buttons that remove them. Browsers are asked for the token there. Requests from browsers
+    /// that change anything with the token taken that way (rather than as a bearer token) have
+    /// to carry the CSRF token of the dashboard as well, see the `csrf` module.
+    /// * `GET /admin/pastes`, which lists pastes as JSON, page by page: `{"pastes": [...],
+    /// "next_cursor": "..."}`. At most `limit` pastes (20 by default, 100 at most) are returned,
+    /// and the next page is requested with `?cursor=<next_cursor>`, which is `null` on the last
+    /// page. The listing is narrowed down by a `creator`, `mime_type` patterns (like `image/*`)
+    /// and the creation date (`since` and `until`, Unix timestamps).
+    /// * `DELETE /admin/pastes`, which removes all the pastes that pass the same filters (at
+    /// least one of them is required), and responds with the number of removed pastes. Pastes of
+    /// a creator created since a moment are removed by `DbInterface::remove_by_creator`. Creators
+    /// have to be recorded to be filtered by, see [record_creators](#method.record_creators).
+    /// * `GET /admin/pastes/<id>`, which describes a paste as JSON, including its creator.
+    /// * `DELETE /admin/pastes/<id>`, which removes a paste (or trashes it).
+    /// * `POST /admin/restore/<id>`, which brings a paste back from the trash (see
+    /// [trash](#method.trash)) and responds with its URL.
+    /// * `GET /admin/tombstones?since=<timestamp>`, which lists the kept tombstones (see
+    /// [tombstones](#method.tombstones)) of pastes removed since the Unix timestamp, if given, as
+    /// JSON: `{"tombstones": [{"id": "...", "removed_at": "...", "actor": "admin"}]}`. The actor
+    /// is either `admin` or `uploader`.
+    /// * `PUT /admin/banner` and `DELETE /admin/banner`, which replace or remove the announcement
+    /// banner (see [banner](#method.banner)).
+    /// * `GET /admin/spam`, which lists pastes flagged by the spam filter (see
+    /// [spam_filter](#method.spam_filter)) that haven't been reviewed yet: `{"flagged": [{"id":
+    /// "...", "score": 0.6, "flagged_at": "..."}]}`.
+    /// * `POST /admin/spam/<id>?label=<spam|ham>`, which tells the spam filter whether the paste
+    /// is spam, so the classifier could learn from its mistakes.
+    ///
+    /// The admin token also allows to remove any paste with `DELETE /<id>`, without the deletion
+    /// token the paste has been given on upload.
+    pub fn admin_token(mut self, token: Secret) -> Self {
+        self.admin_token = Some(token);
+        self
+    }
+
+    /// Enables hotlink protection for binary (non-text) pastes, so a public instance doesn't
+    /// serve images and such for other sites.
+    ///
+    /// A request for a binary paste that comes from a page (according to its `Referer` header) of
+    /// neither the service itself nor one of the `allowed_hosts` is redirected to a page about
+    /// the paste (`<paste URL>?page`). Requests without a `Referer` are served as usual.
+    pub fn hotlink_protection(mut self, allowed_hosts: Vec<String>) -> Self {
+        self.hotlink_allowed_hosts = Some(allowed_hosts);
+        self
+    }
+
+    /// Takes client addresses from the `X-Forwarded-For` and `X-Real-IP` headers of requests that
+    /// come from the proxies (none by default), see [TrustedProxies](struct.TrustedProxies.html).
+    ///
+    /// Behind a reverse proxy every request seems to come from the proxy itself, so the
+    /// addresses the proxy forwards requests for are recorded as creators of pastes, counted by
+    /// the analytics and logged instead. Headers of requests that come from anywhere else are
+    /// ignored, since clients could make them up.
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    /// Enables or disables hiding client addresses from the service (disabled by default).
+    ///
+    /// Right after the address of a client is found (see
+    /// [trusted_proxies](#method.trusted_proxies)), it is replaced with a pseudonym: a hash of the
+    /// address salted with a random value, which is replaced every day and is never stored, put
+    /// into the `100::/64` discard prefix. Everything that asks for the address of a client gets
+    /// the pseudonym instead, so no real address is ever recorded as the creator of a paste,
+    /// reported to the analytics or logged, including by features yet to come.
+    pub fn anonymize_clients(mut self, anonymize: bool) -> Self {
+        self.anonymize_clients = anonymize;
+        self
+    }
+
+    /// Sets the hardening headers added to every response, see
+    /// [SecurityHeaders](struct.SecurityHeaders.html) for the defaults.
+    ///
+    /// Templates that load scripts, styles or images from other sites than the shipped ones do
+    /// need a `Content-Security-Policy` of their own. `SecurityHeaders::none()` turns the headers
+    /// off, for instance when a reverse proxy sets them.
+    pub fn security_headers(mut self, headers: SecurityHeaders) -> Self {
+        self.security_headers = headers;
+        self
+    }
+
+    /// Sets extra pages rendered from templates, like `/about` or `/privacy`, see
+    /// [ExtraPages](../struct.ExtraPages.html).
+    ///
+    /// A page's template gets the page's context along with the `prefix` value, just like the
+    /// `readme.html` template does.
+    pub fn extra_pages(mut self, extra_pages: ExtraPages) -> Self {
+        self.extra_pages = extra_pages;
+        self
+    }
+
+    /// Reports views and uploads of pastes to the analytics, see the
+    /// [analytics](../analytics/index.html) module. A summary of the collected data (if the
+    /// analytics provide one) is added to `/stats` as `analytics`.
+    pub fn analytics(mut self, analytics: Arc<Analytics>) -> Self {
+        self.analytics = Some(analytics);
+        self
+    }
+
+    /// Sets the generator of IDs of new pastes, see [IdGenerator](../trait.IdGenerator.html).
+    ///
+    /// By default the database issues sequential IDs, which are short, but anyone could walk
+    /// through them. With [RandomIds](../struct.RandomIds.html) pastes get IDs that can't be
+    /// guessed, so pastes that are not listed are actually hard to come by:
+    ///
+    /// ```no_run
+    /// # extern crate pastebin;
+    /// # use pastebin::DbInterface;
+    /// # fn run<Db: DbInterface + 'static>(db: Db) {
+    /// use pastebin::RandomIds;
+    /// use pastebin::web::PastebinBuilder;
+    /// use std::sync::Arc;
+    ///
+    /// PastebinBuilder::new(db).id_generator(Arc::new(RandomIds::new(8)))
+    ///                         .run("0.0.0.0:8000")
+    ///                         .unwrap();
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn id_generator(mut self, id_generator: Arc<IdGenerator>) -> Self {
+        self.id_generator = Some(id_generator);
+        self
+    }
+
+    /// Sets how IDs are written in URLs, see [IdEncoding](../struct.IdEncoding.html). By default
+    /// they are in url-safe base64, as short as possible.
+    ///
+    /// Base58 IDs have no characters that look alike and are easier to read out or type in, and
+    /// a minimum length keeps the first pastes from getting one-character URLs:
+    ///
+    /// ```no_run
+    /// # extern crate pastebin;
+    /// # use pastebin::DbInterface;
+    /// # fn run<Db: DbInterface + 'static>(db: Db) {
+    /// use pastebin::IdEncoding;
+    /// use pastebin::web::PastebinBuilder;
+    ///
+    /// PastebinBuilder::new(db).id_encoding(IdEncoding::base58().min_length(6))
+    ///                         .run("0.0.0.0:8000")
+    ///                         .unwrap();
+    /// # }
+    /// # fn main() {}
+    /// ```
+    ///
+    /// Only URLs are affected: the database, logs and dumps keep the IDs as they are. URLs given
+    /// out before switching to another alphabet stop working, so it is better settled on before
+    /// the first upload.
+    pub fn id_encoding(mut self, id_encoding: IdEncoding) -> Self {
+        self.id_encoding = id_encoding;
+        self
+    }
+
+    /// Enables metrics: requests and sizes of their bodies are recorded in the registry, which is
+    /// served at `/metrics` in the Prometheus text format.
+    ///
+    /// To get the database calls recorded as well, wrap the database into a
+    /// [db::InstrumentedDb](../db/struct.InstrumentedDb.html) with the same registry.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sets a handle to put the server into a drain mode, see [Drain](struct.Drain.html).
+    pub fn drain(mut self, drain: Drain) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    /// Starts background jobs and runs a web server on a given address.
+    ///
+    /// Please refer to the [run_web](fn.run_web.html) documentation for the details on the
+    /// returned value.
+    pub fn run<A: ToSocketAddrs>(self, addr: A) -> HttpResult<Listening> {
+        Iron::new(self.into_chain()).http(addr)
+    }
+
+    /// Starts background jobs and runs a web server on an already listening socket, for example
+    /// the one obtained by [inherit_listener](fn.inherit_listener.html).
+    pub fn run_on(self, listener: TcpListener) -> HttpResult<Listening> {
+        Iron::new(self.into_chain()).listen(HttpListener::from(listener), Protocol::http())
+    }
+
+    /// Starts background jobs and builds a request handling chain.
+    fn into_chain(self) -> Chain {
+        redact::set_log_prefix_len(self.log_body_prefix);
+        if let Some(interval) = self.stats_log_interval {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("stats-logger", interval, move || match db.stats() {
+                Ok(stats) => info!("Storage statistics: {} pastes, {} bytes total",
+                                   stats.pastes,
+                                   stats.total_bytes),
+                Err(e) => error!("Can't collect storage statistics: {}", e),
+            });
+        }
+        if let Some(interval) = self.purge_expired_interval {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("expiry-reaper", interval, move || {
+                match db.purge_expired(Utc::now()) {
+                    Ok(purged) => debug!("Purged {} expired pastes", purged),
+                    Err(e) => error!("Can't purge expired pastes: {}", e),
+                }
+            });
+        }
+        if let Some(retention) = self.creator_retention {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("creator-scrubber", Duration::hours(1), move || {
+                match db.scrub_creators(Utc::now() - retention) {
+                    Ok(scrubbed) => debug!("Scrubbed creators of {} pastes", scrubbed),
+                    Err(e) => error!("Can't scrub creators of pastes: {}", e),
+                }
+            });
+        }
+        if let Some(grace_period) = self.trash_grace_period {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("trash-purger", Duration::hours(1), move || {
+                match db.purge_trash(Utc::now() - grace_period) {
+                    Ok(purged) => debug!("Purged {} pastes from the trash", purged),
+                    Err(e) => error!("Can't purge the trash: {}", e),
+                }
+            });
+        }
+        if let Some(retention) = self.tombstone_retention {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("tombstone-purger", Duration::hours(1), move || {
+                match db.purge_tombstones(Utc::now() - retention) {
+                    Ok(purged) => debug!("Purged {} tombstones", purged),
+                    Err(e) => error!("Can't purge tombstones: {}", e),
+                }
+            });
+        }
+        if let Some(ref policy) = self.retention {
+            let db = Arc::clone(&self.db);
+            let policy = policy.clone();
+            jobs::spawn_periodic("retention-sweeper", Duration::hours(1), move || {
+                match policy.sweep(&*db, Utc::now()) {
+                    Ok(removed) => debug!("Swept {} pastes by the retention policy", removed),
+                    Err(e) => error!("Can't sweep pastes by the retention policy: {}", e),
+                }
+            });
+        }
+        if let Some((dir, interval, incremental)) = self.backups {
+            let db = Arc::clone(&self.db);
+            jobs::spawn_periodic("backup", interval, move || {
+                match dump::backup(&*db, &dir, incremental) {
+                    Ok((path, pastes)) => {
+                        info!("Backed up {} pastes to {}", pastes, path.display())
+                    }
+                    Err(e) => error!("Can't back up pastes: {}", e),
+                }
+            });
+        }
+        let mut pastebin = Pastebin::new(self.db,
+                                         self.templates,
+                                         self.url_prefix,
+                                         self.default_ttl,
+                                         self.static_files_path);
+        pastebin = pastebin.size_limits(self.size_limits)
+                           .count_views(self.count_views)
+                           .record_creators(self.record_creators)
+                           .extra_pages(self.extra_pages)
+                           .converters(self.converters)
+                           .id_encoding(self.id_encoding)
+                           .use_trash(self.trash_grace_period.is_some())
+                           .tombstones(self.tombstone_retention.is_some())
+                           .content_permalinks(self.content_permalinks)
+                           .csrf_protection(self.csrf_protection);
+        if let Some(policy) = self.retention {
+            pastebin = pastebin.retention(policy);
+        }
+        if let Some(timeout) = self.upload_timeout {
+            pastebin = pastebin.upload_timeout(timeout);
+        }
+        if let Some(public_key) = self.signing_key {
+            pastebin = pastebin.signing_key(public_key);
+        }
+        if let Some(token) = self.admin_token {
+            pastebin = pastebin.admin_token(token);
+        }
+        if let Some(allowed_hosts) = self.hotlink_allowed_hosts {
+            pastebin = pastebin.hotlink_protection(allowed_hosts);
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                pastebin = pastebin.metrics(metrics.clone());
+            }
+        }
+        if let Some(analytics) = self.analytics {
+            pastebin = pastebin.analytics(analytics);
+        }
+        if let Some(id_generator) = self.id_generator {
+            pastebin = pastebin.id_generator(id_generator);
+        }
+        if let Some(banner) = self.banner {
+            pastebin = pastebin.banner(banner);
+        }
+        if let Some(filter) = self.spam_filter {
+            pastebin = pastebin.spam_filter(filter);
+        }
+        let mut chain = Chain::new(pastebin);
+        // First, so everything else sees the address of the client.
+        if let Some(proxies) = self.trusted_proxies {
+            chain.link_before(proxies);
+        }
+        // Right after the proxies, so the real address goes no further.
+        if self.anonymize_clients {
+            chain.link_before(AnonymizeClients);
+        }
+        #[cfg(feature = "basic-auth")]
+        {
+            if let Some(auth) = self.basic_auth {
+                chain.link_before(auth);
+            }
+        }
+        chain.link_around(PanicCatcher);
+        // Outermost, so requests are counted as in flight until their responses are ready.
+        chain.link_around(self.drain);
+        chain.link_after(ErrorLogger);
+        // After the errors are turned into responses, so error pages get the headers as well.
+        chain.link_after(self.security_headers);
+        // Before the metrics, so they record the bytes actually sent.
+        #[cfg(feature = "http-compression")]
+        {
+            if let Some(compression) = self.compression {
+                chain.link_after(compression);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(metrics) = self.metrics {
+                chain.link_after(RequestMetrics(metrics));
+            }
+        }
+        chain
+    }
 }